@@ -0,0 +1,289 @@
+//------------------------------------------------------------------------------
+//! # Eagle macros
+//!
+//! Attribute macros turning an async function into a function that builds a
+//! runtime and blocks on the body. They are re-exported by `eagle` as
+//! `eagle::main` and `eagle::test`.
+//------------------------------------------------------------------------------
+
+use proc_macro::TokenStream;
+use proc_macro2::{ Span, TokenStream as TokenStream2 };
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{ parse_macro_input, Expr, ExprLit, ItemFn, Lit, MetaNameValue, Token };
+
+
+//------------------------------------------------------------------------------
+/// # main
+///
+/// Runs the async `main` function on an `Executor`.
+///
+/// - flavor: `"multi_thread"` (default) runs the tasks on worker threads,
+///   `"current_thread"` on the main thread.
+/// - worker_threads: Number of worker threads of the `"multi_thread"`
+///   flavor. Defaults to the available parallelism.
+///
+/// ```
+/// #[eagle::main(worker_threads = 4)]
+/// async fn main()
+/// {
+///     println!("running on four workers");
+/// }
+/// ```
+//------------------------------------------------------------------------------
+#[proc_macro_attribute]
+pub fn main( args: TokenStream, item: TokenStream ) -> TokenStream
+{
+    let args = parse_macro_input!(args with Punctuated::<MetaNameValue, Token![,]>::parse_terminated);
+    let function = parse_macro_input!(item as ItemFn);
+    expand(args, function, Kind::Main).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+
+//------------------------------------------------------------------------------
+/// # test
+///
+/// Runs the async test function on an `Executor` or a `Simulation`.
+///
+/// - flavor: `"current_thread"` (default) runs the tasks on the test thread,
+///   `"multi_thread"` on worker threads, and `"simulation"` in a
+///   deterministic simulation with virtual time.
+/// - worker_threads: Number of worker threads of the `"multi_thread"`
+///   flavor. Defaults to the available parallelism.
+/// - seed: Seed of the `"simulation"` flavor. Defaults to zero.
+///
+/// ```
+/// #[eagle::test(flavor = "simulation", seed = 7)]
+/// async fn times_out()
+/// {
+///     eagle::sim::sleep(std::time::Duration::from_secs(30)).await;
+///     assert_eq!(eagle::sim::now().as_secs(), 30);
+/// }
+/// ```
+//------------------------------------------------------------------------------
+#[proc_macro_attribute]
+pub fn test( args: TokenStream, item: TokenStream ) -> TokenStream
+{
+    let args = parse_macro_input!(args with Punctuated::<MetaNameValue, Token![,]>::parse_terminated);
+    let function = parse_macro_input!(item as ItemFn);
+    expand(args, function, Kind::Test).unwrap_or_else(syn::Error::into_compile_error).into()
+}
+
+
+//------------------------------------------------------------------------------
+/// # Kind
+//------------------------------------------------------------------------------
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind
+{
+    Main,
+    Test,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Flavor
+//------------------------------------------------------------------------------
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Flavor
+{
+    MultiThread,
+    CurrentThread,
+    Simulation,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Config
+//------------------------------------------------------------------------------
+struct Config
+{
+    flavor: Flavor,
+    worker_threads: Option<usize>,
+    seed: Option<u64>,
+}
+
+impl Config
+{
+    //--------------------------------------------------------------------------
+    /// Parses the arguments of the attribute.
+    //--------------------------------------------------------------------------
+    fn parse
+    (
+        args: Punctuated<MetaNameValue, Token![,]>,
+        kind: Kind,
+    ) -> syn::Result<Self>
+    {
+        let mut config = Self
+        {
+            flavor: match kind
+            {
+                Kind::Main => Flavor::MultiThread,
+                Kind::Test => Flavor::CurrentThread,
+            },
+            worker_threads: None,
+            seed: None,
+        };
+
+        for arg in &args
+        {
+            let name = arg.path.get_ident().map(|ident| ident.to_string());
+            match (name.as_deref(), &arg.value)
+            {
+                (Some("flavor"), Expr::Lit(ExprLit { lit: Lit::Str(flavor), .. })) =>
+                {
+                    config.flavor = match (flavor.value().as_str(), kind)
+                    {
+                        ("multi_thread", _) => Flavor::MultiThread,
+                        ("current_thread", _) => Flavor::CurrentThread,
+                        ("simulation", Kind::Test) => Flavor::Simulation,
+                        ("simulation", Kind::Main) => return Err(syn::Error::new_spanned
+                        (
+                            flavor,
+                            "the `simulation` flavor is only available in tests",
+                        )),
+                        _ => return Err(syn::Error::new_spanned
+                        (
+                            flavor,
+                            "unknown flavor, expected `multi_thread`, `current_thread` or `simulation`",
+                        )),
+                    };
+                },
+                (Some("worker_threads"), Expr::Lit(ExprLit { lit: Lit::Int(threads), .. })) =>
+                {
+                    let threads = threads.base10_parse()?;
+                    if threads == 0
+                    {
+                        return Err(syn::Error::new_spanned(arg, "`worker_threads` must be at least one"));
+                    }
+                    config.worker_threads = Some(threads);
+                },
+                (Some("seed"), Expr::Lit(ExprLit { lit: Lit::Int(seed), .. })) =>
+                {
+                    config.seed = Some(seed.base10_parse()?);
+                },
+                (Some("flavor" | "worker_threads" | "seed"), value) =>
+                {
+                    return Err(syn::Error::new_spanned(value, "expected a literal"));
+                },
+                _ =>
+                {
+                    return Err(syn::Error::new_spanned
+                    (
+                        &arg.path,
+                        "unknown option, expected `flavor`, `worker_threads` or `seed`",
+                    ));
+                },
+            }
+        }
+
+        if config.worker_threads.is_some() && config.flavor != Flavor::MultiThread
+        {
+            return Err(syn::Error::new
+            (
+                Span::call_site(),
+                "`worker_threads` requires the `multi_thread` flavor",
+            ));
+        }
+        if config.seed.is_some() && config.flavor != Flavor::Simulation
+        {
+            return Err(syn::Error::new
+            (
+                Span::call_site(),
+                "`seed` requires the `simulation` flavor",
+            ));
+        }
+        Ok(config)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # expand
+///
+/// Replaces the async function by one blocking on its body.
+//------------------------------------------------------------------------------
+fn expand
+(
+    args: Punctuated<MetaNameValue, Token![,]>,
+    mut function: ItemFn,
+    kind: Kind,
+) -> syn::Result<TokenStream2>
+{
+    let config = Config::parse(args, kind)?;
+    if function.sig.asyncness.take().is_none()
+    {
+        return Err(syn::Error::new_spanned
+        (
+            function.sig.fn_token,
+            "the `async` keyword is missing from the function declaration",
+        ));
+    }
+    if !function.sig.inputs.is_empty()
+    {
+        return Err(syn::Error::new_spanned
+        (
+            &function.sig.inputs,
+            "the function cannot take arguments",
+        ));
+    }
+
+    let body = &function.block;
+    let run = match config.flavor
+    {
+        Flavor::MultiThread | Flavor::CurrentThread =>
+        {
+            let threads = match (config.flavor, config.worker_threads)
+            {
+                (Flavor::CurrentThread, _) => quote! { 0 },
+                (_, Some(threads)) => quote! { #threads },
+                (_, None) => quote!
+                {
+                    ::std::thread::available_parallelism().map_or(1, |threads| threads.get())
+                },
+            };
+            quote!
+            {
+                let mut executor = ::eagle::executor::Executor::new(#threads);
+                executor.start();
+                match executor.block_on(body)
+                {
+                    Ok(output) => output,
+                    Err(error) => panic!("{}", error),
+                }
+            }
+        },
+        Flavor::Simulation =>
+        {
+            let seed = config.seed.unwrap_or(0);
+            quote!
+            {
+                let mut simulation = ::eagle::sim::Simulation::new(#seed);
+                match simulation.block_on(body)
+                {
+                    Ok(output) => output,
+                    Err(error) => panic!("simulation with seed {} failed: {}", #seed, error),
+                }
+            }
+        },
+    };
+
+    let test = match kind
+    {
+        Kind::Test => quote! { #[::core::prelude::v1::test] },
+        Kind::Main => quote! {},
+    };
+    let attrs = &function.attrs;
+    let vis = &function.vis;
+    let sig = &function.sig;
+    Ok(quote!
+    {
+        #test
+        #(#attrs)*
+        #vis #sig
+        {
+            let body = async move #body;
+            #run
+        }
+    })
+}
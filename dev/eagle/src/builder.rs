@@ -2,7 +2,12 @@
 //! Server builder
 //------------------------------------------------------------------------------
 
-use crate::server::EagleServer;
+use crate::http::HttpOptions;
+use crate::listener::{ ListenerConfig, UnixSocketOptions };
+use crate::server::{ EagleServer, ServerConfig };
+use crate::signal::SignalKind;
+
+use std::time::Duration;
 
 
 //------------------------------------------------------------------------------
@@ -10,7 +15,19 @@ use crate::server::EagleServer;
 //------------------------------------------------------------------------------
 pub struct EagleServerBuilder
 {
-    address: String,
+    addresses: Vec<(String, ListenerConfig)>,
+    unix_socket_mode: Option<u32>,
+    remove_stale_socket: bool,
+    reuse_port: bool,
+    socket_activation: bool,
+    systemd_notify: bool,
+    graceful_shutdown: bool,
+    debug_endpoint: bool,
+    task_dump_signal: Option<SignalKind>,
+    upgrade_signal: Option<SignalKind>,
+    keep_alive: bool,
+    keep_alive_timeout: Duration,
+    max_keep_alive_requests: Option<usize>,
 }
 
 impl EagleServerBuilder
@@ -22,16 +39,223 @@ impl EagleServerBuilder
     {
         Self
         {
-            address: String::new(),
+            addresses: Vec::new(),
+            unix_socket_mode: None,
+            remove_stale_socket: true,
+            reuse_port: false,
+            socket_activation: false,
+            systemd_notify: false,
+            graceful_shutdown: true,
+            debug_endpoint: false,
+            task_dump_signal: None,
+            upgrade_signal: None,
+            keep_alive: true,
+            keep_alive_timeout: Duration::from_secs(5),
+            max_keep_alive_requests: Some(1000),
         }
     }
 
     //--------------------------------------------------------------------------
-    /// Sets the address of the server.
+    /// Sets the address of the server, either a TCP address such as
+    /// `127.0.0.1:8080`, or the path of a Unix domain socket prefixed with
+    /// `unix:`, such as `unix:/run/eagle.sock`.
+    ///
+    /// This replaces the addresses added with `bind`.
     //--------------------------------------------------------------------------
     pub fn address(&mut self, address: &str) -> &mut Self
     {
-        self.address = address.to_string();
+        self.addresses = vec![(address.to_string(), ListenerConfig::default())];
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Adds an address the server listens on, in the same form as `address`.
+    /// The connections accepted on all the addresses are served by the same
+    /// runtime.
+    //--------------------------------------------------------------------------
+    pub fn bind(&mut self, address: &str) -> &mut Self
+    {
+        self.bind_with(address, &ListenerConfig::default())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Adds an address the server listens on, like `bind`, with its own
+    /// configuration, such as a handler for an internal admin port.
+    ///
+    /// TLS is out of scope: the server has no TLS support, so every address
+    /// serves plain HTTP and `ListenerConfig` carries no TLS configuration.
+    ///
+    /// The configuration also applies to the sockets inherited on an upgrade
+    /// from this address, and to the sockets passed by systemd whose
+    /// `FileDescriptorName=` is the address.
+    //--------------------------------------------------------------------------
+    pub fn bind_with(&mut self, address: &str, config: &ListenerConfig) -> &mut Self
+    {
+        self.addresses.push((address.to_string(), config.clone()));
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the permissions of the Unix domain socket file, such as `0o660`
+    /// to let the group of the server connect.
+    ///
+    /// By default, the file is created with the permissions allowed by the
+    /// umask of the process.
+    //--------------------------------------------------------------------------
+    pub fn unix_socket_mode(&mut self, mode: Option<u32>) -> &mut Self
+    {
+        self.unix_socket_mode = mode;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether a socket file already at the path of the Unix domain
+    /// socket is removed before binding, when nothing accepts connections on
+    /// it anymore. A socket still in use, or a file that is not a socket, is
+    /// never removed.
+    ///
+    /// This is enabled by default.
+    //--------------------------------------------------------------------------
+    pub fn remove_stale_socket(&mut self, enabled: bool) -> &mut Self
+    {
+        self.remove_stale_socket = enabled;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether each TCP address is bound by one `SO_REUSEPORT` listener
+    /// per worker thread, instead of a single listener. The kernel then
+    /// balances the incoming connections between the listeners, which accept
+    /// and handle them in parallel. The distribution can be checked with
+    /// `EagleServer::accept_metrics`.
+    ///
+    /// This is disabled by default.
+    //--------------------------------------------------------------------------
+    pub fn reuse_port(&mut self, enabled: bool) -> &mut Self
+    {
+        self.reuse_port = enabled;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether the server listens on the sockets passed by systemd
+    /// through `LISTEN_FDS`, instead of binding its addresses. The addresses
+    /// are still bound when no socket is passed, such as when the server is
+    /// started by hand. The sockets are named in the metrics after their
+    /// `FileDescriptorName=`.
+    ///
+    /// This is disabled by default.
+    //--------------------------------------------------------------------------
+    pub fn socket_activation(&mut self, enabled: bool) -> &mut Self
+    {
+        self.socket_activation = enabled;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether the server sends `READY=1` once it is listening and
+    /// `STOPPING=1` when it shuts down to systemd through `NOTIFY_SOCKET`,
+    /// and pings the watchdog when `WATCHDOG_USEC` is set. Nothing is sent
+    /// when the server is not started by systemd.
+    ///
+    /// This is disabled by default.
+    //--------------------------------------------------------------------------
+    pub fn systemd_notify(&mut self, enabled: bool) -> &mut Self
+    {
+        self.systemd_notify = enabled;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether the server shuts down gracefully on SIGTERM.
+    ///
+    /// This is enabled by default.
+    //--------------------------------------------------------------------------
+    pub fn graceful_shutdown(&mut self, enabled: bool) -> &mut Self
+    {
+        self.graceful_shutdown = enabled;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether the server answers `GET /debug/tasks` with a dump of its
+    /// live tasks, and `GET /debug/listeners` with the accept metrics of its
+    /// listeners.
+    ///
+    /// This is disabled by default, as the dump exposes the internals of the
+    /// server to its clients.
+    //--------------------------------------------------------------------------
+    pub fn debug_endpoint(&mut self, enabled: bool) -> &mut Self
+    {
+        self.debug_endpoint = enabled;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the signal on which the server prints a dump of its live tasks to
+    /// the standard error, such as `SignalKind::user_defined1()`.
+    ///
+    /// This is disabled by default.
+    //--------------------------------------------------------------------------
+    pub fn task_dump_signal(&mut self, signal: Option<SignalKind>) -> &mut Self
+    {
+        self.task_dump_signal = signal;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the signal on which the server upgrades to a new binary without
+    /// dropping connections, such as `SignalKind::user_defined2()`.
+    ///
+    /// The binary at the path the server was started from is executed with
+    /// the same arguments, and inherits the listening sockets. Once the new
+    /// process is listening on them, the server stops accepting connections
+    /// and returns after handling the current ones, as on a graceful
+    /// shutdown. If the new process fails to start, the server goes on.
+    ///
+    /// This is disabled by default.
+    //--------------------------------------------------------------------------
+    pub fn upgrade_signal(&mut self, signal: Option<SignalKind>) -> &mut Self
+    {
+        self.upgrade_signal = signal;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Enables or disables persistent connections. When enabled, a connection
+    /// serves several requests, as HTTP/1.1 does by default and HTTP/1.0 does
+    /// on `Connection: keep-alive`, and pipelined requests are answered in
+    /// order. When disabled, the connection is closed after each response.
+    ///
+    /// This is enabled by default.
+    //--------------------------------------------------------------------------
+    pub fn keep_alive(&mut self, enabled: bool) -> &mut Self
+    {
+        self.keep_alive = enabled;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets how long a persistent connection may stay idle, waiting for the
+    /// next request, before it is closed.
+    ///
+    /// This is 5 seconds by default.
+    //--------------------------------------------------------------------------
+    pub fn keep_alive_timeout(&mut self, timeout: Duration) -> &mut Self
+    {
+        self.keep_alive_timeout = timeout;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets how many requests a persistent connection serves before it is
+    /// closed, or `None` for no limit.
+    ///
+    /// This is 1000 by default.
+    //--------------------------------------------------------------------------
+    pub fn max_keep_alive_requests(&mut self, max: Option<usize>) -> &mut Self
+    {
+        self.max_keep_alive_requests = max;
         self
     }
 
@@ -40,6 +264,35 @@ impl EagleServerBuilder
     //--------------------------------------------------------------------------
     pub fn build(&self) -> EagleServer
     {
-        EagleServer::new(self.address.clone())
+        EagleServer::new(ServerConfig
+        {
+            addresses: self.addresses.clone(),
+            unix_socket: UnixSocketOptions
+            {
+                mode: self.unix_socket_mode,
+                remove_stale: self.remove_stale_socket,
+            },
+            reuse_port: self.reuse_port,
+            socket_activation: self.socket_activation,
+            systemd_notify: self.systemd_notify,
+            graceful_shutdown: self.graceful_shutdown,
+            debug_endpoint: self.debug_endpoint,
+            task_dump_signal: self.task_dump_signal,
+            upgrade_signal: self.upgrade_signal,
+            http: HttpOptions
+            {
+                keep_alive: self.keep_alive,
+                keep_alive_timeout: self.keep_alive_timeout,
+                max_requests: self.max_keep_alive_requests,
+            },
+        })
+    }
+}
+
+impl Default for EagleServerBuilder
+{
+    fn default() -> Self
+    {
+        Self::new()
     }
 }
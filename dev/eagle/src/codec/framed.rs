@@ -0,0 +1,333 @@
+//------------------------------------------------------------------------------
+//! # Framed
+//------------------------------------------------------------------------------
+
+use super::{ Decoder, Encoder };
+use crate::io::{ AsyncRead, AsyncWrite };
+use crate::sink::Sink;
+use crate::stream::Stream;
+
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+/// Number of bytes read from the stream at a time.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Size of the write buffer above which sending waits for it to be flushed.
+const BACKPRESSURE_BOUNDARY: usize = 128 * 1024;
+
+
+//------------------------------------------------------------------------------
+/// # Framed
+///
+/// Stream of the frames decoded from an async stream, and sink of the frames
+/// encoded into it. The encoded frames are buffered until the sink is
+/// flushed, or until the buffer is large enough that sending waits for it.
+///
+/// A decoding error is yielded as an item, and the stream goes on with the
+/// next frame, if the codec can recover.
+//------------------------------------------------------------------------------
+pub struct Framed<T, C>
+{
+    inner: T,
+    codec: C,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<T, C> Framed<T, C>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new Framed.
+    //--------------------------------------------------------------------------
+    pub fn new( inner: T, codec: C ) -> Self
+    {
+        Self
+        {
+            inner,
+            codec,
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+            eof: false,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a reference to the underlying stream.
+    //--------------------------------------------------------------------------
+    pub fn get_ref( &self ) -> &T
+    {
+        &self.inner
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a mutable reference to the underlying stream. Reading from or
+    /// writing to it directly bypasses the buffers.
+    //--------------------------------------------------------------------------
+    pub fn get_mut( &mut self ) -> &mut T
+    {
+        &mut self.inner
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a reference to the codec.
+    //--------------------------------------------------------------------------
+    pub fn codec( &self ) -> &C
+    {
+        &self.codec
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a mutable reference to the codec.
+    //--------------------------------------------------------------------------
+    pub fn codec_mut( &mut self ) -> &mut C
+    {
+        &mut self.codec
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the bytes read but not decoded yet.
+    //--------------------------------------------------------------------------
+    pub fn read_buffer( &self ) -> &[u8]
+    {
+        &self.read_buf
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the underlying stream. The buffered data is lost.
+    //--------------------------------------------------------------------------
+    pub fn into_inner( self ) -> T
+    {
+        self.inner
+    }
+}
+
+impl<T: AsyncRead + Unpin, C: Decoder + Unpin> Stream for Framed<T, C>
+{
+    type Item = Result<C::Item, C::Error>;
+
+    fn poll_next
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Self::Item>>
+    {
+        let this = self.get_mut();
+        loop
+        {
+            if this.eof
+            {
+                return match this.codec.decode_eof(&mut this.read_buf)
+                {
+                    Ok(Some(frame)) => Poll::Ready(Some(Ok(frame))),
+                    Ok(None) => Poll::Ready(None),
+                    Err(error) =>
+                    {
+                        this.read_buf.clear();
+                        Poll::Ready(Some(Err(error)))
+                    },
+                };
+            }
+            if !this.read_buf.is_empty()
+            {
+                match this.codec.decode(&mut this.read_buf)
+                {
+                    Ok(Some(frame)) => return Poll::Ready(Some(Ok(frame))),
+                    Ok(None) => {},
+                    Err(error) => return Poll::Ready(Some(Err(error))),
+                }
+            }
+
+            // The buffer is grown with zeroes, and truncated back to the bytes
+            // actually read.
+            let len = this.read_buf.len();
+            this.read_buf.resize(len + READ_CHUNK, 0);
+            let polled = Pin::new(&mut this.inner).poll_read(cx, &mut this.read_buf[len..]);
+            let read = match &polled
+            {
+                Poll::Ready(Ok(read)) => *read,
+                _ => 0,
+            };
+            this.read_buf.truncate(len + read);
+
+            match polled
+            {
+                Poll::Ready(Ok(0)) => this.eof = true,
+                Poll::Ready(Ok(_)) => {},
+                Poll::Ready(Err(error)) => return Poll::Ready(Some(Err(error.into()))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<T, C, Item> Sink<Item> for Framed<T, C>
+    where
+        T: AsyncWrite + Unpin,
+        C: Encoder<Item> + Unpin,
+{
+    type Error = C::Error;
+
+    fn poll_ready
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Result<(), Self::Error>>
+    {
+        match self.write_buf.len() >= BACKPRESSURE_BOUNDARY
+        {
+            true => self.poll_flush(cx),
+            false => Poll::Ready(Ok(())),
+        }
+    }
+
+    fn start_send( self: Pin<&mut Self>, item: Item ) -> Result<(), Self::Error>
+    {
+        let this = self.get_mut();
+        this.codec.encode(item, &mut this.write_buf)
+    }
+
+    fn poll_flush
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Result<(), Self::Error>>
+    {
+        let this = self.get_mut();
+        let mut written = 0;
+        while written < this.write_buf.len()
+        {
+            let buf = &this.write_buf[written..];
+            match Pin::new(&mut this.inner).poll_write(cx, buf)
+            {
+                Poll::Ready(Ok(0)) =>
+                {
+                    this.write_buf.drain(..written);
+                    let error = io::Error::from(io::ErrorKind::WriteZero);
+                    return Poll::Ready(Err(error.into()));
+                },
+                Poll::Ready(Ok(count)) => written += count,
+                Poll::Ready(Err(error)) =>
+                {
+                    this.write_buf.drain(..written);
+                    return Poll::Ready(Err(error.into()));
+                },
+                Poll::Pending =>
+                {
+                    this.write_buf.drain(..written);
+                    return Poll::Pending;
+                },
+            }
+        }
+        this.write_buf.clear();
+        Pin::new(&mut this.inner).poll_flush(cx).map_err(Into::into)
+    }
+
+    fn poll_close
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Result<(), Self::Error>>
+    {
+        match self.as_mut().poll_flush(cx)
+        {
+            Poll::Ready(Ok(())) =>
+            {
+                Pin::new(&mut self.inner).poll_shutdown(cx).map_err(Into::into)
+            },
+            polled => polled,
+        }
+    }
+}
+
+impl<T: fmt::Debug, C: fmt::Debug> fmt::Debug for Framed<T, C>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Framed")
+            .field("inner", &self.inner)
+            .field("codec", &self.codec)
+            .field("read_buffered", &self.read_buf.len())
+            .field("write_buffered", &self.write_buf.len())
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::codec::{ CodecError, LengthDelimitedCodec, LinesCodec };
+    use crate::executor::Executor;
+    use crate::sink::SinkExt;
+    use crate::stream::StreamExt;
+    use crate::test_util::MockStream;
+
+    #[test]
+    fn framed_decodes_the_stream_and_recovers_from_errors()
+    {
+        let stream = MockStream::new([&b"one\r\ntoo l"[..], b"ong\ntw", b"o\nthree"]);
+        let mut codec = LinesCodec::new();
+        codec.max_length(5);
+        let mut framed = Framed::new(stream, codec);
+        let lines = Executor::new(0).block_on(async move
+        {
+            let mut lines = Vec::new();
+            while let Some(line) = framed.next().await
+            {
+                lines.push(line.map_err(|error| error.to_string()));
+            }
+            lines
+        }).unwrap();
+        assert_eq!(lines,
+        [
+            Ok("one".to_string()),
+            Err("frame exceeds the maximum length of 5 bytes".to_string()),
+            Ok("two".to_string()),
+            Ok("three".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn framed_reports_a_frame_cut_by_the_end_of_the_stream()
+    {
+        let stream = MockStream::new([&b"\0\0\0\x02hi\0\0\0\x05hel"[..]]);
+        let mut framed = Framed::new(stream, LengthDelimitedCodec::new());
+        let frames = Executor::new(0).block_on(async move
+        {
+            let first = framed.next().await.unwrap().unwrap();
+            let second = framed.next().await.unwrap();
+            (first, second, framed.next().await.is_none())
+        }).unwrap();
+        let (first, second, ended) = frames;
+        assert_eq!(first, b"hi");
+        match second
+        {
+            Err(CodecError::Io(error)) => assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof),
+            second => panic!("unexpected {:?}", second),
+        }
+        assert!(ended);
+    }
+
+    #[test]
+    fn framed_buffers_sent_frames_until_flushed()
+    {
+        let stream = MockStream::new([]);
+        let written = stream.written();
+        let mut framed = Framed::new(stream, LinesCodec::new());
+        Executor::new(0).block_on(async move
+        {
+            framed.feed("first".to_string()).await.unwrap();
+            framed.feed("second".to_string()).await.unwrap();
+            assert!(written.lock().unwrap().is_empty());
+
+            framed.send("third".to_string()).await.unwrap();
+            assert_eq!(*written.lock().unwrap(), b"first\nsecond\nthird\n");
+            framed.close().await.unwrap();
+        }).unwrap();
+    }
+}
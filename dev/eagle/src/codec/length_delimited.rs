@@ -0,0 +1,272 @@
+//------------------------------------------------------------------------------
+//! # LengthDelimitedCodec
+//------------------------------------------------------------------------------
+
+use super::{ CodecError, Decoder, Encoder, DEFAULT_MAX_FRAME_LENGTH };
+
+
+//------------------------------------------------------------------------------
+/// # LengthDelimitedCodec
+///
+/// Frames are prefixed with their length, as a big-endian unsigned integer of
+/// four bytes by default. A frame longer than the maximum length is an error;
+/// its bytes are skipped, and decoding resumes after it.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct LengthDelimitedCodec
+{
+    max_frame_length: usize,
+    length_field_length: usize,
+    skipping: u64,
+}
+
+impl LengthDelimitedCodec
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new LengthDelimitedCodec with a four byte length field and
+    /// the default maximum frame length of 8 MiB.
+    //--------------------------------------------------------------------------
+    pub fn new() -> Self
+    {
+        Self
+        {
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            length_field_length: 4,
+            skipping: 0,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the maximum length of a frame, length field excluded.
+    //--------------------------------------------------------------------------
+    pub fn max_frame_length( &mut self, max_frame_length: usize ) -> &mut Self
+    {
+        self.max_frame_length = max_frame_length;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the number of bytes of the length field.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the length is not between one and eight.
+    //--------------------------------------------------------------------------
+    pub fn length_field_length( &mut self, length: usize ) -> &mut Self
+    {
+        if !(1..=8).contains(&length)
+        {
+            panic!("length field length must be between 1 and 8, got {}", length);
+        }
+        self.length_field_length = length;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the largest frame the codec accepts, which the length field
+    /// may limit below the maximum frame length.
+    //--------------------------------------------------------------------------
+    fn max_length( &self ) -> usize
+    {
+        let field_max = match self.length_field_length
+        {
+            8 => u64::MAX,
+            length => (1u64 << (length * 8)) - 1,
+        };
+        self.max_frame_length.min(usize::try_from(field_max).unwrap_or(usize::MAX))
+    }
+}
+
+impl Default for LengthDelimitedCodec
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl Decoder for LengthDelimitedCodec
+{
+    type Item = Vec<u8>;
+    type Error = CodecError;
+
+    fn decode( &mut self, src: &mut Vec<u8> ) -> Result<Option<Vec<u8>>, CodecError>
+    {
+        if self.skipping > 0
+        {
+            let skipped = usize::try_from(self.skipping).unwrap_or(usize::MAX).min(src.len());
+            src.drain(..skipped);
+            self.skipping -= skipped as u64;
+            if self.skipping > 0
+            {
+                return Ok(None);
+            }
+        }
+
+        let header = self.length_field_length;
+        if src.len() < header
+        {
+            return Ok(None);
+        }
+
+        let length = src[..header]
+            .iter()
+            .fold(0u64, |length, byte| (length << 8) | u64::from(*byte));
+        let length = match usize::try_from(length)
+        {
+            Ok(length) if length <= self.max_frame_length => length,
+            _ =>
+            {
+                src.drain(..header);
+                self.skipping = length;
+                return Err(CodecError::MaxLengthExceeded(self.max_frame_length));
+            },
+        };
+
+        if src.len() < header + length
+        {
+            src.reserve(header + length - src.len());
+            return Ok(None);
+        }
+        let frame = src[header..header + length].to_vec();
+        src.drain(..header + length);
+        Ok(Some(frame))
+    }
+}
+
+impl Encoder<Vec<u8>> for LengthDelimitedCodec
+{
+    type Error = CodecError;
+
+    fn encode( &mut self, frame: Vec<u8>, dst: &mut Vec<u8> ) -> Result<(), CodecError>
+    {
+        let max_length = self.max_length();
+        if frame.len() > max_length
+        {
+            return Err(CodecError::MaxLengthExceeded(max_length));
+        }
+
+        let length = (frame.len() as u64).to_be_bytes();
+        dst.reserve(self.length_field_length + frame.len());
+        dst.extend_from_slice(&length[8 - self.length_field_length..]);
+        dst.extend_from_slice(&frame);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    //--------------------------------------------------------------------------
+    /// Decodes the pieces fed one after the other, then the end of the
+    /// stream. Errors are kept as their message.
+    //--------------------------------------------------------------------------
+    fn decode_pieces
+    (
+        codec: &mut LengthDelimitedCodec,
+        pieces: &[&[u8]],
+    ) -> Vec<Result<Vec<u8>, String>>
+    {
+        let mut src = Vec::new();
+        let mut frames = Vec::new();
+        for piece in pieces
+        {
+            src.extend_from_slice(piece);
+            loop
+            {
+                match codec.decode(&mut src)
+                {
+                    Ok(Some(frame)) => frames.push(Ok(frame)),
+                    Ok(None) => break,
+                    Err(error) => frames.push(Err(error.to_string())),
+                }
+            }
+        }
+        if let Err(error) = codec.decode_eof(&mut src)
+        {
+            frames.push(Err(error.to_string()));
+        }
+        frames
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the frames as decoded frames.
+    //--------------------------------------------------------------------------
+    fn frames( frames: &[&[u8]] ) -> Vec<Result<Vec<u8>, String>>
+    {
+        frames.iter().map(|frame| Ok(frame.to_vec())).collect()
+    }
+
+    #[test]
+    fn frames_split_anywhere_decode_the_same()
+    {
+        let input = b"\0\0\0\x05hello\0\0\0\0\0\0\0\x01!";
+        let expected = frames(&[b"hello", b"", b"!"]);
+        for at in 0..=input.len()
+        {
+            let (head, tail) = input.split_at(at);
+            let decoded = decode_pieces(&mut LengthDelimitedCodec::new(), &[head, tail]);
+            assert_eq!(decoded, expected, "split at {}", at);
+        }
+        let bytes: Vec<&[u8]> = input.chunks(1).collect();
+        assert_eq!(decode_pieces(&mut LengthDelimitedCodec::new(), &bytes), expected);
+
+        // A frame cut by the end of the stream is an error.
+        let decoded = decode_pieces(&mut LengthDelimitedCodec::new(), &[b"\0\0\0\x05hel"]);
+        assert_eq!(decoded, [Err("io error: bytes remaining on stream".to_string())]);
+    }
+
+    #[test]
+    fn frames_over_the_max_length_are_skipped()
+    {
+        let input = b"\0\0\0\x05hello\0\0\0\x04four\xff\xff\xff\xff";
+        let too_long = "frame exceeds the maximum length of 4 bytes";
+        for at in 0..=input.len()
+        {
+            let (head, tail) = input.split_at(at);
+            let mut codec = LengthDelimitedCodec::new();
+            codec.max_frame_length(4);
+            let decoded = decode_pieces(&mut codec, &[head, tail]);
+            let expected =
+            [
+                Err(too_long.to_string()),
+                Ok(b"four".to_vec()),
+                Err(too_long.to_string()),
+            ];
+            assert_eq!(decoded, expected, "split at {}", at);
+        }
+
+        let mut codec = LengthDelimitedCodec::new();
+        codec.max_frame_length(4);
+        assert!(matches!
+        (
+            codec.encode(b"hello".to_vec(), &mut Vec::new()),
+            Err(CodecError::MaxLengthExceeded(4))
+        ));
+    }
+
+    #[test]
+    fn encoded_frames_decode_back()
+    {
+        let mut codec = LengthDelimitedCodec::new();
+        codec.length_field_length(2);
+        let mut buf = Vec::new();
+        for frame in [&b"hello"[..], b"", &[7; 300]]
+        {
+            codec.encode(frame.to_vec(), &mut buf).unwrap();
+        }
+        assert_eq!(&buf[..9], b"\0\x05hello\0\0");
+        assert_eq!(decode_pieces(&mut codec, &[&buf]), frames(&[b"hello", b"", &[7; 300]]));
+
+        // The length field limits the frames below the maximum frame length.
+        codec.length_field_length(1);
+        assert!(matches!
+        (
+            codec.encode(vec![0; 256], &mut buf),
+            Err(CodecError::MaxLengthExceeded(255))
+        ));
+    }
+}
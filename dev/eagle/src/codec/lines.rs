@@ -0,0 +1,274 @@
+//------------------------------------------------------------------------------
+//! # LinesCodec
+//------------------------------------------------------------------------------
+
+use super::{ CodecError, Decoder, Encoder, DEFAULT_MAX_FRAME_LENGTH };
+
+
+//------------------------------------------------------------------------------
+/// # LinesCodec
+///
+/// Frames are lines of text ending with `\n` or `\r\n`, decoded without their
+/// line ending. A line longer than the maximum length is an error; its bytes
+/// are discarded up to the next line ending, and decoding resumes after it.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone)]
+pub struct LinesCodec
+{
+    max_length: usize,
+    searched: usize,
+    discarding: bool,
+}
+
+impl LinesCodec
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new LinesCodec with the default maximum length of 8 MiB.
+    //--------------------------------------------------------------------------
+    pub fn new() -> Self
+    {
+        Self
+        {
+            max_length: DEFAULT_MAX_FRAME_LENGTH,
+            searched: 0,
+            discarding: false,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the maximum length of a line, line ending excluded.
+    //--------------------------------------------------------------------------
+    pub fn max_length( &mut self, max_length: usize ) -> &mut Self
+    {
+        self.max_length = max_length;
+        self
+    }
+}
+
+impl Default for LinesCodec
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl Decoder for LinesCodec
+{
+    type Item = String;
+    type Error = CodecError;
+
+    fn decode( &mut self, src: &mut Vec<u8> ) -> Result<Option<String>, CodecError>
+    {
+        loop
+        {
+            // The bytes already searched are not searched again.
+            let newline = src[self.searched..]
+                .iter()
+                .position(|byte| *byte == b'\n')
+                .map(|index| self.searched + index);
+
+            match (self.discarding, newline)
+            {
+                (true, Some(newline)) =>
+                {
+                    src.drain(..=newline);
+                    self.searched = 0;
+                    self.discarding = false;
+                },
+                (true, None) =>
+                {
+                    src.clear();
+                    self.searched = 0;
+                    return Ok(None);
+                },
+                (false, Some(newline)) =>
+                {
+                    self.searched = 0;
+                    let mut line: Vec<u8> = src.drain(..=newline).collect();
+                    line.pop();
+                    if line.last() == Some(&b'\r')
+                    {
+                        line.pop();
+                    }
+                    if line.len() > self.max_length
+                    {
+                        return Err(CodecError::MaxLengthExceeded(self.max_length));
+                    }
+                    return String::from_utf8(line)
+                        .map(Some)
+                        .map_err(|_| CodecError::InvalidUtf8);
+                },
+                (false, None) if src.len() > self.max_length + 1 =>
+                {
+                    // One more byte is allowed for the `\r` of the line ending.
+                    self.discarding = true;
+                    return Err(CodecError::MaxLengthExceeded(self.max_length));
+                },
+                (false, None) =>
+                {
+                    self.searched = src.len();
+                    return Ok(None);
+                },
+            }
+        }
+    }
+
+    fn decode_eof( &mut self, src: &mut Vec<u8> ) -> Result<Option<String>, CodecError>
+    {
+        if let Some(line) = self.decode(src)?
+        {
+            return Ok(Some(line));
+        }
+
+        // The last line may lack its line ending.
+        self.searched = 0;
+        if self.discarding || src.is_empty()
+        {
+            src.clear();
+            self.discarding = false;
+            return Ok(None);
+        }
+        let mut line = std::mem::take(src);
+        if line.last() == Some(&b'\r')
+        {
+            line.pop();
+        }
+        if line.len() > self.max_length
+        {
+            return Err(CodecError::MaxLengthExceeded(self.max_length));
+        }
+        String::from_utf8(line).map(Some).map_err(|_| CodecError::InvalidUtf8)
+    }
+}
+
+impl Encoder<String> for LinesCodec
+{
+    type Error = CodecError;
+
+    fn encode( &mut self, line: String, dst: &mut Vec<u8> ) -> Result<(), CodecError>
+    {
+        if line.len() > self.max_length
+        {
+            return Err(CodecError::MaxLengthExceeded(self.max_length));
+        }
+        dst.reserve(line.len() + 1);
+        dst.extend_from_slice(line.as_bytes());
+        dst.push(b'\n');
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    //--------------------------------------------------------------------------
+    /// Decodes the pieces fed one after the other, then the end of the
+    /// stream. Errors are kept as their message.
+    //--------------------------------------------------------------------------
+    fn decode_pieces( codec: &mut LinesCodec, pieces: &[&[u8]] ) -> Vec<Result<String, String>>
+    {
+        let mut src = Vec::new();
+        let mut lines = Vec::new();
+        for piece in pieces
+        {
+            src.extend_from_slice(piece);
+            loop
+            {
+                match codec.decode(&mut src)
+                {
+                    Ok(Some(line)) => lines.push(Ok(line)),
+                    Ok(None) => break,
+                    Err(error) => lines.push(Err(error.to_string())),
+                }
+            }
+        }
+        loop
+        {
+            match codec.decode_eof(&mut src)
+            {
+                Ok(Some(line)) => lines.push(Ok(line)),
+                Ok(None) => break,
+                Err(error) => lines.push(Err(error.to_string())),
+            }
+        }
+        lines
+    }
+
+    //--------------------------------------------------------------------------
+    /// Asserts that the input decodes to the lines however it is split in
+    /// two, and when fed a byte at a time.
+    //--------------------------------------------------------------------------
+    fn assert_decodes( max_length: usize, input: &[u8], expected: &[Result<&str, &str>] )
+    {
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|line| line.map(str::to_string).map_err(str::to_string))
+            .collect();
+        for at in 0..=input.len()
+        {
+            let (head, tail) = input.split_at(at);
+            let mut codec = LinesCodec::new();
+            codec.max_length(max_length);
+            assert_eq!(decode_pieces(&mut codec, &[head, tail]), expected, "split at {}", at);
+        }
+        let bytes: Vec<&[u8]> = input.chunks(1).collect();
+        let mut codec = LinesCodec::new();
+        codec.max_length(max_length);
+        assert_eq!(decode_pieces(&mut codec, &bytes), expected, "byte at a time");
+    }
+
+    #[test]
+    fn lines_split_anywhere_decode_the_same()
+    {
+        assert_decodes
+        (
+            16,
+            b"hello\r\nworld\n\n\r\nlast",
+            &[Ok("hello"), Ok("world"), Ok(""), Ok(""), Ok("last")],
+        );
+        assert_decodes(16, b"trailing\r", &[Ok("trailing")]);
+        assert_decodes(16, b"", &[]);
+    }
+
+    #[test]
+    fn lines_over_the_max_length_are_skipped()
+    {
+        let too_long = "frame exceeds the maximum length of 5 bytes";
+        assert_decodes
+        (
+            5,
+            b"hello\r\nlonger line\nok\n",
+            &[Ok("hello"), Err(too_long), Ok("ok")],
+        );
+        assert_decodes(5, b"ok\nlonger line", &[Ok("ok"), Err(too_long)]);
+        assert_decodes(5, b"\xff\nok\n", &[Err("frame is not valid UTF-8"), Ok("ok")]);
+    }
+
+    #[test]
+    fn encoded_lines_decode_back()
+    {
+        let lines = ["", "one", "two words", "caf\u{e9}"];
+        let mut codec = LinesCodec::new();
+        let mut buf = Vec::new();
+        for line in lines
+        {
+            codec.encode(line.to_string(), &mut buf).unwrap();
+        }
+        assert_eq!(buf, b"\none\ntwo words\ncaf\xc3\xa9\n");
+
+        let decoded = decode_pieces(&mut codec, &[&buf]);
+        let expected: Vec<_> = lines.iter().map(|line| Ok(line.to_string())).collect();
+        assert_eq!(decoded, expected);
+
+        codec.max_length(3);
+        assert!(matches!
+        (
+            codec.encode("four".to_string(), &mut buf),
+            Err(CodecError::MaxLengthExceeded(3))
+        ));
+    }
+}
@@ -0,0 +1,140 @@
+//------------------------------------------------------------------------------
+//! # Codecs
+//!
+//! A `Decoder` cuts the bytes read from a stream into frames, and an
+//! `Encoder` turns frames back into bytes. `Framed` pairs a codec with an
+//! async stream, and exposes it as a `Stream` of decoded frames and a `Sink`
+//! of frames to encode.
+//!
+//! The built-in codecs refuse frames longer than their maximum length, so a
+//! peer cannot make the read buffer grow without bound.
+//!
+//! ```no_run
+//! use eagle::codec::{ Framed, LinesCodec };
+//! use eagle::net::TcpStream;
+//! use eagle::sink::SinkExt;
+//! use eagle::stream::StreamExt;
+//!
+//! # async fn echo( stream: TcpStream ) -> Result<(), eagle::codec::CodecError> {
+//! let mut lines = Framed::new(stream, LinesCodec::new());
+//! while let Some(line) = lines.next().await
+//! {
+//!     lines.send(line?).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//------------------------------------------------------------------------------
+
+mod framed;
+mod length_delimited;
+mod lines;
+
+pub use framed::Framed;
+pub use length_delimited::LengthDelimitedCodec;
+pub use lines::LinesCodec;
+
+use std::fmt;
+use std::io;
+
+/// Default maximum length of a frame of the built-in codecs.
+const DEFAULT_MAX_FRAME_LENGTH: usize = 8 * 1024 * 1024;
+
+
+//------------------------------------------------------------------------------
+/// # Decoder
+///
+/// Decodes frames from a buffer of bytes read from a stream.
+//------------------------------------------------------------------------------
+pub trait Decoder
+{
+    type Item;
+    type Error: From<io::Error>;
+
+    //--------------------------------------------------------------------------
+    /// Decodes the next frame, removing its bytes from the buffer. Returns
+    /// `None` if the buffer does not hold a whole frame yet.
+    //--------------------------------------------------------------------------
+    fn decode( &mut self, src: &mut Vec<u8> ) -> Result<Option<Self::Item>, Self::Error>;
+
+    //--------------------------------------------------------------------------
+    /// Decodes the next frame once the stream has ended. By default, bytes
+    /// left over that do not make a whole frame are an `UnexpectedEof` error.
+    //--------------------------------------------------------------------------
+    fn decode_eof( &mut self, src: &mut Vec<u8> ) -> Result<Option<Self::Item>, Self::Error>
+    {
+        match self.decode(src)?
+        {
+            Some(frame) => Ok(Some(frame)),
+            None if src.is_empty() => Ok(None),
+            None =>
+            {
+                let error = io::Error::new
+                (
+                    io::ErrorKind::UnexpectedEof,
+                    "bytes remaining on stream",
+                );
+                Err(error.into())
+            },
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Encoder
+///
+/// Encodes frames into a buffer of bytes to write to a stream.
+//------------------------------------------------------------------------------
+pub trait Encoder<Item>
+{
+    type Error: From<io::Error>;
+
+    //--------------------------------------------------------------------------
+    /// Appends the encoded frame to the buffer.
+    //--------------------------------------------------------------------------
+    fn encode( &mut self, item: Item, dst: &mut Vec<u8> ) -> Result<(), Self::Error>;
+}
+
+
+//------------------------------------------------------------------------------
+/// # CodecError
+///
+/// - MaxLengthExceeded: A frame is longer than the maximum length of the
+///   codec. Holds the maximum length.
+/// - InvalidUtf8: A text frame is not valid UTF-8.
+/// - Io: The underlying stream failed.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub enum CodecError
+{
+    MaxLengthExceeded(usize),
+    InvalidUtf8,
+    Io(io::Error),
+}
+
+impl fmt::Display for CodecError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::MaxLengthExceeded(max) =>
+            {
+                write!(f, "frame exceeds the maximum length of {} bytes", max)
+            },
+            Self::InvalidUtf8 => write!(f, "frame is not valid UTF-8"),
+            Self::Io(error) => write!(f, "io error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+impl From<io::Error> for CodecError
+{
+    fn from( error: io::Error ) -> Self
+    {
+        Self::Io(error)
+    }
+}
@@ -0,0 +1,239 @@
+//------------------------------------------------------------------------------
+//! # Cooperative budget
+//!
+//! A task polled by a worker gets a budget of operations. Every runtime
+//! operation (I/O, channels, synchronization primitives) consumes one unit,
+//! and once the budget is exhausted they return `Poll::Pending` after waking
+//! the task, so that a task whose operations are always ready still yields
+//! its worker to the other tasks.
+//!
+//! Outside of a worker, the budget is unlimited.
+//------------------------------------------------------------------------------
+
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+/// Number of operations a task may perform in one poll.
+const DEFAULT_BUDGET: u32 = 128;
+
+thread_local!
+{
+    static BUDGET: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+
+//------------------------------------------------------------------------------
+/// # BudgetGuard
+///
+/// Restores the previous budget when dropped.
+//------------------------------------------------------------------------------
+pub(super) struct BudgetGuard
+{
+    previous: Option<u32>,
+}
+
+impl Drop for BudgetGuard
+{
+    fn drop( &mut self )
+    {
+        BUDGET.with(|budget| budget.set(self.previous));
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # enter
+///
+/// Gives a fresh budget to the task about to be polled on the current thread.
+//------------------------------------------------------------------------------
+pub(super) fn enter() -> BudgetGuard
+{
+    let previous = BUDGET.with(|budget| budget.replace(Some(DEFAULT_BUDGET)));
+    BudgetGuard { previous }
+}
+
+//------------------------------------------------------------------------------
+/// # unlimited
+///
+/// Lifts the budget on the current thread.
+//------------------------------------------------------------------------------
+pub(super) fn unlimited() -> BudgetGuard
+{
+    let previous = BUDGET.with(|budget| budget.replace(None));
+    BudgetGuard { previous }
+}
+
+//------------------------------------------------------------------------------
+/// # poll_proceed
+///
+/// Consumes one unit of the budget of the current task. When the budget is
+/// exhausted, wakes the task and returns `Poll::Pending`, which the operation
+/// must return as is.
+//------------------------------------------------------------------------------
+pub(crate) fn poll_proceed( cx: &mut Context ) -> Poll<()>
+{
+    BUDGET.with(|budget|
+    {
+        match budget.get()
+        {
+            None => Poll::Ready(()),
+            Some(0) =>
+            {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            },
+            Some(remaining) =>
+            {
+                budget.set(Some(remaining - 1));
+                Poll::Ready(())
+            },
+        }
+    })
+}
+
+
+//------------------------------------------------------------------------------
+/// # yield_now
+///
+/// Yields the worker to the other tasks. The current task is queued again and
+/// resumes once it is polled.
+//------------------------------------------------------------------------------
+pub async fn yield_now()
+{
+    YieldNow { yielded: false }.await
+}
+
+
+//------------------------------------------------------------------------------
+/// # YieldNow
+///
+/// Future returned by `yield_now`.
+//------------------------------------------------------------------------------
+struct YieldNow
+{
+    yielded: bool,
+}
+
+impl Future for YieldNow
+{
+    type Output = ();
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<()>
+    {
+        if self.yielded
+        {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::{ spawn, Executor };
+    use crate::sync::mpsc;
+    use crate::test_util::WakeCounter;
+    use std::pin::pin;
+    use std::sync::atomic::{ AtomicBool, Ordering };
+    use std::sync::{ Arc, Mutex };
+
+    #[test]
+    fn always_ready_operations_yield_the_worker()
+    {
+        let mut executor = Executor::new(1);
+        executor.start();
+        let looped = executor.block_on(async
+        {
+            let stop = Arc::new(AtomicBool::new(false));
+            let (tx, mut rx) = mpsc::unbounded_channel();
+
+            // Without the budget, the receive is always ready, and the loop
+            // would keep the only worker until it gives up.
+            let busy = spawn(
+            {
+                let stop = stop.clone();
+                async move
+                {
+                    let mut looped = 0;
+                    while !stop.load(Ordering::SeqCst) && looped < 1_000_000
+                    {
+                        tx.send(looped).unwrap();
+                        rx.recv().await.unwrap();
+                        looped += 1;
+                    }
+                    looped
+                }
+            });
+            spawn(async move { stop.store(true, Ordering::SeqCst) }).await.unwrap();
+            busy.await.unwrap()
+        }).unwrap();
+        assert!(looped < 1_000_000, "{}", looped);
+    }
+
+    #[test]
+    fn yield_now_is_pending_once()
+    {
+        let wakes = WakeCounter::new();
+        let mut yielded = pin!(yield_now());
+        assert!(wakes.poll(yielded.as_mut()).is_pending());
+        assert_eq!(wakes.wakes(), 1);
+        assert!(wakes.poll(yielded.as_mut()).is_ready());
+        assert_eq!(wakes.wakes(), 1);
+    }
+
+    #[test]
+    fn yield_now_lets_the_other_tasks_run()
+    {
+        let steps = Arc::new(Mutex::new(Vec::new()));
+        let order = steps.clone();
+        let mut executor = Executor::new(1);
+        executor.start();
+        executor.block_on(async move
+        {
+            let tasks: Vec<_> = ["a", "b"].into_iter().map(|name|
+            {
+                let steps = steps.clone();
+                spawn(async move
+                {
+                    for step in 0..3
+                    {
+                        steps.lock().unwrap().push(format!("{}{}", name, step));
+                        yield_now().await;
+                    }
+                })
+            }).collect();
+            for task in tasks
+            {
+                task.await.unwrap();
+            }
+        }).unwrap();
+        let order = order.lock().unwrap().join(" ");
+        assert_eq!(order, "a0 b0 a1 b1 a2 b2");
+    }
+
+    #[test]
+    fn budget_is_unlimited_outside_of_a_worker()
+    {
+        let wakes = WakeCounter::new();
+        let proceed = || wakes.poll(pin!(std::future::poll_fn(poll_proceed)));
+        for _ in 0..DEFAULT_BUDGET * 2
+        {
+            assert!(proceed().is_ready());
+        }
+
+        let _budget = enter();
+        for _ in 0..DEFAULT_BUDGET
+        {
+            assert!(proceed().is_ready());
+        }
+        assert!(proceed().is_pending());
+        assert_eq!(wakes.wakes(), 1);
+    }
+}
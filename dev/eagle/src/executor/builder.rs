@@ -0,0 +1,177 @@
+//------------------------------------------------------------------------------
+//! # Task builder
+//!
+//! All the spawn functions go through a `TaskBuilder`, which sets the name,
+//! priority, deadline and spawn location of the task. The name appears in
+//! the task dump and in the panic messages of the task.
+//!
+//! ```no_run
+//! use eagle::executor::TaskBuilder;
+//!
+//! # async fn example() {
+//! let handle = TaskBuilder::new()
+//!     .name("accept")
+//!     .priority(3)
+//!     .spawn(async { 42 });
+//! assert_eq!(handle.await, Ok(42));
+//! # }
+//! ```
+//------------------------------------------------------------------------------
+
+use super::context;
+use super::executor::Executor;
+use super::join_handle::{ joinable, JoinHandle };
+use super::task::{ Task, TaskHeader };
+use super::task_local;
+use super::task_queue::TaskQueue;
+
+use std::future::Future;
+use std::panic::Location;
+use std::sync::Arc;
+use std::time::Instant;
+
+
+//------------------------------------------------------------------------------
+/// # TaskBuilder
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Default)]
+pub struct TaskBuilder
+{
+    name: Option<String>,
+    priority: usize,
+    deadline: Option<Instant>,
+    location: Option<&'static Location<'static>>,
+}
+
+impl TaskBuilder
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new TaskBuilder for an unnamed task with the default
+    /// priority and no deadline.
+    //--------------------------------------------------------------------------
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the name of the task.
+    //--------------------------------------------------------------------------
+    pub fn name( &mut self, name: &str ) -> &mut Self
+    {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the priority of the task. Higher priorities run first.
+    //--------------------------------------------------------------------------
+    pub fn priority( &mut self, priority: usize ) -> &mut Self
+    {
+        self.priority = priority;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the deadline of the task. Without one, the task inherits the
+    /// deadline of the task spawning it.
+    //--------------------------------------------------------------------------
+    pub fn deadline( &mut self, deadline: Instant ) -> &mut Self
+    {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the spawn location reported for the task, for functions spawning
+    /// on behalf of their caller. It defaults to the caller of the spawn
+    /// method.
+    //--------------------------------------------------------------------------
+    pub fn location( &mut self, location: &'static Location<'static> ) -> &mut Self
+    {
+        self.location = Some(location);
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Spawns the task on the executor running the current task.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of an executor.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub fn spawn<F>( &self, future: F ) -> JoinHandle<F::Output>
+        where
+            F: Future + Send + 'static,
+            F::Output: Send + 'static,
+    {
+        match context::current()
+        {
+            Some(queue) => self.spawn_joinable(&queue, future),
+            None => panic!("spawn must be called from the context of an executor"),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Spawns the task on the given executor.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub fn spawn_on<F>( &self, executor: &Executor, future: F ) -> JoinHandle<F::Output>
+        where
+            F: Future + Send + 'static,
+            F::Output: Send + 'static,
+    {
+        self.spawn_joinable(executor.queue(), future)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Spawns the task onto the queue. If the task cannot be queued, it is
+    /// dropped and the JoinHandle reports it as cancelled.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub(super) fn spawn_joinable<F>
+    (
+        &self,
+        queue: &TaskQueue,
+        future: F,
+    ) -> JoinHandle<F::Output>
+        where
+            F: Future + Send + 'static,
+            F::Output: Send + 'static,
+    {
+        let (future, handle) = joinable(future);
+        self.spawn_task(queue, future);
+        handle
+    }
+
+    //--------------------------------------------------------------------------
+    /// Schedules a new task driving the future. Without a deadline of its
+    /// own, the task inherits the one of the current task, as well as its
+    /// inherited task-local values. The task is registered for the task dump.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub(super) fn spawn_task<F>( &self, queue: &TaskQueue, future: F )
+        where F: Future<Output = ()> + Send + 'static
+    {
+        let deadline = self.deadline.or_else(context::current_deadline);
+        let location = self.location.unwrap_or(Location::caller());
+        let registry = queue.task_registry();
+        let header = Arc::new(TaskHeader::new
+        (
+            registry.next_id(),
+            self.name.clone(),
+            location,
+        ));
+        let registration = registry.register(header.clone());
+        let live = queue.task_counters().track();
+        let future = task_local::inherit(Box::pin(async move
+        {
+            let _registration = registration;
+            let _live = live;
+            future.await
+        }));
+        let task = Task::from_boxed(header, future, self.priority).with_deadline(deadline);
+        task.spawn(queue);
+    }
+}
@@ -0,0 +1,122 @@
+//------------------------------------------------------------------------------
+//! # Executor context
+//!
+//! Keeps the task queue of the executor running on the current thread, so
+//! that tasks can spawn other tasks without a reference to the executor, and
+//! the task being polled, so that spawned tasks can inherit from it.
+//------------------------------------------------------------------------------
+
+use super::task::Task;
+use super::task_queue::TaskQueue;
+
+use std::cell::RefCell;
+use std::time::Instant;
+
+thread_local!
+{
+    static CURRENT: RefCell<Option<TaskQueue>> = const { RefCell::new(None) };
+    static CURRENT_TASK: RefCell<Option<Task>> = const { RefCell::new(None) };
+}
+
+
+//------------------------------------------------------------------------------
+/// # EnterGuard
+///
+/// Restores the previous context when dropped.
+//------------------------------------------------------------------------------
+pub(super) struct EnterGuard
+{
+    previous: Option<TaskQueue>,
+}
+
+impl Drop for EnterGuard
+{
+    fn drop( &mut self )
+    {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # enter
+///
+/// Sets the queue of the executor running on the current thread.
+//------------------------------------------------------------------------------
+pub(super) fn enter( queue: TaskQueue ) -> EnterGuard
+{
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(queue));
+    EnterGuard { previous }
+}
+
+//------------------------------------------------------------------------------
+/// # current
+///
+/// Returns the queue of the executor running on the current thread.
+//------------------------------------------------------------------------------
+pub(super) fn current() -> Option<TaskQueue>
+{
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+
+//------------------------------------------------------------------------------
+/// # TaskGuard
+///
+/// Restores the previous task when dropped.
+//------------------------------------------------------------------------------
+pub(super) struct TaskGuard
+{
+    previous: Option<Task>,
+}
+
+impl Drop for TaskGuard
+{
+    fn drop( &mut self )
+    {
+        let previous = self.previous.take();
+        CURRENT_TASK.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # enter_task
+///
+/// Sets the task being polled on the current thread.
+//------------------------------------------------------------------------------
+pub(super) fn enter_task( task: &Task ) -> TaskGuard
+{
+    let previous = CURRENT_TASK.with(|current|
+    {
+        current.borrow_mut().replace(task.clone())
+    });
+    TaskGuard { previous }
+}
+
+//------------------------------------------------------------------------------
+/// # current_deadline
+///
+/// Returns the deadline of the task being polled on the current thread.
+//------------------------------------------------------------------------------
+pub(super) fn current_deadline() -> Option<Instant>
+{
+    CURRENT_TASK.with(|current|
+    {
+        current.borrow().as_ref().and_then(|task| task.deadline())
+    })
+}
+
+//------------------------------------------------------------------------------
+/// # current_task_name
+///
+/// Returns the name of the task being polled on the current thread.
+//------------------------------------------------------------------------------
+pub(super) fn current_task_name() -> Option<String>
+{
+    CURRENT_TASK.with(|current|
+    {
+        current.borrow().as_ref().and_then(|task| task.name().map(str::to_string))
+    })
+}
@@ -0,0 +1,235 @@
+//------------------------------------------------------------------------------
+//! # Task dump
+//!
+//! Every spawned task is registered with its executor until its future
+//! completes or is dropped. A `TaskDump` lists the registered tasks with
+//! where they were spawned and when they were last polled, which shows the
+//! tasks a stuck request is waiting on.
+//!
+//! ```no_run
+//! use eagle::executor::task_dump;
+//! use eagle::signal::{ signal, SignalKind };
+//! use eagle::stream::StreamExt;
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let mut dumps = signal(SignalKind::user_defined1())?;
+//! eagle::spawn(async move
+//! {
+//!     while dumps.next().await.is_some()
+//!     {
+//!         eprintln!("{}", task_dump());
+//!     }
+//! });
+//! # Ok(())
+//! # }
+//! ```
+//------------------------------------------------------------------------------
+
+use super::task::{ TaskHeader, TaskState };
+
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::Location;
+use std::sync::{ Arc, Mutex, PoisonError };
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::{ Duration, Instant };
+
+
+//------------------------------------------------------------------------------
+/// # TaskDump
+///
+/// The live tasks of an executor, by id. Its `Display` implementation prints
+/// one task per line.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskDump
+{
+    pub tasks: Vec<TaskSnapshot>,
+}
+
+impl fmt::Display for TaskDump
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        writeln!(f, "{} live tasks", self.tasks.len())?;
+        for task in &self.tasks
+        {
+            writeln!(f, "{}", task)?;
+        }
+        Ok(())
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # TaskSnapshot
+///
+/// - id: Id of the task, unique within its executor.
+/// - name: Name of the task, if it was given one.
+/// - state: State of the task when the dump was taken.
+/// - location: Where the task was spawned.
+/// - age: Time since the task was spawned.
+/// - last_poll: Time since the last poll of the task started, or `None` if
+///   it was never polled.
+/// - last_poll_duration: How long the last completed poll of the task took.
+/// - max_poll_duration: How long the longest poll of the task took. A task
+///   blocking its worker shows up here even if the histogram of
+///   `RuntimeMetrics` cannot tell which task it is.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaskSnapshot
+{
+    pub id: u64,
+    pub name: Option<String>,
+    pub state: TaskState,
+    pub location: &'static Location<'static>,
+    pub age: Duration,
+    pub last_poll: Option<Duration>,
+    pub last_poll_duration: Duration,
+    pub max_poll_duration: Duration,
+}
+
+impl fmt::Display for TaskSnapshot
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "task {}", self.id)?;
+        if let Some(name) = &self.name
+        {
+            write!(f, " \"{}\"", name)?;
+        }
+        write!(f, " {} age={:?}", self.state, self.age)?;
+        match self.last_poll
+        {
+            Some(last_poll) =>
+            {
+                write!(f, " last_poll={:?} ago", last_poll)?;
+                write!
+                (
+                    f,
+                    " poll_duration={:?} max_poll_duration={:?}",
+                    self.last_poll_duration,
+                    self.max_poll_duration,
+                )?;
+            },
+            None => write!(f, " last_poll=never")?,
+        }
+        write!(f, " spawned at {}", self.location)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # TaskRegistry
+//------------------------------------------------------------------------------
+#[derive(Default)]
+pub(super) struct TaskRegistry
+{
+    next_id: AtomicU64,
+    tasks: Mutex<HashMap<u64, Arc<TaskHeader>>>,
+}
+
+impl TaskRegistry
+{
+    //--------------------------------------------------------------------------
+    /// Returns a new task id.
+    //--------------------------------------------------------------------------
+    pub(super) fn next_id( &self ) -> u64
+    {
+        self.next_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Registers a task until the returned guard is dropped.
+    //--------------------------------------------------------------------------
+    pub(super) fn register( self: &Arc<Self>, header: Arc<TaskHeader> ) -> Registration
+    {
+        let id = header.id();
+        self.tasks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(id, header);
+        Registration { registry: self.clone(), id }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a dump of the registered tasks.
+    //--------------------------------------------------------------------------
+    pub(super) fn dump( &self ) -> TaskDump
+    {
+        let now = Instant::now();
+        let mut tasks: Vec<TaskSnapshot> = self.tasks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .map(|header| header.snapshot(now))
+            .collect();
+        tasks.sort_by_key(|task| task.id);
+        TaskDump { tasks }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Registration
+///
+/// Held by the future of a spawned task, so that the task is removed from
+/// the registry when its future completes or is dropped.
+//------------------------------------------------------------------------------
+pub(super) struct Registration
+{
+    registry: Arc<TaskRegistry>,
+    id: u64,
+}
+
+impl Drop for Registration
+{
+    fn drop( &mut self )
+    {
+        self.registry
+            .tasks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&self.id);
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use crate::executor::{ task_dump, yield_now, Executor, TaskBuilder };
+
+    use std::future::pending;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn dump_reports_the_poll_durations_of_each_task()
+    {
+        let executor = Executor::new(0);
+        let dump = executor.block_on(async
+        {
+            TaskBuilder::new().name("slow").spawn(async
+            {
+                thread::sleep(Duration::from_millis(20));
+                yield_now().await;
+                pending::<()>().await
+            });
+            for _ in 0..4
+            {
+                yield_now().await;
+            }
+            task_dump()
+        });
+
+        let dump = dump.unwrap();
+        let slow = dump.tasks
+            .iter()
+            .find(|task| task.name.as_deref() == Some("slow"))
+            .unwrap();
+        assert!(slow.max_poll_duration >= Duration::from_millis(20));
+        assert!(slow.last_poll_duration < slow.max_poll_duration);
+        assert!(slow.to_string().contains("max_poll_duration="));
+    }
+}
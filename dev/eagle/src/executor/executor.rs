@@ -2,35 +2,60 @@
 //! # Async executor
 //------------------------------------------------------------------------------
 
+use super::budget;
+use super::builder::TaskBuilder;
+use super::context;
+use super::dump::TaskDump;
+use super::join_handle::{ JoinError, JoinHandle };
+use super::metrics::{ PollHistogram, RuntimeMetrics, WorkerCounters };
 use super::reactor::Reactor;
-use super::task::Task;
-use super::task_queue::{ TaskQueue, TaskQueueError };
-use super::worker::Worker;
+use super::scope::{ GroupError, Scope };
+use super::task_queue::{
+    DeadlineStats,
+    OverflowPolicy,
+    SchedulingPolicy,
+    TaskQueue,
+    DEFAULT_QUEUE_CAPACITY,
+};
+use super::waker::waker_fn;
+use super::worker::{ poll_task, Worker, IDLE_TIMEOUT };
 
-use std::future::Future;
-use std::sync::Arc;
-use std::sync::{ Condvar, Mutex, PoisonError };
+use std::fmt;
+use std::future::{ poll_fn, Future };
+use std::panic::{ self, AssertUnwindSafe };
+use std::pin::pin;
+use std::sync::PoisonError;
+use std::task::{ Context, Poll };
+use std::thread;
+use std::time::{ Duration, Instant };
 
 
 //------------------------------------------------------------------------------
 /// # ExecutorError
 //------------------------------------------------------------------------------
 #[derive(Debug)]
-pub(crate) enum ExecutorError
+pub enum ExecutorError
 {
-    TaskQueueError(TaskQueueError),
     PoisonError(String),
+    Panicked(String),
     NoResult,
 }
 
-impl From<TaskQueueError> for ExecutorError
+impl fmt::Display for ExecutorError
 {
-    fn from( error: TaskQueueError ) -> Self
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
     {
-        Self::TaskQueueError(error)
+        match self
+        {
+            Self::PoisonError(error) => write!(f, "poison error: {}", error),
+            Self::Panicked(message) => write!(f, "task panicked: {}", message),
+            Self::NoResult => write!(f, "no result"),
+        }
     }
 }
 
+impl std::error::Error for ExecutorError {}
+
 impl<E> From<PoisonError<E>> for ExecutorError
 {
     fn from( error: PoisonError<E> ) -> Self
@@ -39,37 +64,56 @@ impl<E> From<PoisonError<E>> for ExecutorError
     }
 }
 
+impl From<JoinError> for ExecutorError
+{
+    fn from( error: JoinError ) -> Self
+    {
+        match error
+        {
+            JoinError::Panicked(message) => Self::Panicked(message),
+            JoinError::Cancelled => Self::NoResult,
+        }
+    }
+}
+
 
 //------------------------------------------------------------------------------
 /// # Executor
+///
+/// - counters: Polls run on the calling thread when there are no workers.
 //------------------------------------------------------------------------------
-pub(crate) struct Executor<T: Clone>
+pub struct Executor
 {
-    workers: Vec<Worker<T>>,
-    queue: TaskQueue<T>,
-    is_done: Arc<(Mutex<Option<T>>, Condvar)>,
-    reactor: Reactor,
+    workers: Vec<Worker>,
+    queue: TaskQueue,
+    counters: WorkerCounters,
 }
 
-impl<T: Send + Clone + 'static> Executor<T>
+impl Executor
 {
     //--------------------------------------------------------------------------
-    /// Creates a new Executor.
+    /// Creates a new Executor with the given number of worker threads. With
+    /// none, the tasks only run in `block_on`, on the calling thread.
     //--------------------------------------------------------------------------
-    pub(crate) fn new( num_threads: usize ) -> Self
+    pub fn new( num_threads: usize ) -> Self
     {
-        let queue = TaskQueue::new();
+        Self::with_queue_capacity(num_threads, DEFAULT_QUEUE_CAPACITY)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new Executor whose injection queue holds up to the given
+    /// number of tasks, rounded up to a power of two of at least two. The
+    /// tasks scheduled while it is full are handled according to the
+    /// overflow policy.
+    //--------------------------------------------------------------------------
+    pub fn with_queue_capacity( num_threads: usize, capacity: usize ) -> Self
+    {
+        let queue = TaskQueue::new(capacity);
         let mut workers = Vec::with_capacity(num_threads);
-        let is_done = Arc::new((Mutex::new(None), Condvar::new()));
 
         for id in 0..num_threads
         {
-            let worker = Worker::new
-            (
-                id,
-                (&queue).clone(),
-                is_done.clone(),
-            );
+            let worker = Worker::new(id, queue.clone());
             workers.push(worker);
         }
 
@@ -77,15 +121,106 @@ impl<T: Send + Clone + 'static> Executor<T>
         {
             workers,
             queue,
-            is_done,
-            reactor: Reactor::new(),
+            counters: WorkerCounters::default(),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets how long a task may wait at the head of its priority level before
+    /// it is promoted to the next level. `None` disables the promotion.
+    //--------------------------------------------------------------------------
+    pub fn aging_interval( &mut self, interval: Option<Duration> ) -> &mut Self
+    {
+        self.queue.set_aging_interval(interval);
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the scheduling policy.
+    //--------------------------------------------------------------------------
+    pub fn scheduling_policy( &mut self, policy: SchedulingPolicy ) -> &mut Self
+    {
+        self.queue.set_policy(policy);
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether the tasks whose deadline has passed before they run are
+    /// dropped instead of polled. Their JoinHandle reports them as cancelled.
+    //--------------------------------------------------------------------------
+    pub fn shed_expired( &mut self, shed: bool ) -> &mut Self
+    {
+        self.queue.set_shed_expired(shed);
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets what happens to the tasks scheduled while the injection queue is
+    /// full.
+    //--------------------------------------------------------------------------
+    pub fn overflow_policy( &mut self, policy: OverflowPolicy ) -> &mut Self
+    {
+        self.queue.set_overflow_policy(policy);
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns how many tasks met, missed or were shed at their deadline.
+    //--------------------------------------------------------------------------
+    pub fn deadline_stats( &self ) -> DeadlineStats
+    {
+        self.queue.deadline_stats()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a snapshot of the runtime metrics. Without worker threads, the
+    /// thread running the tasks in `block_on` and `scope` is reported as the
+    /// only worker.
+    //--------------------------------------------------------------------------
+    pub fn metrics( &self ) -> RuntimeMetrics
+    {
+        let mut poll_durations = PollHistogram::default();
+        let counters: Vec<&WorkerCounters> = match self.workers.is_empty()
+        {
+            true => vec![&self.counters],
+            false => self.workers.iter().map(Worker::counters).collect(),
+        };
+        let workers = counters
+            .into_iter()
+            .map(|counters|
+            {
+                counters.add_poll_durations(&mut poll_durations);
+                counters.snapshot()
+            })
+            .collect();
+        let reactor = Reactor::try_get();
+
+        RuntimeMetrics
+        {
+            workers,
+            queue_depth: self.queue.len(),
+            rejected_tasks: self.queue.rejected(),
+            spawned_tasks: self.queue.task_counters().spawned(),
+            live_tasks: self.queue.task_counters().live(),
+            reactor_events: reactor.map_or(0, |reactor| reactor.event_count()),
+            registered_sources: reactor.map_or(0, |reactor| reactor.source_count()),
+            deadlines: self.queue.deadline_stats(),
+            poll_durations,
         }
     }
-    
+
+    //--------------------------------------------------------------------------
+    /// Returns a dump of the live tasks.
+    //--------------------------------------------------------------------------
+    pub fn task_dump( &self ) -> TaskDump
+    {
+        self.queue.task_registry().dump()
+    }
+
     //--------------------------------------------------------------------------
     /// Runs the worker threads.
     //--------------------------------------------------------------------------
-    pub(crate) fn start( &mut self )
+    pub fn start( &mut self )
     {
         for worker in &mut self.workers
         {
@@ -94,49 +229,163 @@ impl<T: Send + Clone + 'static> Executor<T>
     }
 
     //--------------------------------------------------------------------------
-    /// Spawns a new task.
+    /// Spawns a new task with the default priority. Like all the spawn
+    /// functions, the task inherits the deadline of the task spawning it.
     //--------------------------------------------------------------------------
-    fn spawn( &self, task: Task<T> ) -> Result<(), ExecutorError>
+    #[track_caller]
+    pub fn spawn<F>( &self, future: F ) -> JoinHandle<F::Output>
+        where
+            F: Future + Send + 'static,
+            F::Output: Send + 'static,
     {
-        self.queue.push(task)?;
-        Ok(())
+        self.spawn_with_priority(0, future)
     }
 
     //--------------------------------------------------------------------------
-    /// Blocks the current thread on the given future.
+    /// Spawns a new task with a priority. Higher priorities run first, and
+    /// tasks with the same priority run in the order they were scheduled.
     //--------------------------------------------------------------------------
-    pub(crate) fn block_on<F>
+    #[track_caller]
+    pub fn spawn_with_priority<F>
     (
         &self,
+        priority: usize,
         future: F,
-    ) -> Result<F::Output, ExecutorError>
+    ) -> JoinHandle<F::Output>
         where
-            F: Future<Output = T> + Send + 'static,
-            T: Clone,
+            F: Future + Send + 'static,
+            F::Output: Send + 'static,
     {
-        let task = Task::new(future);
-        self.spawn(task)?;
+        TaskBuilder::new().priority(priority).spawn_on(self, future)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Spawns a new task with a deadline, which the earliest-deadline-first
+    /// policy schedules by.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub fn spawn_with_deadline<F>
+    (
+        &self,
+        deadline: Instant,
+        future: F,
+    ) -> JoinHandle<F::Output>
+        where
+            F: Future + Send + 'static,
+            F::Output: Send + 'static,
+    {
+        TaskBuilder::new().deadline(deadline).spawn_on(self, future)
+    }
 
-        let (lock, cvar) = &*self.is_done;
-        let mut result = lock.lock()?;
-        while result.is_none()
+    //--------------------------------------------------------------------------
+    /// Returns the queue of the executor.
+    //--------------------------------------------------------------------------
+    pub(super) fn queue( &self ) -> &TaskQueue
+    {
+        &self.queue
+    }
+
+    //--------------------------------------------------------------------------
+    /// Blocks the current thread on the given future. Without worker threads,
+    /// the tasks run on the current thread until the future completes.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub fn block_on<F>
+    (
+        &self,
+        future: F,
+    ) -> Result<F::Output, ExecutorError>
+        where
+            F: Future + Send + 'static,
+            F::Output: Send + 'static,
+    {
+        let _guard = context::enter(self.queue.clone());
+        let handle = self.spawn(future);
+        if self.workers.is_empty()
         {
-            result = cvar.wait(result)?;
+            while !handle.is_finished()
+            {
+                self.run_once();
+            }
         }
+        Ok(park_on(handle)?)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Runs the tasks on the current thread until the future completes. The
+    /// future is polled again after each task, as only these tasks can make
+    /// it progress.
+    //--------------------------------------------------------------------------
+    fn run_until<F: Future>( &self, future: F ) -> F::Output
+    {
+        let _guard = context::enter(self.queue.clone());
+        let _budget_guard = budget::unlimited();
+        let mut future = pin!(future);
 
-        let result = match result.take()
+        let waker = waker_fn(|| {});
+        let mut context = Context::from_waker(&waker);
+        loop
         {
-            Some(result) => result,
-            None =>
+            if let Poll::Ready(output) = future.as_mut().poll(&mut context)
             {
-                return Err(ExecutorError::NoResult);
+                return output;
             }
+            self.run_once();
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls the next task on the current thread, waiting up to the idle
+    /// timeout for one to be scheduled.
+    //--------------------------------------------------------------------------
+    fn run_once( &self )
+    {
+        if let Some(task) = self.queue.pop_timeout(IDLE_TIMEOUT)
+        {
+            let started = Instant::now();
+            poll_task(&self.queue, &task);
+            self.counters.record_poll(started.elapsed());
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Runs a scope whose tasks may borrow from the caller, and blocks the
+    /// current thread until all of them have completed. Returns the outputs
+    /// of the tasks in the order they were spawned, or the first failure,
+    /// which cancels the other tasks. Without worker threads, the tasks run
+    /// on the current thread.
+    ///
+    /// If the closure panics, the tasks are cancelled and the panic resumes
+    /// once they have stopped.
+    //--------------------------------------------------------------------------
+    pub fn scope<'env, T, E, F>( &self, f: F ) -> Result<Vec<T>, GroupError<E>>
+        where
+            F: for<'scope> FnOnce(&'scope Scope<'scope, 'env, T, E>),
+            T: Send,
+            E: Send,
+    {
+        let scope = Scope::new(self.queue.clone());
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+        if result.is_err()
+        {
+            scope.cancel();
+        }
+        let joined = match self.workers.is_empty()
+        {
+            true => self.run_until(poll_fn(|cx| scope.poll_join(cx))),
+            false => park_on(poll_fn(|cx| scope.poll_join(cx))),
         };
-        Ok(result)
+
+        if let Err(payload) = result
+        {
+            panic::resume_unwind(payload);
+        }
+        joined
     }
 }
 
-impl<T: Clone> Drop for Executor<T>
+impl Drop for Executor
 {
     fn drop( &mut self )
     {
@@ -148,5 +397,147 @@ impl<T: Clone> Drop for Executor<T>
                 let _ = thread.join();
             }
         }
+        self.queue.clear();
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # spawn
+///
+/// Spawns a new task on the executor running the current task.
+///
+/// # Panics
+///
+/// Panics if called outside of an executor.
+//------------------------------------------------------------------------------
+#[track_caller]
+pub fn spawn<F>( future: F ) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+{
+    spawn_with_priority(0, future)
+}
+
+//------------------------------------------------------------------------------
+/// # spawn_with_priority
+///
+/// Spawns a new task with a priority on the executor running the current
+/// task.
+///
+/// # Panics
+///
+/// Panics if called outside of an executor.
+//------------------------------------------------------------------------------
+#[track_caller]
+pub fn spawn_with_priority<F>( priority: usize, future: F ) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+{
+    TaskBuilder::new().priority(priority).spawn(future)
+}
+
+//------------------------------------------------------------------------------
+/// # spawn_with_deadline
+///
+/// Spawns a new task with a deadline on the executor running the current
+/// task.
+///
+/// # Panics
+///
+/// Panics if called outside of an executor.
+//------------------------------------------------------------------------------
+#[track_caller]
+pub fn spawn_with_deadline<F>( deadline: Instant, future: F ) -> JoinHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+{
+    TaskBuilder::new().deadline(deadline).spawn(future)
+}
+
+//------------------------------------------------------------------------------
+/// # task_dump
+///
+/// Returns a dump of the live tasks of the executor running the current task.
+///
+/// # Panics
+///
+/// Panics if called outside of an executor.
+//------------------------------------------------------------------------------
+pub fn task_dump() -> TaskDump
+{
+    match context::current()
+    {
+        Some(queue) => queue.task_registry().dump(),
+        None => panic!("task_dump must be called from the context of an executor"),
+    }
+}
+
+//------------------------------------------------------------------------------
+/// # park_on
+///
+/// Polls the future on the current thread, parking it until the future is
+/// woken. The budget is unlimited while parked, as a task waiting here does
+/// not give the worker back.
+//------------------------------------------------------------------------------
+pub(super) fn park_on<F: Future>( future: F ) -> F::Output
+{
+    let _budget_guard = budget::unlimited();
+    let mut future = pin!(future);
+
+    let waker =
+    {
+        let thread = thread::current();
+        waker_fn(move || thread.unpark())
+    };
+    let mut context = Context::from_waker(&waker);
+    loop
+    {
+        match future.as_mut().poll(&mut context)
+        {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    #[test]
+    fn scope_without_workers_runs_the_tasks_inline()
+    {
+        let executor = Executor::new(0);
+        let values = [1, 2, 3];
+        let doubled = executor.scope::<_, (), _>(|scope|
+        {
+            for value in &values
+            {
+                scope.spawn(async move
+                {
+                    budget::yield_now().await;
+                    Ok(value * 2)
+                });
+            }
+        });
+        assert_eq!(doubled, Ok(vec![2, 4, 6]));
+    }
+
+    #[test]
+    fn polls_without_workers_are_counted()
+    {
+        let executor = Executor::new(0);
+        executor.block_on(async { spawn(async {}).await }).unwrap().unwrap();
+
+        let metrics = executor.metrics();
+        assert_eq!(metrics.workers.len(), 1);
+        assert!(metrics.total_polls() >= 2);
+        assert_eq!(metrics.poll_durations.count(), metrics.total_polls());
     }
 }
@@ -0,0 +1,313 @@
+//------------------------------------------------------------------------------
+//! # Injection queue
+//!
+//! Bounded multi-producer multi-consumer FIFO queue over a ring buffer,
+//! without locks. Each slot carries a sequence number telling whether it is
+//! ready to be written or read at the current lap, so producers and consumers
+//! only contend on the index they claim with a compare-and-swap.
+//------------------------------------------------------------------------------
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+
+
+//------------------------------------------------------------------------------
+/// # CachePadded
+///
+/// Keeps the head and the tail on separate cache lines.
+//------------------------------------------------------------------------------
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+
+//------------------------------------------------------------------------------
+/// # Slot
+///
+/// The sequence is the position the slot is written at next, and that
+/// position plus one once it is written.
+//------------------------------------------------------------------------------
+struct Slot<T>
+{
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Injector
+//------------------------------------------------------------------------------
+pub(super) struct Injector<T>
+{
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+// The values are moved in and out of the slots by one thread at a time, as
+// claimed by the sequence numbers.
+unsafe impl<T: Send> Send for Injector<T> {}
+unsafe impl<T: Send> Sync for Injector<T> {}
+
+impl<T> Injector<T>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new Injector. The capacity is rounded up to a power of two,
+    /// and to at least two: with a single slot, the sequence of a written
+    /// slot is also the one of a free slot at the next lap.
+    //--------------------------------------------------------------------------
+    pub(super) fn new( capacity: usize ) -> Self
+    {
+        let capacity = capacity.max(2).next_power_of_two();
+        let slots = (0..capacity)
+            .map(|index| Slot
+            {
+                sequence: AtomicUsize::new(index),
+                value: UnsafeCell::new(MaybeUninit::uninit()),
+            })
+            .collect();
+
+        Self
+        {
+            slots,
+            mask: capacity - 1,
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the capacity of the queue.
+    //--------------------------------------------------------------------------
+    pub(super) fn capacity( &self ) -> usize
+    {
+        self.slots.len()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pushes a value at the tail, or gives it back if the queue is full.
+    //--------------------------------------------------------------------------
+    pub(super) fn push( &self, value: T ) -> Result<(), T>
+    {
+        let mut position = self.tail.0.load(Ordering::Relaxed);
+        loop
+        {
+            let slot = &self.slots[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence as isize).wrapping_sub(position as isize)
+            {
+                0 =>
+                {
+                    match self.tail.0.compare_exchange_weak
+                    (
+                        position,
+                        position.wrapping_add(1),
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    )
+                    {
+                        Ok(_) =>
+                        {
+                            unsafe { (*slot.value.get()).write(value) };
+                            slot.sequence.store(position.wrapping_add(1), Ordering::Release);
+                            return Ok(());
+                        },
+                        Err(current) => position = current,
+                    }
+                },
+                // The slot still holds the value of the previous lap.
+                lag if lag < 0 => return Err(value),
+                _ => position = self.tail.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pops the value at the head, if any.
+    //--------------------------------------------------------------------------
+    pub(super) fn pop( &self ) -> Option<T>
+    {
+        let mut position = self.head.0.load(Ordering::Relaxed);
+        loop
+        {
+            let slot = &self.slots[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+            match (sequence as isize).wrapping_sub(position.wrapping_add(1) as isize)
+            {
+                0 =>
+                {
+                    match self.head.0.compare_exchange_weak
+                    (
+                        position,
+                        position.wrapping_add(1),
+                        Ordering::Relaxed,
+                        Ordering::Relaxed,
+                    )
+                    {
+                        Ok(_) =>
+                        {
+                            let value = unsafe { (*slot.value.get()).assume_init_read() };
+                            slot.sequence.store
+                            (
+                                position.wrapping_add(self.mask + 1),
+                                Ordering::Release,
+                            );
+                            return Some(value);
+                        },
+                        Err(current) => position = current,
+                    }
+                },
+                // The slot has not been written at this lap yet.
+                lag if lag < 0 => return None,
+                _ => position = self.head.0.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of values in the queue. It may be outdated as soon
+    /// as it is returned.
+    //--------------------------------------------------------------------------
+    pub(super) fn len( &self ) -> usize
+    {
+        // The head is loaded first, so that it cannot be ahead of the tail.
+        let head = self.head.0.load(Ordering::Acquire);
+        let tail = self.tail.0.load(Ordering::Acquire);
+        tail.wrapping_sub(head).min(self.capacity())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the queue is empty.
+    //--------------------------------------------------------------------------
+    pub(super) fn is_empty( &self ) -> bool
+    {
+        self.len() == 0
+    }
+}
+
+impl<T> Drop for Injector<T>
+{
+    fn drop( &mut self )
+    {
+        while self.pop().is_some() {}
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    use std::sync::Arc;
+    use std::thread;
+
+    #[test]
+    fn small_capacities_push_and_pop_over_several_laps()
+    {
+        for requested in [0, 1, 2]
+        {
+            let injector = Injector::new(requested);
+            assert_eq!(injector.capacity(), 2);
+            for lap in 0..4
+            {
+                assert_eq!(injector.push(lap * 2), Ok(()));
+                assert_eq!(injector.push(lap * 2 + 1), Ok(()));
+                assert_eq!(injector.push(100), Err(100));
+                assert_eq!(injector.len(), 2);
+                assert_eq!(injector.pop(), Some(lap * 2));
+                assert_eq!(injector.pop(), Some(lap * 2 + 1));
+                assert_eq!(injector.pop(), None);
+                assert!(injector.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn capacity_is_rounded_up_to_a_power_of_two()
+    {
+        let injector = Injector::new(5);
+        assert_eq!(injector.capacity(), 8);
+        for value in 0..8
+        {
+            assert_eq!(injector.push(value), Ok(()));
+        }
+        assert_eq!(injector.push(8), Err(8));
+        assert_eq!(injector.pop(), Some(0));
+        assert_eq!(injector.push(8), Ok(()));
+        let values: Vec<_> = std::iter::from_fn(|| injector.pop()).collect();
+        assert_eq!(values, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn values_left_in_the_queue_are_dropped()
+    {
+        let value = Arc::new(());
+        {
+            let injector = Injector::new(4);
+            injector.push(value.clone()).unwrap();
+            injector.push(value.clone()).unwrap();
+            assert_eq!(Arc::strong_count(&value), 3);
+        }
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[test]
+    fn concurrent_producers_and_consumers_see_every_value_once()
+    {
+        const PRODUCERS: usize = 4;
+        const VALUES: usize = 10_000;
+
+        let injector = Arc::new(Injector::new(16));
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|producer|
+            {
+                let injector = injector.clone();
+                thread::spawn(move ||
+                {
+                    for index in 0..VALUES
+                    {
+                        let mut value = producer * VALUES + index;
+                        while let Err(rejected) = injector.push(value)
+                        {
+                            value = rejected;
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+        let consumers: Vec<_> = (0..PRODUCERS)
+            .map(|_|
+            {
+                let injector = injector.clone();
+                thread::spawn(move ||
+                {
+                    let mut values = Vec::new();
+                    while values.len() < VALUES
+                    {
+                        match injector.pop()
+                        {
+                            Some(value) => values.push(value),
+                            None => thread::yield_now(),
+                        }
+                    }
+                    values
+                })
+            })
+            .collect();
+
+        for producer in producers
+        {
+            producer.join().unwrap();
+        }
+        let mut values: Vec<_> = consumers
+            .into_iter()
+            .flat_map(|consumer| consumer.join().unwrap())
+            .collect();
+        values.sort_unstable();
+        assert_eq!(values, (0..PRODUCERS * VALUES).collect::<Vec<_>>());
+        assert!(injector.is_empty());
+    }
+}
@@ -0,0 +1,323 @@
+//------------------------------------------------------------------------------
+//! # Join handle
+//!
+//! The output of a spawned task is passed to a completion callback, which for
+//! a `JoinHandle` sends it through a one-shot channel. If the task is aborted
+//! or dropped before it completes, the callback reports it as cancelled. The
+//! future is always dropped before the callback runs.
+//------------------------------------------------------------------------------
+
+use super::task::panic_message;
+use crate::sync::oneshot;
+
+use std::fmt;
+use std::future::Future;
+use std::panic::{ self, AssertUnwindSafe };
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex, PoisonError };
+use std::sync::atomic::{ AtomicBool, Ordering };
+use std::task::{ Context, Poll, Waker };
+
+
+//------------------------------------------------------------------------------
+/// # JoinError
+///
+/// The task did not complete, either because it was cancelled or because it
+/// panicked.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinError
+{
+    Cancelled,
+    Panicked(String),
+}
+
+impl JoinError
+{
+    //--------------------------------------------------------------------------
+    /// Returns whether the task was cancelled.
+    //--------------------------------------------------------------------------
+    pub fn is_cancelled( &self ) -> bool
+    {
+        matches!(self, Self::Cancelled)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the task panicked.
+    //--------------------------------------------------------------------------
+    pub fn is_panic( &self ) -> bool
+    {
+        matches!(self, Self::Panicked(_))
+    }
+}
+
+impl fmt::Display for JoinError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Cancelled => write!(f, "task was cancelled"),
+            Self::Panicked(message) => write!(f, "task panicked: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for JoinError {}
+
+
+//------------------------------------------------------------------------------
+/// # JoinHandle
+///
+/// Future resolving to the output of a spawned task. Dropping the handle
+/// detaches the task, which keeps running.
+//------------------------------------------------------------------------------
+pub struct JoinHandle<T>
+{
+    receiver: oneshot::Receiver<Result<T, JoinError>>,
+    abort: AbortHandle,
+}
+
+impl<T> JoinHandle<T>
+{
+    //--------------------------------------------------------------------------
+    /// Returns whether the task has completed.
+    //--------------------------------------------------------------------------
+    pub fn is_finished( &self ) -> bool
+    {
+        self.receiver.is_complete()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Aborts the task. See `AbortHandle::abort`.
+    //--------------------------------------------------------------------------
+    pub fn abort( &self )
+    {
+        self.abort.abort();
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a handle aborting the task without owning its output.
+    //--------------------------------------------------------------------------
+    pub fn abort_handle( &self ) -> AbortHandle
+    {
+        self.abort.clone()
+    }
+}
+
+impl<T> Future for JoinHandle<T>
+{
+    type Output = Result<T, JoinError>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        match Pin::new(&mut self.receiver).poll(cx)
+        {
+            Poll::Ready(Ok(result)) => Poll::Ready(result),
+            Poll::Ready(Err(_)) => Poll::Ready(Err(JoinError::Cancelled)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl<T> fmt::Debug for JoinHandle<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("JoinHandle").finish_non_exhaustive()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # AbortHandle
+///
+/// Aborts a spawned task.
+//------------------------------------------------------------------------------
+#[derive(Clone)]
+pub struct AbortHandle
+{
+    state: Arc<AbortState>,
+}
+
+impl AbortHandle
+{
+    //--------------------------------------------------------------------------
+    /// Aborts the task. Its future is dropped the next time it would be
+    /// polled, and its output is reported as cancelled. A task that has
+    /// already completed is not affected.
+    //--------------------------------------------------------------------------
+    pub fn abort( &self )
+    {
+        self.state.aborted.store(true, Ordering::Release);
+        let waker = self.state.waker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(waker) = waker
+        {
+            waker.wake();
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the task has been aborted.
+    //--------------------------------------------------------------------------
+    pub fn is_aborted( &self ) -> bool
+    {
+        self.state.aborted.load(Ordering::Acquire)
+    }
+}
+
+impl fmt::Debug for AbortHandle
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("AbortHandle")
+            .field("aborted", &self.is_aborted())
+            .finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # AbortState
+//------------------------------------------------------------------------------
+#[derive(Default)]
+struct AbortState
+{
+    aborted: AtomicBool,
+    waker: Mutex<Option<Waker>>,
+}
+
+
+//------------------------------------------------------------------------------
+/// # joinable
+///
+/// Wraps a future so that its output, or its panic, is sent to a JoinHandle.
+//------------------------------------------------------------------------------
+pub(super) fn joinable<F>
+(
+    future: F,
+) -> (impl Future<Output = ()> + Send + 'static, JoinHandle<F::Output>)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    let (task, abort) = joinable_with(future, move |result|
+    {
+        let _ = sender.send(result);
+    });
+    (task, JoinHandle { receiver, abort })
+}
+
+//------------------------------------------------------------------------------
+/// # joinable_with
+///
+/// Wraps a future so that its output, its panic or its cancellation is passed
+/// to `complete`, which is called exactly once.
+//------------------------------------------------------------------------------
+pub(super) fn joinable_with<F, C>
+(
+    future: F,
+    complete: C,
+) -> (Joinable<F, C>, AbortHandle)
+    where
+        F: Future,
+        C: FnOnce(Result<F::Output, JoinError>),
+{
+    let state = Arc::new(AbortState::default());
+    let task = Joinable
+    {
+        future: Some(future),
+        abort: state.clone(),
+        complete: Some(complete),
+    };
+    (task, AbortHandle { state })
+}
+
+
+//------------------------------------------------------------------------------
+/// # Joinable
+///
+/// Future driving a spawned future and passing its result to the completion
+/// callback.
+//------------------------------------------------------------------------------
+pub(super) struct Joinable<F, C>
+    where
+        F: Future,
+        C: FnOnce(Result<F::Output, JoinError>),
+{
+    future: Option<F>,
+    abort: Arc<AbortState>,
+    complete: Option<C>,
+}
+
+impl<F, C> Joinable<F, C>
+    where
+        F: Future,
+        C: FnOnce(Result<F::Output, JoinError>),
+{
+    //--------------------------------------------------------------------------
+    /// Drops the future in place and passes the result to the callback,
+    /// unless it was already called.
+    //--------------------------------------------------------------------------
+    fn finish( &mut self, result: Result<F::Output, JoinError> )
+    {
+        if let Some(complete) = self.complete.take()
+        {
+            // The future is structurally pinned, so it is dropped in place.
+            unsafe { Pin::new_unchecked(&mut self.future) }.set(None);
+            complete(result);
+        }
+    }
+}
+
+impl<F, C> Future for Joinable<F, C>
+    where
+        F: Future,
+        C: FnOnce(Result<F::Output, JoinError>),
+{
+    type Output = ();
+
+    fn poll( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<()>
+    {
+        let this = unsafe { self.get_unchecked_mut() };
+
+        // The waker is registered before checking the flag, so that an abort
+        // racing with this poll still wakes the task.
+        *this.abort.waker.lock().unwrap_or_else(PoisonError::into_inner) =
+            Some(cx.waker().clone());
+        if this.abort.aborted.load(Ordering::Acquire)
+        {
+            this.finish(Err(JoinError::Cancelled));
+            return Poll::Ready(());
+        }
+
+        let future = match unsafe { Pin::new_unchecked(&mut this.future) }.as_pin_mut()
+        {
+            Some(future) => future,
+            None => return Poll::Ready(()),
+        };
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| future.poll(cx)))
+        {
+            Ok(Poll::Ready(output)) => Ok(output),
+            Ok(Poll::Pending) => return Poll::Pending,
+            Err(payload) => Err(JoinError::Panicked(panic_message(payload.as_ref()))),
+        };
+        this.finish(result);
+        Poll::Ready(())
+    }
+}
+
+impl<F, C> Drop for Joinable<F, C>
+    where
+        F: Future,
+        C: FnOnce(Result<F::Output, JoinError>),
+{
+    fn drop( &mut self )
+    {
+        self.finish(Err(JoinError::Cancelled));
+    }
+}
@@ -0,0 +1,282 @@
+//------------------------------------------------------------------------------
+//! # Join set
+//!
+//! A set of spawned tasks whose outputs are received in the order the tasks
+//! complete. Each task sends its output through a channel shared by the set,
+//! so that waiting for the next one does not poll every task.
+//------------------------------------------------------------------------------
+
+use super::builder::TaskBuilder;
+use super::context;
+use super::join_handle::{ joinable_with, AbortHandle, JoinError };
+use super::task_queue::TaskQueue;
+use crate::sync::mpsc;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::{ poll_fn, Future };
+use std::mem;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+type Completion<T> = (u64, Result<T, JoinError>);
+
+
+//------------------------------------------------------------------------------
+/// # JoinSet
+///
+/// Dropping the set aborts all the tasks still running.
+//------------------------------------------------------------------------------
+pub struct JoinSet<T>
+{
+    tasks: HashMap<u64, AbortHandle>,
+    next_id: u64,
+    sender: mpsc::UnboundedSender<Completion<T>>,
+    receiver: mpsc::UnboundedReceiver<Completion<T>>,
+}
+
+impl<T: Send> JoinSet<T>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new JoinSet.
+    //--------------------------------------------------------------------------
+    pub fn new() -> Self
+    {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        Self
+        {
+            tasks: HashMap::new(),
+            next_id: 0,
+            sender,
+            receiver,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of tasks whose output has not been received yet.
+    //--------------------------------------------------------------------------
+    pub fn len( &self ) -> usize
+    {
+        self.tasks.len()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the set has no task left.
+    //--------------------------------------------------------------------------
+    pub fn is_empty( &self ) -> bool
+    {
+        self.tasks.is_empty()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Spawns a task in the set on the executor running the current task.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of an executor.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub fn spawn<F>( &mut self, future: F ) -> AbortHandle
+        where
+            F: Future<Output = T> + Send + 'static,
+            T: 'static,
+    {
+        match context::current()
+        {
+            Some(queue) => unsafe { self.spawn_unchecked(&queue, future) },
+            None => panic!("spawn must be called from the context of an executor"),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Spawns a task in the set without requiring it to be `'static`.
+    ///
+    /// # Safety
+    ///
+    /// The caller must keep everything the future borrows alive until the set
+    /// has received the output of the task.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub(super) unsafe fn spawn_unchecked<F>
+    (
+        &mut self,
+        queue: &TaskQueue,
+        future: F,
+    ) -> AbortHandle
+        where F: Future<Output = T> + Send
+    {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let sender = self.sender.clone();
+        let (task, abort) = joinable_with(future, move |result|
+        {
+            let _ = sender.send((id, result));
+        });
+        let task: Pin<Box<dyn Future<Output = ()> + Send + '_>> = Box::pin(task);
+        let task: Pin<Box<dyn Future<Output = ()> + Send + 'static>> =
+            mem::transmute(task);
+
+        TaskBuilder::new().spawn_task(queue, task);
+        self.tasks.insert(id, abort.clone());
+        abort
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits for the next task to complete and returns its output. Returns
+    /// `None` once the set is empty.
+    //--------------------------------------------------------------------------
+    pub async fn join_next( &mut self ) -> Option<Result<T, JoinError>>
+    {
+        poll_fn(|cx| self.poll_join_next(cx)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for the output of the next task to complete.
+    //--------------------------------------------------------------------------
+    pub fn poll_join_next
+    (
+        &mut self,
+        cx: &mut Context,
+    ) -> Poll<Option<Result<T, JoinError>>>
+    {
+        if self.tasks.is_empty()
+        {
+            return Poll::Ready(None);
+        }
+        match self.receiver.poll_recv(cx)
+        {
+            Poll::Ready(Some((id, result))) =>
+            {
+                self.tasks.remove(&id);
+                Poll::Ready(Some(result))
+            },
+            // The set holds a sender, so the channel is never closed.
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Aborts all the tasks. Their outputs are still received, as cancelled
+    /// unless they completed first.
+    //--------------------------------------------------------------------------
+    pub fn abort_all( &self )
+    {
+        for abort in self.tasks.values()
+        {
+            abort.abort();
+        }
+    }
+}
+
+impl<T: Send> Default for JoinSet<T>
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl<T> Drop for JoinSet<T>
+{
+    fn drop( &mut self )
+    {
+        for abort in self.tasks.values()
+        {
+            abort.abort();
+        }
+    }
+}
+
+impl<T> fmt::Debug for JoinSet<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("JoinSet")
+            .field("len", &self.tasks.len())
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Simulation;
+    use crate::sim;
+    use crate::sync::oneshot;
+
+    use std::future::pending;
+    use std::time::Duration;
+
+    #[test]
+    fn outputs_are_received_in_completion_order()
+    {
+        let outputs = Simulation::new(0).block_on(async
+        {
+            let mut set = JoinSet::new();
+            for (id, delay) in [(1, 30), (2, 10), (3, 20)]
+            {
+                set.spawn(async move
+                {
+                    sim::sleep(Duration::from_millis(delay)).await;
+                    id
+                });
+            }
+            assert_eq!(set.len(), 3);
+
+            let mut outputs = Vec::new();
+            while let Some(output) = set.join_next().await
+            {
+                outputs.push(output.unwrap());
+            }
+            assert!(set.is_empty());
+            outputs
+        });
+        assert_eq!(outputs.unwrap(), [2, 3, 1]);
+    }
+
+    #[test]
+    fn aborted_tasks_are_received_as_cancelled()
+    {
+        let outputs = Simulation::new(0).block_on(async
+        {
+            let mut set = JoinSet::new();
+            set.spawn(async { 1 });
+            set.spawn(pending::<i32>());
+            let abort = set.spawn(pending::<i32>());
+            assert_eq!(set.join_next().await, Some(Ok(1)));
+
+            set.abort_all();
+            assert!(abort.is_aborted());
+            let mut outputs = Vec::new();
+            while let Some(output) = set.join_next().await
+            {
+                outputs.push(output);
+            }
+            outputs
+        });
+        assert_eq!(outputs.unwrap(), [Err(JoinError::Cancelled), Err(JoinError::Cancelled)]);
+    }
+
+    #[test]
+    fn dropping_the_set_aborts_its_tasks()
+    {
+        let result = Simulation::new(0).block_on(async
+        {
+            let (sender, receiver) = oneshot::channel::<()>();
+            let mut set = JoinSet::new();
+            set.spawn(async move
+            {
+                let _sender = sender;
+                pending::<()>().await
+            });
+            sim::sleep(Duration::from_millis(1)).await;
+            drop(set);
+            receiver.await
+        });
+        assert!(result.unwrap().is_err());
+    }
+}
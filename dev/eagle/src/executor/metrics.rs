@@ -0,0 +1,260 @@
+//------------------------------------------------------------------------------
+//! # Runtime metrics
+//!
+//! The counters are updated with relaxed atomics by the workers and the
+//! spawners, and read into a `RuntimeMetrics` snapshot by
+//! `Executor::metrics`. The values of a snapshot are not taken at a single
+//! instant, so they may be slightly inconsistent with each other.
+//------------------------------------------------------------------------------
+
+use super::task_queue::DeadlineStats;
+
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::time::Duration;
+
+/// Upper bounds of the poll duration buckets. The last bucket has no bound.
+const POLL_BUCKET_BOUNDS: [Duration; 5] =
+[
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+];
+
+const POLL_BUCKETS: usize = POLL_BUCKET_BOUNDS.len() + 1;
+
+
+//------------------------------------------------------------------------------
+/// # RuntimeMetrics
+///
+/// - workers: The metrics of each worker, by worker id.
+/// - queue_depth: Tasks waiting in the queue to be polled.
+/// - rejected_tasks: Spawned tasks cancelled because the injection queue was
+///   full, with the `Reject` overflow policy.
+/// - spawned_tasks: Tasks spawned since the executor was created.
+/// - live_tasks: Spawned tasks that have neither completed nor been dropped.
+/// - reactor_events: IO events dispatched by the reactor, which is shared by
+///   all the executors.
+/// - registered_sources: IO sources registered in the reactor.
+/// - deadlines: See `DeadlineStats`.
+/// - poll_durations: How long the polls of all the workers took.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeMetrics
+{
+    pub workers: Vec<WorkerMetrics>,
+    pub queue_depth: usize,
+    pub rejected_tasks: u64,
+    pub spawned_tasks: u64,
+    pub live_tasks: u64,
+    pub reactor_events: u64,
+    pub registered_sources: usize,
+    pub deadlines: DeadlineStats,
+    pub poll_durations: PollHistogram,
+}
+
+impl RuntimeMetrics
+{
+    //--------------------------------------------------------------------------
+    /// Returns the number of polls of all the workers.
+    //--------------------------------------------------------------------------
+    pub fn total_polls( &self ) -> u64
+    {
+        self.workers.iter().map(|worker| worker.polls).sum()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the time all the workers spent polling tasks.
+    //--------------------------------------------------------------------------
+    pub fn total_busy( &self ) -> Duration
+    {
+        self.workers.iter().map(|worker| worker.busy).sum()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # WorkerMetrics
+///
+/// - polls: Tasks polled by the worker.
+/// - busy: Time the worker spent polling tasks.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WorkerMetrics
+{
+    pub polls: u64,
+    pub busy: Duration,
+}
+
+
+//------------------------------------------------------------------------------
+/// # PollHistogram
+///
+/// Number of polls by duration, in buckets of increasing powers of ten from
+/// 10µs to 100ms. Polls taking milliseconds usually mean that a future blocks
+/// its worker.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PollHistogram
+{
+    counts: [u64; POLL_BUCKETS],
+}
+
+impl PollHistogram
+{
+    //--------------------------------------------------------------------------
+    /// Returns the upper bound and the count of each bucket. The bound of the
+    /// last bucket is `None`.
+    //--------------------------------------------------------------------------
+    pub fn buckets( &self ) -> impl Iterator<Item = (Option<Duration>, u64)> + '_
+    {
+        POLL_BUCKET_BOUNDS
+            .iter()
+            .copied()
+            .map(Some)
+            .chain([None])
+            .zip(self.counts.iter().copied())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of polls.
+    //--------------------------------------------------------------------------
+    pub fn count( &self ) -> u64
+    {
+        self.counts.iter().sum()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of polls that took at least the given duration,
+    /// counting only the buckets whose lower bound is not below it.
+    //--------------------------------------------------------------------------
+    pub fn count_at_least( &self, duration: Duration ) -> u64
+    {
+        if duration.is_zero()
+        {
+            return self.count();
+        }
+        let first = POLL_BUCKET_BOUNDS
+            .iter()
+            .position(|bound| *bound >= duration)
+            .map_or(POLL_BUCKETS, |index| index + 1);
+        self.counts[first..].iter().sum()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # WorkerCounters
+//------------------------------------------------------------------------------
+#[derive(Default)]
+pub(super) struct WorkerCounters
+{
+    polls: AtomicU64,
+    busy_nanos: AtomicU64,
+    poll_durations: [AtomicU64; POLL_BUCKETS],
+}
+
+impl WorkerCounters
+{
+    //--------------------------------------------------------------------------
+    /// Records a poll.
+    //--------------------------------------------------------------------------
+    pub(super) fn record_poll( &self, duration: Duration )
+    {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        let bucket = POLL_BUCKET_BOUNDS
+            .iter()
+            .position(|bound| duration < *bound)
+            .unwrap_or(POLL_BUCKET_BOUNDS.len());
+
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.busy_nanos.fetch_add(nanos, Ordering::Relaxed);
+        self.poll_durations[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the metrics of the worker.
+    //--------------------------------------------------------------------------
+    pub(super) fn snapshot( &self ) -> WorkerMetrics
+    {
+        WorkerMetrics
+        {
+            polls: self.polls.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(self.busy_nanos.load(Ordering::Relaxed)),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Adds the poll durations of the worker to the histogram.
+    //--------------------------------------------------------------------------
+    pub(super) fn add_poll_durations( &self, histogram: &mut PollHistogram )
+    {
+        for (count, counter) in histogram.counts.iter_mut().zip(&self.poll_durations)
+        {
+            *count += counter.load(Ordering::Relaxed);
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # TaskCounters
+//------------------------------------------------------------------------------
+#[derive(Default)]
+pub(super) struct TaskCounters
+{
+    spawned: AtomicU64,
+    live: AtomicU64,
+}
+
+impl TaskCounters
+{
+    //--------------------------------------------------------------------------
+    /// Counts a spawned task. It stays live until the guard is dropped.
+    //--------------------------------------------------------------------------
+    pub(super) fn track( self: &Arc<Self> ) -> LiveGuard
+    {
+        self.spawned.fetch_add(1, Ordering::Relaxed);
+        self.live.fetch_add(1, Ordering::Relaxed);
+        LiveGuard { counters: self.clone() }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of spawned tasks.
+    //--------------------------------------------------------------------------
+    pub(super) fn spawned( &self ) -> u64
+    {
+        self.spawned.load(Ordering::Relaxed)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of live tasks.
+    //--------------------------------------------------------------------------
+    pub(super) fn live( &self ) -> u64
+    {
+        self.live.load(Ordering::Relaxed)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # LiveGuard
+///
+/// Held by the future of a spawned task, so that the task stops being live
+/// when its future completes or is dropped.
+//------------------------------------------------------------------------------
+pub(super) struct LiveGuard
+{
+    counters: Arc<TaskCounters>,
+}
+
+impl Drop for LiveGuard
+{
+    fn drop( &mut self )
+    {
+        self.counters.live.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
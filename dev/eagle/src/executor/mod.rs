@@ -2,11 +2,43 @@
 //! Async runtime
 //------------------------------------------------------------------------------
 
+mod budget;
+mod builder;
+mod context;
+mod dump;
+#[allow(clippy::module_inception)]
 mod executor;
+mod injector;
+mod join_handle;
+mod join_set;
+mod metrics;
+mod scope;
+mod simulation;
 mod task_queue;
 mod task;
+mod task_local;
 mod waker;
 mod worker;
-mod reactor;
+pub(crate) mod reactor;
 
-pub(crate) use executor::Executor;
+pub(crate) use budget::poll_proceed;
+
+pub use budget::yield_now;
+pub use builder::TaskBuilder;
+pub use executor::{
+    spawn,
+    spawn_with_deadline,
+    spawn_with_priority,
+    task_dump,
+    Executor,
+    ExecutorError,
+};
+pub use dump::{ TaskDump, TaskSnapshot };
+pub use join_handle::{ AbortHandle, JoinError, JoinHandle };
+pub use join_set::JoinSet;
+pub use metrics::{ PollHistogram, RuntimeMetrics, WorkerMetrics };
+pub use scope::{ GroupError, Scope, TaskGroup };
+pub use simulation::{ SimError, Simulation };
+pub use task::TaskState;
+pub use task_local::{ AccessError, LocalKey, TaskLocalFuture };
+pub use task_queue::{ DeadlineStats, OverflowPolicy, SchedulingPolicy };
@@ -1,4 +1,10 @@
+//------------------------------------------------------------------------------
+//! IO reactor
+//------------------------------------------------------------------------------
+
+#[allow(clippy::module_inception)]
 mod reactor;
 mod source;
 
-pub(super) use reactor::Reactor;
+pub(crate) use reactor::Reactor;
+pub(crate) use source::Source;
@@ -1,10 +1,22 @@
 //------------------------------------------------------------------------------
 //! # Reactor
+//!
+//! The reactor waits for IO events with epoll on its own thread and wakes the
+//! tasks waiting on the registered sources.
 //------------------------------------------------------------------------------
 
 use super::source::Source;
 
-use std::sync::{ Arc, Mutex };
+use std::collections::HashMap;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::{ Arc, Mutex, MutexGuard, OnceLock };
+use std::sync::atomic::{ AtomicU64, AtomicUsize, Ordering };
+use std::thread;
+
+static REACTOR: OnceLock<Reactor> = OnceLock::new();
+
+const MAX_EVENTS: usize = 256;
 
 
 //------------------------------------------------------------------------------
@@ -12,7 +24,10 @@ use std::sync::{ Arc, Mutex };
 //------------------------------------------------------------------------------
 pub(crate) struct Reactor
 {
-    sources: Arc<Mutex<Vec<Source>>>
+    epoll_fd: RawFd,
+    sources: Mutex<HashMap<usize, Arc<Source>>>,
+    next_key: AtomicUsize,
+    events: AtomicU64,
 }
 
 impl Reactor
@@ -20,19 +35,188 @@ impl Reactor
     //--------------------------------------------------------------------------
     /// Creates a new Reactor.
     //--------------------------------------------------------------------------
-    pub(crate) fn new() -> Self
+    fn new() -> io::Result<Self>
+    {
+        let epoll_fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if epoll_fd < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self
+        {
+            epoll_fd,
+            sources: Mutex::new(HashMap::new()),
+            next_key: AtomicUsize::new(0),
+            events: AtomicU64::new(0),
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the global Reactor, starting its thread on the first call.
+    //--------------------------------------------------------------------------
+    pub(crate) fn get() -> io::Result<&'static Self>
     {
-        Self
+        if let Some(reactor) = REACTOR.get()
+        {
+            return Ok(reactor);
+        }
+
+        let reactor = Self::new()?;
+        if REACTOR.set(reactor).is_ok()
         {
-            sources: Arc::new(Mutex::new(Vec::new()))
+            thread::Builder::new()
+                .name("eagle-reactor".to_string())
+                .spawn(||
+                {
+                    if let Some(reactor) = REACTOR.get()
+                    {
+                        reactor.run();
+                    }
+                })?;
         }
+
+        REACTOR.get().ok_or_else(|| io::Error::other("reactor is not running"))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the global Reactor if it has been started.
+    //--------------------------------------------------------------------------
+    pub(crate) fn try_get() -> Option<&'static Self>
+    {
+        REACTOR.get()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of IO events dispatched so far.
+    //--------------------------------------------------------------------------
+    pub(crate) fn event_count( &self ) -> u64
+    {
+        self.events.load(Ordering::Relaxed)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of registered sources.
+    //--------------------------------------------------------------------------
+    pub(crate) fn source_count( &self ) -> usize
+    {
+        self.lock_sources().map_or(0, |sources| sources.len())
     }
 
     //--------------------------------------------------------------------------
     /// Registers a new IO.
     //--------------------------------------------------------------------------
-    pub(crate) fn register( &self, source: Source )
+    pub(crate) fn register( &self, fd: RawFd ) -> io::Result<Arc<Source>>
+    {
+        let key = self.next_key.fetch_add(1, Ordering::Relaxed);
+        let source = Arc::new(Source::new(fd, key, self.epoll_fd));
+
+        let mut event = libc::epoll_event
+        {
+            events: libc::EPOLLONESHOT as u32,
+            u64: key as u64,
+        };
+        let result = unsafe
+        {
+            libc::epoll_ctl(self.epoll_fd, libc::EPOLL_CTL_ADD, fd, &mut event)
+        };
+        if result < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        self.lock_sources()?.insert(key, source.clone());
+        Ok(source)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Deregisters the IO.
+    //--------------------------------------------------------------------------
+    pub(crate) fn deregister( &self, source: &Source ) -> io::Result<()>
+    {
+        self.lock_sources()?.remove(&source.key());
+        let result = unsafe
+        {
+            libc::epoll_ctl
+            (
+                self.epoll_fd,
+                libc::EPOLL_CTL_DEL,
+                source.raw_fd(),
+                std::ptr::null_mut(),
+            )
+        };
+        if result < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits for IO events and dispatches them to the sources.
+    //--------------------------------------------------------------------------
+    fn run( &self )
+    {
+        let mut events = vec![libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        loop
+        {
+            let count = unsafe
+            {
+                libc::epoll_wait
+                (
+                    self.epoll_fd,
+                    events.as_mut_ptr(),
+                    MAX_EVENTS as libc::c_int,
+                    -1,
+                )
+            };
+            if count < 0
+            {
+                if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted
+                {
+                    continue;
+                }
+                break;
+            }
+
+            let sources = match self.lock_sources()
+            {
+                Ok(sources) => sources,
+                Err(_) => break,
+            };
+            let ready: Vec<(Arc<Source>, u32)> = events[..count as usize]
+                .iter()
+                .filter_map(|event|
+                {
+                    let key = event.u64 as usize;
+                    sources.get(&key).map(|source| (source.clone(), event.events))
+                })
+                .collect();
+            drop(sources);
+
+            self.events.fetch_add(ready.len() as u64, Ordering::Relaxed);
+            for (source, events) in ready
+            {
+                source.dispatch(events);
+            }
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Locks the registered sources.
+    //--------------------------------------------------------------------------
+    fn lock_sources( &self ) -> io::Result<MutexGuard<'_, HashMap<usize, Arc<Source>>>>
+    {
+        self.sources
+            .lock()
+            .map_err(|error| io::Error::other(error.to_string()))
+    }
+}
+
+impl Drop for Reactor
+{
+    fn drop( &mut self )
     {
-        self.sources.lock().unwrap().push(source);
+        unsafe { libc::close(self.epoll_fd) };
     }
 }
@@ -1,11 +1,197 @@
 //------------------------------------------------------------------------------
 //! # IO Source
+//!
+//! A source is a file descriptor registered with the reactor. It is armed in
+//! oneshot mode every time a task waits on it, so that a readiness event is
+//! delivered once to the tasks waiting at that moment.
 //------------------------------------------------------------------------------
 
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::{ Mutex, MutexGuard };
+use std::task::{ Context, Poll, Waker };
+
+
+//------------------------------------------------------------------------------
+/// # Wakers
+//------------------------------------------------------------------------------
+#[derive(Default)]
+struct Wakers
+{
+    readers: Vec<Waker>,
+    writers: Vec<Waker>,
+}
+
+impl Wakers
+{
+    //--------------------------------------------------------------------------
+    /// Returns the epoll events the wakers are interested in.
+    //--------------------------------------------------------------------------
+    fn interest( &self ) -> u32
+    {
+        let mut events = 0;
+        if !self.readers.is_empty()
+        {
+            events |= libc::EPOLLIN | libc::EPOLLRDHUP;
+        }
+        if !self.writers.is_empty()
+        {
+            events |= libc::EPOLLOUT;
+        }
+        events as u32
+    }
+}
+
 
 //------------------------------------------------------------------------------
 /// # Source
 //------------------------------------------------------------------------------
 pub(crate) struct Source
 {
+    fd: RawFd,
+    key: usize,
+    epoll_fd: RawFd,
+    wakers: Mutex<Wakers>,
+}
+
+impl Source
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new Source.
+    //--------------------------------------------------------------------------
+    pub(super) fn new( fd: RawFd, key: usize, epoll_fd: RawFd ) -> Self
+    {
+        Self
+        {
+            fd,
+            key,
+            epoll_fd,
+            wakers: Mutex::new(Wakers::default()),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the raw file descriptor.
+    //--------------------------------------------------------------------------
+    pub(crate) fn raw_fd( &self ) -> RawFd
+    {
+        self.fd
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the key of the source in the reactor.
+    //--------------------------------------------------------------------------
+    pub(super) fn key( &self ) -> usize
+    {
+        self.key
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits until the source becomes readable.
+    ///
+    /// This always returns `Poll::Pending` unless an error occurs, so it must
+    /// be called only after the IO operation returned `WouldBlock`.
+    //--------------------------------------------------------------------------
+    pub(crate) fn poll_readable( &self, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        let result = self.lock_wakers().and_then(|mut wakers|
+        {
+            if wakers.readers.iter().all(|w| !w.will_wake(cx.waker()))
+            {
+                wakers.readers.push(cx.waker().clone());
+            }
+            self.rearm(&wakers)
+        });
+        match result
+        {
+            Ok(()) => Poll::Pending,
+            Err(error) => Poll::Ready(Err(error)),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits until the source becomes writable.
+    ///
+    /// This always returns `Poll::Pending` unless an error occurs, so it must
+    /// be called only after the IO operation returned `WouldBlock`.
+    //--------------------------------------------------------------------------
+    pub(crate) fn poll_writable( &self, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        let result = self.lock_wakers().and_then(|mut wakers|
+        {
+            if wakers.writers.iter().all(|w| !w.will_wake(cx.waker()))
+            {
+                wakers.writers.push(cx.waker().clone());
+            }
+            self.rearm(&wakers)
+        });
+        match result
+        {
+            Ok(()) => Poll::Pending,
+            Err(error) => Poll::Ready(Err(error)),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Wakes the tasks waiting for the given epoll events.
+    //--------------------------------------------------------------------------
+    pub(super) fn dispatch( &self, events: u32 )
+    {
+        let closed = (libc::EPOLLERR | libc::EPOLLHUP) as u32;
+        let readable = (libc::EPOLLIN | libc::EPOLLRDHUP) as u32 | closed;
+        let writable = libc::EPOLLOUT as u32 | closed;
+
+        let mut ready = Vec::new();
+        if let Ok(mut wakers) = self.lock_wakers()
+        {
+            if events & readable != 0
+            {
+                ready.append(&mut wakers.readers);
+            }
+            if events & writable != 0
+            {
+                ready.append(&mut wakers.writers);
+            }
+            if wakers.interest() != 0
+            {
+                let _ = self.rearm(&wakers);
+            }
+        }
+
+        for waker in ready
+        {
+            waker.wake();
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Arms the source for the events the wakers are interested in.
+    //--------------------------------------------------------------------------
+    fn rearm( &self, wakers: &Wakers ) -> io::Result<()>
+    {
+        let mut event = libc::epoll_event
+        {
+            events: wakers.interest() | libc::EPOLLONESHOT as u32,
+            u64: self.key as u64,
+        };
+        let result = unsafe
+        {
+            libc::epoll_ctl(self.epoll_fd, libc::EPOLL_CTL_MOD, self.fd, &mut event)
+        };
+        if result < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Locks the wakers.
+    //--------------------------------------------------------------------------
+    fn lock_wakers( &self ) -> io::Result<MutexGuard<'_, Wakers>>
+    {
+        self.wakers
+            .lock()
+            .map_err(|error| io::Error::other(error.to_string()))
+    }
 }
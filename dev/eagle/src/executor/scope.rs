@@ -0,0 +1,423 @@
+//------------------------------------------------------------------------------
+//! # Structured concurrency
+//!
+//! A `TaskGroup` owns its child tasks: joining it waits for all of them, and
+//! the first failure cancels the others and is returned to the parent.
+//!
+//! `Executor::scope` runs a group whose tasks may borrow from the caller. It
+//! blocks the calling thread until every task of the scope has completed and
+//! its future has been dropped, so the borrows cannot outlive the data, even
+//! when the scope panics.
+//------------------------------------------------------------------------------
+
+use super::join_handle::JoinError;
+use super::join_set::JoinSet;
+use super::task_queue::TaskQueue;
+
+use std::fmt;
+use std::future::{ poll_fn, Future };
+use std::marker::PhantomData;
+use std::sync::{ Mutex, PoisonError };
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # GroupError
+///
+/// - Failed: A task returned an error.
+/// - Join: A task panicked or was cancelled from outside of the group.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupError<E>
+{
+    Failed(E),
+    Join(JoinError),
+}
+
+impl<E: fmt::Display> fmt::Display for GroupError<E>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Failed(error) => write!(f, "task failed: {}", error),
+            Self::Join(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> std::error::Error for GroupError<E> {}
+
+
+//------------------------------------------------------------------------------
+/// # TaskGroup
+///
+/// Dropping the group without joining it aborts the tasks still running.
+//------------------------------------------------------------------------------
+pub struct TaskGroup<T, E>
+{
+    set: JoinSet<(usize, Result<T, E>)>,
+    outputs: Vec<Option<T>>,
+    error: Option<GroupError<E>>,
+}
+
+impl<T: Send, E: Send> TaskGroup<T, E>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new TaskGroup.
+    //--------------------------------------------------------------------------
+    pub fn new() -> Self
+    {
+        Self
+        {
+            set: JoinSet::new(),
+            outputs: Vec::new(),
+            error: None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Spawns a task in the group on the executor running the current task.
+    /// If the group has already failed, the task is cancelled right away.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of an executor.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub fn spawn<F>( &mut self, future: F )
+        where
+            F: Future<Output = Result<T, E>> + Send + 'static,
+            T: 'static,
+            E: 'static,
+    {
+        let index = self.outputs.len();
+        self.outputs.push(None);
+        let abort = self.set.spawn(async move { (index, future.await) });
+        if self.error.is_some()
+        {
+            abort.abort();
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Spawns a task in the group without requiring it to be `'static`.
+    ///
+    /// # Safety
+    ///
+    /// The caller must keep everything the future borrows alive until the
+    /// group has been joined.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    unsafe fn spawn_unchecked<F>( &mut self, queue: &TaskQueue, future: F )
+        where F: Future<Output = Result<T, E>> + Send
+    {
+        let index = self.outputs.len();
+        self.outputs.push(None);
+        let abort = self.set.spawn_unchecked(queue, async move
+        {
+            (index, future.await)
+        });
+        if self.error.is_some()
+        {
+            abort.abort();
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Cancels all the tasks of the group.
+    //--------------------------------------------------------------------------
+    pub fn cancel( &self )
+    {
+        self.set.abort_all();
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits for all the tasks and returns their outputs in the order they
+    /// were spawned.
+    ///
+    /// On the first failure, the other tasks are cancelled, and the error is
+    /// returned once all of them have stopped.
+    //--------------------------------------------------------------------------
+    pub async fn join( mut self ) -> Result<Vec<T>, GroupError<E>>
+    {
+        poll_fn(|cx| self.poll_join(cx)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for all the tasks to complete. See `join`.
+    //--------------------------------------------------------------------------
+    fn poll_join( &mut self, cx: &mut Context ) -> Poll<Result<Vec<T>, GroupError<E>>>
+    {
+        loop
+        {
+            let failure = match self.set.poll_join_next(cx)
+            {
+                Poll::Ready(Some(Ok((index, Ok(output))))) =>
+                {
+                    self.outputs[index] = Some(output);
+                    continue;
+                },
+                Poll::Ready(Some(Ok((_, Err(e))))) => GroupError::Failed(e),
+                Poll::Ready(Some(Err(e))) => GroupError::Join(e),
+                Poll::Ready(None) => break,
+                Poll::Pending => return Poll::Pending,
+            };
+            if self.error.is_none()
+            {
+                self.error = Some(failure);
+                self.set.abort_all();
+            }
+        }
+
+        let outputs = std::mem::take(&mut self.outputs);
+        match self.error.take()
+        {
+            Some(error) => Poll::Ready(Err(error)),
+            None => Poll::Ready(Ok(outputs.into_iter().flatten().collect())),
+        }
+    }
+}
+
+impl<T: Send, E: Send> Default for TaskGroup<T, E>
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl<T, E> fmt::Debug for TaskGroup<T, E>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("TaskGroup")
+            .field("set", &self.set)
+            .field("failed", &self.error.is_some())
+            .finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Scope
+///
+/// Task group passed to the closure of `Executor::scope`. Its tasks may
+/// borrow anything that outlives the scope.
+//------------------------------------------------------------------------------
+pub struct Scope<'scope, 'env: 'scope, T, E>
+{
+    group: Mutex<TaskGroup<T, E>>,
+    queue: TaskQueue,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+impl<'scope, T: Send, E: Send> Scope<'scope, '_, T, E>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new Scope.
+    //--------------------------------------------------------------------------
+    pub(super) fn new( queue: TaskQueue ) -> Self
+    {
+        Self
+        {
+            group: Mutex::new(TaskGroup::new()),
+            queue,
+            scope: PhantomData,
+            env: PhantomData,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Spawns a task in the scope.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub fn spawn<F>( &'scope self, future: F )
+        where F: Future<Output = Result<T, E>> + Send + 'scope
+    {
+        let mut group = self.group.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // The scope joins the group before returning, and the tasks of the
+        // scope can only spawn before they complete, so the borrows of the
+        // future are kept alive as long as the task runs.
+        unsafe { group.spawn_unchecked(&self.queue, future) };
+    }
+
+    //--------------------------------------------------------------------------
+    /// Cancels all the tasks of the scope.
+    //--------------------------------------------------------------------------
+    pub fn cancel( &self )
+    {
+        self.group.lock().unwrap_or_else(PoisonError::into_inner).cancel();
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for all the tasks of the scope to complete. The group stays in
+    /// the scope, so the tasks can still spawn in it while it is joined.
+    //--------------------------------------------------------------------------
+    pub(super) fn poll_join
+    (
+        &self,
+        cx: &mut Context,
+    ) -> Poll<Result<Vec<T>, GroupError<E>>>
+    {
+        self.group.lock().unwrap_or_else(PoisonError::into_inner).poll_join(cx)
+    }
+}
+
+impl<T, E> fmt::Debug for Scope<'_, '_, T, E>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Scope").finish_non_exhaustive()
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::{ Executor, Simulation };
+    use crate::sim;
+    use crate::sync::oneshot;
+
+    use std::future::pending;
+    use std::panic::{ self, AssertUnwindSafe };
+    use std::sync::atomic::{ AtomicUsize, Ordering };
+    use std::time::Duration;
+
+    #[test]
+    fn group_returns_outputs_in_spawn_order()
+    {
+        let result = Simulation::new(0).block_on(async
+        {
+            let mut group = TaskGroup::<u64, ()>::new();
+            for (id, delay) in [(1, 30), (2, 10), (3, 20)]
+            {
+                group.spawn(async move
+                {
+                    sim::sleep(Duration::from_millis(delay)).await;
+                    Ok(id)
+                });
+            }
+            group.join().await
+        });
+        assert_eq!(result.unwrap(), Ok(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn group_failure_cancels_the_other_tasks()
+    {
+        let result = Simulation::new(0).block_on(async
+        {
+            let (sender, receiver) = oneshot::channel::<()>();
+            let mut group = TaskGroup::<(), &str>::new();
+            group.spawn(async move
+            {
+                let _sender = sender;
+                pending().await
+            });
+            group.spawn(async
+            {
+                sim::sleep(Duration::from_millis(1)).await;
+                Err("failed")
+            });
+            let joined = group.join().await;
+
+            // The cancelled task has dropped its sender by the time the group
+            // returns.
+            (joined, receiver.await.is_err())
+        });
+        assert_eq!(result.unwrap(), (Err(GroupError::Failed("failed")), true));
+    }
+
+    #[test]
+    fn group_reports_a_panicking_task()
+    {
+        let result = Simulation::new(0).block_on(async
+        {
+            let mut group = TaskGroup::<(), ()>::new();
+            group.spawn(async { panic!("boom") });
+            group.join().await
+        });
+        match result.unwrap()
+        {
+            Err(GroupError::Join(error)) => assert!(error.is_panic()),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn scope_tasks_borrow_from_the_caller()
+    {
+        let mut executor = Executor::new(2);
+        executor.start();
+
+        let counter = AtomicUsize::new(0);
+        let names = vec!["a", "bb", "ccc"];
+        let lengths = executor.scope::<_, (), _>(|scope|
+        {
+            for name in &names
+            {
+                let counter = &counter;
+                scope.spawn(async move
+                {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    Ok(name.len())
+                });
+            }
+        });
+        assert_eq!(lengths, Ok(vec![1, 2, 3]));
+        assert_eq!(counter.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn scope_cancel_stops_pending_tasks()
+    {
+        let mut executor = Executor::new(2);
+        executor.start();
+
+        let result = executor.scope::<(), (), _>(|scope|
+        {
+            scope.spawn(pending());
+            scope.spawn(pending());
+            scope.cancel();
+        });
+        assert_eq!(result, Err(GroupError::Join(JoinError::Cancelled)));
+    }
+
+    #[test]
+    fn panic_in_scope_resumes_after_the_tasks_stop()
+    {
+        let mut executor = Executor::new(2);
+        executor.start();
+
+        let stopped = AtomicUsize::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(||
+        {
+            executor.scope::<(), (), _>(|scope|
+            {
+                struct Stop<'a>(&'a AtomicUsize);
+                impl Drop for Stop<'_>
+                {
+                    fn drop( &mut self )
+                    {
+                        self.0.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+
+                let stop = Stop(&stopped);
+                scope.spawn(async move
+                {
+                    let _stop = stop;
+                    pending().await
+                });
+                panic!("scope failed");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(stopped.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,290 @@
+//------------------------------------------------------------------------------
+//! # Simulation runtime
+//!
+//! Runs the tasks on the calling thread, picking the next one to poll at
+//! random among the ready ones. The woken tasks are sorted by id before the
+//! pick, so the order they were woken in does not matter, and the seed alone
+//! decides the interleaving. When no task is ready, the virtual clock jumps
+//! to the next timer; when there is no timer either, the simulation is
+//! deadlocked.
+//------------------------------------------------------------------------------
+
+use super::builder::TaskBuilder;
+use super::context;
+use super::dump::TaskDump;
+use super::executor::park_on;
+use super::join_handle::JoinError;
+use super::task::Task;
+use super::task_queue::{ TaskQueue, DEFAULT_QUEUE_CAPACITY };
+use super::worker::poll_task;
+use crate::sim::{ self, World };
+
+use std::fmt;
+use std::future::Future;
+use std::sync::{ Arc, Mutex, MutexGuard, PoisonError };
+use std::time::Duration;
+
+
+//------------------------------------------------------------------------------
+/// # SimError
+///
+/// - Deadlock: The main future is pending, but no task is ready and no timer
+///   is left to wake one. Holds the dump of the live tasks.
+/// - Join: The main future panicked or was cancelled.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimError
+{
+    Deadlock(TaskDump),
+    Join(JoinError),
+}
+
+impl fmt::Display for SimError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Deadlock(dump) => write!(f, "simulation deadlocked, {}", dump),
+            Self::Join(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SimError {}
+
+impl From<JoinError> for SimError
+{
+    fn from( error: JoinError ) -> Self
+    {
+        Self::Join(error)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Simulation
+///
+/// Deterministic runtime for tests. See the `sim` module.
+//------------------------------------------------------------------------------
+pub struct Simulation
+{
+    seed: u64,
+    queue: TaskQueue,
+    ready: Vec<Task>,
+    world: Arc<Mutex<World>>,
+}
+
+impl Simulation
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new Simulation. The network latency defaults to a random
+    /// value between zero and one millisecond.
+    //--------------------------------------------------------------------------
+    pub fn new( seed: u64 ) -> Self
+    {
+        Self
+        {
+            seed,
+            queue: TaskQueue::new(DEFAULT_QUEUE_CAPACITY),
+            ready: Vec::new(),
+            world: Arc::new(Mutex::new(World::new(seed))),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the range of the latency of the simulated network.
+    //--------------------------------------------------------------------------
+    pub fn latency( &mut self, min: Duration, max: Duration ) -> &mut Self
+    {
+        self.world().set_latency(min, max);
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the seed of the simulation.
+    //--------------------------------------------------------------------------
+    pub fn seed( &self ) -> u64
+    {
+        self.seed
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the virtual time elapsed since the start of the simulation.
+    //--------------------------------------------------------------------------
+    pub fn elapsed( &self ) -> Duration
+    {
+        self.world().now()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a dump of the live tasks.
+    //--------------------------------------------------------------------------
+    pub fn task_dump( &self ) -> TaskDump
+    {
+        self.queue.task_registry().dump()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Runs the future as a task of the simulation, along with the tasks it
+    /// spawns, until it completes. The tasks still pending afterwards resume
+    /// on the next call.
+    //--------------------------------------------------------------------------
+    #[track_caller]
+    pub fn block_on<F>( &mut self, future: F ) -> Result<F::Output, SimError>
+        where
+            F: Future + Send + 'static,
+            F::Output: Send + 'static,
+    {
+        let _context = context::enter(self.queue.clone());
+        let _world = sim::enter(self.world.clone());
+        let handle = TaskBuilder::new().spawn_joinable(&self.queue, future);
+
+        while !handle.is_finished()
+        {
+            while let Some(task) = self.queue.pop()
+            {
+                self.ready.push(task);
+            }
+
+            if self.ready.is_empty()
+            {
+                let wakers = self.world().advance();
+                if wakers.is_empty()
+                {
+                    return Err(SimError::Deadlock(self.task_dump()));
+                }
+                wakers.into_iter().for_each(|waker| waker.wake());
+                continue;
+            }
+
+            self.ready.sort_by_key(Task::id);
+            let index = self.world().random(self.ready.len());
+            let task = self.ready.swap_remove(index);
+            poll_task(&self.queue, &task);
+        }
+        Ok(park_on(handle)?)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Locks the world.
+    //--------------------------------------------------------------------------
+    fn world( &self ) -> MutexGuard<'_, World>
+    {
+        self.world.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Simulation
+{
+    fn drop( &mut self )
+    {
+        // The tasks, timers and listeners are dropped with the world unlocked,
+        // as dropping a future may access it.
+        let _world = sim::enter(self.world.clone());
+        let cleared = self.world().clear();
+        drop(cleared);
+        for task in self.ready.drain(..)
+        {
+            let _ = task.cancel();
+        }
+        self.queue.clear();
+    }
+}
+
+impl fmt::Debug for Simulation
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Simulation")
+            .field("seed", &self.seed)
+            .field("elapsed", &self.elapsed())
+            .finish_non_exhaustive()
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::{ spawn, yield_now };
+
+    //--------------------------------------------------------------------------
+    /// Runs a few tasks yielding between steps, and returns the order the
+    /// steps ran in.
+    //--------------------------------------------------------------------------
+    fn interleaving( seed: u64 ) -> Vec<(usize, usize)>
+    {
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let steps = order.clone();
+        Simulation::new(seed).block_on(async move
+        {
+            let handles: Vec<_> = (0..8).map(|id|
+            {
+                let steps = steps.clone();
+                spawn(async move
+                {
+                    for step in 0..4
+                    {
+                        steps.lock().unwrap().push((id, step));
+                        yield_now().await;
+                    }
+                })
+            }).collect();
+            for handle in handles
+            {
+                handle.await.unwrap();
+            }
+        }).unwrap();
+        let order = order.lock().unwrap().clone();
+        order
+    }
+
+    #[test]
+    fn same_seed_replays_the_same_interleaving()
+    {
+        for seed in 0..16
+        {
+            let order = interleaving(seed);
+            assert_eq!(order.len(), 32);
+            assert_eq!(order, interleaving(seed), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn different_seeds_give_different_interleavings()
+    {
+        let orders: Vec<_> = (0..4).map(interleaving).collect();
+        for (i, order) in orders.iter().enumerate()
+        {
+            for other in &orders[i + 1..]
+            {
+                assert_ne!(order, other);
+            }
+        }
+    }
+
+    #[test]
+    fn idle_tasks_jump_to_the_next_timer()
+    {
+        let mut simulation = Simulation::new(0);
+        let now = simulation.block_on(async
+        {
+            let timer = spawn(sim::sleep(Duration::from_secs(3600)));
+            sim::sleep(Duration::from_secs(60)).await;
+            let first = sim::now();
+            timer.await.unwrap();
+            (first, sim::now())
+        }).unwrap();
+        assert_eq!(now, (Duration::from_secs(60), Duration::from_secs(3600)));
+        assert_eq!(simulation.elapsed(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn pending_forever_is_a_deadlock()
+    {
+        let result = Simulation::new(0).block_on(std::future::pending::<()>());
+        assert!(matches!(result, Err(SimError::Deadlock(_))));
+    }
+}
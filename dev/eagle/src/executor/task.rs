@@ -4,12 +4,20 @@
 //! This is the structure of the task handled by the async executor.
 //------------------------------------------------------------------------------
 
+use super::context;
+use super::dump::TaskSnapshot;
+use super::task_queue::TaskQueue;
+
+use std::fmt;
 use std::future::Future;
+use std::panic::{ self, AssertUnwindSafe, Location };
 use std::pin::Pin;
 use std::task::{ Context, Poll };
-use std::sync::{ Arc, Mutex, PoisonError };
+use std::sync::{ Arc, Mutex, PoisonError, TryLockError };
+use std::sync::atomic::{ AtomicBool, AtomicU64, Ordering };
+use std::time::{ Duration, Instant };
 
-type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
+pub(super) type BoxFuture = Pin<Box<dyn Future<Output = ()> + Send + 'static>>;
 
 
 //------------------------------------------------------------------------------
@@ -19,8 +27,23 @@ type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send + 'static>>;
 pub(crate) enum TaskError
 {
     PoisonError(String),
+    Panicked(String),
 }
 
+impl fmt::Display for TaskError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::PoisonError(error) => write!(f, "poison error: {}", error),
+            Self::Panicked(message) => write!(f, "task panicked: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for TaskError {}
+
 impl<E> From<PoisonError<E>> for TaskError
 {
     fn from( error: PoisonError<E> ) -> Self
@@ -35,99 +58,365 @@ impl<E> From<PoisonError<E>> for TaskError
 ///
 /// - Ready: The task is ready to be polled.
 /// - Running: The task is currently running.
+/// - Waiting: The task is waiting to be woken.
 /// - Done: The task has completed.
 //------------------------------------------------------------------------------
-#[derive(Clone)]
-pub(crate) enum TaskState
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TaskState
 {
     Ready,
     Running,
+    Waiting,
     Done,
 }
 
+impl fmt::Display for TaskState
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Ready => write!(f, "ready"),
+            Self::Running => write!(f, "running"),
+            Self::Waiting => write!(f, "waiting"),
+            Self::Done => write!(f, "done"),
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # TaskHeader
+///
+/// The part of a task that outlives its queue entries and is listed by the
+/// task dump.
+//------------------------------------------------------------------------------
+pub(super) struct TaskHeader
+{
+    id: u64,
+    name: Option<String>,
+    location: &'static Location<'static>,
+    spawned: Instant,
+
+    /// Nanoseconds from the spawn to the start of the last poll, plus one, so
+    /// that zero means the task was never polled.
+    last_poll: AtomicU64,
+
+    /// How long the last completed poll and the longest one took, in
+    /// nanoseconds.
+    last_poll_duration: AtomicU64,
+    max_poll_duration: AtomicU64,
+    state: Mutex<TaskState>,
+}
+
+impl TaskHeader
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new TaskHeader.
+    //--------------------------------------------------------------------------
+    pub(super) fn new
+    (
+        id: u64,
+        name: Option<String>,
+        location: &'static Location<'static>,
+    ) -> Self
+    {
+        Self
+        {
+            id,
+            name,
+            location,
+            spawned: Instant::now(),
+            last_poll: AtomicU64::new(0),
+            last_poll_duration: AtomicU64::new(0),
+            max_poll_duration: AtomicU64::new(0),
+            state: Mutex::new(TaskState::Ready),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the id of the task.
+    //--------------------------------------------------------------------------
+    pub(super) fn id( &self ) -> u64
+    {
+        self.id
+    }
+
+    //--------------------------------------------------------------------------
+    /// Records the start of a poll.
+    //--------------------------------------------------------------------------
+    fn record_poll( &self )
+    {
+        let nanos = u64::try_from(self.spawned.elapsed().as_nanos()).unwrap_or(u64::MAX);
+        self.last_poll.store(nanos.saturating_add(1), Ordering::Relaxed);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Records how long a poll took.
+    //--------------------------------------------------------------------------
+    fn record_poll_duration( &self, duration: Duration )
+    {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.last_poll_duration.store(nanos, Ordering::Relaxed);
+        self.max_poll_duration.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a snapshot of the task. The state is locked while the task is
+    /// polled, so a task whose state is locked is reported as running.
+    //--------------------------------------------------------------------------
+    pub(super) fn snapshot( &self, now: Instant ) -> TaskSnapshot
+    {
+        let state = match self.state.try_lock()
+        {
+            Ok(state) => *state,
+            Err(TryLockError::WouldBlock) => TaskState::Running,
+            Err(TryLockError::Poisoned(error)) => *error.into_inner(),
+        };
+        let age = now.saturating_duration_since(self.spawned);
+        let last_poll = match self.last_poll.load(Ordering::Relaxed)
+        {
+            0 => None,
+            nanos => Some(age.saturating_sub(Duration::from_nanos(nanos - 1))),
+        };
+
+        TaskSnapshot
+        {
+            id: self.id,
+            name: self.name.clone(),
+            state,
+            location: self.location,
+            age,
+            last_poll,
+            last_poll_duration: Duration::from_nanos
+            (
+                self.last_poll_duration.load(Ordering::Relaxed)
+            ),
+            max_poll_duration: Duration::from_nanos
+            (
+                self.max_poll_duration.load(Ordering::Relaxed)
+            ),
+        }
+    }
+}
+
 
 //------------------------------------------------------------------------------
 /// # Task
+///
+/// The output of the future is sent through the `JoinHandle`, so the task
+/// itself only drives a future returning `()`.
 //------------------------------------------------------------------------------
 #[derive(Clone)]
-pub(crate) struct Task<T>
+pub(crate) struct Task
 {
-    future: Arc<Mutex<BoxFuture<T>>>,
-    state: Arc<Mutex<TaskState>>,
+    future: Arc<Mutex<Option<BoxFuture>>>,
+    header: Arc<TaskHeader>,
     priority: usize,
+    deadline: Option<Instant>,
+    scheduled: Arc<AtomicBool>,
 }
 
-impl<T: Send + Clone + 'static> Task<T>
+impl Task
 {
     //--------------------------------------------------------------------------
-    /// Creates a new Task.
+    /// Creates a new Task from a boxed future.
     //--------------------------------------------------------------------------
-    pub(super) fn new<F>( future: F ) -> Self
-        where F: Future<Output = T> + Send + 'static
+    pub(super) fn from_boxed
+    (
+        header: Arc<TaskHeader>,
+        future: BoxFuture,
+        priority: usize,
+    ) -> Self
     {
-        Self::with_priority(future, 0)
+        Self
+        {
+            future: Arc::new(Mutex::new(Some(future))),
+            header,
+            priority,
+            deadline: None,
+            scheduled: Arc::new(AtomicBool::new(false)),
+        }
     }
 
     //--------------------------------------------------------------------------
-    /// Creates a new Task with a priority.
+    /// Sets the deadline of the task.
     //--------------------------------------------------------------------------
-    pub(super) fn with_priority<F>( future: F, priority: usize ) -> Self
-        where F: Future<Output = T> + Send + 'static
+    pub(super) fn with_deadline( mut self, deadline: Option<Instant> ) -> Self
     {
-        Self
+        self.deadline = deadline;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the id of the task.
+    //--------------------------------------------------------------------------
+    pub(super) fn id( &self ) -> u64
+    {
+        self.header.id
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the name of the task.
+    //--------------------------------------------------------------------------
+    pub(super) fn name( &self ) -> Option<&str>
+    {
+        self.header.name.as_deref()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the priority of the task.
+    //--------------------------------------------------------------------------
+    pub(super) fn priority( &self ) -> usize
+    {
+        self.priority
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the deadline of the task.
+    //--------------------------------------------------------------------------
+    pub(super) fn deadline( &self ) -> Option<Instant>
+    {
+        self.deadline
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the deadline of the task has passed.
+    //--------------------------------------------------------------------------
+    pub(super) fn is_expired( &self, now: Instant ) -> bool
+    {
+        self.deadline.is_some_and(|deadline| deadline <= now)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Drops the future without completing it, so that its JoinHandle reports
+    /// the task as cancelled.
+    //--------------------------------------------------------------------------
+    pub(super) fn cancel( &self ) -> Result<(), TaskError>
+    {
+        let mut state = self.header.state.lock()?;
+        let future = self.future.lock()?.take();
+        *state = TaskState::Done;
+        drop(state);
+
+        // Dropping the future may wake other tasks, so no lock is held.
+        drop(future);
+        Ok(())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pushes the newly spawned task onto the queue. It is cancelled instead
+    /// if the queue is full and rejects the spawned tasks.
+    //--------------------------------------------------------------------------
+    pub(super) fn spawn( &self, queue: &TaskQueue )
+    {
+        self.scheduled.store(true, Ordering::Release);
+        queue.push_spawned(self.clone());
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pushes the task onto the queue unless it is already queued.
+    //--------------------------------------------------------------------------
+    pub(super) fn schedule( &self, queue: &TaskQueue )
+    {
+        if self.scheduled.swap(true, Ordering::AcqRel)
         {
-            future: Arc::new(Mutex::new(Box::pin(future))),
-            state: Arc::new(Mutex::new(TaskState::Ready)),
-            priority,
+            return;
         }
+        if let Ok(mut state) = self.header.state.try_lock()
+        {
+            if let TaskState::Waiting = *state
+            {
+                *state = TaskState::Ready;
+            }
+        }
+        queue.push(self.clone());
     }
 
     //--------------------------------------------------------------------------
     /// Polls the task.
+    ///
+    /// A panic in the future is caught and completes the task, so that it
+    /// does not take the worker thread down with it.
     //--------------------------------------------------------------------------
     pub(super) fn poll
     (
-        &mut self,
+        &self,
         context: &mut Context,
-    ) -> Result<Poll<T>, TaskError>
+    ) -> Result<Poll<()>, TaskError>
     {
-        let mut state = self.state.lock()?;
+        let mut state = self.header.state.lock()?;
+        if let TaskState::Done = *state
+        {
+            // A task can be woken more than once before it is polled, so the
+            // finished future must not be polled again.
+            return Ok(Poll::Pending);
+        }
         *state = TaskState::Running;
+        self.header.record_poll();
+        let started = Instant::now();
+
+        // Wakes from now on must queue the task again.
+        self.scheduled.store(false, Ordering::Release);
+
         let mut future = self.future.lock()?;
-        match future.as_mut().poll(context)
+        let result = match future.as_mut()
+        {
+            Some(future) =>
+            {
+                panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(context)))
+            },
+            None => Ok(Poll::Ready(())),
+        };
+        self.header.record_poll_duration(started.elapsed());
+        match result
         {
-            Poll::Ready(result) =>
+            Ok(Poll::Ready(())) =>
             {
+                *future = None;
                 *state = TaskState::Done;
-                Ok(Poll::Ready(result))
+                Ok(Poll::Ready(()))
+            },
+            Ok(Poll::Pending) =>
+            {
+                *state = TaskState::Waiting;
+                Ok(Poll::Pending)
+            },
+            Err(payload) =>
+            {
+                *future = None;
+                *state = TaskState::Done;
+                Err(TaskError::Panicked(panic_message(payload.as_ref())))
             },
-            Poll::Pending => Ok(Poll::Pending)
         }
     }
 }
 
-impl<T> PartialEq for Task<T>
+
+//------------------------------------------------------------------------------
+/// # panic_message
+///
+/// Extracts the message of a panic payload, naming the task being polled if
+/// it has a name.
+//------------------------------------------------------------------------------
+pub(super) fn panic_message( payload: &(dyn std::any::Any + Send) ) -> String
 {
-    fn eq( &self, other: &Self ) -> bool
+    let message = if let Some(message) = payload.downcast_ref::<&str>()
     {
-        self.priority == other.priority
+        message.to_string()
     }
-}
-
-impl<T> Eq for Task<T> {}
-
-impl<T> PartialOrd for Task<T>
-{
-    fn partial_cmp( &self, other: &Self ) -> Option<std::cmp::Ordering>
+    else if let Some(message) = payload.downcast_ref::<String>()
     {
-        Some(self.cmp(other))
+        message.clone()
     }
-}
+    else
+    {
+        "unknown panic".to_string()
+    };
 
-impl<T> Ord for Task<T>
-{
-    fn cmp( &self, other: &Self ) -> std::cmp::Ordering
+    match context::current_task_name()
     {
-        self.priority.cmp(&other.priority)
+        Some(name) => format!("{} (in task \"{}\")", message, name),
+        None => message,
     }
 }
@@ -0,0 +1,329 @@
+//------------------------------------------------------------------------------
+//! # Task-local storage
+//!
+//! A task-local value is set for the duration of a future with
+//! `LocalKey::scope`. It is moved into a thread-local slot each time the
+//! future is polled and moved back out afterwards, so it follows the task
+//! across `.await` points and workers.
+//!
+//! Values set with `LocalKey::scope_inherited` are also cloned into the tasks
+//! spawned from the scope.
+//------------------------------------------------------------------------------
+
+use super::task::BoxFuture;
+
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+use std::thread::LocalKey as ThreadLocalKey;
+
+thread_local!
+{
+    static INHERITED: RefCell<Vec<&'static dyn Inherit>> = const
+    {
+        RefCell::new(Vec::new())
+    };
+}
+
+
+//------------------------------------------------------------------------------
+/// # AccessError
+///
+/// The task-local value is not set in the current task.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccessError(());
+
+impl fmt::Display for AccessError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "task-local value not set")
+    }
+}
+
+impl std::error::Error for AccessError {}
+
+
+//------------------------------------------------------------------------------
+/// # LocalKey
+///
+/// Key of a task-local value, declared with `task_local!`.
+//------------------------------------------------------------------------------
+pub struct LocalKey<T: 'static>
+{
+    #[doc(hidden)]
+    pub inner: ThreadLocalKey<RefCell<Option<T>>>,
+}
+
+impl<T: 'static> LocalKey<T>
+{
+    //--------------------------------------------------------------------------
+    /// Sets the value for the duration of the future.
+    //--------------------------------------------------------------------------
+    pub fn scope<F: Future>( &'static self, value: T, future: F ) -> TaskLocalFuture<T, F>
+    {
+        TaskLocalFuture
+        {
+            key: self,
+            slot: Some(value),
+            future: Some(future),
+            inherit: None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the value for the duration of the future, and for the tasks
+    /// spawned from it, which get a clone of the value current when they are
+    /// spawned.
+    //--------------------------------------------------------------------------
+    pub fn scope_inherited<F: Future>
+    (
+        &'static self,
+        value: T,
+        future: F,
+    ) -> TaskLocalFuture<T, F>
+        where T: Clone + Send
+    {
+        TaskLocalFuture
+        {
+            key: self,
+            slot: Some(value),
+            future: Some(future),
+            inherit: Some(self),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Accesses the value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value is not set in the current task.
+    //--------------------------------------------------------------------------
+    pub fn with<F, R>( &'static self, f: F ) -> R
+        where F: FnOnce(&T) -> R
+    {
+        match self.try_with(f)
+        {
+            Ok(result) => result,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Accesses the value, failing if it is not set in the current task.
+    //--------------------------------------------------------------------------
+    pub fn try_with<F, R>( &'static self, f: F ) -> Result<R, AccessError>
+        where F: FnOnce(&T) -> R
+    {
+        self.inner.with(|slot|
+        {
+            match slot.borrow().as_ref()
+            {
+                Some(value) => Ok(f(value)),
+                None => Err(AccessError(())),
+            }
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Swaps the value of the current thread with the one of the slot.
+    //--------------------------------------------------------------------------
+    fn swap( &'static self, slot: &mut Option<T> )
+    {
+        self.inner.with(|current| mem::swap(&mut *current.borrow_mut(), slot));
+    }
+}
+
+impl<T: 'static> fmt::Debug for LocalKey<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("LocalKey").finish_non_exhaustive()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # TaskLocalFuture
+///
+/// Future returned by `LocalKey::scope`. The inner future is polled and
+/// dropped with the value set.
+//------------------------------------------------------------------------------
+pub struct TaskLocalFuture<T: 'static, F>
+{
+    key: &'static LocalKey<T>,
+    slot: Option<T>,
+    future: Option<F>,
+    inherit: Option<&'static dyn Inherit>,
+}
+
+impl<T: 'static, F> TaskLocalFuture<T, F>
+{
+    //--------------------------------------------------------------------------
+    /// Runs the closure with the value set.
+    //--------------------------------------------------------------------------
+    fn enter<R>( &mut self, f: impl FnOnce(&mut Option<F>) -> R ) -> R
+    {
+        //----------------------------------------------------------------------
+        /// Moves the value back into the slot, even if the closure panics.
+        //----------------------------------------------------------------------
+        struct Guard<'a, T: 'static>
+        {
+            key: &'static LocalKey<T>,
+            slot: &'a mut Option<T>,
+            inherit: bool,
+        }
+
+        impl<T: 'static> Drop for Guard<'_, T>
+        {
+            fn drop( &mut self )
+            {
+                self.key.swap(self.slot);
+                if self.inherit
+                {
+                    INHERITED.with(|inherited| inherited.borrow_mut().pop());
+                }
+            }
+        }
+
+        self.key.swap(&mut self.slot);
+        if let Some(key) = self.inherit
+        {
+            INHERITED.with(|inherited| inherited.borrow_mut().push(key));
+        }
+        let _guard = Guard
+        {
+            key: self.key,
+            slot: &mut self.slot,
+            inherit: self.inherit.is_some(),
+        };
+        f(&mut self.future)
+    }
+}
+
+impl<T: 'static, F: Future> Future for TaskLocalFuture<T, F>
+{
+    type Output = F::Output;
+
+    fn poll( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = unsafe { self.get_unchecked_mut() };
+        this.enter(|future|
+        {
+            match future
+            {
+                Some(future) => unsafe { Pin::new_unchecked(future) }.poll(cx),
+                None => panic!("TaskLocalFuture polled after completion"),
+            }
+        })
+    }
+}
+
+impl<T: 'static, F> Drop for TaskLocalFuture<T, F>
+{
+    fn drop( &mut self )
+    {
+        if self.future.is_some()
+        {
+            // The future is structurally pinned, so it is dropped in place.
+            self.enter(|future| unsafe { Pin::new_unchecked(future) }.set(None));
+        }
+    }
+}
+
+impl<T: 'static, F> fmt::Debug for TaskLocalFuture<T, F>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("TaskLocalFuture")
+            .field("inherit", &self.inherit.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Inherit
+///
+/// Task-local key whose value is passed down to spawned tasks.
+//------------------------------------------------------------------------------
+trait Inherit: Sync
+{
+    //--------------------------------------------------------------------------
+    /// Wraps the future in a scope with a clone of the current value.
+    //--------------------------------------------------------------------------
+    fn inherit( &'static self, future: BoxFuture ) -> BoxFuture;
+}
+
+impl<T: Clone + Send + 'static> Inherit for LocalKey<T>
+{
+    fn inherit( &'static self, future: BoxFuture ) -> BoxFuture
+    {
+        match self.try_with(T::clone)
+        {
+            Ok(value) => Box::pin(self.scope_inherited(value, future)),
+            Err(_) => future,
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # inherit
+///
+/// Wraps a future being spawned in the inherited scopes of the current task.
+//------------------------------------------------------------------------------
+pub(super) fn inherit( future: BoxFuture ) -> BoxFuture
+{
+    let keys = INHERITED.with(|inherited| inherited.borrow().clone());
+    keys.into_iter().fold(future, |future, key| key.inherit(future))
+}
+
+
+//------------------------------------------------------------------------------
+/// # task_local!
+///
+/// Declares task-local keys.
+///
+/// ```
+/// eagle::task_local!
+/// {
+///     static REQUEST_ID: u64;
+/// }
+///
+/// # eagle::executor::Executor::new(0).block_on(async {
+/// REQUEST_ID.scope(42, async
+/// {
+///     assert_eq!(REQUEST_ID.with(|id| *id), 42);
+/// }).await;
+/// # }).unwrap();
+/// ```
+//------------------------------------------------------------------------------
+#[macro_export]
+macro_rules! task_local
+{
+    () => {};
+
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty; $($rest:tt)*) =>
+    {
+        $(#[$attr])*
+        $vis static $name: $crate::executor::LocalKey<$t> =
+        {
+            ::std::thread_local!
+            {
+                static KEY: ::std::cell::RefCell<Option<$t>> = const
+                {
+                    ::std::cell::RefCell::new(None)
+                };
+            }
+            $crate::executor::LocalKey { inner: KEY }
+        };
+
+        $crate::task_local!($($rest)*);
+    };
+}
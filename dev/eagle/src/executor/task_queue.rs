@@ -1,27 +1,300 @@
 //------------------------------------------------------------------------------
-/// # Task queue
+//! # Task queue
+//!
+//! Most tasks have neither a priority nor a deadline. They go through the
+//! injector, a bounded lock-free ring, so that spawning and waking them takes
+//! no lock. When the ring is full, the overflow policy either spills them
+//! into the ordered levels below, or rejects the newly spawned ones.
+//!
+//! The other tasks are kept in one FIFO queue per priority level behind a
+//! mutex, and the highest level is served first. To keep low priority tasks
+//! from starving, a task waiting at the head of its level for longer than the
+//! aging interval is promoted to the next level. The ordered levels are
+//! served before the injector, unless the injector has not been served for
+//! the aging interval either.
+//!
+//! With the earliest-deadline-first policy, tasks carrying a deadline are
+//! served before the priority levels, earliest deadline first. Tasks whose
+//! deadline has already passed can be shed instead of being polled.
 //------------------------------------------------------------------------------
 
+use super::dump::TaskRegistry;
+use super::injector::Injector;
+use super::metrics::TaskCounters;
 use super::task::Task;
 
-use std::collections::BinaryHeap;
-use std::sync::{ Arc, RwLock, PoisonError };
+use std::collections::{ BTreeMap, VecDeque };
+use std::sync::{ Arc, Condvar, Mutex, MutexGuard, PoisonError };
+use std::sync::atomic::{ fence, AtomicBool, AtomicU64, AtomicUsize, Ordering };
+use std::time::{ Duration, Instant };
 
+const DEFAULT_AGING_INTERVAL: Duration = Duration::from_millis(100);
 
+/// Default capacity of the injection queue.
+pub(super) const DEFAULT_QUEUE_CAPACITY: usize = 4096;
+
+
+//------------------------------------------------------------------------------
+/// # SchedulingPolicy
+///
+/// - Priority: Tasks are served by priority only.
+/// - EarliestDeadlineFirst: Tasks with a deadline are served first, earliest
+///   deadline first, and the other tasks by priority.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SchedulingPolicy
+{
+    #[default]
+    Priority,
+    EarliestDeadlineFirst,
+}
+
+
+//------------------------------------------------------------------------------
+/// # OverflowPolicy
+///
+/// What happens to a task scheduled while the injection queue is full.
+///
+/// - Spill: The task is queued with the tasks that have a priority or a
+///   deadline, behind a lock, and may run before the tasks already in the
+///   injection queue.
+/// - Reject: A spawned task is cancelled, and its JoinHandle reports it as
+///   cancelled. A woken task is still spilled, as it may be running.
 //------------------------------------------------------------------------------
-/// # TaskQueueError
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy
+{
+    #[default]
+    Spill,
+    Reject,
+}
+
+
+//------------------------------------------------------------------------------
+/// # DeadlineStats
+///
+/// - met: Tasks completed before their deadline.
+/// - missed: Tasks completed after their deadline.
+/// - shed: Tasks dropped because their deadline passed before they ran.
 //------------------------------------------------------------------------------
-#[derive(Debug)]
-pub(crate) enum TaskQueueError
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeadlineStats
 {
-    PoisonError(String),
+    pub met: u64,
+    pub missed: u64,
+    pub shed: u64,
 }
 
-impl<E> From<PoisonError<E>> for TaskQueueError
+
+//------------------------------------------------------------------------------
+/// # DeadlineCounters
+//------------------------------------------------------------------------------
+#[derive(Default)]
+struct DeadlineCounters
 {
-    fn from( error: PoisonError<E> ) -> Self
+    met: AtomicU64,
+    missed: AtomicU64,
+    shed: AtomicU64,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Entry
+//------------------------------------------------------------------------------
+struct Entry
+{
+    task: Task,
+    since: Instant,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Levels
+//------------------------------------------------------------------------------
+struct Levels
+{
+    queues: BTreeMap<usize, VecDeque<Entry>>,
+    deadlines: BTreeMap<(Instant, u64), Task>,
+    sequence: u64,
+    len: usize,
+    aging_interval: Option<Duration>,
+    policy: SchedulingPolicy,
+    shed_expired: bool,
+}
+
+impl Levels
+{
+    //--------------------------------------------------------------------------
+    /// Inserts a task where the policy serves it from.
+    //--------------------------------------------------------------------------
+    fn insert( &mut self, task: Task, since: Instant )
+    {
+        match (self.policy, task.deadline())
+        {
+            (SchedulingPolicy::EarliestDeadlineFirst, Some(deadline)) =>
+            {
+                // The sequence keeps tasks with the same deadline in FIFO
+                // order.
+                self.sequence += 1;
+                self.deadlines.insert((deadline, self.sequence), task);
+            },
+            _ =>
+            {
+                let entry = Entry { task, since };
+                self.queues
+                    .entry(entry.task.priority())
+                    .or_default()
+                    .push_back(entry);
+            },
+        }
+        self.len += 1;
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the policy, moving the queued tasks to where it serves them from.
+    //--------------------------------------------------------------------------
+    fn set_policy( &mut self, policy: SchedulingPolicy )
     {
-        Self::PoisonError(format!("{:?}", error))
+        self.policy = policy;
+
+        let now = Instant::now();
+        let deadlines = std::mem::take(&mut self.deadlines);
+        let queues = std::mem::take(&mut self.queues);
+        self.len = 0;
+        for task in deadlines.into_values()
+        {
+            self.insert(task, now);
+        }
+        for entry in queues.into_values().flatten()
+        {
+            self.insert(entry.task, entry.since);
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Promotes the tasks that have waited too long at the head of a level
+    /// below the highest one.
+    //--------------------------------------------------------------------------
+    fn age( &mut self, now: Instant )
+    {
+        let interval = match self.aging_interval
+        {
+            Some(interval) => interval,
+            None => return,
+        };
+        let highest = match self.queues.keys().next_back()
+        {
+            Some(highest) => *highest,
+            None => return,
+        };
+
+        let levels: Vec<usize> = self.queues.keys().copied().collect();
+        for level in levels.into_iter().filter(|level| *level < highest)
+        {
+            let mut promoted = Vec::new();
+            if let Some(queue) = self.queues.get_mut(&level)
+            {
+                while queue.front().is_some_and(|e| now - e.since >= interval)
+                {
+                    if let Some(mut entry) = queue.pop_front()
+                    {
+                        entry.since = now;
+                        promoted.push(entry);
+                    }
+                }
+                if queue.is_empty()
+                {
+                    self.queues.remove(&level);
+                }
+            }
+            if !promoted.is_empty()
+            {
+                self.queues.entry(level + 1).or_default().extend(promoted);
+            }
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pops the next task to run at the given instant. The expired tasks found
+    /// on the way are pushed onto `shed` when shedding is enabled.
+    //--------------------------------------------------------------------------
+    fn pop( &mut self, shed: &mut Vec<Task>, now: Instant ) -> Option<Task>
+    {
+        self.age(now);
+
+        while let Some(task) = self.pop_next()
+        {
+            if self.shed_expired && task.is_expired(now)
+            {
+                shed.push(task);
+                continue;
+            }
+            return Some(task);
+        }
+        None
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pops the earliest deadline, or else the first task of the highest
+    /// level.
+    //--------------------------------------------------------------------------
+    fn pop_next( &mut self ) -> Option<Task>
+    {
+        if let Some((_, task)) = self.deadlines.pop_first()
+        {
+            self.len -= 1;
+            return Some(task);
+        }
+
+        let mut last = self.queues.last_entry()?;
+        let entry = last.get_mut().pop_front();
+        if last.get().is_empty()
+        {
+            last.remove();
+        }
+        if entry.is_some()
+        {
+            self.len -= 1;
+        }
+        entry.map(|entry| entry.task)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # QueueState
+///
+/// - ordered: Number of tasks in the levels, so that popping does not lock
+///   them when they are empty.
+/// - reject: Whether the overflow policy is `Reject`.
+/// - rejected: Spawned tasks cancelled because the injector was full.
+/// - aging: Aging interval in nanoseconds, or `u64::MAX` when disabled.
+/// - injector_served: When the injector was last popped or found empty, in
+///   nanoseconds since `epoch`.
+/// - sleepers: Workers waiting on `available`.
+//------------------------------------------------------------------------------
+struct QueueState
+{
+    ordered: AtomicUsize,
+    reject: AtomicBool,
+    rejected: AtomicU64,
+    aging: AtomicU64,
+    injector_served: AtomicU64,
+    epoch: Instant,
+    sleepers: AtomicUsize,
+    sleep: Mutex<()>,
+    available: Condvar,
+}
+
+impl QueueState
+{
+    //--------------------------------------------------------------------------
+    /// Returns the nanoseconds elapsed from the epoch to the given instant.
+    //--------------------------------------------------------------------------
+    fn nanos( &self, instant: Instant ) -> u64
+    {
+        u64::try_from(instant.saturating_duration_since(self.epoch).as_nanos())
+            .unwrap_or(u64::MAX)
     }
 }
 
@@ -30,47 +303,563 @@ impl<E> From<PoisonError<E>> for TaskQueueError
 /// # TaskQueue
 //------------------------------------------------------------------------------
 #[derive(Clone)]
-pub(super) struct TaskQueue<T: Clone>
+pub(crate) struct TaskQueue
 {
-    heap: Arc<RwLock<BinaryHeap<Task<T>>>>,
+    injector: Arc<Injector<Task>>,
+    levels: Arc<Mutex<Levels>>,
+    state: Arc<QueueState>,
+    counters: Arc<DeadlineCounters>,
+    tasks: Arc<TaskCounters>,
+    registry: Arc<TaskRegistry>,
 }
 
-impl<T: Clone> TaskQueue<T>
+impl TaskQueue
 {
     //--------------------------------------------------------------------------
-    /// Creates a new TaskQueue.
+    /// Creates a new TaskQueue whose injector holds up to the given number of
+    /// tasks, rounded up to a power of two of at least two.
     //--------------------------------------------------------------------------
-    pub(super) fn new() -> Self
+    pub(super) fn new( capacity: usize ) -> Self
     {
+        let epoch = Instant::now();
         Self
         {
-            heap: Arc::new(RwLock::new(BinaryHeap::new())),
+            injector: Arc::new(Injector::new(capacity)),
+            levels: Arc::new(Mutex::new(Levels
+            {
+                queues: BTreeMap::new(),
+                deadlines: BTreeMap::new(),
+                sequence: 0,
+                len: 0,
+                aging_interval: Some(DEFAULT_AGING_INTERVAL),
+                policy: SchedulingPolicy::default(),
+                shed_expired: false,
+            })),
+            state: Arc::new(QueueState
+            {
+                ordered: AtomicUsize::new(0),
+                reject: AtomicBool::new(false),
+                rejected: AtomicU64::new(0),
+                aging: AtomicU64::new(DEFAULT_AGING_INTERVAL.as_nanos() as u64),
+                injector_served: AtomicU64::new(0),
+                epoch,
+                sleepers: AtomicUsize::new(0),
+                sleep: Mutex::new(()),
+                available: Condvar::new(),
+            }),
+            counters: Arc::new(DeadlineCounters::default()),
+            tasks: Arc::new(TaskCounters::default()),
+            registry: Arc::new(TaskRegistry::default()),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Locks the levels. They are left consistent by every operation, so a
+    /// poisoned lock is recovered.
+    //--------------------------------------------------------------------------
+    fn lock_levels( &self ) -> MutexGuard<'_, Levels>
+    {
+        self.levels.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the aging interval. `None` disables aging.
+    //--------------------------------------------------------------------------
+    pub(super) fn set_aging_interval( &self, interval: Option<Duration> )
+    {
+        let nanos = interval.map_or(u64::MAX, |interval|
+        {
+            u64::try_from(interval.as_nanos()).unwrap_or(u64::MAX)
+        });
+        self.state.aging.store(nanos, Ordering::Relaxed);
+        self.lock_levels().aging_interval = interval;
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the scheduling policy.
+    //--------------------------------------------------------------------------
+    pub(super) fn set_policy( &self, policy: SchedulingPolicy )
+    {
+        self.lock_levels().set_policy(policy);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether the tasks whose deadline has passed are shed.
+    //--------------------------------------------------------------------------
+    pub(super) fn set_shed_expired( &self, shed: bool )
+    {
+        self.lock_levels().shed_expired = shed;
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets what happens to the tasks pushed while the injector is full.
+    //--------------------------------------------------------------------------
+    pub(super) fn set_overflow_policy( &self, policy: OverflowPolicy )
+    {
+        let reject = policy == OverflowPolicy::Reject;
+        self.state.reject.store(reject, Ordering::Relaxed);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Records whether a task with a deadline completed in time.
+    //--------------------------------------------------------------------------
+    pub(super) fn record_completion( &self, task: &Task )
+    {
+        if task.deadline().is_none()
+        {
+            return;
+        }
+        let counter = match task.is_expired(Instant::now())
+        {
+            true => &self.counters.missed,
+            false => &self.counters.met,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the counters of the tasks spawned onto the queue.
+    //--------------------------------------------------------------------------
+    pub(super) fn task_counters( &self ) -> &Arc<TaskCounters>
+    {
+        &self.tasks
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the registry of the live tasks spawned onto the queue.
+    //--------------------------------------------------------------------------
+    pub(super) fn task_registry( &self ) -> &Arc<TaskRegistry>
+    {
+        &self.registry
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the deadline statistics.
+    //--------------------------------------------------------------------------
+    pub(super) fn deadline_stats( &self ) -> DeadlineStats
+    {
+        DeadlineStats
+        {
+            met: self.counters.met.load(Ordering::Relaxed),
+            missed: self.counters.missed.load(Ordering::Relaxed),
+            shed: self.counters.shed.load(Ordering::Relaxed),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of spawned tasks rejected because the injector was
+    /// full.
+    //--------------------------------------------------------------------------
+    pub(super) fn rejected( &self ) -> u64
+    {
+        self.state.rejected.load(Ordering::Relaxed)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pushes a task that was woken.
+    //--------------------------------------------------------------------------
+    pub(super) fn push( &self, task: Task )
+    {
+        if let Err(task) = self.inject(task)
+        {
+            self.push_ordered(task);
+        }
+        self.notify_one();
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pushes a task that was just spawned. Unlike a woken task, it is
+    /// cancelled if the injector is full and the overflow policy is `Reject`.
+    //--------------------------------------------------------------------------
+    pub(super) fn push_spawned( &self, task: Task )
+    {
+        if let Err(task) = self.inject(task)
+        {
+            if self.state.reject.load(Ordering::Relaxed)
+            {
+                self.state.rejected.fetch_add(1, Ordering::Relaxed);
+                let _ = task.cancel();
+                return;
+            }
+            self.push_ordered(task);
+        }
+        self.notify_one();
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pushes a task onto the injector if it has neither a priority nor a
+    /// deadline. Gives the task back if it must be ordered, or if the
+    /// injector is full.
+    //--------------------------------------------------------------------------
+    fn inject( &self, task: Task ) -> Result<(), Task>
+    {
+        if task.priority() > 0 || task.deadline().is_some()
+        {
+            return Err(task);
+        }
+
+        // An empty injector has nothing waiting, so its aging starts over.
+        if self.injector.is_empty()
+        {
+            let now = self.state.nanos(Instant::now());
+            self.state.injector_served.store(now, Ordering::Relaxed);
+        }
+        self.injector.push(task)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pushes a task onto the levels.
+    //--------------------------------------------------------------------------
+    fn push_ordered( &self, task: Task )
+    {
+        let mut levels = self.lock_levels();
+        levels.insert(task, Instant::now());
+        self.state.ordered.store(levels.len, Ordering::Release);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Wakes a worker waiting for a task, if any.
+    //--------------------------------------------------------------------------
+    fn notify_one( &self )
+    {
+        // Pairs with the fence in `pop_timeout`: either the worker sees the
+        // task pushed, or this sees the worker waiting.
+        fence(Ordering::SeqCst);
+        if self.state.sleepers.load(Ordering::SeqCst) > 0
+        {
+            // Taking the lock waits for the worker to be waiting.
+            drop(self.state.sleep.lock().unwrap_or_else(PoisonError::into_inner));
+            self.state.available.notify_one();
         }
     }
 
     //--------------------------------------------------------------------------
-    /// Pushes a task onto the queue.
+    /// Pops the next task without waiting.
+    ///
+    /// The tasks with a priority or a deadline are served first, unless the
+    /// head of the injector has waited for the aging interval, like a task at
+    /// the head of level zero.
     //--------------------------------------------------------------------------
-    pub(super) fn push( &self, task: Task<T> ) -> Result<(), TaskQueueError>
+    pub(super) fn pop( &self ) -> Option<Task>
     {
-        self.heap.write()?.push(task);
-        Ok(())
+        self.pop_at(Instant::now())
     }
 
     //--------------------------------------------------------------------------
-    /// Pops the highest priority task from the queue.
+    /// Pops the next task as `pop` does, aging the tasks up to the given
+    /// instant.
     //--------------------------------------------------------------------------
-    pub(super) fn pop( &self ) -> Result<Option<Task<T>>, TaskQueueError>
+    fn pop_at( &self, now: Instant ) -> Option<Task>
     {
-        Ok(self.heap.write()?.pop())
+        let starved = self.injector_starved(now);
+        if !starved
+        {
+            if let Some(task) = self.pop_ordered(now)
+            {
+                return Some(task);
+            }
+        }
+        if let Some(task) = self.injector.pop()
+        {
+            let now = self.state.nanos(now);
+            self.state.injector_served.store(now, Ordering::Relaxed);
+            return Some(task);
+        }
+        match starved
+        {
+            true => self.pop_ordered(now),
+            false => None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the injector has not been served for the aging
+    /// interval at the given instant while tasks were waiting in it.
+    //--------------------------------------------------------------------------
+    fn injector_starved( &self, now: Instant ) -> bool
+    {
+        let aging = self.state.aging.load(Ordering::Relaxed);
+        if aging == u64::MAX || self.injector.is_empty()
+        {
+            return false;
+        }
+        let served = self.state.injector_served.load(Ordering::Relaxed);
+        self.state.nanos(now).saturating_sub(served) >= aging
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pops the next task from the levels at the given instant.
+    //--------------------------------------------------------------------------
+    fn pop_ordered( &self, now: Instant ) -> Option<Task>
+    {
+        if self.state.ordered.load(Ordering::Acquire) == 0
+        {
+            return None;
+        }
+
+        let mut shed = Vec::new();
+        let task =
+        {
+            let mut levels = self.lock_levels();
+            let task = levels.pop(&mut shed, now);
+            self.state.ordered.store(levels.len, Ordering::Release);
+            task
+        };
+
+        // The shed tasks are cancelled after unlocking, as dropping a future
+        // may wake another task and push it onto this queue.
+        for task in shed
+        {
+            self.counters.shed.fetch_add(1, Ordering::Relaxed);
+            let _ = task.cancel();
+        }
+        task
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pops the next task, waiting up to the timeout for one to be pushed.
+    //--------------------------------------------------------------------------
+    pub(super) fn pop_timeout( &self, timeout: Duration ) -> Option<Task>
+    {
+        if let Some(task) = self.pop()
+        {
+            return Some(task);
+        }
+
+        let task =
+        {
+            let sleep = self.state.sleep.lock().unwrap_or_else(PoisonError::into_inner);
+            self.state.sleepers.fetch_add(1, Ordering::SeqCst);
+            fence(Ordering::SeqCst);
+            let task = self.pop();
+            if task.is_none()
+            {
+                drop(self.state.available.wait_timeout(sleep, timeout));
+            }
+            self.state.sleepers.fetch_sub(1, Ordering::SeqCst);
+            task
+        };
+        task.or_else(|| self.pop())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Wakes all the workers waiting for a task.
+    //--------------------------------------------------------------------------
+    pub(super) fn notify_all( &self )
+    {
+        drop(self.state.sleep.lock().unwrap_or_else(PoisonError::into_inner));
+        self.state.available.notify_all();
+    }
+
+    //--------------------------------------------------------------------------
+    /// Removes all the tasks from the queue.
+    //--------------------------------------------------------------------------
+    pub(super) fn clear( &self )
+    {
+        // The tasks are dropped after unlocking, as dropping a future may wake
+        // another task and push it onto this queue.
+        let queues =
+        {
+            let mut levels = self.lock_levels();
+            levels.len = 0;
+            self.state.ordered.store(0, Ordering::Release);
+            (
+                std::mem::take(&mut levels.queues),
+                std::mem::take(&mut levels.deadlines),
+            )
+        };
+        drop(queues);
+        while self.injector.pop().is_some() {}
     }
 
     //--------------------------------------------------------------------------
     /// Returns the number of tasks in the queue.
     //--------------------------------------------------------------------------
-    pub(super) fn len( &self ) -> Result<usize, TaskQueueError>
+    pub(super) fn len( &self ) -> usize
     {
-        let heap = self.heap.read()?;
-        Ok(heap.len())
+        self.injector.len() + self.state.ordered.load(Ordering::Acquire)
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use super::super::task::TaskHeader;
+
+    use std::panic::Location;
+
+    fn task( id: u64, priority: usize ) -> Task
+    {
+        let header = Arc::new(TaskHeader::new(id, None, Location::caller()));
+        Task::from_boxed(header, Box::pin(async {}), priority)
+    }
+
+    fn due( id: u64, deadline: Instant ) -> Task
+    {
+        task(id, 0).with_deadline(Some(deadline))
+    }
+
+    fn drain( queue: &TaskQueue ) -> Vec<u64>
+    {
+        std::iter::from_fn(|| queue.pop()).map(|task| task.id()).collect()
+    }
+
+    fn drain_at( queue: &TaskQueue, now: Instant ) -> Vec<u64>
+    {
+        std::iter::from_fn(|| queue.pop_at(now)).map(|task| task.id()).collect()
+    }
+
+    #[test]
+    fn highest_level_is_served_first_in_fifo_order()
+    {
+        let queue = TaskQueue::new(16);
+        queue.push(task(1, 0));
+        queue.push(task(2, 1));
+        queue.push(task(3, 2));
+        queue.push(task(4, 1));
+        queue.push(task(5, 2));
+        queue.push(task(6, 0));
+        assert_eq!(queue.len(), 6);
+        assert_eq!(drain(&queue), [3, 5, 2, 4, 1, 6]);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn waiting_task_is_promoted_to_the_next_level()
+    {
+        let queue = TaskQueue::new(16);
+        queue.set_aging_interval(Some(Duration::from_secs(60)));
+        queue.push(task(1, 1));
+        queue.push(task(2, 3));
+        let later = Instant::now() + Duration::from_secs(60);
+
+        // Task 1 moves up to level 2 ahead of a task pushed there later.
+        assert_eq!(queue.pop_at(later).map(|task| task.id()), Some(2));
+        queue.push(task(3, 2));
+        assert_eq!(drain_at(&queue, later), [1, 3]);
+    }
+
+    #[test]
+    fn without_aging_levels_are_strict()
+    {
+        let queue = TaskQueue::new(16);
+        queue.set_aging_interval(None);
+        queue.push(task(1, 0));
+        queue.push(task(2, 1));
+        queue.push(task(3, 3));
+        let later = Instant::now() + Duration::from_secs(3600);
+        assert_eq!(queue.pop_at(later).map(|task| task.id()), Some(3));
+        queue.push(task(4, 2));
+        assert_eq!(drain_at(&queue, later), [4, 2, 1]);
+    }
+
+    #[test]
+    fn earliest_deadline_is_served_first()
+    {
+        let queue = TaskQueue::new(16);
+        queue.set_policy(SchedulingPolicy::EarliestDeadlineFirst);
+        let now = Instant::now();
+        queue.push(task(1, 5));
+        queue.push(due(2, now + Duration::from_secs(30)));
+        queue.push(due(3, now + Duration::from_secs(10)));
+        queue.push(due(4, now + Duration::from_secs(20)));
+        queue.push(due(5, now + Duration::from_secs(10)));
+        queue.push(task(6, 0));
+        assert_eq!(drain(&queue), [3, 5, 4, 2, 1, 6]);
+    }
+
+    #[test]
+    fn changing_the_policy_moves_the_queued_tasks()
+    {
+        let queue = TaskQueue::new(16);
+        let now = Instant::now();
+        queue.push(task(1, 1));
+        queue.push(due(2, now + Duration::from_secs(20)));
+        queue.push(due(3, now + Duration::from_secs(10)));
+
+        queue.set_policy(SchedulingPolicy::EarliestDeadlineFirst);
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.pop().map(|task| task.id()), Some(3));
+
+        // Back under the priority policy, the tasks with a deadline keep
+        // their order behind the higher level.
+        queue.push(due(4, now + Duration::from_secs(5)));
+        queue.set_policy(SchedulingPolicy::Priority);
+        assert_eq!(drain(&queue), [1, 4, 2]);
+    }
+
+    #[test]
+    fn expired_tasks_are_shed_when_enabled()
+    {
+        let queue = TaskQueue::new(16);
+        queue.set_policy(SchedulingPolicy::EarliestDeadlineFirst);
+        let now = Instant::now();
+        queue.push(due(1, now));
+        queue.push(due(2, now + Duration::from_secs(10)));
+        assert_eq!(drain(&queue), [1, 2]);
+        assert_eq!(queue.deadline_stats().shed, 0);
+
+        queue.set_shed_expired(true);
+        let expired = due(3, now);
+        queue.push(expired.clone());
+        queue.push(due(4, now + Duration::from_secs(10)));
+        assert_eq!(drain(&queue), [4]);
+        assert_eq!(queue.deadline_stats().shed, 1);
+
+        // The shed task is cancelled rather than polled.
+        let waker = std::task::Waker::noop();
+        let mut context = std::task::Context::from_waker(waker);
+        assert_eq!(expired.poll(&mut context).ok(), Some(std::task::Poll::Pending));
+    }
+
+    #[test]
+    fn completions_are_counted_against_the_deadline()
+    {
+        let queue = TaskQueue::new(16);
+        let now = Instant::now();
+        queue.record_completion(&task(1, 0));
+        queue.record_completion(&due(2, now + Duration::from_secs(10)));
+        queue.record_completion(&due(3, now));
+        let stats = queue.deadline_stats();
+        assert_eq!((stats.met, stats.missed, stats.shed), (1, 1, 0));
+    }
+
+    #[test]
+    fn starved_injector_is_served_before_the_levels()
+    {
+        let queue = TaskQueue::new(16);
+        queue.set_aging_interval(Some(Duration::from_secs(60)));
+        queue.push(task(1, 0));
+        queue.push(task(2, 1));
+        assert_eq!(queue.pop().map(|task| task.id()), Some(2));
+
+        queue.push(task(3, 1));
+        let later = Instant::now() + Duration::from_secs(60);
+        assert_eq!(drain_at(&queue, later), [1, 3]);
+    }
+
+    #[test]
+    fn full_injector_spills_or_rejects()
+    {
+        let queue = TaskQueue::new(2);
+        queue.push_spawned(task(1, 0));
+        queue.push_spawned(task(2, 0));
+        queue.push_spawned(task(3, 0));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(queue.rejected(), 0);
+        queue.clear();
+
+        queue.set_overflow_policy(OverflowPolicy::Reject);
+        queue.push_spawned(task(1, 0));
+        queue.push_spawned(task(2, 0));
+        queue.push_spawned(task(3, 0));
+        assert_eq!(queue.rejected(), 1);
+
+        // A woken task is spilled even when the spawned ones are rejected.
+        queue.push(task(4, 0));
+        assert_eq!(queue.len(), 3);
+        assert_eq!(drain(&queue), [4, 1, 2]);
     }
 }
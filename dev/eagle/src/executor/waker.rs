@@ -1,5 +1,5 @@
 //------------------------------------------------------------------------------
-/// # Waker
+//! # Waker
 //------------------------------------------------------------------------------
 
 use std::mem::ManuallyDrop;
@@ -40,10 +40,7 @@ impl<F: Fn() + Send + Sync + 'static> WakerHelper<F>
     //--------------------------------------------------------------------------
     unsafe fn clone_waker( ptr: *const () ) -> RawWaker
     {
-        let arc = ManuallyDrop::new(Arc::from_raw(ptr as *const F));
-        let cloned_arc = arc.clone();
-        std::mem::forget(arc);
-        let ptr = Arc::into_raw(cloned_arc.into()) as *const ();
+        Arc::increment_strong_count(ptr as *const F);
         RawWaker::new(ptr, &Self::VTABLE)
     }
 
@@ -63,7 +60,6 @@ impl<F: Fn() + Send + Sync + 'static> WakerHelper<F>
     {
         let arc = ManuallyDrop::new(Arc::from_raw(ptr as *const F));
         (arc)();
-        std::mem::forget(arc);
     }
 
     //--------------------------------------------------------------------------
@@ -2,46 +2,49 @@
 //! # Async executor worker
 //------------------------------------------------------------------------------
 
+use super::budget;
+use super::context;
+use super::metrics::WorkerCounters;
+use super::task::Task;
 use super::task_queue::TaskQueue;
 use super::waker::waker_fn;
 
-use std::sync::{ Arc, Condvar, Mutex };
+use std::sync::Arc;
 use std::sync::atomic::{ AtomicBool, Ordering };
 use std::task::{ Context, Poll };
 use std::thread::{ self, JoinHandle };
+use std::time::{ Duration, Instant };
+
+/// How long an idle worker sleeps before checking whether it was stopped.
+pub(super) const IDLE_TIMEOUT: Duration = Duration::from_millis(100);
 
 
 //------------------------------------------------------------------------------
 /// # Worker
 //------------------------------------------------------------------------------
-pub(super) struct Worker<T: Clone>
+pub(super) struct Worker
 {
     id: usize,
-    queue: TaskQueue<T>,
-    is_done: Arc<(Mutex<Option<T>>, Condvar)>,
+    queue: TaskQueue,
     is_stopped: Arc<AtomicBool>,
+    counters: Arc<WorkerCounters>,
 
     pub(super) join_handle: Option<JoinHandle<()>>,
 }
 
-impl<T: Send + Clone + 'static> Worker<T>
+impl Worker
 {
     //--------------------------------------------------------------------------
     /// Creates a new Worker.
     //--------------------------------------------------------------------------
-    pub(super) fn new
-    (
-        id: usize,
-        queue: TaskQueue<T>,
-        is_done: Arc<(Mutex<Option<T>>, Condvar)>,
-    ) -> Self
+    pub(super) fn new( id: usize, queue: TaskQueue ) -> Self
     {
         Self
         {
             id,
             queue,
-            is_done,
             is_stopped: Arc::new(AtomicBool::new(false)),
+            counters: Arc::new(WorkerCounters::default()),
             join_handle: None,
         }
     }
@@ -52,13 +55,14 @@ impl<T: Send + Clone + 'static> Worker<T>
     pub(super) fn run( &mut self )
     {
         let queue = self.queue.clone();
-        let is_done = self.is_done.clone();
         let is_stopped = self.is_stopped.clone();
+        let counters = self.counters.clone();
 
         let join_handle = thread::Builder::new()
             .name(self.id.to_string())
             .spawn(move ||
             {
+                let _guard = context::enter(queue.clone());
                 loop
                 {
                     if is_stopped.load(Ordering::SeqCst)
@@ -66,46 +70,15 @@ impl<T: Send + Clone + 'static> Worker<T>
                         break;
                     }
 
-                    let mut task = match queue.pop()
-                    {
-                        Ok(task) =>
-                        {
-                            match task
-                            {
-                                Some(task) => task,
-                                None => continue,
-                            }
-                        }
-                        Err(_) => break,
-                    };
-
-                    let cloned_task = task.clone();
-                    let waker =
+                    let task = match queue.pop_timeout(IDLE_TIMEOUT)
                     {
-                        let queue = queue.clone();
-                        waker_fn(move ||
-                        {
-                            let _ = queue.push(cloned_task.clone());
-                        })
+                        Some(task) => task,
+                        None => continue,
                     };
-                    let mut context = Context::from_waker(&waker);
 
-                    match task.poll(&mut context)
-                    {
-                        Ok(Poll::Ready(result)) =>
-                        {
-                            let (lock, cvar) = &*is_done;
-                            let mut done = match lock.lock()
-                            {
-                                Ok(lock) => lock,
-                                Err(_) => continue,
-                            };
-                            *done = Some(result);
-                            cvar.notify_one();
-                        },
-                        Ok(Poll::Pending) => {},
-                        Err(_) => continue,
-                    };
+                    let started = Instant::now();
+                    poll_task(&queue, &task);
+                    counters.record_poll(started.elapsed());
                 }
             });
 
@@ -114,20 +87,26 @@ impl<T: Send + Clone + 'static> Worker<T>
             self.join_handle = Some(join_handle);
         }
     }
-}
 
-impl<T: Clone> Worker<T>
-{
+    //--------------------------------------------------------------------------
+    /// Returns the counters of the Worker.
+    //--------------------------------------------------------------------------
+    pub(super) fn counters( &self ) -> &WorkerCounters
+    {
+        &self.counters
+    }
+
     //--------------------------------------------------------------------------
     /// Stops the Worker.
     //--------------------------------------------------------------------------
     pub(super) fn stop( &self )
     {
         self.is_stopped.store(true, Ordering::SeqCst);
+        self.queue.notify_all();
     }
 }
 
-impl<T: Clone> Drop for Worker<T>
+impl Drop for Worker
 {
     fn drop( &mut self )
     {
@@ -138,3 +117,31 @@ impl<T: Clone> Drop for Worker<T>
         }
     }
 }
+
+
+//------------------------------------------------------------------------------
+/// # poll_task
+///
+/// Polls a task popped from the queue, with a waker scheduling it back onto
+/// the queue and a fresh budget.
+//------------------------------------------------------------------------------
+pub(super) fn poll_task( queue: &TaskQueue, task: &Task )
+{
+    let waker =
+    {
+        let queue = queue.clone();
+        let task = task.clone();
+        waker_fn(move ||
+        {
+            task.schedule(&queue);
+        })
+    };
+    let mut context = Context::from_waker(&waker);
+
+    let _task_guard = context::enter_task(task);
+    let _budget_guard = budget::enter();
+    if let Ok(Poll::Ready(())) = task.poll(&mut context)
+    {
+        queue.record_completion(task);
+    }
+}
@@ -0,0 +1,585 @@
+//------------------------------------------------------------------------------
+//! # Future combinators
+//!
+//! Waits on several futures at once, either for all of them (`join!`,
+//! `try_join!`, `join_all`) or for the first one to complete (`select!`,
+//! `select_all`). The futures run concurrently within the current task; use
+//! `spawn` to run them in parallel.
+//------------------------------------------------------------------------------
+
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::future::Future;
+use std::hash::{ BuildHasher, Hasher };
+use std::mem;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # MaybeDone
+///
+/// Future keeping the output of the inner future once it has completed.
+///
+/// - Future: The inner future has not completed yet.
+/// - Done: The inner future has completed and its output was not taken yet.
+/// - Gone: The output was taken, or the future was cancelled.
+//------------------------------------------------------------------------------
+pub enum MaybeDone<F: Future>
+{
+    Future(F),
+    Done(F::Output),
+    Gone,
+}
+
+impl<F: Future> MaybeDone<F>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new MaybeDone.
+    //--------------------------------------------------------------------------
+    pub fn new( future: F ) -> Self
+    {
+        Self::Future(future)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the output if the future has completed.
+    //--------------------------------------------------------------------------
+    pub fn output_mut( self: Pin<&mut Self> ) -> Option<&mut F::Output>
+    {
+        match unsafe { self.get_unchecked_mut() }
+        {
+            Self::Done(output) => Some(output),
+            _ => None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Takes the output if the future has completed.
+    //--------------------------------------------------------------------------
+    pub fn take_output( self: Pin<&mut Self> ) -> Option<F::Output>
+    {
+        match &*self
+        {
+            Self::Done(_) => {},
+            _ => return None,
+        }
+
+        // The output is not pinned, so it can be moved out.
+        match mem::replace(unsafe { self.get_unchecked_mut() }, Self::Gone)
+        {
+            Self::Done(output) => Some(output),
+            _ => None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Drops the future, or its output, in place.
+    //--------------------------------------------------------------------------
+    pub fn cancel( self: Pin<&mut Self> )
+    {
+        // Setting a pinned value drops the previous one in place.
+        let mut this = self;
+        this.set(Self::Gone);
+    }
+}
+
+impl<F: Future> Future for MaybeDone<F>
+{
+    type Output = ();
+
+    fn poll( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<()>
+    {
+        let this = unsafe { self.get_unchecked_mut() };
+        let output = match this
+        {
+            Self::Future(future) =>
+            {
+                match unsafe { Pin::new_unchecked(future) }.poll(cx)
+                {
+                    Poll::Ready(output) => output,
+                    Poll::Pending => return Poll::Pending,
+                }
+            },
+            _ => return Poll::Ready(()),
+        };
+        *this = Self::Done(output);
+        Poll::Ready(())
+    }
+}
+
+impl<F: Future> fmt::Debug for MaybeDone<F>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Future(_) => write!(f, "MaybeDone::Future"),
+            Self::Done(_) => write!(f, "MaybeDone::Done"),
+            Self::Gone => write!(f, "MaybeDone::Gone"),
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # join_all
+///
+/// Waits for all the futures and returns their outputs in order.
+//------------------------------------------------------------------------------
+pub fn join_all<I>( futures: I ) -> JoinAll<I::Item>
+    where
+        I: IntoIterator,
+        I::Item: Future,
+{
+    let futures: Box<[_]> = futures.into_iter().map(MaybeDone::new).collect();
+    JoinAll { futures: Box::into_pin(futures) }
+}
+
+
+//------------------------------------------------------------------------------
+/// # JoinAll
+///
+/// Future returned by `join_all`.
+//------------------------------------------------------------------------------
+pub struct JoinAll<F: Future>
+{
+    futures: Pin<Box<[MaybeDone<F>]>>,
+}
+
+impl<F: Future> Future for JoinAll<F>
+{
+    type Output = Vec<F::Output>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let mut is_pending = false;
+        for future in iter_pin_mut(self.futures.as_mut())
+        {
+            if future.poll(cx).is_pending()
+            {
+                is_pending = true;
+            }
+        }
+        if is_pending
+        {
+            return Poll::Pending;
+        }
+
+        let outputs = iter_pin_mut(self.futures.as_mut())
+            .map(|future| future.take_output().expect("polled after completion"))
+            .collect();
+        Poll::Ready(outputs)
+    }
+}
+
+impl<F: Future> fmt::Debug for JoinAll<F>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("JoinAll")
+            .field("len", &self.futures.len())
+            .finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # select_all
+///
+/// Waits for the first of the futures to complete. Returns its output, its
+/// index, and the other futures, which are not cancelled.
+///
+/// # Panics
+///
+/// Panics if there is no future.
+//------------------------------------------------------------------------------
+pub fn select_all<I>( futures: I ) -> SelectAll<I::Item>
+    where
+        I: IntoIterator,
+        I::Item: Future + Unpin,
+{
+    let futures: Vec<_> = futures.into_iter().collect();
+    assert!(!futures.is_empty(), "select_all requires at least one future");
+    SelectAll { futures }
+}
+
+
+//------------------------------------------------------------------------------
+/// # SelectAll
+///
+/// Future returned by `select_all`.
+//------------------------------------------------------------------------------
+pub struct SelectAll<F>
+{
+    futures: Vec<F>,
+}
+
+impl<F: Future + Unpin> Future for SelectAll<F>
+{
+    type Output = (F::Output, usize, Vec<F>);
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let ready = self.futures
+            .iter_mut()
+            .enumerate()
+            .find_map(|(index, future)|
+            {
+                match Pin::new(future).poll(cx)
+                {
+                    Poll::Ready(output) => Some((index, output)),
+                    Poll::Pending => None,
+                }
+            });
+
+        match ready
+        {
+            Some((index, output)) =>
+            {
+                drop(self.futures.swap_remove(index));
+                let rest = mem::take(&mut self.futures);
+                Poll::Ready((output, index, rest))
+            },
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<F> fmt::Debug for SelectAll<F>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("SelectAll")
+            .field("len", &self.futures.len())
+            .finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # iter_pin_mut
+///
+/// Iterates over pinned references to the elements of a pinned slice.
+//------------------------------------------------------------------------------
+fn iter_pin_mut<T>( slice: Pin<&mut [T]> ) -> impl Iterator<Item = Pin<&mut T>>
+{
+    unsafe { slice.get_unchecked_mut() }
+        .iter_mut()
+        .map(|item| unsafe { Pin::new_unchecked(item) })
+}
+
+
+//------------------------------------------------------------------------------
+/// # random_start
+///
+/// Returns the branch a fair `select!` polls first. In a simulation, it is
+/// drawn from the seeded generator of the simulation.
+//------------------------------------------------------------------------------
+#[doc(hidden)]
+pub fn random_start( branches: usize ) -> usize
+{
+    if let Some(random) = crate::sim::random(branches)
+    {
+        return random;
+    }
+
+    thread_local!
+    {
+        static STATE: Cell<u64> = Cell::new
+        (
+            RandomState::new().build_hasher().finish() | 1
+        );
+    }
+
+    // xorshift64
+    let random = STATE.with(|state|
+    {
+        let mut x = state.get();
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        state.set(x);
+        x
+    });
+    (random % branches as u64) as usize
+}
+
+
+//------------------------------------------------------------------------------
+/// # join!
+///
+/// Waits for all the futures and returns their outputs as a tuple. Must be
+/// used inside an async context.
+///
+/// ```
+/// # eagle::executor::Executor::new(0).block_on(async {
+/// async fn double( x: u32 ) -> u32 { x * 2 }
+///
+/// let (a, b) = eagle::join!(double(1), double(2));
+/// assert_eq!((a, b), (2, 4));
+/// # }).unwrap();
+/// ```
+//------------------------------------------------------------------------------
+#[macro_export]
+macro_rules! join
+{
+    (@ { ( $($count:tt)* ) $( ( $($skip:tt)* ) $e:expr, )* }) =>
+    {{
+        use ::std::future::Future;
+        use ::std::pin::Pin;
+        use ::std::task::Poll;
+
+        let mut futures = ( $( $crate::future::MaybeDone::new($e), )* );
+
+        // The futures are only accessed through this pinned reference, so
+        // they are never moved.
+        let mut futures = unsafe { Pin::new_unchecked(&mut futures) };
+        ::std::future::poll_fn(|cx|
+        {
+            let mut is_pending = false;
+            $(
+                let ( $($skip,)* future, .. ) =
+                    unsafe { futures.as_mut().get_unchecked_mut() };
+                if unsafe { Pin::new_unchecked(future) }.poll(cx).is_pending()
+                {
+                    is_pending = true;
+                }
+            )*
+            match is_pending
+            {
+                true => Poll::Pending,
+                false => Poll::Ready(()),
+            }
+        }).await;
+
+        ( $({
+            let ( $($skip,)* future, .. ) =
+                unsafe { futures.as_mut().get_unchecked_mut() };
+            unsafe { Pin::new_unchecked(future) }
+                .take_output()
+                .expect("join! output taken twice")
+        },)* )
+    }};
+
+    (@ { ( $($s:tt)* ) $($t:tt)* } $e:expr, $($r:tt)*) =>
+    {
+        $crate::join!(@ { ( $($s)* _ ) $($t)* ( $($s)* ) $e, } $($r)*)
+    };
+
+    ( $($e:expr),+ $(,)? ) =>
+    {
+        $crate::join!(@ { () } $($e,)*)
+    };
+
+    () => { () };
+}
+
+
+//------------------------------------------------------------------------------
+/// # try_join!
+///
+/// Waits for all the futures returning a `Result` and returns their outputs
+/// as a tuple. On the first error, the other futures are cancelled and the
+/// error is returned. Must be used inside an async context.
+///
+/// ```
+/// # eagle::executor::Executor::new(0).block_on(async {
+/// async fn parse( s: &str ) -> Result<u32, std::num::ParseIntError>
+/// {
+///     s.parse()
+/// }
+///
+/// assert_eq!(eagle::try_join!(parse("1"), parse("2")), Ok((1, 2)));
+/// assert!(eagle::try_join!(parse("1"), parse("x")).is_err());
+/// # }).unwrap();
+/// ```
+//------------------------------------------------------------------------------
+#[macro_export]
+macro_rules! try_join
+{
+    (@ { ( $($count:tt)* ) $( ( $($skip:tt)* ) $e:expr, )* }) =>
+    {{
+        use ::std::future::Future;
+        use ::std::pin::Pin;
+        use ::std::task::Poll;
+
+        let mut futures = ( $( $crate::future::MaybeDone::new($e), )* );
+
+        // The futures are only accessed through this pinned reference, so
+        // they are never moved.
+        let mut futures = unsafe { Pin::new_unchecked(&mut futures) };
+        let result = ::std::future::poll_fn(|cx|
+        {
+            let mut is_pending = false;
+            $(
+                let ( $($skip,)* future, .. ) =
+                    unsafe { futures.as_mut().get_unchecked_mut() };
+                let mut future = unsafe { Pin::new_unchecked(future) };
+                if future.as_mut().poll(cx).is_pending()
+                {
+                    is_pending = true;
+                }
+                else if let Some(Err(_)) = future.as_mut().output_mut()
+                {
+                    if let Some(Err(error)) = future.take_output()
+                    {
+                        return Poll::Ready(Err(error));
+                    }
+                }
+            )*
+            match is_pending
+            {
+                true => Poll::Pending,
+                false => Poll::Ready(Ok(())),
+            }
+        }).await;
+
+        match result
+        {
+            Err(error) =>
+            {
+                // Cancels the other futures before returning the error.
+                $(
+                    let ( $($skip,)* future, .. ) =
+                        unsafe { futures.as_mut().get_unchecked_mut() };
+                    unsafe { Pin::new_unchecked(future) }.cancel();
+                )*
+                Err(error)
+            },
+            Ok(()) => Ok(( $({
+                let ( $($skip,)* future, .. ) =
+                    unsafe { futures.as_mut().get_unchecked_mut() };
+                match unsafe { Pin::new_unchecked(future) }.take_output()
+                {
+                    Some(Ok(output)) => output,
+                    _ => unreachable!("try_join! output taken twice"),
+                }
+            },)* )),
+        }
+    }};
+
+    (@ { ( $($s:tt)* ) $($t:tt)* } $e:expr, $($r:tt)*) =>
+    {
+        $crate::try_join!(@ { ( $($s)* _ ) $($t)* ( $($s)* ) $e, } $($r)*)
+    };
+
+    ( $($e:expr),+ $(,)? ) =>
+    {
+        $crate::try_join!(@ { () } $($e,)*)
+    };
+
+    () => { Ok(()) };
+}
+
+
+//------------------------------------------------------------------------------
+/// # select!
+///
+/// Waits for the first of the futures to complete, cancels the others, and
+/// runs the handler of the completed branch with its output bound to the
+/// pattern, which must be irrefutable. Must be used inside an async context.
+///
+/// The branches are polled starting from a random one, so that none is
+/// favored. With `biased;` first, they are polled in order.
+///
+/// ```
+/// use eagle::sync::oneshot;
+///
+/// # eagle::executor::Executor::new(0).block_on(async {
+/// let (_stop, shutdown) = oneshot::channel::<()>();
+/// let (sender, request) = oneshot::channel();
+/// sender.send(42).unwrap();
+///
+/// let handled = eagle::select!
+/// {
+///     biased;
+///     _ = shutdown => None,
+///     request = request => Some(request.unwrap()),
+/// };
+/// assert_eq!(handled, Some(42));
+/// # }).unwrap();
+/// ```
+//------------------------------------------------------------------------------
+#[macro_export]
+macro_rules! select
+{
+    (@ $mode:ident { ( $($count:tt)* ) [ $($total:tt)* ]
+        $( ( $($skip:tt)* ) [ $($index:tt)* ] $p:pat = $e:expr => $h:expr, )*
+    }) =>
+    {{
+        use ::std::future::Future;
+        use ::std::pin::Pin;
+        use ::std::task::Poll;
+
+        const BRANCHES: usize = $($total)*;
+
+        let mut futures = Box::pin(( $( $crate::future::MaybeDone::new($e), )* ));
+        let start = $crate::select!(@start $mode BRANCHES);
+        ::std::future::poll_fn(|cx|
+        {
+            for offset in 0..BRANCHES
+            {
+                let branch = (start + offset) % BRANCHES;
+                $(
+                    if branch == $($index)*
+                    {
+                        let ( $($skip,)* future, .. ) =
+                            unsafe { futures.as_mut().get_unchecked_mut() };
+                        if unsafe { Pin::new_unchecked(future) }.poll(cx).is_ready()
+                        {
+                            return Poll::Ready(());
+                        }
+                    }
+                )*
+            }
+            Poll::Pending
+        }).await;
+
+        // Only the completed branch has an output. Dropping the futures
+        // cancels the other branches before the handler runs.
+        $(
+            if let Some(output) =
+            {
+                let ( $($skip,)* future, .. ) =
+                    unsafe { futures.as_mut().get_unchecked_mut() };
+                unsafe { Pin::new_unchecked(future) }.take_output()
+            }
+            {
+                drop(futures);
+                let $p = output;
+                $h
+            }
+            else
+        )*
+        {
+            unreachable!("select! completed without output")
+        }
+    }};
+
+    (@start biased $branches:expr) => { 0 };
+    (@start fair $branches:expr) =>
+    {
+        $crate::future::random_start($branches)
+    };
+
+    (@ $mode:ident { ( $($s:tt)* ) [ $($i:tt)* ] $($t:tt)* }
+        $p:pat = $e:expr => $h:expr $(, $($r:tt)*)?
+    ) =>
+    {
+        $crate::select!(@ $mode { ( $($s)* _ ) [ $($i)* + 1 ] $($t)*
+            ( $($s)* ) [ $($i)* ] $p = $e => $h,
+        } $($($r)*)?)
+    };
+
+    (biased; $($branches:tt)+) =>
+    {
+        $crate::select!(@ biased { () [ 0 ] } $($branches)+)
+    };
+
+    ($($branches:tt)+) =>
+    {
+        $crate::select!(@ fair { () [ 0 ] } $($branches)+)
+    };
+}
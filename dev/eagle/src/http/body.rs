@@ -0,0 +1,177 @@
+//------------------------------------------------------------------------------
+//! # Body
+//------------------------------------------------------------------------------
+
+use crate::stream::{ Stream, StreamExt };
+
+use std::fmt;
+use std::io;
+use std::mem;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # Frame
+///
+/// - Data: A chunk of the body.
+/// - Trailers: The fields sent after the body, with lowercase names.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame
+{
+    Data(Vec<u8>),
+    Trailers(Vec<(String, String)>),
+}
+
+
+//------------------------------------------------------------------------------
+/// # Body
+///
+/// The body of a request or a response, either held in a buffer, or a
+/// stream of frames produced or consumed as it is transferred, with its
+/// length if known.
+///
+/// A response with a stream of unknown length is sent with the chunked
+/// transfer coding, along with its trailers. To an HTTP/1.0 client, which
+/// does not know the chunked coding, it is sent until the connection closes,
+/// and its trailers are dropped.
+//------------------------------------------------------------------------------
+pub enum Body
+{
+    Full(Vec<u8>),
+    Stream
+    {
+        frames: Pin<Box<dyn Stream<Item = io::Result<Frame>> + Send>>,
+        length: Option<u64>,
+    },
+}
+
+impl Body
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new empty Body.
+    //--------------------------------------------------------------------------
+    pub fn empty() -> Self
+    {
+        Self::Full(Vec::new())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new Body from a stream of frames of unknown length.
+    //--------------------------------------------------------------------------
+    pub fn stream<S>( frames: S ) -> Self
+        where S: Stream<Item = io::Result<Frame>> + Send + 'static,
+    {
+        Self::Stream { frames: Box::pin(frames), length: None }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new Body from a stream of frames whose data adds up to the
+    /// length. Sending a different length is an error, which closes the
+    /// connection.
+    //--------------------------------------------------------------------------
+    pub fn sized<S>( length: u64, frames: S ) -> Self
+        where S: Stream<Item = io::Result<Frame>> + Send + 'static,
+    {
+        Self::Stream { frames: Box::pin(frames), length: Some(length) }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the length of the body, if known.
+    //--------------------------------------------------------------------------
+    pub fn length( &self ) -> Option<u64>
+    {
+        match self
+        {
+            Self::Full(data) => Some(data.len() as u64),
+            Self::Stream { length, .. } => *length,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Reads the whole body into a buffer, and returns it with the trailers.
+    /// Fails if the body is longer than the limit.
+    //--------------------------------------------------------------------------
+    pub async fn collect
+    (
+        mut self,
+        limit: usize,
+    ) -> io::Result<(Vec<u8>, Vec<(String, String)>)>
+    {
+        let mut data = Vec::new();
+        let mut trailers = Vec::new();
+        while let Some(frame) = self.next().await
+        {
+            match frame?
+            {
+                Frame::Data(chunk) if chunk.len() > limit - data.len() =>
+                {
+                    let error = "body exceeds the limit";
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+                },
+                Frame::Data(chunk) => data.extend_from_slice(&chunk),
+                Frame::Trailers(fields) => trailers.extend(fields),
+            }
+        }
+        Ok((data, trailers))
+    }
+}
+
+impl Stream for Body
+{
+    type Item = io::Result<Frame>;
+
+    fn poll_next
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Self::Item>>
+    {
+        match self.get_mut()
+        {
+            Self::Full(data) if data.is_empty() => Poll::Ready(None),
+            Self::Full(data) => Poll::Ready(Some(Ok(Frame::Data(mem::take(data))))),
+            Self::Stream { frames, .. } => frames.as_mut().poll_next(cx),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body
+{
+    fn from( data: Vec<u8> ) -> Self
+    {
+        Self::Full(data)
+    }
+}
+
+impl From<String> for Body
+{
+    fn from( text: String ) -> Self
+    {
+        Self::Full(text.into_bytes())
+    }
+}
+
+impl From<&str> for Body
+{
+    fn from( text: &str ) -> Self
+    {
+        Self::Full(text.as_bytes().to_vec())
+    }
+}
+
+impl fmt::Debug for Body
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Full(data) => f.debug_tuple("Full").field(&data.len()).finish(),
+            Self::Stream { length, .. } =>
+            {
+                f.debug_struct("Stream").field("length", length).finish_non_exhaustive()
+            },
+        }
+    }
+}
@@ -0,0 +1,365 @@
+//------------------------------------------------------------------------------
+//! # ChunkedCodec
+//------------------------------------------------------------------------------
+
+use super::body::Frame;
+use super::{ parse_field, MAX_HEAD_SIZE };
+use crate::codec::{ Decoder, Encoder };
+
+use std::io;
+use std::str;
+
+/// Maximum length of a chunk size line, extensions included.
+const MAX_SIZE_LINE: usize = 1024;
+
+
+//------------------------------------------------------------------------------
+/// # ChunkedCodec
+///
+/// The chunked transfer coding. A body is decoded incrementally into data
+/// frames as its chunks arrive, and ends with a trailers frame, possibly
+/// empty, after which nothing more is decoded. Chunk extensions are ignored.
+///
+/// Encoding a data frame writes a chunk, and encoding a trailers frame writes
+/// the last chunk and the trailers, which ends the body.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Default)]
+pub struct ChunkedCodec
+{
+    state: State,
+    trailers: Vec<(String, String)>,
+    trailers_size: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+enum State
+{
+    #[default]
+    Size,
+    Data(u64),
+    DataEnd,
+    Trailers,
+    Done,
+}
+
+impl ChunkedCodec
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new ChunkedCodec.
+    //--------------------------------------------------------------------------
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the whole body has been decoded.
+    //--------------------------------------------------------------------------
+    pub fn is_done( &self ) -> bool
+    {
+        matches!(self.state, State::Done)
+    }
+}
+
+impl Decoder for ChunkedCodec
+{
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn decode( &mut self, src: &mut Vec<u8> ) -> io::Result<Option<Frame>>
+    {
+        loop
+        {
+            match self.state
+            {
+                State::Size =>
+                {
+                    let Some(line) = take_line(src, MAX_SIZE_LINE)? else
+                    {
+                        return Ok(None);
+                    };
+                    let size = line.split(';').next().unwrap_or_default();
+                    let size = size.trim_end_matches([' ', '\t']);
+
+                    // `from_str_radix` would also take a sign.
+                    if size.is_empty()
+                        || size.len() > 16
+                        || !size.bytes().all(|b| b.is_ascii_hexdigit())
+                    {
+                        return Err(invalid("invalid chunk size"));
+                    }
+                    let size = u64::from_str_radix(size, 16)
+                        .map_err(|_| invalid("invalid chunk size"))?;
+                    self.state = match size
+                    {
+                        0 => State::Trailers,
+                        size => State::Data(size),
+                    };
+                },
+                State::Data(remaining) =>
+                {
+                    if src.is_empty()
+                    {
+                        return Ok(None);
+                    }
+                    let length = usize::try_from(remaining).unwrap_or(usize::MAX).min(src.len());
+                    let data: Vec<u8> = src.drain(..length).collect();
+                    self.state = match remaining - length as u64
+                    {
+                        0 => State::DataEnd,
+                        remaining => State::Data(remaining),
+                    };
+                    return Ok(Some(Frame::Data(data)));
+                },
+                State::DataEnd =>
+                {
+                    match src.as_slice()
+                    {
+                        [b'\r', b'\n', ..] => drop(src.drain(..2)),
+                        [b'\n', ..] => drop(src.drain(..1)),
+                        [] | [b'\r'] => return Ok(None),
+                        _ => return Err(invalid("missing chunk terminator")),
+                    }
+                    self.state = State::Size;
+                },
+                State::Trailers =>
+                {
+                    let limit = MAX_HEAD_SIZE - self.trailers_size;
+                    let Some(line) = take_line(src, limit)? else
+                    {
+                        return Ok(None);
+                    };
+                    if line.is_empty()
+                    {
+                        self.state = State::Done;
+                        return Ok(Some(Frame::Trailers(std::mem::take(&mut self.trailers))));
+                    }
+                    self.trailers_size += line.len();
+                    let field = parse_field(&line).ok_or_else(|| invalid("invalid trailer"))?;
+                    self.trailers.push(field);
+                },
+                State::Done => return Ok(None),
+            }
+        }
+    }
+}
+
+impl Encoder<Frame> for ChunkedCodec
+{
+    type Error = io::Error;
+
+    fn encode( &mut self, item: Frame, dst: &mut Vec<u8> ) -> io::Result<()>
+    {
+        match item
+        {
+            // An empty chunk would end the body.
+            Frame::Data(data) if data.is_empty() => {},
+            Frame::Data(data) =>
+            {
+                dst.extend_from_slice(format!("{:X}\r\n", data.len()).as_bytes());
+                dst.extend_from_slice(&data);
+                dst.extend_from_slice(b"\r\n");
+            },
+            Frame::Trailers(trailers) =>
+            {
+                dst.extend_from_slice(b"0\r\n");
+                for (name, value) in trailers
+                {
+                    dst.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+                }
+                dst.extend_from_slice(b"\r\n");
+            },
+        }
+        Ok(())
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # take_line
+///
+/// Removes the line at the start of the buffer and returns it without its
+/// line ending, or `None` if the buffer does not hold a whole line yet. Fails
+/// if the line is longer than the limit, or is not valid UTF-8.
+//------------------------------------------------------------------------------
+fn take_line( src: &mut Vec<u8>, limit: usize ) -> io::Result<Option<String>>
+{
+    let Some(newline) = src.iter().take(limit + 2).position(|byte| *byte == b'\n') else
+    {
+        return match src.len() > limit + 1
+        {
+            true => Err(invalid("line too long")),
+            false => Ok(None),
+        };
+    };
+    let line: Vec<u8> = src.drain(..=newline).collect();
+    let line = line.strip_suffix(b"\n").unwrap_or(&line);
+    let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+    // A line ending with a bare newline may be one byte over the limit.
+    if line.len() > limit
+    {
+        return Err(invalid("line too long"));
+    }
+    match str::from_utf8(line)
+    {
+        Ok(line) => Ok(Some(line.to_string())),
+        Err(_) => Err(invalid("line is not valid UTF-8")),
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # invalid
+//------------------------------------------------------------------------------
+fn invalid( message: &str ) -> io::Error
+{
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+
+    /// Data and trailers of a decoded body.
+    type Decoded = (Vec<u8>, Vec<(String, String)>);
+
+    //--------------------------------------------------------------------------
+    /// Decodes the bytes fed in pieces of the given size, and returns the
+    /// data and the trailers.
+    //--------------------------------------------------------------------------
+    fn decode_in_pieces
+    (
+        body: &[u8],
+        piece: usize,
+    ) -> io::Result<Decoded>
+    {
+        let mut codec = ChunkedCodec::new();
+        let mut src = Vec::new();
+        let mut data = Vec::new();
+        for bytes in body.chunks(piece)
+        {
+            src.extend_from_slice(bytes);
+            while let Some(frame) = codec.decode(&mut src)?
+            {
+                match frame
+                {
+                    Frame::Data(bytes) => data.extend(bytes),
+                    Frame::Trailers(trailers) =>
+                    {
+                        assert!(codec.is_done());
+                        assert_eq!(codec.decode(&mut src)?, None);
+                        return Ok((data, trailers));
+                    },
+                }
+            }
+        }
+        Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+    }
+
+    #[test]
+    fn chunks_split_anywhere_decode_the_same()
+    {
+        let body = b"5;ext=1\r\nhello\r\n7\n, world\n0\r\nX-Sum: 42\r\n\r\n";
+        for piece in 1..body.len()
+        {
+            let (data, trailers) = decode_in_pieces(body, piece).unwrap();
+            assert_eq!(data, b"hello, world");
+            assert_eq!(trailers, [("x-sum".to_string(), "42".to_string())]);
+        }
+    }
+
+    #[test]
+    fn missing_chunk_terminator_is_rejected()
+    {
+        for body in [&b"5\r\nhelloX\r\n0\r\n\r\n"[..], b"5\r\nhello\rX0\r\n\r\n"]
+        {
+            let error = decode_in_pieces(body, body.len()).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn truncated_body_fails_at_the_end_of_the_stream()
+    {
+        let mut codec = ChunkedCodec::new();
+        let mut src = b"5\r\nhel".to_vec();
+        assert_eq!(codec.decode_eof(&mut src).unwrap(), Some(Frame::Data(b"hel".to_vec())));
+        let mut src = b"lo\r".to_vec();
+        assert_eq!(codec.decode_eof(&mut src).unwrap(), Some(Frame::Data(b"lo".to_vec())));
+        let error = codec.decode_eof(&mut src).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn size_line_is_limited()
+    {
+        let mut body = b"5;".to_vec();
+        body.resize(MAX_SIZE_LINE + 8, b'x');
+        let error = decode_in_pieces(&body, 64).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn trailers_are_limited_to_the_head_size()
+    {
+        let field = format!("x-pad: {}", "a".repeat(MAX_HEAD_SIZE / 4));
+        let mut fits = b"0\r\n".to_vec();
+        let mut over = fits.clone();
+        for _ in 0..3
+        {
+            fits.extend_from_slice(format!("{}\r\n", field).as_bytes());
+        }
+        for _ in 0..5
+        {
+            over.extend_from_slice(format!("{}\r\n", field).as_bytes());
+        }
+        fits.extend_from_slice(b"\r\n");
+        over.extend_from_slice(b"\r\n");
+
+        let (_, trailers) = decode_in_pieces(&fits, 100).unwrap();
+        assert_eq!(trailers.len(), 3);
+        let error = decode_in_pieces(&over, 100).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        // A line ending with a bare newline is held to the same limit.
+        let mut bare = format!("0\r\nx-pad: {}\n", "a".repeat(MAX_HEAD_SIZE - 6)).into_bytes();
+        bare.extend_from_slice(b"x: y\r\n\r\n");
+        let error = decode_in_pieces(&bare, bare.len()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn chunk_size_is_hex_digits_only()
+    {
+        for line in ["+5", "-5", " 5", "5 x", "0x5", "", "12345678123456789"]
+        {
+            let mut src = format!("{}\r\nhello\r\n", line).into_bytes();
+            let error = ChunkedCodec::new().decode(&mut src).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", line);
+        }
+
+        let mut src = b"0000000000000005 \t;name=value\r\nhello".to_vec();
+        let frame = ChunkedCodec::new().decode(&mut src).unwrap();
+        assert_eq!(frame, Some(Frame::Data(b"hello".to_vec())));
+    }
+
+    #[test]
+    fn encoded_frames_decode_back()
+    {
+        let mut codec = ChunkedCodec::new();
+        let mut encoded = Vec::new();
+        codec.encode(Frame::Data(b"hello".to_vec()), &mut encoded).unwrap();
+        codec.encode(Frame::Data(Vec::new()), &mut encoded).unwrap();
+        codec.encode(Frame::Data(vec![b'x'; 300]), &mut encoded).unwrap();
+        let trailers = vec![("x-sum".to_string(), "1".to_string())];
+        codec.encode(Frame::Trailers(trailers.clone()), &mut encoded).unwrap();
+        assert!(encoded.starts_with(b"5\r\nhello\r\n12C\r\n"));
+
+        let (data, decoded) = decode_in_pieces(&encoded, 7).unwrap();
+        assert_eq!(data.len(), 305);
+        assert_eq!(decoded, trailers);
+    }
+}
@@ -0,0 +1,1197 @@
+//------------------------------------------------------------------------------
+//! # HTTP
+//!
+//! HTTP/1.x connections. A connection is persistent by default in HTTP/1.1,
+//! and when the client asks for it with `Connection: keep-alive` in HTTP/1.0.
+//! Pipelined requests are answered in order, and the responses to the
+//! requests received together are written together.
+//!
+//! Bodies are streamed: the handler reads the body of a request as it
+//! arrives, with `Content-Length` or the chunked transfer coding, and a
+//! response of unknown length is sent with the chunked transfer coding.
+//!
+//! ```no_run
+//! use eagle::http::{ self, Body, Frame, HttpOptions, Response };
+//! use eagle::net::TcpStream;
+//! use eagle::stream::StreamExt;
+//! use eagle::sync::{ mpsc, watch };
+//!
+//! # async fn example( stream: TcpStream ) {
+//! let (_stop_tx, stop) = watch::channel(false);
+//! http::serve(stream, HttpOptions::default(), stop, |mut request| async move
+//! {
+//!     // Sends back the size of each chunk of the upload as it arrives.
+//!     let (tx, rx) = mpsc::channel(1);
+//!     eagle::spawn(async move
+//!     {
+//!         while let Some(Ok(Frame::Data(data))) = request.body.next().await
+//!         {
+//!             let _ = tx.send(Ok(Frame::Data(format!("{}\n", data.len()).into()))).await;
+//!         }
+//!     });
+//!     Response::new(200, Body::stream(rx))
+//! }).await;
+//! # }
+//! ```
+//------------------------------------------------------------------------------
+
+mod body;
+mod chunked;
+
+pub use body::{ Body, Frame };
+pub use chunked::ChunkedCodec;
+
+use crate::codec::{ Decoder, Encoder };
+use crate::io::{ split, AsyncRead, AsyncWrite, AsyncWriteExt };
+use crate::stream::StreamExt;
+use crate::sync::{ mpsc, watch };
+use crate::timer::Timer;
+
+use std::fmt::Write;
+use std::future::{ pending, poll_fn, Future };
+use std::io;
+use std::pin::{ pin, Pin };
+use std::str;
+use std::task::Poll;
+use std::time::Duration;
+
+/// Maximum size of the request line and headers of a request, and of the
+/// trailers of a chunked body.
+const MAX_HEAD_SIZE: usize = 8 * 1024;
+
+/// Number of bytes read from the connection at a time.
+const READ_CHUNK: usize = 8 * 1024;
+
+/// Number of frames of a request body read ahead of the handler.
+const BODY_FRAMES: usize = 4;
+
+
+//------------------------------------------------------------------------------
+/// # HttpOptions
+///
+/// - keep_alive: Whether connections may be persistent.
+/// - keep_alive_timeout: How long a connection may wait for a request, or
+///   for the next bytes of a request.
+/// - max_requests: Requests served on a connection before it is closed.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub struct HttpOptions
+{
+    pub keep_alive: bool,
+    pub keep_alive_timeout: Duration,
+    pub max_requests: Option<usize>,
+}
+
+impl Default for HttpOptions
+{
+    fn default() -> Self
+    {
+        Self
+        {
+            keep_alive: true,
+            keep_alive_timeout: Duration::from_secs(5),
+            max_requests: Some(1000),
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Request
+///
+/// A request, with lowercase header names. Its body is read from the
+/// connection as the handler consumes it.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct Request
+{
+    pub method: String,
+    pub target: String,
+    pub minor_version: u8,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+impl Request
+{
+    //--------------------------------------------------------------------------
+    /// Returns the value of the first header with the lowercase name.
+    //--------------------------------------------------------------------------
+    pub fn header( &self, name: &str ) -> Option<&str>
+    {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the `Connection` header has the option.
+    //--------------------------------------------------------------------------
+    fn has_connection_option( &self, option: &str ) -> bool
+    {
+        self.headers
+            .iter()
+            .filter(|(header, _)| header == "connection")
+            .flat_map(|(_, value)| value.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case(option))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the client asks for the connection to persist.
+    //--------------------------------------------------------------------------
+    fn wants_keep_alive( &self ) -> bool
+    {
+        match self.minor_version
+        {
+            0 => self.has_connection_option("keep-alive"),
+            _ => !self.has_connection_option("close"),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the client waits for `100 Continue` before sending the
+    /// body.
+    //--------------------------------------------------------------------------
+    fn expects_continue( &self ) -> bool
+    {
+        self.minor_version > 0
+            && self.header("expect").is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Response
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct Response
+{
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Body,
+}
+
+impl Response
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new Response.
+    //--------------------------------------------------------------------------
+    pub fn new( status: u16, body: impl Into<Body> ) -> Self
+    {
+        Self { status, headers: Vec::new(), body: body.into() }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Adds a header to the response.
+    //--------------------------------------------------------------------------
+    pub fn header( mut self, name: &str, value: &str ) -> Self
+    {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Appends the status line and the headers of the response to the buffer.
+    /// The framing headers set by the handler are left out for the ones of
+    /// the delimiter, and the connection header tells whether the connection
+    /// persists.
+    //--------------------------------------------------------------------------
+    fn encode_head
+    (
+        &self,
+        out: &mut Vec<u8>,
+        delimiter: Delimiter,
+        keep_alive: bool,
+        minor_version: u8,
+    )
+    {
+        let mut head = String::new();
+        let _ = write!(head, "HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers
+        {
+            if name.eq_ignore_ascii_case("content-length")
+                || name.eq_ignore_ascii_case("transfer-encoding")
+            {
+                continue;
+            }
+            let _ = write!(head, "{}: {}\r\n", name, value);
+        }
+        match delimiter
+        {
+            Delimiter::Length(length) =>
+            {
+                let _ = write!(head, "Content-Length: {}\r\n", length);
+            },
+            Delimiter::Chunked => head.push_str("Transfer-Encoding: chunked\r\n"),
+            Delimiter::Close | Delimiter::Empty => {},
+        }
+        match (keep_alive, minor_version)
+        {
+            (false, _) => head.push_str("Connection: close\r\n"),
+            (true, 0) => head.push_str("Connection: keep-alive\r\n"),
+            (true, _) => {},
+        }
+        head.push_str("\r\n");
+        out.extend_from_slice(head.as_bytes());
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Delimiter
+///
+/// How the end of the body of a response is found.
+///
+/// - Length: After `Content-Length` bytes.
+/// - Chunked: At the last chunk of the chunked transfer coding.
+/// - Close: When the connection closes.
+/// - Empty: There is no body, as for the 1xx, 204 and 304 responses.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+enum Delimiter
+{
+    Length(u64),
+    Chunked,
+    Close,
+    Empty,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Framing
+///
+/// How the end of the body of a request is found.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+enum Framing
+{
+    Length(u64),
+    Chunked,
+}
+
+
+//------------------------------------------------------------------------------
+/// # serve
+///
+/// Serves the requests of the connection with the handler, until the
+/// connection is closed by either side.
+///
+/// The body of a request is read while the handler runs and its response is
+/// written, so a response may stream while the request body still arrives.
+/// The connection is closed after a response produced before the body was
+/// read to its end.
+///
+/// A connection that is not persistent is closed after its response. An idle
+/// connection is closed after the keep-alive timeout, or as soon as the stop
+/// channel is set.
+//------------------------------------------------------------------------------
+pub async fn serve<S, H, F>
+(
+    stream: S,
+    options: HttpOptions,
+    mut stop: watch::Receiver<bool>,
+    mut handler: H,
+)
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+        H: FnMut(Request) -> F,
+        F: Future<Output = Response>,
+{
+    let Ok(timer) = Timer::new() else
+    {
+        return;
+    };
+    let (mut reader, mut writer) = split(stream);
+    let mut buf = Vec::new();
+    let mut out = Vec::new();
+    let mut served = 0;
+    let mut skipped = 0;
+
+    loop
+    {
+        // The empty lines allowed before a request line are dropped as they
+        // arrive, so that a client sending only those does not look busy.
+        let blank = buf.iter().take_while(|byte| matches!(byte, b'\r' | b'\n')).count();
+        buf.drain(..blank);
+        skipped += blank;
+
+        match parse_head(&buf, skipped)
+        {
+            Parsed::Complete(mut request, len) =>
+            {
+                buf.drain(..len);
+                skipped = 0;
+                served += 1;
+                let mut keep_alive = options.keep_alive
+                    && request.wants_keep_alive()
+                    && options.max_requests.is_none_or(|max| served < max)
+                    && !*stop.borrow();
+                let minor_version = request.minor_version;
+                let head = request.method == "HEAD";
+
+                let framing = match body_framing(&request)
+                {
+                    Ok(framing) => framing,
+                    Err(status) =>
+                    {
+                        let response = Response::new(status, "");
+                        let written = write_response
+                        (
+                            &mut writer,
+                            &mut out,
+                            response,
+                            false,
+                            minor_version,
+                            head,
+                        ).await;
+                        if written.is_ok()
+                        {
+                            let _ = finish(&mut writer, &out).await;
+                        }
+                        return;
+                    },
+                };
+                // A request with both lengths may be an attempt to smuggle
+                // another one, so the connection is not reused.
+                if request.header("transfer-encoding").is_some()
+                    && request.header("content-length").is_some()
+                {
+                    keep_alive = false;
+                }
+
+                let (sender, receiver) = mpsc::channel(BODY_FRAMES);
+                if let Some(framing) = framing
+                {
+                    request.body = match framing
+                    {
+                        Framing::Length(length) => Body::sized(length, receiver),
+                        Framing::Chunked => Body::stream(receiver),
+                    };
+                    if request.expects_continue() && buf.is_empty()
+                    {
+                        out.extend_from_slice(b"HTTP/1.1 100 Continue\r\n\r\n");
+                    }
+                    // The responses to the previous requests are written
+                    // before the body is read.
+                    if flush(&mut writer, &mut out).await.is_err()
+                    {
+                        return;
+                    }
+                }
+
+                let written =
+                {
+                    let mut body = pin!(read_body
+                    (
+                        &mut reader,
+                        &mut buf,
+                        framing,
+                        sender,
+                        &timer,
+                        options.keep_alive_timeout,
+                    ));
+                    let mut read = None;
+                    let response = alongside(body.as_mut(), &mut read, handler(request)).await;
+                    keep_alive = keep_alive && matches!(read, Some(Ok(())));
+                    let written = write_response
+                    (
+                        &mut writer,
+                        &mut out,
+                        response,
+                        keep_alive,
+                        minor_version,
+                        head,
+                    );
+                    alongside(body.as_mut(), &mut read, written).await
+                };
+
+                match written
+                {
+                    // The next request may be pipelined after this one.
+                    Ok(true) => continue,
+                    Ok(false) =>
+                    {
+                        let _ = finish(&mut writer, &out).await;
+                        return;
+                    },
+                    Err(_) => return,
+                }
+            },
+            Parsed::Invalid(status) =>
+            {
+                let response = Response::new(status, "");
+                if write_response(&mut writer, &mut out, response, false, 1, false).await.is_ok()
+                {
+                    let _ = finish(&mut writer, &out).await;
+                }
+                return;
+            },
+            Parsed::Partial => {},
+        }
+
+        // Every request received is answered before waiting for more.
+        if flush(&mut writer, &mut out).await.is_err()
+        {
+            return;
+        }
+
+        let idle = buf.is_empty();
+        if idle && *stop.borrow()
+        {
+            let _ = writer.shutdown().await;
+            return;
+        }
+        let stop = match idle
+        {
+            true => Some(&mut stop),
+            false => None,
+        };
+        match fill(&mut reader, &mut buf, &timer, options.keep_alive_timeout, stop).await
+        {
+            Ok(true) => {},
+            Ok(false) =>
+            {
+                let _ = writer.shutdown().await;
+                return;
+            },
+            Err(_) => return,
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # alongside
+///
+/// Runs the future, and the body reader alongside it until the reader
+/// completes, storing its result.
+//------------------------------------------------------------------------------
+async fn alongside<R, F>
+(
+    mut reader: Pin<&mut R>,
+    read: &mut Option<io::Result<()>>,
+    future: F,
+) -> F::Output
+    where
+        R: Future<Output = io::Result<()>>,
+        F: Future,
+{
+    let mut future = pin!(future);
+    poll_fn(|cx|
+    {
+        if read.is_none()
+        {
+            if let Poll::Ready(result) = reader.as_mut().poll(cx)
+            {
+                *read = Some(result);
+            }
+        }
+        future.as_mut().poll(cx)
+    }).await
+}
+
+
+//------------------------------------------------------------------------------
+/// # fill
+///
+/// Reads more of the connection into the buffer. Returns false if the
+/// timeout expires, or the stop channel, if any, changes first. Fails if the
+/// connection is closed.
+//------------------------------------------------------------------------------
+async fn fill<S: AsyncRead + Unpin>
+(
+    stream: &mut S,
+    buf: &mut Vec<u8>,
+    timer: &Timer,
+    timeout: Duration,
+    stop: Option<&mut watch::Receiver<bool>>,
+) -> io::Result<bool>
+{
+    timer.set(timeout, None)?;
+    let mut stopped = pin!(async move
+    {
+        match stop
+        {
+            Some(stop) => { let _ = stop.changed().await; },
+            None => pending::<()>().await,
+        }
+    });
+
+    let mut chunk = [0; READ_CHUNK];
+    let read = poll_fn(|cx|
+    {
+        if stopped.as_mut().poll(cx).is_ready()
+        {
+            return Poll::Ready(None);
+        }
+        if timer.poll_expired(cx).is_ready()
+        {
+            return Poll::Ready(None);
+        }
+        Pin::new(&mut *stream).poll_read(cx, &mut chunk).map(Some)
+    }).await;
+    match read
+    {
+        Some(Ok(0)) => Err(io::ErrorKind::UnexpectedEof.into()),
+        Some(Ok(read)) =>
+        {
+            buf.extend_from_slice(&chunk[..read]);
+            Ok(true)
+        },
+        Some(Err(error)) => Err(error),
+        None => Ok(false),
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # read_body
+///
+/// Reads the body of a request, if any, from the buffer and the connection,
+/// and sends its frames to the handler. Once the handler drops the body, the
+/// rest is read and discarded. An error is also sent to the handler.
+//------------------------------------------------------------------------------
+async fn read_body<S: AsyncRead + Unpin>
+(
+    stream: &mut S,
+    buf: &mut Vec<u8>,
+    framing: Option<Framing>,
+    sender: mpsc::Sender<io::Result<Frame>>,
+    timer: &Timer,
+    timeout: Duration,
+) -> io::Result<()>
+{
+    let Some(framing) = framing else
+    {
+        return Ok(());
+    };
+    let mut sender = Some(sender);
+    let mut codec = ChunkedCodec::new();
+    let mut remaining = match framing
+    {
+        Framing::Length(length) => length,
+        Framing::Chunked => 0,
+    };
+
+    loop
+    {
+        let frame = match framing
+        {
+            Framing::Chunked => codec.decode(buf),
+            Framing::Length(_) if remaining == 0 => Ok(Some(Frame::Trailers(Vec::new()))),
+            Framing::Length(_) if buf.is_empty() => Ok(None),
+            Framing::Length(_) =>
+            {
+                let length = usize::try_from(remaining).unwrap_or(usize::MAX).min(buf.len());
+                remaining -= length as u64;
+                Ok(Some(Frame::Data(buf.drain(..length).collect())))
+            },
+        };
+
+        let read = match frame
+        {
+            Ok(Some(Frame::Trailers(trailers))) =>
+            {
+                if !trailers.is_empty()
+                {
+                    deliver(&mut sender, Ok(Frame::Trailers(trailers))).await;
+                }
+                return Ok(());
+            },
+            Ok(Some(frame)) =>
+            {
+                deliver(&mut sender, Ok(frame)).await;
+                continue;
+            },
+            Ok(None) => fill(stream, buf, timer, timeout, None).await,
+            Err(error) => Err(error),
+        };
+        let error = match read
+        {
+            Ok(true) => continue,
+            Ok(false) => io::Error::new(io::ErrorKind::TimedOut, "timed out reading the body"),
+            Err(error) => error,
+        };
+        deliver(&mut sender, Err(io::Error::new(error.kind(), error.to_string()))).await;
+        return Err(error);
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # deliver
+///
+/// Sends a frame of the body to the handler, unless it dropped the body.
+//------------------------------------------------------------------------------
+async fn deliver
+(
+    sender: &mut Option<mpsc::Sender<io::Result<Frame>>>,
+    frame: io::Result<Frame>,
+)
+{
+    if let Some(tx) = sender.as_ref()
+    {
+        if tx.send(frame).await.is_err()
+        {
+            *sender = None;
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # write_response
+///
+/// Writes the response, without its body for a response to `HEAD` or a status
+/// that has none. A body in a buffer is appended to the output buffer, to be
+/// written with the next responses; a streamed body is written as it is
+/// produced. Returns whether the connection persists.
+//------------------------------------------------------------------------------
+async fn write_response<S: AsyncWrite + Unpin>
+(
+    stream: &mut S,
+    out: &mut Vec<u8>,
+    response: Response,
+    keep_alive: bool,
+    minor_version: u8,
+    head: bool,
+) -> io::Result<bool>
+{
+    let delimiter = match response.body.length()
+    {
+        _ if !has_body(response.status) => Delimiter::Empty,
+        Some(length) => Delimiter::Length(length),
+        None if minor_version > 0 => Delimiter::Chunked,
+        None => Delimiter::Close,
+    };
+    let keep_alive = keep_alive && !matches!(delimiter, Delimiter::Close);
+    response.encode_head(out, delimiter, keep_alive, minor_version);
+    if head || matches!(delimiter, Delimiter::Empty)
+    {
+        return Ok(keep_alive);
+    }
+
+    let mut frames = match response.body
+    {
+        Body::Full(data) =>
+        {
+            out.extend_from_slice(&data);
+            return Ok(keep_alive);
+        },
+        body => body,
+    };
+
+    let mut codec = ChunkedCodec::new();
+    let mut written = 0u64;
+    let mut trailers = Vec::new();
+    flush(stream, out).await?;
+    while let Some(frame) = frames.next().await
+    {
+        let data = match frame?
+        {
+            Frame::Data(data) => data,
+            Frame::Trailers(fields) =>
+            {
+                trailers.extend(fields);
+                continue;
+            },
+        };
+        written += data.len() as u64;
+        match delimiter
+        {
+            Delimiter::Length(length) if written > length => return Err(length_mismatch()),
+            Delimiter::Chunked => codec.encode(Frame::Data(data), out)?,
+            _ => out.extend_from_slice(&data),
+        }
+        flush(stream, out).await?;
+    }
+    match delimiter
+    {
+        Delimiter::Length(length) if written != length => return Err(length_mismatch()),
+        Delimiter::Chunked => codec.encode(Frame::Trailers(trailers), out)?,
+        _ => {},
+    }
+    Ok(keep_alive)
+}
+
+
+//------------------------------------------------------------------------------
+/// # length_mismatch
+//------------------------------------------------------------------------------
+fn length_mismatch() -> io::Error
+{
+    io::Error::new(io::ErrorKind::InvalidData, "body does not match its length")
+}
+
+
+//------------------------------------------------------------------------------
+/// # flush
+///
+/// Writes the output buffer to the connection, and clears it.
+//------------------------------------------------------------------------------
+async fn flush<S: AsyncWrite + Unpin>( stream: &mut S, out: &mut Vec<u8> ) -> io::Result<()>
+{
+    if out.is_empty()
+    {
+        return Ok(());
+    }
+    stream.write_all(out).await?;
+    stream.flush().await?;
+    out.clear();
+    Ok(())
+}
+
+
+//------------------------------------------------------------------------------
+/// # finish
+///
+/// Writes the last responses and closes the connection.
+//------------------------------------------------------------------------------
+async fn finish<S: AsyncWrite + Unpin>( stream: &mut S, out: &[u8] ) -> io::Result<()>
+{
+    stream.write_all(out).await?;
+    stream.flush().await?;
+    stream.shutdown().await
+}
+
+
+//------------------------------------------------------------------------------
+/// # body_framing
+///
+/// Returns how the body of the request is delimited, or `None` if it has no
+/// body. Fails with the status to answer if the framing is invalid, or uses a
+/// transfer coding other than chunked.
+//------------------------------------------------------------------------------
+fn body_framing( request: &Request ) -> Result<Option<Framing>, u16>
+{
+    let codings: Vec<&str> = request.headers
+        .iter()
+        .filter(|(header, _)| header == "transfer-encoding")
+        .flat_map(|(_, value)| value.split(','))
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty())
+        .collect();
+    match codings.as_slice()
+    {
+        [] => {},
+        // The chunked coding is only defined for HTTP/1.1, and must be last.
+        _ if request.minor_version == 0 => return Err(400),
+        [coding] if coding.eq_ignore_ascii_case("chunked") => return Ok(Some(Framing::Chunked)),
+        [.., last] if !last.eq_ignore_ascii_case("chunked") => return Err(400),
+        _ => return Err(501),
+    }
+
+    // The length is digits only: `parse` would also take a sign.
+    let mut lengths = request.headers
+        .iter()
+        .filter(|(header, _)| header == "content-length")
+        .map(|(_, value)| value.trim())
+        .map(|value| match !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit())
+        {
+            true => value.parse::<u64>().ok(),
+            false => None,
+        });
+    match lengths.next()
+    {
+        None => Ok(None),
+        Some(Some(length)) if lengths.all(|other| other == Some(length)) =>
+        {
+            Ok((length > 0).then_some(Framing::Length(length)))
+        },
+        Some(_) => Err(400),
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Parsed
+//------------------------------------------------------------------------------
+enum Parsed
+{
+    Complete(Request, usize),
+    Partial,
+    Invalid(u16),
+}
+
+
+//------------------------------------------------------------------------------
+/// # parse_head
+///
+/// Parses the head of the request at the start of the buffer, and returns it
+/// with its length. The buffer starts after the empty lines before the
+/// request line, and `skipped` is their length, which counts towards the
+/// size of the head.
+//------------------------------------------------------------------------------
+fn parse_head( buf: &[u8], skipped: usize ) -> Parsed
+{
+    let end = buf
+        .windows(2)
+        .position(|window| window == b"\n\n")
+        .map(|position| (position, 2))
+        .into_iter()
+        .chain
+        (
+            buf
+                .windows(4)
+                .position(|window| window == b"\r\n\r\n")
+                .map(|position| (position, 4)),
+        )
+        .min_by_key(|(position, _)| *position);
+    let Some((end, terminator)) = end else
+    {
+        return match skipped + buf.len() > MAX_HEAD_SIZE
+        {
+            true => Parsed::Invalid(431),
+            false => Parsed::Partial,
+        };
+    };
+    if skipped + end > MAX_HEAD_SIZE
+    {
+        return Parsed::Invalid(431);
+    }
+
+    let Ok(head) = str::from_utf8(&buf[..end]) else
+    {
+        return Parsed::Invalid(400);
+    };
+    let mut lines = head.split('\n').map(|line| line.strip_suffix('\r').unwrap_or(line));
+
+    let Some(request_line) = lines.next() else
+    {
+        return Parsed::Invalid(400);
+    };
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next()) else
+    {
+        return Parsed::Invalid(400);
+    };
+    if method.is_empty() || target.is_empty()
+    {
+        return Parsed::Invalid(400);
+    }
+    let minor_version = match version
+    {
+        "HTTP/1.0" => 0,
+        "HTTP/1.1" => 1,
+        version if version.starts_with("HTTP/") => return Parsed::Invalid(505),
+        _ => return Parsed::Invalid(400),
+    };
+
+    let mut headers = Vec::new();
+    for line in lines
+    {
+        match parse_field(line)
+        {
+            Some(field) => headers.push(field),
+            None => return Parsed::Invalid(400),
+        }
+    }
+
+    let request = Request
+    {
+        method: method.to_string(),
+        target: target.to_string(),
+        minor_version,
+        headers,
+        body: Body::empty(),
+    };
+    Parsed::Complete(request, end + terminator)
+}
+
+
+//------------------------------------------------------------------------------
+/// # parse_field
+///
+/// Parses a header or trailer line into its lowercase name and its value.
+/// Line folding is obsolete, and rejected.
+//------------------------------------------------------------------------------
+fn parse_field( line: &str ) -> Option<(String, String)>
+{
+    let (name, value) = line.split_once(':')?;
+    if name.is_empty() || name.contains(|c: char| c.is_ascii_whitespace())
+    {
+        return None;
+    }
+    Some((name.to_ascii_lowercase(), value.trim().to_string()))
+}
+
+
+//------------------------------------------------------------------------------
+/// # has_body
+///
+/// Returns whether a response with the status has a body. The 1xx, 204 and
+/// 304 responses have none, and so no body framing either.
+//------------------------------------------------------------------------------
+fn has_body( status: u16 ) -> bool
+{
+    !matches!(status, 100..=199 | 204 | 304)
+}
+
+
+//------------------------------------------------------------------------------
+/// # reason
+///
+/// Returns the reason phrase of the status code.
+//------------------------------------------------------------------------------
+fn reason( status: u16 ) -> &'static str
+{
+    match status
+    {
+        200 => "OK",
+        204 => "No Content",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Content Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "",
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::{ yield_now, Executor };
+    use crate::sim::{ self, Simulation };
+    use crate::test_util::MockStream;
+
+    //--------------------------------------------------------------------------
+    /// Serves the stream with a handler echoing the method and target.
+    //--------------------------------------------------------------------------
+    fn run( stream: MockStream ) -> String
+    {
+        run_with(stream, |request: Request| async move
+        {
+            Response::new(200, format!("{} {}", request.method, request.target))
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Serves the stream with a handler echoing the target and the body.
+    //--------------------------------------------------------------------------
+    fn run_echo( stream: MockStream ) -> String
+    {
+        run_with(stream, |request: Request| async move
+        {
+            let body = match request.body.collect(1024).await
+            {
+                Ok((body, _)) => String::from_utf8(body).unwrap(),
+                Err(error) => error.to_string(),
+            };
+            Response::new(200, format!("{} {}", request.target, body))
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Serves the stream with the handler, and sets the stop channel after a
+    /// few yields, which only stops the connection once it is idle. Returns
+    /// what the server wrote.
+    //--------------------------------------------------------------------------
+    fn run_with<H, F>( stream: MockStream, handler: H ) -> String
+        where
+            H: FnMut(Request) -> F + Send + 'static,
+            F: Future<Output = Response> + Send,
+    {
+        let written = stream.written();
+        let options = HttpOptions
+        {
+            keep_alive_timeout: Duration::from_secs(60),
+            ..HttpOptions::default()
+        };
+        Executor::new(0).block_on(async move
+        {
+            let (stop_tx, stop_rx) = watch::channel(false);
+            crate::join!
+            (
+                serve(stream, options, stop_rx, handler),
+                async move
+                {
+                    for _ in 0..8
+                    {
+                        yield_now().await;
+                    }
+                    let _ = stop_tx.send(true);
+                },
+            );
+        }).unwrap();
+        let written = written.lock().unwrap().clone();
+        String::from_utf8(written).unwrap()
+    }
+
+    #[test]
+    fn empty_lines_leave_the_connection_idle()
+    {
+        let written = run(MockStream::new([&b"\r\n\r\n"[..], b"\n"]).stall());
+        assert_eq!(written, "");
+
+        let written = run(MockStream::new([&b"\r\nGET / HTTP/1.1\r\n\r\n"[..], b"\r\n"]).stall());
+        assert!(written.starts_with("HTTP/1.1 200 OK\r\n"), "{}", written);
+        assert!(written.ends_with("GET /"), "{}", written);
+    }
+
+    #[test]
+    fn empty_lines_count_towards_the_head_size()
+    {
+        let blank = b"\r\n".repeat(MAX_HEAD_SIZE / 2 + 1);
+        let written = run(MockStream::new([blank.as_slice()]).stall());
+        assert!(written.starts_with("HTTP/1.1 431 "), "{}", written);
+    }
+
+    #[test]
+    fn content_length_must_be_digits()
+    {
+        for length in ["+5", "-5", "5 5", "0x5", "", "99999999999999999999"]
+        {
+            let head = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\nhello", length);
+            let written = run(MockStream::new([head.as_bytes()]));
+            assert!(written.starts_with("HTTP/1.1 400 "), "{:?}: {}", length, written);
+        }
+
+        let head = "POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 5\r\n\r\nhello";
+        let written = run(MockStream::new([head.as_bytes()]));
+        assert!(written.starts_with("HTTP/1.1 200 "), "{}", written);
+    }
+
+    #[test]
+    fn idle_connection_is_closed_after_the_keep_alive_timeout()
+    {
+        let stream = MockStream::new([&b"GET / HTTP/1.1\r\n\r\n"[..]]).stall();
+        let written = stream.written();
+        let options = HttpOptions
+        {
+            keep_alive_timeout: Duration::from_secs(60),
+            ..HttpOptions::default()
+        };
+        let elapsed = Simulation::new(0).block_on(async move
+        {
+            let (_stop_tx, stop_rx) = watch::channel(false);
+            serve(stream, options, stop_rx, |_| async { Response::new(200, "") }).await;
+            sim::now()
+        }).unwrap();
+        assert_eq!(elapsed, Duration::from_secs(60));
+        let written = written.lock().unwrap().clone();
+        assert!(written.starts_with(b"HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn pipelined_requests_are_answered_in_order()
+    {
+        let written = run_echo(MockStream::new(
+        [
+            &b"GET /a HTTP/1.1\r\n\r\nPOST /b HTTP/1.1\r\nContent-Length: 5\r\n\r\nhel"[..],
+            b"loGET /c HTTP/1.1\r\nConnection: close\r\n\r\nGET /d HTTP/1.1\r\n\r\n",
+        ]));
+        let a = written.find("/a ").unwrap();
+        let b = written.find("/b hello").unwrap();
+        let c = written.find("/c ").unwrap();
+        assert!(a < b && b < c, "{}", written);
+        assert_eq!(written.matches("HTTP/1.1 200 OK").count(), 3, "{}", written);
+        assert!(!written.contains("/d"), "{}", written);
+    }
+
+    #[test]
+    fn chunked_body_with_content_length_closes_the_connection()
+    {
+        let written = run_echo(MockStream::new(
+        [
+            &b"POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n"[..],
+            b"5\r\nhello\r\n0\r\n\r\nGET /smuggled HTTP/1.1\r\n\r\n",
+        ]).stall());
+        assert!(written.contains("Connection: close\r\n"), "{}", written);
+        assert!(written.ends_with("/a hello"), "{}", written);
+        assert!(!written.contains("/smuggled"), "{}", written);
+    }
+
+    #[test]
+    fn continue_is_sent_before_a_body_not_received_yet()
+    {
+        let written = run_echo(MockStream::new(
+        [
+            &b"POST /a HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n"[..],
+            b"hello",
+        ]).stall());
+        assert!(written.starts_with("HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 200 OK\r\n"), "{}", written);
+        assert!(written.ends_with("/a hello"), "{}", written);
+
+        let written = run_echo(MockStream::new(
+        [
+            &b"POST /a HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\nhello"[..],
+        ]).stall());
+        assert!(written.starts_with("HTTP/1.1 200 OK\r\n"), "{}", written);
+    }
+
+    #[test]
+    fn early_response_does_not_wait_for_the_body()
+    {
+        let stream = MockStream::new
+        (
+            [&b"POST /upload HTTP/1.1\r\nContent-Length: 100\r\n\r\npart"[..]]
+        ).stall();
+        let written = run_with(stream, |_| async { Response::new(413, "too large") });
+        assert!(written.starts_with("HTTP/1.1 413 "), "{}", written);
+        assert!(written.contains("Connection: close\r\n"), "{}", written);
+        assert!(written.ends_with("too large"), "{}", written);
+
+        // A body read in full keeps the connection open for the next request.
+        let stream = MockStream::new
+        ([
+            &b"POST /a HTTP/1.1\r\nContent-Length: 4\r\n\r\npart"[..],
+            b"GET /b HTTP/1.1\r\n\r\n",
+        ]);
+        let written = run_with(stream, |request: Request| async move
+        {
+            Response::new(200, request.target)
+        });
+        assert!(written.ends_with("/b"), "{}", written);
+    }
+
+    #[test]
+    fn framing_headers_of_the_handler_are_replaced()
+    {
+        let written = run_with(MockStream::new([&b"GET / HTTP/1.1\r\n\r\n"[..]]), |_| async
+        {
+            Response::new(200, "hello")
+                .header("Content-Length", "3")
+                .header("transfer-encoding", "chunked")
+                .header("X-Kept", "yes")
+        });
+        assert_eq!(written.matches("Content-Length").count(), 1, "{}", written);
+        assert!(written.contains("Content-Length: 5\r\n"), "{}", written);
+        assert!(!written.to_ascii_lowercase().contains("transfer-encoding"), "{}", written);
+        assert!(written.contains("X-Kept: yes\r\n"), "{}", written);
+        assert!(written.ends_with("\r\n\r\nhello"), "{}", written);
+    }
+
+    #[test]
+    fn responses_without_a_body_have_no_framing()
+    {
+        for status in [204, 304]
+        {
+            let written = run_with(MockStream::new(
+            [
+                &b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n"[..],
+            ]), move |request: Request| async move
+            {
+                match request.target.as_str()
+                {
+                    "/a" => Response::new(status, "ignored").header("Content-Length", "7"),
+                    _ => Response::new(200, "next"),
+                }
+            });
+            let head = format!("HTTP/1.1 {} {}\r\n\r\n", status, reason(status));
+            assert!(written.starts_with(&format!("{}HTTP/1.1 200 OK\r\n", head)), "{}", written);
+            assert!(!written.contains("ignored"), "{}", written);
+            assert!(written.ends_with("next"), "{}", written);
+        }
+    }
+}
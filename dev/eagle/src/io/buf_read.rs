@@ -0,0 +1,377 @@
+//------------------------------------------------------------------------------
+//! # AsyncBufRead
+//------------------------------------------------------------------------------
+
+use super::read::AsyncRead;
+use crate::stream::Stream;
+
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # AsyncBufRead
+///
+/// Reader with an internal buffer, which can be searched before the bytes are
+/// consumed.
+//------------------------------------------------------------------------------
+pub trait AsyncBufRead: AsyncRead
+{
+    //--------------------------------------------------------------------------
+    /// Attempts to return the buffered data, filling the buffer first if it
+    /// is empty. An empty slice means the end of the stream.
+    //--------------------------------------------------------------------------
+    fn poll_fill_buf( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<&[u8]>>;
+
+    //--------------------------------------------------------------------------
+    /// Marks the given number of buffered bytes as read.
+    //--------------------------------------------------------------------------
+    fn consume( self: Pin<&mut Self>, amount: usize );
+}
+
+impl<R: AsyncBufRead + Unpin + ?Sized> AsyncBufRead for &mut R
+{
+    fn poll_fill_buf( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<&[u8]>>
+    {
+        Pin::new(&mut **self.get_mut()).poll_fill_buf(cx)
+    }
+
+    fn consume( mut self: Pin<&mut Self>, amount: usize )
+    {
+        Pin::new(&mut **self).consume(amount)
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + ?Sized> AsyncBufRead for Box<R>
+{
+    fn poll_fill_buf( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<&[u8]>>
+    {
+        Pin::new(&mut **self.get_mut()).poll_fill_buf(cx)
+    }
+
+    fn consume( mut self: Pin<&mut Self>, amount: usize )
+    {
+        Pin::new(&mut **self).consume(amount)
+    }
+}
+
+impl AsyncBufRead for &[u8]
+{
+    fn poll_fill_buf( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<&[u8]>>
+    {
+        Poll::Ready(Ok(*self.get_mut()))
+    }
+
+    fn consume( mut self: Pin<&mut Self>, amount: usize )
+    {
+        *self = &self[amount..];
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # AsyncBufReadExt
+///
+/// Extension methods for buffered readers.
+//------------------------------------------------------------------------------
+pub trait AsyncBufReadExt: AsyncBufRead
+{
+    //--------------------------------------------------------------------------
+    /// Reads until the delimiter or the end of the stream, appending the bytes
+    /// to the vector, delimiter included. Returns the number of bytes read.
+    //--------------------------------------------------------------------------
+    fn read_until<'a>
+    (
+        &'a mut self,
+        delimiter: u8,
+        buf: &'a mut Vec<u8>,
+    ) -> ReadUntil<'a, Self>
+        where Self: Unpin
+    {
+        ReadUntil { reader: self, delimiter, buf, read: 0 }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Reads until a newline or the end of the stream, appending the line to
+    /// the string, newline included. Returns the number of bytes read, zero
+    /// at the end of the stream.
+    ///
+    /// Fails with `InvalidData` if the line is not valid UTF-8, leaving the
+    /// string unchanged.
+    //--------------------------------------------------------------------------
+    fn read_line<'a>( &'a mut self, line: &'a mut String ) -> ReadLine<'a, Self>
+        where Self: Unpin
+    {
+        ReadLine { reader: self, line, bytes: Vec::new(), read: 0 }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a stream over the lines of the reader, without their trailing
+    /// `\n` or `\r\n`.
+    //--------------------------------------------------------------------------
+    fn lines( self ) -> Lines<Self>
+        where Self: Sized
+    {
+        Lines { reader: self, bytes: Vec::new(), read: 0 }
+    }
+}
+
+impl<R: AsyncBufRead + ?Sized> AsyncBufReadExt for R {}
+
+
+//------------------------------------------------------------------------------
+/// # poll_read_until
+///
+/// Moves the buffered bytes up to the delimiter into the vector, counting them
+/// in `read`. Returns the count once the delimiter or the end of the stream is
+/// reached, and resets it.
+//------------------------------------------------------------------------------
+fn poll_read_until<R: AsyncBufRead + ?Sized>
+(
+    mut reader: Pin<&mut R>,
+    cx: &mut Context,
+    delimiter: u8,
+    buf: &mut Vec<u8>,
+    read: &mut usize,
+) -> Poll<io::Result<usize>>
+{
+    loop
+    {
+        let (done, used) =
+        {
+            let available = match reader.as_mut().poll_fill_buf(cx)
+            {
+                Poll::Ready(Ok(available)) => available,
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                Poll::Pending => return Poll::Pending,
+            };
+            match available.iter().position(|byte| *byte == delimiter)
+            {
+                Some(index) =>
+                {
+                    buf.extend_from_slice(&available[..=index]);
+                    (true, index + 1)
+                },
+                None =>
+                {
+                    buf.extend_from_slice(available);
+                    (available.is_empty(), available.len())
+                },
+            }
+        };
+        reader.as_mut().consume(used);
+        *read += used;
+        if done
+        {
+            return Poll::Ready(Ok(mem::take(read)));
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # ReadUntil
+///
+/// Future returned by `AsyncBufReadExt::read_until`.
+//------------------------------------------------------------------------------
+pub struct ReadUntil<'a, R: ?Sized>
+{
+    reader: &'a mut R,
+    delimiter: u8,
+    buf: &'a mut Vec<u8>,
+    read: usize,
+}
+
+impl<R: AsyncBufRead + Unpin + ?Sized> Future for ReadUntil<'_, R>
+{
+    type Output = io::Result<usize>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = &mut *self;
+        poll_read_until(Pin::new(&mut *this.reader), cx, this.delimiter, this.buf, &mut this.read)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # ReadLine
+///
+/// Future returned by `AsyncBufReadExt::read_line`. The bytes are collected
+/// apart, and appended to the string once the line is complete.
+//------------------------------------------------------------------------------
+pub struct ReadLine<'a, R: ?Sized>
+{
+    reader: &'a mut R,
+    line: &'a mut String,
+    bytes: Vec<u8>,
+    read: usize,
+}
+
+impl<R: AsyncBufRead + Unpin + ?Sized> Future for ReadLine<'_, R>
+{
+    type Output = io::Result<usize>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = &mut *self;
+        let reader = Pin::new(&mut *this.reader);
+        match poll_read_until(reader, cx, b'\n', &mut this.bytes, &mut this.read)
+        {
+            Poll::Ready(Ok(read)) =>
+            {
+                let line = into_string(mem::take(&mut this.bytes))?;
+                this.line.push_str(&line);
+                Poll::Ready(Ok(read))
+            },
+            polled => polled,
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Lines
+///
+/// Stream returned by `AsyncBufReadExt::lines`.
+//------------------------------------------------------------------------------
+pub struct Lines<R>
+{
+    reader: R,
+    bytes: Vec<u8>,
+    read: usize,
+}
+
+impl<R> Lines<R>
+{
+    //--------------------------------------------------------------------------
+    /// Returns the underlying reader.
+    //--------------------------------------------------------------------------
+    pub fn into_inner( self ) -> R
+    {
+        self.reader
+    }
+}
+
+impl<R: AsyncBufRead + Unpin> Stream for Lines<R>
+{
+    type Item = io::Result<String>;
+
+    fn poll_next
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Self::Item>>
+    {
+        let this = &mut *self;
+        let reader = Pin::new(&mut this.reader);
+        match poll_read_until(reader, cx, b'\n', &mut this.bytes, &mut this.read)
+        {
+            Poll::Ready(Ok(0)) => Poll::Ready(None),
+            Poll::Ready(Ok(_)) =>
+            {
+                let mut bytes = mem::take(&mut this.bytes);
+                if bytes.last() == Some(&b'\n')
+                {
+                    bytes.pop();
+                    if bytes.last() == Some(&b'\r')
+                    {
+                        bytes.pop();
+                    }
+                }
+                Poll::Ready(Some(into_string(bytes)))
+            },
+            Poll::Ready(Err(error)) => Poll::Ready(Some(Err(error))),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # into_string
+///
+/// Converts the bytes of a line into a string.
+//------------------------------------------------------------------------------
+fn into_string( bytes: Vec<u8> ) -> io::Result<String>
+{
+    String::from_utf8(bytes).map_err(|_|
+    {
+        io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+    })
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+    use crate::io::BufReader;
+    use crate::stream::StreamExt;
+    use crate::test_util::MockStream;
+
+    #[test]
+    fn read_line_spans_buffer_boundaries()
+    {
+        // The buffer holds four bytes, so the lines and the two bytes of the
+        // `é` are cut across refills.
+        let stream = MockStream::new([&b"caf\xc3"[..], b"\xa9 au lait\nsecond", b" line\nlast"]);
+        let mut reader = BufReader::with_capacity(4, stream);
+        let lines = Executor::new(0).block_on(async move
+        {
+            let mut lines = Vec::new();
+            loop
+            {
+                let mut line = String::new();
+                match reader.read_line(&mut line).await.unwrap()
+                {
+                    0 => return lines,
+                    read => lines.push((read, line)),
+                }
+            }
+        }).unwrap();
+        assert_eq!(lines,
+        [
+            (14, "caf\u{e9} au lait\n".to_string()),
+            (12, "second line\n".to_string()),
+            (4, "last".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn read_line_leaves_the_string_unchanged_on_invalid_utf8()
+    {
+        let mut reader = BufReader::with_capacity(4, MockStream::new([&b"ok\n\xff\xfe\n"[..]]));
+        let (first, error, line) = Executor::new(0).block_on(async move
+        {
+            let mut line = String::new();
+            let first = reader.read_line(&mut line).await.unwrap();
+            let error = reader.read_line(&mut line).await.unwrap_err();
+            (first, error, line)
+        }).unwrap();
+        assert_eq!(first, 3);
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(line, "ok\n");
+    }
+
+    #[test]
+    fn lines_strip_the_line_endings()
+    {
+        let stream = MockStream::new([&b"one\r"[..], b"\ntwo\n\nthree"]);
+        let mut lines = BufReader::with_capacity(3, stream).lines();
+        let lines = Executor::new(0).block_on(async move
+        {
+            let mut collected = Vec::new();
+            while let Some(line) = lines.next().await
+            {
+                collected.push(line.unwrap());
+            }
+            collected
+        }).unwrap();
+        assert_eq!(lines, ["one", "two", "", "three"]);
+    }
+}
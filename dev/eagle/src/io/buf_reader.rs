@@ -0,0 +1,223 @@
+//------------------------------------------------------------------------------
+//! # BufReader
+//------------------------------------------------------------------------------
+
+use super::buf_read::AsyncBufRead;
+use super::read::AsyncRead;
+use super::write::AsyncWrite;
+use super::DEFAULT_BUF_SIZE;
+
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # BufReader
+///
+/// Adds a buffer to a reader, so that small reads do not each reach the
+/// underlying object. Writes go straight through to it.
+//------------------------------------------------------------------------------
+pub struct BufReader<R>
+{
+    inner: R,
+    buf: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+}
+
+impl<R: AsyncRead> BufReader<R>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new BufReader with the default capacity.
+    //--------------------------------------------------------------------------
+    pub fn new( inner: R ) -> Self
+    {
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new BufReader with the given capacity.
+    //--------------------------------------------------------------------------
+    pub fn with_capacity( capacity: usize, inner: R ) -> Self
+    {
+        Self
+        {
+            inner,
+            buf: vec![0; capacity].into_boxed_slice(),
+            pos: 0,
+            filled: 0,
+        }
+    }
+}
+
+impl<R> BufReader<R>
+{
+    //--------------------------------------------------------------------------
+    /// Returns a reference to the underlying reader.
+    //--------------------------------------------------------------------------
+    pub fn get_ref( &self ) -> &R
+    {
+        &self.inner
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a mutable reference to the underlying reader. Reading from it
+    /// directly skips the buffered data.
+    //--------------------------------------------------------------------------
+    pub fn get_mut( &mut self ) -> &mut R
+    {
+        &mut self.inner
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the buffered data.
+    //--------------------------------------------------------------------------
+    pub fn buffer( &self ) -> &[u8]
+    {
+        &self.buf[self.pos..self.filled]
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the underlying reader. The buffered data is lost.
+    //--------------------------------------------------------------------------
+    pub fn into_inner( self ) -> R
+    {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for BufReader<R>
+{
+    fn poll_read
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        // A read at least as large as the buffer bypasses it when it is empty.
+        if self.pos == self.filled && buf.len() >= self.buf.len()
+        {
+            return Pin::new(&mut self.inner).poll_read(cx, buf);
+        }
+
+        let available = match self.as_mut().poll_fill_buf(cx)
+        {
+            Poll::Ready(Ok(available)) => available,
+            Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+            Poll::Pending => return Poll::Pending,
+        };
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufRead for BufReader<R>
+{
+    fn poll_fill_buf( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<&[u8]>>
+    {
+        let this = self.get_mut();
+        if this.pos >= this.filled
+        {
+            match Pin::new(&mut this.inner).poll_read(cx, &mut this.buf)
+            {
+                Poll::Ready(Ok(read)) =>
+                {
+                    this.pos = 0;
+                    this.filled = read;
+                },
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(&this.buf[this.pos..this.filled]))
+    }
+
+    fn consume( mut self: Pin<&mut Self>, amount: usize )
+    {
+        self.pos = (self.pos + amount).min(self.filled);
+    }
+}
+
+impl<R: AsyncWrite + Unpin> AsyncWrite for BufReader<R>
+{
+    fn poll_write
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+impl<R: fmt::Debug> fmt::Debug for BufReader<R>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("BufReader")
+            .field("inner", &self.inner)
+            .field("buffered", &(self.filled - self.pos))
+            .field("capacity", &self.buf.len())
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+    use crate::io::AsyncReadExt;
+    use crate::test_util::MockStream;
+
+    #[test]
+    fn small_reads_are_served_from_the_buffer()
+    {
+        let mut reader = BufReader::with_capacity(8, MockStream::new([&b"abcdef"[..], b"ghij"]));
+        Executor::new(0).block_on(async move
+        {
+            let mut buf = [0; 2];
+            assert_eq!(reader.read(&mut buf).await.unwrap(), 2);
+            assert_eq!(&buf, b"ab");
+            assert_eq!(reader.buffer(), b"cdef");
+
+            // A read does not go past the buffered data.
+            let mut buf = [0; 16];
+            assert_eq!(reader.read(&mut buf).await.unwrap(), 4);
+            assert_eq!(&buf[..4], b"cdef");
+            assert_eq!(reader.read(&mut buf).await.unwrap(), 4);
+            assert_eq!(&buf[..4], b"ghij");
+            assert!(reader.buffer().is_empty());
+            assert_eq!(reader.read(&mut buf).await.unwrap(), 0);
+        }).unwrap();
+    }
+
+    #[test]
+    fn large_reads_bypass_an_empty_buffer()
+    {
+        let mut reader = BufReader::with_capacity(4, MockStream::new([&b"0123456789"[..]]));
+        Executor::new(0).block_on(async move
+        {
+            let mut buf = [0; 10];
+            assert_eq!(reader.read(&mut buf).await.unwrap(), 10);
+            assert!(reader.buffer().is_empty());
+        }).unwrap();
+    }
+}
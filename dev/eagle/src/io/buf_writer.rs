@@ -0,0 +1,270 @@
+//------------------------------------------------------------------------------
+//! # BufWriter
+//------------------------------------------------------------------------------
+
+use super::buf_read::AsyncBufRead;
+use super::read::AsyncRead;
+use super::write::AsyncWrite;
+use super::DEFAULT_BUF_SIZE;
+
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # BufWriter
+///
+/// Adds a buffer to a writer, so that small writes are gathered before they
+/// reach the underlying object. The buffered data is only written out by
+/// `flush` or `shutdown`, or when the buffer is full; dropping the BufWriter
+/// loses it. Reads go straight through to the underlying object.
+//------------------------------------------------------------------------------
+pub struct BufWriter<W>
+{
+    inner: W,
+    buf: Vec<u8>,
+    written: usize,
+}
+
+impl<W: AsyncWrite> BufWriter<W>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new BufWriter with the default capacity.
+    //--------------------------------------------------------------------------
+    pub fn new( inner: W ) -> Self
+    {
+        Self::with_capacity(DEFAULT_BUF_SIZE, inner)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new BufWriter with the given capacity.
+    //--------------------------------------------------------------------------
+    pub fn with_capacity( capacity: usize, inner: W ) -> Self
+    {
+        Self
+        {
+            inner,
+            buf: Vec::with_capacity(capacity),
+            written: 0,
+        }
+    }
+}
+
+impl<W> BufWriter<W>
+{
+    //--------------------------------------------------------------------------
+    /// Returns a reference to the underlying writer.
+    //--------------------------------------------------------------------------
+    pub fn get_ref( &self ) -> &W
+    {
+        &self.inner
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a mutable reference to the underlying writer. Writing to it
+    /// directly goes ahead of the buffered data.
+    //--------------------------------------------------------------------------
+    pub fn get_mut( &mut self ) -> &mut W
+    {
+        &mut self.inner
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the buffered data.
+    //--------------------------------------------------------------------------
+    pub fn buffer( &self ) -> &[u8]
+    {
+        &self.buf[self.written..]
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the underlying writer. The buffered data is lost.
+    //--------------------------------------------------------------------------
+    pub fn into_inner( self ) -> W
+    {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin> BufWriter<W>
+{
+    //--------------------------------------------------------------------------
+    /// Writes the buffered data to the underlying writer.
+    //--------------------------------------------------------------------------
+    fn poll_flush_buf( &mut self, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        while self.written < self.buf.len()
+        {
+            let buf = &self.buf[self.written..];
+            match Pin::new(&mut self.inner).poll_write(cx, buf)
+            {
+                Poll::Ready(Ok(0)) =>
+                {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                },
+                Poll::Ready(Ok(written)) => self.written += written,
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        self.buf.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for BufWriter<W>
+{
+    fn poll_write
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        let this = self.get_mut();
+        if this.buf.len() + buf.len() > this.buf.capacity()
+        {
+            match this.poll_flush_buf(cx)
+            {
+                Poll::Ready(Ok(())) => {},
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        // A write at least as large as the buffer bypasses it.
+        if buf.len() >= this.buf.capacity()
+        {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        this.buf.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        let this = self.get_mut();
+        match this.poll_flush_buf(cx)
+        {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_flush(cx),
+            polled => polled,
+        }
+    }
+
+    fn poll_shutdown( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        let this = self.get_mut();
+        match this.poll_flush_buf(cx)
+        {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_shutdown(cx),
+            polled => polled,
+        }
+    }
+}
+
+impl<W: AsyncRead + Unpin> AsyncRead for BufWriter<W>
+{
+    fn poll_read
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl<W: AsyncBufRead + Unpin> AsyncBufRead for BufWriter<W>
+{
+    fn poll_fill_buf( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<&[u8]>>
+    {
+        Pin::new(&mut self.get_mut().inner).poll_fill_buf(cx)
+    }
+
+    fn consume( mut self: Pin<&mut Self>, amount: usize )
+    {
+        Pin::new(&mut self.inner).consume(amount)
+    }
+}
+
+impl<W: fmt::Debug> fmt::Debug for BufWriter<W>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("BufWriter")
+            .field("inner", &self.inner)
+            .field("buffered", &(self.buf.len() - self.written))
+            .field("capacity", &self.buf.capacity())
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+    use crate::io::AsyncWriteExt;
+    use crate::test_util::MockStream;
+
+    #[test]
+    fn writes_are_buffered_until_flushed()
+    {
+        let stream = MockStream::new([]).max_write(3);
+        let written = stream.written();
+        let mut writer = BufWriter::with_capacity(8, stream);
+        Executor::new(0).block_on(async move
+        {
+            writer.write_all(b"ab").await.unwrap();
+            writer.write_all(b"cd").await.unwrap();
+            assert!(written.lock().unwrap().is_empty());
+            assert_eq!(writer.buffer(), b"abcd");
+
+            writer.flush().await.unwrap();
+            assert_eq!(*written.lock().unwrap(), b"abcd");
+            assert!(writer.buffer().is_empty());
+        }).unwrap();
+    }
+
+    #[test]
+    fn full_buffer_is_written_out_first()
+    {
+        let stream = MockStream::new([]);
+        let written = stream.written();
+        let mut writer = BufWriter::with_capacity(8, stream);
+        Executor::new(0).block_on(async move
+        {
+            // A write as large as the buffer bypasses it.
+            writer.write_all(b"abcdefgh").await.unwrap();
+            assert!(writer.buffer().is_empty());
+            writer.write_all(b"0123").await.unwrap();
+            writer.write_all(b"4567").await.unwrap();
+            assert_eq!(*written.lock().unwrap(), b"abcdefgh");
+            writer.write_all(b"89").await.unwrap();
+            assert_eq!(*written.lock().unwrap(), b"abcdefgh01234567");
+            assert_eq!(writer.buffer(), b"89");
+
+            // The buffered data is written out before a write bypassing it.
+            writer.write_all(b"large write").await.unwrap();
+            assert_eq!(*written.lock().unwrap(), b"abcdefgh0123456789large write");
+            writer.shutdown().await.unwrap();
+        }).unwrap();
+    }
+
+    #[test]
+    fn flush_fails_if_nothing_is_taken()
+    {
+        let mut writer = BufWriter::with_capacity(8, MockStream::new([]).max_write(0));
+        let error = Executor::new(0).block_on(async move
+        {
+            writer.write_all(b"abc").await.unwrap();
+            writer.flush().await.unwrap_err()
+        }).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+    }
+}
@@ -0,0 +1,148 @@
+//------------------------------------------------------------------------------
+//! # copy
+//------------------------------------------------------------------------------
+
+use super::read::AsyncRead;
+use super::write::AsyncWrite;
+use super::DEFAULT_BUF_SIZE;
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # copy
+///
+/// Copies the reader into the writer until the end of the stream, then
+/// flushes the writer. Resolves to the number of bytes copied.
+//------------------------------------------------------------------------------
+pub fn copy<'a, R, W>( reader: &'a mut R, writer: &'a mut W ) -> Copy<'a, R, W>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+        W: AsyncWrite + Unpin + ?Sized,
+{
+    Copy
+    {
+        reader,
+        writer,
+        buf: vec![0; DEFAULT_BUF_SIZE].into_boxed_slice(),
+        pos: 0,
+        filled: 0,
+        done: false,
+        copied: 0,
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Copy
+///
+/// Future returned by `copy`.
+//------------------------------------------------------------------------------
+pub struct Copy<'a, R: ?Sized, W: ?Sized>
+{
+    reader: &'a mut R,
+    writer: &'a mut W,
+    buf: Box<[u8]>,
+    pos: usize,
+    filled: usize,
+    done: bool,
+    copied: u64,
+}
+
+impl<R, W> Future for Copy<'_, R, W>
+    where
+        R: AsyncRead + Unpin + ?Sized,
+        W: AsyncWrite + Unpin + ?Sized,
+{
+    type Output = io::Result<u64>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = &mut *self;
+        loop
+        {
+            if this.pos == this.filled && !this.done
+            {
+                match Pin::new(&mut *this.reader).poll_read(cx, &mut this.buf)
+                {
+                    Poll::Ready(Ok(0)) => this.done = true,
+                    Poll::Ready(Ok(read)) =>
+                    {
+                        this.pos = 0;
+                        this.filled = read;
+                    },
+                    Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            while this.pos < this.filled
+            {
+                let buf = &this.buf[this.pos..this.filled];
+                match Pin::new(&mut *this.writer).poll_write(cx, buf)
+                {
+                    Poll::Ready(Ok(0)) =>
+                    {
+                        return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                    },
+                    Poll::Ready(Ok(written)) =>
+                    {
+                        this.pos += written;
+                        this.copied += written as u64;
+                    },
+                    Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+
+            if this.done
+            {
+                return match Pin::new(&mut *this.writer).poll_flush(cx)
+                {
+                    Poll::Ready(Ok(())) => Poll::Ready(Ok(this.copied)),
+                    Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+                    Poll::Pending => Poll::Pending,
+                };
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+    use crate::test_util::MockStream;
+
+    #[test]
+    fn copy_moves_everything_through_short_writes()
+    {
+        let data: Vec<u8> = (0..DEFAULT_BUF_SIZE * 2 + 7).map(|i| i as u8).collect();
+        let mut reader = MockStream::new(data.chunks(1500));
+        let mut writer = MockStream::new([]).max_write(1000);
+        let written = writer.written();
+        let copied = Executor::new(0).block_on(async move
+        {
+            copy(&mut reader, &mut writer).await.unwrap()
+        }).unwrap();
+        assert_eq!(copied, data.len() as u64);
+        assert_eq!(*written.lock().unwrap(), data);
+    }
+
+    #[test]
+    fn copy_fails_if_the_writer_takes_nothing()
+    {
+        let mut reader = MockStream::new([&b"data"[..]]);
+        let mut writer = MockStream::new([]).max_write(0);
+        let error = Executor::new(0).block_on(async move
+        {
+            copy(&mut reader, &mut writer).await.unwrap_err()
+        }).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+    }
+}
@@ -0,0 +1,45 @@
+//------------------------------------------------------------------------------
+//! # Async IO
+//!
+//! `AsyncRead`, `AsyncWrite` and `AsyncBufRead` are the asynchronous versions
+//! of the traits of `std::io`. Instead of blocking, an IO object that is not
+//! ready returns `Poll::Pending` and wakes the task once it is. The extension
+//! traits provide the futures built on them, such as `read_exact`,
+//! `write_all` or `read_line`.
+//!
+//! ```no_run
+//! use eagle::io::{ AsyncBufReadExt, AsyncWriteExt, BufReader };
+//! use eagle::net::TcpStream;
+//!
+//! # async fn echo() -> std::io::Result<()> {
+//! let stream = TcpStream::connect("127.0.0.1:7000".parse().unwrap()).await?;
+//! let mut stream = BufReader::new(stream);
+//! let mut line = String::new();
+//! while stream.read_line(&mut line).await? > 0
+//! {
+//!     stream.write_all(line.as_bytes()).await?;
+//!     line.clear();
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//------------------------------------------------------------------------------
+
+mod buf_read;
+mod buf_reader;
+mod buf_writer;
+mod copy;
+mod read;
+mod split;
+mod write;
+
+pub use buf_read::{ AsyncBufRead, AsyncBufReadExt, Lines, ReadLine, ReadUntil };
+pub use buf_reader::BufReader;
+pub use buf_writer::BufWriter;
+pub use copy::{ copy, Copy };
+pub use read::{ AsyncRead, AsyncReadExt, Read, ReadExact, ReadToEnd };
+pub use split::{ split, ReadHalf, WriteHalf };
+pub use write::{ AsyncWrite, AsyncWriteExt, Flush, Shutdown, Write, WriteAll };
+
+/// Default capacity of the buffers.
+const DEFAULT_BUF_SIZE: usize = 8 * 1024;
@@ -0,0 +1,273 @@
+//------------------------------------------------------------------------------
+//! # AsyncRead
+//------------------------------------------------------------------------------
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+/// Number of bytes `read_to_end` reads at a time.
+const READ_TO_END_CHUNK: usize = 4096;
+
+
+//------------------------------------------------------------------------------
+/// # AsyncRead
+///
+/// Reads bytes from an IO object without blocking.
+//------------------------------------------------------------------------------
+pub trait AsyncRead
+{
+    //--------------------------------------------------------------------------
+    /// Attempts to read into the buffer, and returns the number of bytes
+    /// read. Zero means the end of the stream, unless the buffer is empty.
+    ///
+    /// Returns `Poll::Pending` and wakes the task later if no data is
+    /// available yet.
+    //--------------------------------------------------------------------------
+    fn poll_read
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>;
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> AsyncRead for &mut R
+{
+    fn poll_read
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> AsyncRead for Box<R>
+{
+    fn poll_read
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut **self).poll_read(cx, buf)
+    }
+}
+
+impl AsyncRead for &[u8]
+{
+    fn poll_read
+    (
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        Poll::Ready(io::Read::read(&mut *self, buf))
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # AsyncReadExt
+///
+/// Extension methods for readers.
+//------------------------------------------------------------------------------
+pub trait AsyncReadExt: AsyncRead
+{
+    //--------------------------------------------------------------------------
+    /// Reads into the buffer, and returns the number of bytes read.
+    //--------------------------------------------------------------------------
+    fn read<'a>( &'a mut self, buf: &'a mut [u8] ) -> Read<'a, Self>
+        where Self: Unpin
+    {
+        Read { reader: self, buf }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Reads exactly enough bytes to fill the buffer. Fails with
+    /// `UnexpectedEof` if the stream ends before.
+    //--------------------------------------------------------------------------
+    fn read_exact<'a>( &'a mut self, buf: &'a mut [u8] ) -> ReadExact<'a, Self>
+        where Self: Unpin
+    {
+        ReadExact { reader: self, buf, filled: 0 }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Reads until the end of the stream, appending the bytes to the vector.
+    /// Returns the number of bytes read.
+    //--------------------------------------------------------------------------
+    fn read_to_end<'a>( &'a mut self, buf: &'a mut Vec<u8> ) -> ReadToEnd<'a, Self>
+        where Self: Unpin
+    {
+        ReadToEnd { reader: self, buf, read: 0 }
+    }
+}
+
+impl<R: AsyncRead + ?Sized> AsyncReadExt for R {}
+
+
+//------------------------------------------------------------------------------
+/// # Read
+///
+/// Future returned by `AsyncReadExt::read`.
+//------------------------------------------------------------------------------
+pub struct Read<'a, R: ?Sized>
+{
+    reader: &'a mut R,
+    buf: &'a mut [u8],
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> Future for Read<'_, R>
+{
+    type Output = io::Result<usize>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = &mut *self;
+        Pin::new(&mut *this.reader).poll_read(cx, this.buf)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # ReadExact
+///
+/// Future returned by `AsyncReadExt::read_exact`.
+//------------------------------------------------------------------------------
+pub struct ReadExact<'a, R: ?Sized>
+{
+    reader: &'a mut R,
+    buf: &'a mut [u8],
+    filled: usize,
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> Future for ReadExact<'_, R>
+{
+    type Output = io::Result<()>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = &mut *self;
+        while this.filled < this.buf.len()
+        {
+            let buf = &mut this.buf[this.filled..];
+            match Pin::new(&mut *this.reader).poll_read(cx, buf)
+            {
+                Poll::Ready(Ok(0)) =>
+                {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                },
+                Poll::Ready(Ok(read)) => this.filled += read,
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # ReadToEnd
+///
+/// Future returned by `AsyncReadExt::read_to_end`.
+//------------------------------------------------------------------------------
+pub struct ReadToEnd<'a, R: ?Sized>
+{
+    reader: &'a mut R,
+    buf: &'a mut Vec<u8>,
+    read: usize,
+}
+
+impl<R: AsyncRead + Unpin + ?Sized> Future for ReadToEnd<'_, R>
+{
+    type Output = io::Result<usize>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = &mut *self;
+        loop
+        {
+            // The vector is grown with zeroes, and truncated back to the bytes
+            // actually read.
+            let len = this.buf.len();
+            this.buf.resize(len + READ_TO_END_CHUNK, 0);
+            let polled = Pin::new(&mut *this.reader).poll_read(cx, &mut this.buf[len..]);
+            let read = match &polled
+            {
+                Poll::Ready(Ok(read)) => *read,
+                _ => 0,
+            };
+            this.buf.truncate(len + read);
+
+            match polled
+            {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Ok(this.read)),
+                Poll::Ready(Ok(read)) => this.read += read,
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+    use crate::test_util::MockStream;
+
+    #[test]
+    fn read_exact_gathers_short_reads()
+    {
+        let mut stream = MockStream::new([&b"he"[..], b"l", b"lo wor", b"ld"]);
+        let (first, second, rest) = Executor::new(0).block_on(async move
+        {
+            let mut first = [0; 5];
+            stream.read_exact(&mut first).await.unwrap();
+            let mut second = [0; 1];
+            stream.read_exact(&mut second).await.unwrap();
+            let mut rest = Vec::new();
+            assert_eq!(stream.read_to_end(&mut rest).await.unwrap(), 5);
+            (first, second, rest)
+        }).unwrap();
+        assert_eq!((&first, &second, rest.as_slice()), (b"hello", b" ", &b"world"[..]));
+    }
+
+    #[test]
+    fn read_exact_fails_if_the_stream_ends_first()
+    {
+        let mut stream = MockStream::new([&b"abc"[..], b"de"]);
+        let error = Executor::new(0).block_on(async move
+        {
+            let mut buf = [0; 8];
+            stream.read_exact(&mut buf).await.unwrap_err()
+        }).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn read_to_end_appends_past_a_chunk()
+    {
+        let data: Vec<u8> = (0..READ_TO_END_CHUNK * 2 + 10).map(|i| i as u8).collect();
+        let mut stream = MockStream::new(data.chunks(1000));
+        let buf = Executor::new(0).block_on(async move
+        {
+            let mut buf = b"head".to_vec();
+            let read = stream.read_to_end(&mut buf).await.unwrap();
+            assert_eq!(read, READ_TO_END_CHUNK * 2 + 10);
+            buf
+        }).unwrap();
+        assert_eq!(&buf[..4], b"head");
+        assert_eq!(&buf[4..], data);
+    }
+}
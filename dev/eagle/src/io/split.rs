@@ -0,0 +1,220 @@
+//------------------------------------------------------------------------------
+//! # split
+//------------------------------------------------------------------------------
+
+use super::read::AsyncRead;
+use super::write::AsyncWrite;
+
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex, MutexGuard, PoisonError };
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # split
+///
+/// Splits a stream into a read half and a write half, which can be moved to
+/// different tasks. The halves share the stream behind a lock held for the
+/// duration of a single poll.
+//------------------------------------------------------------------------------
+pub fn split<T>( stream: T ) -> (ReadHalf<T>, WriteHalf<T>)
+    where T: AsyncRead + AsyncWrite + Unpin
+{
+    let inner = Arc::new(Mutex::new(stream));
+    (ReadHalf { inner: inner.clone() }, WriteHalf { inner })
+}
+
+
+//------------------------------------------------------------------------------
+/// # ReadHalf
+///
+/// Read half of a stream returned by `split`.
+//------------------------------------------------------------------------------
+pub struct ReadHalf<T>
+{
+    inner: Arc<Mutex<T>>,
+}
+
+impl<T> ReadHalf<T>
+{
+    //--------------------------------------------------------------------------
+    /// Returns whether the two halves come from the same stream.
+    //--------------------------------------------------------------------------
+    pub fn is_pair_of( &self, other: &WriteHalf<T> ) -> bool
+    {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Joins the two halves back into the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the halves do not come from the same stream.
+    //--------------------------------------------------------------------------
+    pub fn unsplit( self, other: WriteHalf<T> ) -> T
+    {
+        if !self.is_pair_of(&other)
+        {
+            panic!("unsplit called on halves of different streams");
+        }
+        drop(other);
+        match Arc::try_unwrap(self.inner)
+        {
+            Ok(inner) => inner.into_inner().unwrap_or_else(PoisonError::into_inner),
+            Err(_) => unreachable!("both halves were given back"),
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for ReadHalf<T>
+{
+    fn poll_read
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut *lock(&self.inner)).poll_read(cx, buf)
+    }
+}
+
+impl<T> fmt::Debug for ReadHalf<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("ReadHalf").finish_non_exhaustive()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # WriteHalf
+///
+/// Write half of a stream returned by `split`.
+//------------------------------------------------------------------------------
+pub struct WriteHalf<T>
+{
+    inner: Arc<Mutex<T>>,
+}
+
+impl<T> WriteHalf<T>
+{
+    //--------------------------------------------------------------------------
+    /// Returns whether the two halves come from the same stream.
+    //--------------------------------------------------------------------------
+    pub fn is_pair_of( &self, other: &ReadHalf<T> ) -> bool
+    {
+        other.is_pair_of(self)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for WriteHalf<T>
+{
+    fn poll_write
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut *lock(&self.inner)).poll_write(cx, buf)
+    }
+
+    fn poll_flush( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Pin::new(&mut *lock(&self.inner)).poll_flush(cx)
+    }
+
+    fn poll_shutdown( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Pin::new(&mut *lock(&self.inner)).poll_shutdown(cx)
+    }
+}
+
+impl<T> fmt::Debug for WriteHalf<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("WriteHalf").finish_non_exhaustive()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # lock
+///
+/// Locks the shared stream. It is only locked for a single poll, so a
+/// poisoned lock still holds a usable stream.
+//------------------------------------------------------------------------------
+fn lock<T>( mutex: &Mutex<T> ) -> MutexGuard<'_, T>
+{
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::{ spawn, yield_now, Simulation };
+    use crate::io::{ AsyncReadExt, AsyncWriteExt };
+    use crate::test_util::MockStream;
+
+    #[test]
+    fn halves_are_used_from_two_tasks()
+    {
+        for seed in 0..8
+        {
+            let stream = MockStream::new([&b"one "[..], b"two ", b"three"]);
+            let written = stream.written();
+            let (mut reader, mut writer) = split(stream);
+            let (read, stream) = Simulation::new(seed).block_on(async move
+            {
+                let reading = spawn(async move
+                {
+                    let mut read = Vec::new();
+                    let mut buf = [0; 3];
+                    loop
+                    {
+                        match reader.read(&mut buf).await.unwrap()
+                        {
+                            0 => return (read, reader),
+                            len => read.extend_from_slice(&buf[..len]),
+                        }
+                        yield_now().await;
+                    }
+                });
+                let writing = spawn(async move
+                {
+                    for piece in [&b"un "[..], b"deux ", b"trois"]
+                    {
+                        writer.write_all(piece).await.unwrap();
+                        yield_now().await;
+                    }
+                    writer.shutdown().await.unwrap();
+                    writer
+                });
+                let (read, reader) = reading.await.unwrap();
+                let writer = writing.await.unwrap();
+                assert!(reader.is_pair_of(&writer));
+                (read, reader.unsplit(writer))
+            }).unwrap();
+            assert_eq!(read, b"one two three", "seed {}", seed);
+            assert_eq!(*written.lock().unwrap(), b"un deux trois", "seed {}", seed);
+            assert!(stream.written().lock().unwrap().ends_with(b"trois"));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "halves of different streams")]
+    fn unsplit_rejects_halves_of_different_streams()
+    {
+        let (reader, _) = split(MockStream::new([]));
+        let (_, writer) = split(MockStream::new([]));
+        reader.unsplit(writer);
+    }
+}
@@ -0,0 +1,294 @@
+//------------------------------------------------------------------------------
+//! # AsyncWrite
+//------------------------------------------------------------------------------
+
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # AsyncWrite
+///
+/// Writes bytes to an IO object without blocking.
+//------------------------------------------------------------------------------
+pub trait AsyncWrite
+{
+    //--------------------------------------------------------------------------
+    /// Attempts to write the buffer, and returns the number of bytes written.
+    ///
+    /// Returns `Poll::Pending` and wakes the task later if the object cannot
+    /// take any data yet.
+    //--------------------------------------------------------------------------
+    fn poll_write
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>;
+
+    //--------------------------------------------------------------------------
+    /// Attempts to write out the buffered data.
+    //--------------------------------------------------------------------------
+    fn poll_flush( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>;
+
+    //--------------------------------------------------------------------------
+    /// Attempts to flush the object and shut down its write side, so that the
+    /// peer reaches the end of the stream.
+    //--------------------------------------------------------------------------
+    fn poll_shutdown( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>;
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> AsyncWrite for &mut W
+{
+    fn poll_write
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_flush( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_shutdown( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Pin::new(&mut **self).poll_shutdown(cx)
+    }
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> AsyncWrite for Box<W>
+{
+    fn poll_write
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        Pin::new(&mut **self).poll_write(cx, buf)
+    }
+
+    fn poll_flush( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_shutdown( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Pin::new(&mut **self).poll_shutdown(cx)
+    }
+}
+
+impl AsyncWrite for Vec<u8>
+{
+    fn poll_write
+    (
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        self.get_mut().extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # AsyncWriteExt
+///
+/// Extension methods for writers.
+//------------------------------------------------------------------------------
+pub trait AsyncWriteExt: AsyncWrite
+{
+    //--------------------------------------------------------------------------
+    /// Writes the buffer, and returns the number of bytes written.
+    //--------------------------------------------------------------------------
+    fn write<'a>( &'a mut self, buf: &'a [u8] ) -> Write<'a, Self>
+        where Self: Unpin
+    {
+        Write { writer: self, buf }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Writes the whole buffer. Fails with `WriteZero` if the object stops
+    /// taking data before.
+    //--------------------------------------------------------------------------
+    fn write_all<'a>( &'a mut self, buf: &'a [u8] ) -> WriteAll<'a, Self>
+        where Self: Unpin
+    {
+        WriteAll { writer: self, buf }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Writes out the buffered data.
+    //--------------------------------------------------------------------------
+    fn flush( &mut self ) -> Flush<'_, Self>
+        where Self: Unpin
+    {
+        Flush { writer: self }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Flushes the object and shuts down its write side.
+    //--------------------------------------------------------------------------
+    fn shutdown( &mut self ) -> Shutdown<'_, Self>
+        where Self: Unpin
+    {
+        Shutdown { writer: self }
+    }
+}
+
+impl<W: AsyncWrite + ?Sized> AsyncWriteExt for W {}
+
+
+//------------------------------------------------------------------------------
+/// # Write
+///
+/// Future returned by `AsyncWriteExt::write`.
+//------------------------------------------------------------------------------
+pub struct Write<'a, W: ?Sized>
+{
+    writer: &'a mut W,
+    buf: &'a [u8],
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for Write<'_, W>
+{
+    type Output = io::Result<usize>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = &mut *self;
+        Pin::new(&mut *this.writer).poll_write(cx, this.buf)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # WriteAll
+///
+/// Future returned by `AsyncWriteExt::write_all`.
+//------------------------------------------------------------------------------
+pub struct WriteAll<'a, W: ?Sized>
+{
+    writer: &'a mut W,
+    buf: &'a [u8],
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for WriteAll<'_, W>
+{
+    type Output = io::Result<()>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = &mut *self;
+        while !this.buf.is_empty()
+        {
+            match Pin::new(&mut *this.writer).poll_write(cx, this.buf)
+            {
+                Poll::Ready(Ok(0)) =>
+                {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                },
+                Poll::Ready(Ok(written)) => this.buf = &this.buf[written..],
+                Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Flush
+///
+/// Future returned by `AsyncWriteExt::flush`.
+//------------------------------------------------------------------------------
+pub struct Flush<'a, W: ?Sized>
+{
+    writer: &'a mut W,
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for Flush<'_, W>
+{
+    type Output = io::Result<()>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        Pin::new(&mut *self.writer).poll_flush(cx)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Shutdown
+///
+/// Future returned by `AsyncWriteExt::shutdown`.
+//------------------------------------------------------------------------------
+pub struct Shutdown<'a, W: ?Sized>
+{
+    writer: &'a mut W,
+}
+
+impl<W: AsyncWrite + Unpin + ?Sized> Future for Shutdown<'_, W>
+{
+    type Output = io::Result<()>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        Pin::new(&mut *self.writer).poll_shutdown(cx)
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+    use crate::test_util::MockStream;
+
+    #[test]
+    fn write_all_retries_short_writes()
+    {
+        let mut stream = MockStream::new([]).max_write(3);
+        let written = stream.written();
+        Executor::new(0).block_on(async move
+        {
+            assert_eq!(stream.write(b"hello").await.unwrap(), 3);
+            stream.write_all(b"lo, world").await.unwrap();
+            stream.shutdown().await.unwrap();
+        }).unwrap();
+        assert_eq!(*written.lock().unwrap(), b"hello, world");
+    }
+
+    #[test]
+    fn write_all_fails_if_nothing_is_taken()
+    {
+        let mut stream = MockStream::new([]).max_write(0);
+        let error = Executor::new(0).block_on(async move
+        {
+            stream.write_all(b"hello").await.unwrap_err()
+        }).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::WriteZero);
+    }
+}
@@ -0,0 +1,33 @@
+//------------------------------------------------------------------------------
+//! # Eagle
+//!
+//! A simple and fast web framework for Rust.
+//------------------------------------------------------------------------------
+
+mod builder;
+mod listener;
+mod server;
+mod timer;
+mod upgrade;
+
+#[cfg(test)]
+mod test_util;
+
+pub mod codec;
+pub mod executor;
+pub mod future;
+pub mod http;
+pub mod io;
+pub mod net;
+pub mod signal;
+pub mod sim;
+pub mod sink;
+pub mod stream;
+pub mod sync;
+pub mod systemd;
+
+pub use builder::EagleServerBuilder;
+pub use eagle_macros::{ main, test };
+pub use executor::{ spawn, spawn_with_deadline, spawn_with_priority, yield_now };
+pub use listener::{ ListenerConfig, ListenerMetrics };
+pub use server::EagleServer;
@@ -0,0 +1,676 @@
+//------------------------------------------------------------------------------
+//! Listener module
+//------------------------------------------------------------------------------
+
+use crate::http::{ Request, Response };
+use crate::io::{ AsyncRead, AsyncWrite };
+use crate::net::{ TcpListener, TcpStream, UnixListener, UnixStream };
+use crate::systemd::ListenFd;
+
+use std::fmt;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::mem;
+use std::net::ToSocketAddrs;
+use std::os::unix::fs::{ FileTypeExt, PermissionsExt };
+use std::os::unix::io::{ AsRawFd, OwnedFd, RawFd };
+use std::os::unix::net;
+use std::path::{ Path, PathBuf };
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{ AtomicU64, Ordering };
+use std::task::{ Context, Poll };
+
+/// Prefix of the addresses of Unix domain sockets.
+const UNIX_PREFIX: &str = "unix:";
+
+/// Handler answering the requests accepted by a listener.
+pub(crate) type Handler =
+    Arc<dyn Fn(Request) -> Pin<Box<dyn Future<Output = Response> + Send>> + Send + Sync>;
+
+
+//------------------------------------------------------------------------------
+/// # ListenerConfig
+///
+/// Configuration of an address added with `EagleServerBuilder::bind_with`.
+/// Without a handler, the listener answers with the default handler of the
+/// server.
+///
+/// ```no_run
+/// use eagle::{ EagleServerBuilder, ListenerConfig };
+/// use eagle::http::Response;
+///
+/// let server = EagleServerBuilder::new()
+///     .bind("0.0.0.0:8080")
+///     .bind_with
+///     (
+///         "127.0.0.1:9090",
+///         ListenerConfig::new().handler(|_| async { Response::new(200, "admin\n") }),
+///     )
+///     .build();
+/// ```
+//------------------------------------------------------------------------------
+#[derive(Clone, Default)]
+pub struct ListenerConfig
+{
+    pub(crate) handler: Option<Handler>,
+}
+
+impl ListenerConfig
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new ListenerConfig with the default handler.
+    //--------------------------------------------------------------------------
+    pub fn new() -> Self
+    {
+        Self::default()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the handler answering the requests accepted on the address. The
+    /// debug endpoints of the server are still served before it.
+    //--------------------------------------------------------------------------
+    pub fn handler<H, F>( &mut self, handler: H ) -> &mut Self
+        where
+            H: Fn(Request) -> F + Send + Sync + 'static,
+            F: Future<Output = Response> + Send + 'static,
+    {
+        self.handler = Some(Arc::new(move |request|
+        {
+            Box::pin(handler(request)) as Pin<Box<dyn Future<Output = Response> + Send>>
+        }));
+        self
+    }
+}
+
+impl fmt::Debug for ListenerConfig
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("ListenerConfig")
+            .field("handler", &self.handler.is_some())
+            .finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # UnixSocketOptions
+///
+/// Options applied when the server listens on a Unix domain socket.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct UnixSocketOptions
+{
+    pub(crate) mode: Option<u32>,
+    pub(crate) remove_stale: bool,
+}
+
+
+//------------------------------------------------------------------------------
+/// # ListenerMetrics
+///
+/// - address: The address the listener is bound to, as given to the server.
+/// - accepted: Connections accepted by the listener.
+/// - failed: Accepts that failed, such as when the process ran out of file
+///   descriptors.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenerMetrics
+{
+    pub address: String,
+    pub accepted: u64,
+    pub failed: u64,
+}
+
+
+//------------------------------------------------------------------------------
+/// # AcceptCounters
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub(crate) struct AcceptCounters
+{
+    address: String,
+    accepted: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl AcceptCounters
+{
+    //--------------------------------------------------------------------------
+    /// Returns a snapshot of the counters.
+    //--------------------------------------------------------------------------
+    pub(crate) fn snapshot( &self ) -> ListenerMetrics
+    {
+        ListenerMetrics
+        {
+            address: self.address.clone(),
+            accepted: self.accepted.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Listener
+///
+/// Listener on a TCP address, or on a Unix domain socket for the addresses
+/// prefixed with `unix:`, counting the connections it accepts.
+//------------------------------------------------------------------------------
+pub(crate) struct Listener
+{
+    socket: Socket,
+    counters: Arc<AcceptCounters>,
+}
+
+impl Listener
+{
+    //--------------------------------------------------------------------------
+    /// Binds a listener to the address.
+    //--------------------------------------------------------------------------
+    pub(crate) fn bind( address: &str, options: UnixSocketOptions ) -> io::Result<Self>
+    {
+        let socket = match address.strip_prefix(UNIX_PREFIX)
+        {
+            Some(path) => Socket::bind_unix(PathBuf::from(path), options)?,
+            None => Socket::Tcp(TcpListener::bind(address)?),
+        };
+        Ok(Self::new(address, socket))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Binds `count` listeners to the TCP address with `SO_REUSEPORT`. When
+    /// the port is zero, all the listeners share the port picked for the
+    /// first one. A Unix domain socket cannot be shared, so a single listener
+    /// is bound to it.
+    //--------------------------------------------------------------------------
+    pub(crate) fn bind_reuse_port
+    (
+        address: &str,
+        count: usize,
+        options: UnixSocketOptions,
+    ) -> io::Result<Vec<Self>>
+    {
+        if address.starts_with(UNIX_PREFIX)
+        {
+            return Ok(vec![Self::bind(address, options)?]);
+        }
+
+        let mut resolved = address.to_socket_addrs()?;
+        let Some(mut socket_address) = resolved.next() else
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no address resolved"));
+        };
+        let mut listeners = Vec::with_capacity(count);
+        for _ in 0..count.max(1)
+        {
+            let listener = TcpListener::bind_reuse_port(socket_address)?;
+            socket_address = listener.local_addr()?;
+            listeners.push(Self::new(address, Socket::Tcp(listener)));
+        }
+        Ok(listeners)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Adopts a listening socket passed by the service manager. It is named
+    /// after its `FileDescriptorName=`, or else its local address. The socket
+    /// file of an adopted Unix domain socket belongs to the service manager,
+    /// and is not removed.
+    //--------------------------------------------------------------------------
+    pub(crate) fn from_listen_fd( listen_fd: ListenFd ) -> io::Result<Self>
+    {
+        Self::adopt(listen_fd.fd, listen_fd.name, false)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Adopts a listening socket, named after `name`, or else its local
+    /// address. With `owns_file`, the socket file of a Unix domain socket is
+    /// removed when the listener is dropped.
+    //--------------------------------------------------------------------------
+    pub(crate) fn adopt
+    (
+        fd: OwnedFd,
+        name: Option<String>,
+        owns_file: bool,
+    ) -> io::Result<Self>
+    {
+        if socket_option(&fd, libc::SO_TYPE)? != libc::SOCK_STREAM
+            || socket_option(&fd, libc::SO_ACCEPTCONN)? == 0
+        {
+            let error = "not a listening stream socket";
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, error));
+        }
+
+        let (socket, address) = match socket_option(&fd, libc::SO_DOMAIN)?
+        {
+            libc::AF_UNIX =>
+            {
+                let listener = UnixListener::from_std(net::UnixListener::from(fd))?;
+                let local = listener.local_addr()?;
+                let address = match local.as_pathname()
+                {
+                    Some(path) => format!("{}{}", UNIX_PREFIX, path.display()),
+                    None => format!("{:?}", local),
+                };
+                let path = local.as_pathname().filter(|_| owns_file).map(PathBuf::from);
+                (Socket::Unix(listener, path), address)
+            },
+            libc::AF_INET | libc::AF_INET6 =>
+            {
+                let listener = TcpListener::from_std(std::net::TcpListener::from(fd))?;
+                let address = listener.local_addr()?.to_string();
+                (Socket::Tcp(listener), address)
+            },
+            _ =>
+            {
+                let error = "unsupported socket domain";
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, error));
+            },
+        };
+        Ok(Self::new(&name.unwrap_or(address), socket))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new Listener with zeroed counters.
+    //--------------------------------------------------------------------------
+    fn new( address: &str, socket: Socket ) -> Self
+    {
+        let counters = AcceptCounters
+        {
+            address: address.to_string(),
+            accepted: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        };
+        Self { socket, counters: Arc::new(counters) }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the address the listener is named after.
+    //--------------------------------------------------------------------------
+    pub(crate) fn address( &self ) -> &str
+    {
+        &self.counters.address
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the listener removes its socket file when dropped.
+    //--------------------------------------------------------------------------
+    pub(crate) fn owns_file( &self ) -> bool
+    {
+        matches!(self.socket, Socket::Unix(_, Some(_)))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Leaves the socket file in place when the listener is dropped, once the
+    /// socket has been handed over to another process.
+    //--------------------------------------------------------------------------
+    pub(crate) fn disown( &mut self )
+    {
+        if let Socket::Unix(_, path) = &mut self.socket
+        {
+            *path = None;
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the accept counters of the listener.
+    //--------------------------------------------------------------------------
+    pub(crate) fn counters( &self ) -> &Arc<AcceptCounters>
+    {
+        &self.counters
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for a connection.
+    //--------------------------------------------------------------------------
+    pub(crate) fn poll_accept( &self, cx: &mut Context ) -> Poll<io::Result<Connection>>
+    {
+        let polled = match &self.socket
+        {
+            Socket::Tcp(listener) =>
+            {
+                listener.poll_accept(cx).map_ok(|(stream, _)| Connection::Tcp(stream))
+            },
+            Socket::Unix(listener, _) =>
+            {
+                listener.poll_accept(cx).map_ok(|(stream, _)| Connection::Unix(stream))
+            },
+        };
+        match &polled
+        {
+            Poll::Ready(Ok(_)) => self.counters.accepted.fetch_add(1, Ordering::Relaxed),
+            Poll::Ready(Err(_)) => self.counters.failed.fetch_add(1, Ordering::Relaxed),
+            Poll::Pending => 0,
+        };
+        polled
+    }
+}
+
+impl AsRawFd for Listener
+{
+    fn as_raw_fd( &self ) -> RawFd
+    {
+        match &self.socket
+        {
+            Socket::Tcp(listener) => listener.as_raw_fd(),
+            Socket::Unix(listener, _) => listener.as_raw_fd(),
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Socket
+///
+/// The socket file of a Unix listener, if it has created one, is removed when
+/// it is dropped.
+//------------------------------------------------------------------------------
+enum Socket
+{
+    Tcp(TcpListener),
+    Unix(UnixListener, Option<PathBuf>),
+}
+
+impl Socket
+{
+    //--------------------------------------------------------------------------
+    /// Binds a listener to the Unix domain socket at the path.
+    //--------------------------------------------------------------------------
+    fn bind_unix( path: PathBuf, options: UnixSocketOptions ) -> io::Result<Self>
+    {
+        if options.remove_stale
+        {
+            remove_stale_socket(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        if let Some(mode) = options.mode
+        {
+            if let Err(error) = fs::set_permissions(&path, fs::Permissions::from_mode(mode))
+            {
+                let _ = fs::remove_file(&path);
+                return Err(error);
+            }
+        }
+        Ok(Socket::Unix(listener, Some(path)))
+    }
+}
+
+impl Drop for Socket
+{
+    fn drop( &mut self )
+    {
+        if let Socket::Unix(_, Some(path)) = self
+        {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # socket_option
+///
+/// Returns the value of the integer socket option at the `SOL_SOCKET` level.
+//------------------------------------------------------------------------------
+fn socket_option( fd: &OwnedFd, option: libc::c_int ) -> io::Result<libc::c_int>
+{
+    let mut value: libc::c_int = 0;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let result = unsafe
+    {
+        libc::getsockopt
+        (
+            fd.as_raw_fd(),
+            libc::SOL_SOCKET,
+            option,
+            &mut value as *mut _ as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    match result < 0
+    {
+        true => Err(io::Error::last_os_error()),
+        false => Ok(value),
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # remove_stale_socket
+///
+/// Removes the socket file left behind at the path by a server that is gone,
+/// which is detected by nothing accepting connections on it. Other files are
+/// left alone, and binding to their path fails.
+//------------------------------------------------------------------------------
+fn remove_stale_socket( path: &Path ) -> io::Result<()>
+{
+    let metadata = match fs::symlink_metadata(path)
+    {
+        Ok(metadata) => metadata,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    if !metadata.file_type().is_socket()
+    {
+        return Ok(());
+    }
+    match net::UnixStream::connect(path)
+    {
+        Err(error) if error.kind() == io::ErrorKind::ConnectionRefused =>
+        {
+            fs::remove_file(path)
+        },
+        _ => Ok(()),
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Connection
+///
+/// Connection accepted by a `Listener`.
+//------------------------------------------------------------------------------
+pub(crate) enum Connection
+{
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl AsyncRead for Connection
+{
+    fn poll_read
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        match self.get_mut()
+        {
+            Connection::Tcp(stream) => Pin::new(stream).poll_read(cx, buf),
+            Connection::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Connection
+{
+    fn poll_write
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        match self.get_mut()
+        {
+            Connection::Tcp(stream) => Pin::new(stream).poll_write(cx, buf),
+            Connection::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        match self.get_mut()
+        {
+            Connection::Tcp(stream) => Pin::new(stream).poll_flush(cx),
+            Connection::Unix(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        match self.get_mut()
+        {
+            Connection::Tcp(stream) => Pin::new(stream).poll_shutdown(cx),
+            Connection::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+    use std::env;
+    use std::future::poll_fn;
+    use std::process;
+
+    //--------------------------------------------------------------------------
+    /// Returns a path for a socket of the test, with nothing at it.
+    //--------------------------------------------------------------------------
+    fn socket_path( name: &str ) -> PathBuf
+    {
+        let path = env::temp_dir().join(format!("eagle-{}-{}.sock", name, process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the options removing the stale socket files.
+    //--------------------------------------------------------------------------
+    fn options( mode: Option<u32> ) -> UnixSocketOptions
+    {
+        UnixSocketOptions { mode, remove_stale: true }
+    }
+
+    #[test]
+    fn stale_socket_file_is_removed()
+    {
+        let path = socket_path("stale");
+        drop(net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        remove_stale_socket(&path).unwrap();
+        assert!(!path.exists());
+        remove_stale_socket(&path).unwrap();
+
+        // Binding removes the file left behind, and the listener removes its
+        // own when dropped.
+        drop(net::UnixListener::bind(&path).unwrap());
+        let address = format!("{}{}", UNIX_PREFIX, path.display());
+        let listener = Listener::bind(&address, options(None)).unwrap();
+        assert!(listener.owns_file());
+        drop(listener);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn live_socket_and_other_files_are_left_alone()
+    {
+        let path = socket_path("live");
+        let live = net::UnixListener::bind(&path).unwrap();
+        remove_stale_socket(&path).unwrap();
+        assert!(path.exists());
+        let address = format!("{}{}", UNIX_PREFIX, path.display());
+        let error = Listener::bind(&address, options(None)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        drop(live);
+        fs::remove_file(&path).unwrap();
+
+        let path = socket_path("regular");
+        fs::write(&path, b"data").unwrap();
+        remove_stale_socket(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+        let address = format!("{}{}", UNIX_PREFIX, path.display());
+        assert!(Listener::bind(&address, options(None)).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"data");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn unix_address_binds_a_socket_with_the_mode()
+    {
+        let path = socket_path("mode");
+        let address = format!("{}{}", UNIX_PREFIX, path.display());
+        let listener = Listener::bind(&address, options(Some(0o640))).unwrap();
+        assert!(matches!(listener.socket, Socket::Unix(_, Some(_))));
+        assert_eq!(listener.address(), address);
+
+        let metadata = fs::symlink_metadata(&path).unwrap();
+        assert!(metadata.file_type().is_socket());
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o640);
+    }
+
+    #[test]
+    fn reuse_port_listeners_share_the_port_and_count_their_accepts()
+    {
+        let listeners = Listener::bind_reuse_port("127.0.0.1:0", 2, options(None)).unwrap();
+        let ports: Vec<_> = listeners
+            .iter()
+            .map(|listener| match &listener.socket
+            {
+                Socket::Tcp(tcp) => tcp.local_addr().unwrap().port(),
+                Socket::Unix(..) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(ports.len(), 2);
+        assert_eq!(ports[0], ports[1]);
+        assert_ne!(ports[0], 0);
+
+        // The connections are queued by the kernel until accepted.
+        let clients: Vec<_> = (0..8)
+            .map(|_| std::net::TcpStream::connect(("127.0.0.1", ports[0])).unwrap())
+            .collect();
+        let (accepted, metrics) = Executor::new(0).block_on(async move
+        {
+            let mut accepted = vec![0; listeners.len()];
+            let mut connections = Vec::new();
+            while connections.len() < 8
+            {
+                let (index, connection) = poll_fn(|cx|
+                {
+                    for (index, listener) in listeners.iter().enumerate()
+                    {
+                        if let Poll::Ready(connection) = listener.poll_accept(cx)
+                        {
+                            return Poll::Ready((index, connection.unwrap()));
+                        }
+                    }
+                    Poll::Pending
+                }).await;
+                accepted[index] += 1;
+                connections.push(connection);
+            }
+
+            let metrics: Vec<_> = listeners
+                .iter()
+                .map(|listener| listener.counters().snapshot())
+                .collect();
+            (accepted, metrics)
+        }).unwrap();
+        drop(clients);
+
+        for (accepted, metrics) in accepted.iter().zip(&metrics)
+        {
+            assert_eq!(metrics.accepted, *accepted);
+            assert_eq!(metrics.failed, 0);
+            assert_eq!(metrics.address, "127.0.0.1:0");
+        }
+        assert_eq!(metrics.iter().map(|metrics| metrics.accepted).sum::<u64>(), 8);
+    }
+}
@@ -2,11 +2,7 @@
 //! Main entry point for the Eagle server.
 //------------------------------------------------------------------------------
 
-mod executor;
-mod builder;
-mod server;
-
-use builder::EagleServerBuilder;
+use eagle::EagleServerBuilder;
 
 fn main()
 {
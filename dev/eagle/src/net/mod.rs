@@ -0,0 +1,119 @@
+//------------------------------------------------------------------------------
+//! # Networking
+//!
+//! Non-blocking sockets driven by the reactor. An operation that would block
+//! registers the task with the reactor and returns `Poll::Pending`, and the
+//! task is woken once the socket is ready.
+//------------------------------------------------------------------------------
+
+mod tcp;
+mod udp;
+mod unix;
+
+pub use tcp::{ TcpListener, TcpStream };
+pub use udp::UdpSocket;
+pub use unix::{ UnixListener, UnixStream };
+
+use crate::executor::reactor::{ Reactor, Source };
+
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::Arc;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # Registration
+///
+/// A socket registered with the reactor, deregistered when dropped. It must be
+/// dropped before the socket is closed, so it is declared before the socket in
+/// the structs holding both.
+//------------------------------------------------------------------------------
+pub(crate) struct Registration
+{
+    source: Arc<Source>,
+}
+
+impl Registration
+{
+    //--------------------------------------------------------------------------
+    /// Registers the file descriptor with the reactor.
+    //--------------------------------------------------------------------------
+    pub(crate) fn new( fd: RawFd ) -> io::Result<Self>
+    {
+        let source = Reactor::get()?.register(fd)?;
+        Ok(Self { source })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Runs the IO operation, waiting for the socket to become readable if it
+    /// would block.
+    //--------------------------------------------------------------------------
+    pub(crate) fn poll_read<T>
+    (
+        &self,
+        cx: &mut Context,
+        op: impl FnMut() -> io::Result<T>,
+    ) -> Poll<io::Result<T>>
+    {
+        poll_io(cx, op, |cx| self.source.poll_readable(cx))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Runs the IO operation, waiting for the socket to become writable if it
+    /// would block.
+    //--------------------------------------------------------------------------
+    pub(crate) fn poll_write<T>
+    (
+        &self,
+        cx: &mut Context,
+        op: impl FnMut() -> io::Result<T>,
+    ) -> Poll<io::Result<T>>
+    {
+        poll_io(cx, op, |cx| self.source.poll_writable(cx))
+    }
+}
+
+impl Drop for Registration
+{
+    fn drop( &mut self )
+    {
+        if let Some(reactor) = Reactor::try_get()
+        {
+            let _ = reactor.deregister(&self.source);
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # poll_io
+///
+/// Runs the IO operation, retrying it when interrupted. When it would block,
+/// waits for the readiness with `wait`, which only returns an error or
+/// `Poll::Pending`.
+//------------------------------------------------------------------------------
+fn poll_io<T>
+(
+    cx: &mut Context,
+    mut op: impl FnMut() -> io::Result<T>,
+    wait: impl FnOnce(&mut Context) -> Poll<io::Result<()>>,
+) -> Poll<io::Result<T>>
+{
+    loop
+    {
+        match op()
+        {
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock =>
+            {
+                return match wait(cx)
+                {
+                    Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+                    _ => Poll::Pending,
+                };
+            },
+            result => return Poll::Ready(result),
+        }
+    }
+}
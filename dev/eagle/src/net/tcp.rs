@@ -0,0 +1,379 @@
+//------------------------------------------------------------------------------
+//! # TCP
+//------------------------------------------------------------------------------
+
+use super::Registration;
+use crate::executor::poll_proceed;
+use crate::io::{ AsyncRead, AsyncWrite };
+
+use std::fmt;
+use std::future::poll_fn;
+use std::io::{ self, Read, Write };
+use std::mem;
+use std::net::{ self, Shutdown, SocketAddr, ToSocketAddrs };
+use std::os::unix::io::{ AsRawFd, FromRawFd, OwnedFd, RawFd };
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # TcpListener
+//------------------------------------------------------------------------------
+pub struct TcpListener
+{
+    registration: Registration,
+    inner: net::TcpListener,
+}
+
+impl TcpListener
+{
+    //--------------------------------------------------------------------------
+    /// Binds a listener to the address. Resolving a host name blocks.
+    //--------------------------------------------------------------------------
+    pub fn bind( address: impl ToSocketAddrs ) -> io::Result<Self>
+    {
+        Self::from_std(net::TcpListener::bind(address)?)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Binds a listener to the address with `SO_REUSEPORT`, so that several
+    /// listeners of the same user can be bound to it, and the kernel balances
+    /// the incoming connections between them.
+    //--------------------------------------------------------------------------
+    pub fn bind_reuse_port( address: SocketAddr ) -> io::Result<Self>
+    {
+        let domain = match address
+        {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        let fd = unsafe
+        {
+            libc::socket
+            (
+                domain,
+                libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                0,
+            )
+        };
+        if fd < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let listener = net::TcpListener::from(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        for option in [libc::SO_REUSEADDR, libc::SO_REUSEPORT]
+        {
+            let enabled: libc::c_int = 1;
+            let result = unsafe
+            {
+                libc::setsockopt
+                (
+                    fd,
+                    libc::SOL_SOCKET,
+                    option,
+                    &enabled as *const _ as *const libc::c_void,
+                    mem::size_of::<libc::c_int>() as libc::socklen_t,
+                )
+            };
+            if result < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        let (storage, len) = socket_addr(&address);
+        if unsafe { libc::bind(fd, &storage as *const _ as *const libc::sockaddr, len) } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        if unsafe { libc::listen(fd, libc::SOMAXCONN) } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        Self::from_std(listener)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a TcpListener from a standard listener, which is switched to
+    /// non-blocking mode.
+    //--------------------------------------------------------------------------
+    pub fn from_std( listener: net::TcpListener ) -> io::Result<Self>
+    {
+        listener.set_nonblocking(true)?;
+        Ok(Self
+        {
+            registration: Registration::new(listener.as_raw_fd())?,
+            inner: listener,
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the local address of the listener.
+    //--------------------------------------------------------------------------
+    pub fn local_addr( &self ) -> io::Result<SocketAddr>
+    {
+        self.inner.local_addr()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits for a connection and returns it with the address of the peer.
+    //--------------------------------------------------------------------------
+    pub async fn accept( &self ) -> io::Result<(TcpStream, SocketAddr)>
+    {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for a connection. See `accept`.
+    //--------------------------------------------------------------------------
+    pub fn poll_accept( &self, cx: &mut Context ) -> Poll<io::Result<(TcpStream, SocketAddr)>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        match self.registration.poll_read(cx, || self.inner.accept())
+        {
+            Poll::Ready(Ok((stream, address))) =>
+            {
+                Poll::Ready(TcpStream::from_std(stream).map(|stream| (stream, address)))
+            },
+            Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl AsRawFd for TcpListener
+{
+    fn as_raw_fd( &self ) -> RawFd
+    {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl fmt::Debug for TcpListener
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        self.inner.fmt(f)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # TcpStream
+//------------------------------------------------------------------------------
+pub struct TcpStream
+{
+    registration: Registration,
+    inner: net::TcpStream,
+}
+
+impl TcpStream
+{
+    //--------------------------------------------------------------------------
+    /// Connects to the address without blocking the worker thread.
+    //--------------------------------------------------------------------------
+    pub async fn connect( address: SocketAddr ) -> io::Result<Self>
+    {
+        let domain = match address
+        {
+            SocketAddr::V4(_) => libc::AF_INET,
+            SocketAddr::V6(_) => libc::AF_INET6,
+        };
+        let fd = unsafe
+        {
+            libc::socket
+            (
+                domain,
+                libc::SOCK_STREAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC,
+                0,
+            )
+        };
+        if fd < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let stream = net::TcpStream::from(unsafe { OwnedFd::from_raw_fd(fd) });
+
+        let (storage, len) = socket_addr(&address);
+        let result = unsafe
+        {
+            libc::connect(fd, &storage as *const _ as *const libc::sockaddr, len)
+        };
+        if result < 0
+        {
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() != Some(libc::EINPROGRESS)
+            {
+                return Err(error);
+            }
+        }
+
+        // The connection is established, or has failed, once the socket is
+        // writable.
+        let stream = Self { registration: Registration::new(fd)?, inner: stream };
+        poll_fn(|cx|
+        {
+            if let Some(error) = stream.inner.take_error()?
+            {
+                return Poll::Ready(Err(error));
+            }
+            stream.registration.poll_write(cx, || match stream.inner.peer_addr()
+            {
+                Err(error) if error.kind() == io::ErrorKind::NotConnected =>
+                {
+                    Err(io::ErrorKind::WouldBlock.into())
+                },
+                connected => connected,
+            })
+        }).await?;
+        Ok(stream)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a TcpStream from a standard stream, which is switched to
+    /// non-blocking mode.
+    //--------------------------------------------------------------------------
+    pub fn from_std( stream: net::TcpStream ) -> io::Result<Self>
+    {
+        stream.set_nonblocking(true)?;
+        Ok(Self
+        {
+            registration: Registration::new(stream.as_raw_fd())?,
+            inner: stream,
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the local address of the connection.
+    //--------------------------------------------------------------------------
+    pub fn local_addr( &self ) -> io::Result<SocketAddr>
+    {
+        self.inner.local_addr()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the address of the peer.
+    //--------------------------------------------------------------------------
+    pub fn peer_addr( &self ) -> io::Result<SocketAddr>
+    {
+        self.inner.peer_addr()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether Nagle's algorithm is disabled.
+    //--------------------------------------------------------------------------
+    pub fn set_nodelay( &self, nodelay: bool ) -> io::Result<()>
+    {
+        self.inner.set_nodelay(nodelay)
+    }
+}
+
+impl AsyncRead for TcpStream
+{
+    fn poll_read
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        let this = self.get_mut();
+        this.registration.poll_read(cx, || (&this.inner).read(buf))
+    }
+}
+
+impl AsyncWrite for TcpStream
+{
+    fn poll_write
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        let this = self.get_mut();
+        this.registration.poll_write(cx, || (&this.inner).write(buf))
+    }
+
+    fn poll_flush( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Poll::Ready(self.inner.shutdown(Shutdown::Write))
+    }
+}
+
+impl AsRawFd for TcpStream
+{
+    fn as_raw_fd( &self ) -> RawFd
+    {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl fmt::Debug for TcpStream
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        self.inner.fmt(f)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # socket_addr
+///
+/// Converts the address into a C socket address.
+//------------------------------------------------------------------------------
+fn socket_addr( address: &SocketAddr ) -> (libc::sockaddr_storage, libc::socklen_t)
+{
+    let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+    let len = match address
+    {
+        SocketAddr::V4(address) =>
+        {
+            let sockaddr = libc::sockaddr_in
+            {
+                sin_family: libc::AF_INET as libc::sa_family_t,
+                sin_port: address.port().to_be(),
+                sin_addr: libc::in_addr
+                {
+                    s_addr: u32::from_ne_bytes(address.ip().octets()),
+                },
+                sin_zero: [0; 8],
+            };
+            unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in).write(sockaddr) };
+            mem::size_of::<libc::sockaddr_in>()
+        },
+        SocketAddr::V6(address) =>
+        {
+            let sockaddr = libc::sockaddr_in6
+            {
+                sin6_family: libc::AF_INET6 as libc::sa_family_t,
+                sin6_port: address.port().to_be(),
+                sin6_flowinfo: address.flowinfo(),
+                sin6_addr: libc::in6_addr { s6_addr: address.ip().octets() },
+                sin6_scope_id: address.scope_id(),
+            };
+            unsafe { (&mut storage as *mut _ as *mut libc::sockaddr_in6).write(sockaddr) };
+            mem::size_of::<libc::sockaddr_in6>()
+        },
+    };
+    (storage, len as libc::socklen_t)
+}
@@ -0,0 +1,406 @@
+//------------------------------------------------------------------------------
+//! # UDP
+//------------------------------------------------------------------------------
+
+use super::Registration;
+use crate::executor::poll_proceed;
+
+use std::fmt;
+use std::future::poll_fn;
+use std::io;
+use std::net::{ self, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs };
+use std::os::unix::io::{ AsRawFd, RawFd };
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # UdpSocket
+///
+/// A UDP socket. Without `connect`, datagrams are sent to and received from
+/// any address; once connected, `send` and `recv` exchange datagrams with the
+/// peer only.
+//------------------------------------------------------------------------------
+pub struct UdpSocket
+{
+    registration: Registration,
+    inner: net::UdpSocket,
+}
+
+impl UdpSocket
+{
+    //--------------------------------------------------------------------------
+    /// Binds a socket to the address. Resolving a host name blocks.
+    //--------------------------------------------------------------------------
+    pub fn bind( address: impl ToSocketAddrs ) -> io::Result<Self>
+    {
+        Self::from_std(net::UdpSocket::bind(address)?)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a UdpSocket from a standard socket, which is switched to
+    /// non-blocking mode.
+    //--------------------------------------------------------------------------
+    pub fn from_std( socket: net::UdpSocket ) -> io::Result<Self>
+    {
+        socket.set_nonblocking(true)?;
+        Ok(Self
+        {
+            registration: Registration::new(socket.as_raw_fd())?,
+            inner: socket,
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the local address of the socket.
+    //--------------------------------------------------------------------------
+    pub fn local_addr( &self ) -> io::Result<SocketAddr>
+    {
+        self.inner.local_addr()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the address of the peer the socket is connected to.
+    //--------------------------------------------------------------------------
+    pub fn peer_addr( &self ) -> io::Result<SocketAddr>
+    {
+        self.inner.peer_addr()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Connects the socket to the peer, so that `send` and `recv` can be used
+    /// and datagrams from other addresses are dropped.
+    //--------------------------------------------------------------------------
+    pub fn connect( &self, address: SocketAddr ) -> io::Result<()>
+    {
+        self.inner.connect(address)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sends the datagram to the address, and returns the number of bytes
+    /// sent.
+    //--------------------------------------------------------------------------
+    pub async fn send_to( &self, buf: &[u8], target: SocketAddr ) -> io::Result<usize>
+    {
+        poll_fn(|cx| self.poll_send_to(cx, buf, target)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for sending a datagram. See `send_to`.
+    //--------------------------------------------------------------------------
+    pub fn poll_send_to
+    (
+        &self,
+        cx: &mut Context,
+        buf: &[u8],
+        target: SocketAddr,
+    ) -> Poll<io::Result<usize>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        self.registration.poll_write(cx, || self.inner.send_to(buf, target))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Receives a datagram into the buffer, and returns the number of bytes
+    /// received with the address of the sender. The bytes that do not fit in
+    /// the buffer are discarded.
+    //--------------------------------------------------------------------------
+    pub async fn recv_from( &self, buf: &mut [u8] ) -> io::Result<(usize, SocketAddr)>
+    {
+        poll_fn(|cx| self.poll_recv_from(cx, buf)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for a datagram. See `recv_from`.
+    //--------------------------------------------------------------------------
+    pub fn poll_recv_from
+    (
+        &self,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        self.registration.poll_read(cx, || self.inner.recv_from(buf))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Receives the next datagram like `recv_from`, but leaves it in the
+    /// queue.
+    //--------------------------------------------------------------------------
+    pub async fn peek_from( &self, buf: &mut [u8] ) -> io::Result<(usize, SocketAddr)>
+    {
+        poll_fn(|cx| self.poll_peek_from(cx, buf)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for peeking a datagram. See `peek_from`.
+    //--------------------------------------------------------------------------
+    pub fn poll_peek_from
+    (
+        &self,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<(usize, SocketAddr)>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        self.registration.poll_read(cx, || self.inner.peek_from(buf))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sends the datagram to the connected peer.
+    //--------------------------------------------------------------------------
+    pub async fn send( &self, buf: &[u8] ) -> io::Result<usize>
+    {
+        poll_fn(|cx| self.poll_send(cx, buf)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for sending a datagram to the connected peer. See `send`.
+    //--------------------------------------------------------------------------
+    pub fn poll_send( &self, cx: &mut Context, buf: &[u8] ) -> Poll<io::Result<usize>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        self.registration.poll_write(cx, || self.inner.send(buf))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Receives a datagram from the connected peer.
+    //--------------------------------------------------------------------------
+    pub async fn recv( &self, buf: &mut [u8] ) -> io::Result<usize>
+    {
+        poll_fn(|cx| self.poll_recv(cx, buf)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for a datagram from the connected peer. See `recv`.
+    //--------------------------------------------------------------------------
+    pub fn poll_recv( &self, cx: &mut Context, buf: &mut [u8] ) -> Poll<io::Result<usize>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        self.registration.poll_read(cx, || self.inner.recv(buf))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Receives the next datagram from the connected peer like `recv`, but
+    /// leaves it in the queue.
+    //--------------------------------------------------------------------------
+    pub async fn peek( &self, buf: &mut [u8] ) -> io::Result<usize>
+    {
+        poll_fn(|cx| self.poll_peek(cx, buf)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for peeking a datagram from the connected peer. See `peek`.
+    //--------------------------------------------------------------------------
+    pub fn poll_peek( &self, cx: &mut Context, buf: &mut [u8] ) -> Poll<io::Result<usize>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        self.registration.poll_read(cx, || self.inner.peek(buf))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether datagrams may be sent to broadcast addresses.
+    //--------------------------------------------------------------------------
+    pub fn set_broadcast( &self, broadcast: bool ) -> io::Result<()>
+    {
+        self.inner.set_broadcast(broadcast)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the time-to-live of the IPv4 multicast datagrams sent.
+    //--------------------------------------------------------------------------
+    pub fn set_multicast_ttl_v4( &self, ttl: u32 ) -> io::Result<()>
+    {
+        self.inner.set_multicast_ttl_v4(ttl)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether the IPv4 multicast datagrams sent are looped back to the
+    /// local sockets.
+    //--------------------------------------------------------------------------
+    pub fn set_multicast_loop_v4( &self, multicast_loop: bool ) -> io::Result<()>
+    {
+        self.inner.set_multicast_loop_v4(multicast_loop)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets whether the IPv6 multicast datagrams sent are looped back to the
+    /// local sockets.
+    //--------------------------------------------------------------------------
+    pub fn set_multicast_loop_v6( &self, multicast_loop: bool ) -> io::Result<()>
+    {
+        self.inner.set_multicast_loop_v6(multicast_loop)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Joins the IPv4 multicast group on the interface with the given
+    /// address, or on the default one if it is unspecified.
+    //--------------------------------------------------------------------------
+    pub fn join_multicast_v4( &self, group: Ipv4Addr, interface: Ipv4Addr ) -> io::Result<()>
+    {
+        self.inner.join_multicast_v4(&group, &interface)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Leaves the IPv4 multicast group. See `join_multicast_v4`.
+    //--------------------------------------------------------------------------
+    pub fn leave_multicast_v4( &self, group: Ipv4Addr, interface: Ipv4Addr ) -> io::Result<()>
+    {
+        self.inner.leave_multicast_v4(&group, &interface)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Joins the IPv6 multicast group on the interface with the given index,
+    /// or on the default one if it is zero.
+    //--------------------------------------------------------------------------
+    pub fn join_multicast_v6( &self, group: Ipv6Addr, interface: u32 ) -> io::Result<()>
+    {
+        self.inner.join_multicast_v6(&group, interface)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Leaves the IPv6 multicast group. See `join_multicast_v6`.
+    //--------------------------------------------------------------------------
+    pub fn leave_multicast_v6( &self, group: Ipv6Addr, interface: u32 ) -> io::Result<()>
+    {
+        self.inner.leave_multicast_v6(&group, interface)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns and clears the pending error of the socket, such as an ICMP
+    /// port unreachable reported for a previous datagram.
+    //--------------------------------------------------------------------------
+    pub fn take_error( &self ) -> io::Result<Option<io::Error>>
+    {
+        self.inner.take_error()
+    }
+}
+
+impl AsRawFd for UdpSocket
+{
+    fn as_raw_fd( &self ) -> RawFd
+    {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl fmt::Debug for UdpSocket
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        self.inner.fmt(f)
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+
+    //--------------------------------------------------------------------------
+    /// Binds a socket to a free port of the loopback interface.
+    //--------------------------------------------------------------------------
+    fn loopback() -> UdpSocket
+    {
+        UdpSocket::bind("127.0.0.1:0").unwrap()
+    }
+
+    #[test]
+    fn datagrams_are_exchanged_with_any_address()
+    {
+        let (first, second) = (loopback(), loopback());
+        let first_addr = first.local_addr().unwrap();
+        let second_addr = second.local_addr().unwrap();
+        Executor::new(0).block_on(async move
+        {
+            assert_eq!(first.send_to(b"ping", second_addr).await.unwrap(), 4);
+            let mut buf = [0; 16];
+            assert_eq!(second.recv_from(&mut buf).await.unwrap(), (4, first_addr));
+            assert_eq!(&buf[..4], b"ping");
+
+            second.send_to(b"pong", first_addr).await.unwrap();
+            assert_eq!(first.recv_from(&mut buf).await.unwrap(), (4, second_addr));
+            assert_eq!(&buf[..4], b"pong");
+        }).unwrap();
+    }
+
+    #[test]
+    fn peek_leaves_the_datagram_queued()
+    {
+        let (first, second) = (loopback(), loopback());
+        let first_addr = first.local_addr().unwrap();
+        let second_addr = second.local_addr().unwrap();
+        Executor::new(0).block_on(async move
+        {
+            first.send_to(b"one", second_addr).await.unwrap();
+            first.send_to(b"two", second_addr).await.unwrap();
+
+            let mut buf = [0; 16];
+            assert_eq!(second.peek_from(&mut buf).await.unwrap(), (3, first_addr));
+            assert_eq!(&buf[..3], b"one");
+            assert_eq!(second.peek(&mut buf).await.unwrap(), 3);
+            assert_eq!(second.recv_from(&mut buf).await.unwrap(), (3, first_addr));
+            assert_eq!(&buf[..3], b"one");
+            assert_eq!(second.recv_from(&mut buf).await.unwrap(), (3, first_addr));
+            assert_eq!(&buf[..3], b"two");
+        }).unwrap();
+    }
+
+    #[test]
+    fn connected_sockets_exchange_with_their_peer_only()
+    {
+        let (first, second, other) = (loopback(), loopback(), loopback());
+        let first_addr = first.local_addr().unwrap();
+        let second_addr = second.local_addr().unwrap();
+        first.connect(second_addr).unwrap();
+        second.connect(first_addr).unwrap();
+        assert_eq!(first.peer_addr().unwrap(), second_addr);
+        assert!(other.peer_addr().is_err());
+
+        Executor::new(0).block_on(async move
+        {
+            // The datagram of a socket that is not the peer is filtered out.
+            other.send_to(b"stray", second_addr).await.unwrap();
+            first.send(b"hello").await.unwrap();
+            let mut buf = [0; 16];
+            assert_eq!(second.recv(&mut buf).await.unwrap(), 5);
+            assert_eq!(&buf[..5], b"hello");
+
+            second.send(b"world").await.unwrap();
+            assert_eq!(first.recv(&mut buf).await.unwrap(), 5);
+            assert_eq!(&buf[..5], b"world");
+        }).unwrap();
+    }
+
+    #[test]
+    fn multicast_group_is_joined_and_left()
+    {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let group = Ipv4Addr::new(239, 255, 0, 1);
+        socket.set_multicast_loop_v4(true).unwrap();
+        socket.set_multicast_ttl_v4(1).unwrap();
+        socket.join_multicast_v4(group, Ipv4Addr::LOCALHOST).unwrap();
+        socket.leave_multicast_v4(group, Ipv4Addr::LOCALHOST).unwrap();
+        assert!(socket.take_error().unwrap().is_none());
+    }
+}
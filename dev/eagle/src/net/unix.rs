@@ -0,0 +1,305 @@
+//------------------------------------------------------------------------------
+//! # Unix domain sockets
+//------------------------------------------------------------------------------
+
+use super::Registration;
+use crate::executor::poll_proceed;
+use crate::io::{ AsyncRead, AsyncWrite };
+
+use std::fmt;
+use std::future::poll_fn;
+use std::io::{ self, Read, Write };
+use std::net::Shutdown;
+use std::os::unix::io::{ AsRawFd, RawFd };
+use std::os::unix::net::{ self, SocketAddr };
+use std::path::Path;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # UnixListener
+///
+/// Listener on a Unix domain socket. The socket file is left behind when the
+/// listener is dropped.
+//------------------------------------------------------------------------------
+pub struct UnixListener
+{
+    registration: Registration,
+    inner: net::UnixListener,
+}
+
+impl UnixListener
+{
+    //--------------------------------------------------------------------------
+    /// Binds a listener to the path. Fails with `AddrInUse` if the file
+    /// already exists.
+    //--------------------------------------------------------------------------
+    pub fn bind( path: impl AsRef<Path> ) -> io::Result<Self>
+    {
+        Self::from_std(net::UnixListener::bind(path)?)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a UnixListener from a standard listener, which is switched to
+    /// non-blocking mode.
+    //--------------------------------------------------------------------------
+    pub fn from_std( listener: net::UnixListener ) -> io::Result<Self>
+    {
+        listener.set_nonblocking(true)?;
+        Ok(Self
+        {
+            registration: Registration::new(listener.as_raw_fd())?,
+            inner: listener,
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the local address of the listener.
+    //--------------------------------------------------------------------------
+    pub fn local_addr( &self ) -> io::Result<SocketAddr>
+    {
+        self.inner.local_addr()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits for a connection and returns it with the address of the peer.
+    //--------------------------------------------------------------------------
+    pub async fn accept( &self ) -> io::Result<(UnixStream, SocketAddr)>
+    {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for a connection. See `accept`.
+    //--------------------------------------------------------------------------
+    pub fn poll_accept( &self, cx: &mut Context ) -> Poll<io::Result<(UnixStream, SocketAddr)>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        match self.registration.poll_read(cx, || self.inner.accept())
+        {
+            Poll::Ready(Ok((stream, address))) =>
+            {
+                Poll::Ready(UnixStream::from_std(stream).map(|stream| (stream, address)))
+            },
+            Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl AsRawFd for UnixListener
+{
+    fn as_raw_fd( &self ) -> RawFd
+    {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl fmt::Debug for UnixListener
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        self.inner.fmt(f)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # UnixStream
+//------------------------------------------------------------------------------
+pub struct UnixStream
+{
+    registration: Registration,
+    inner: net::UnixStream,
+}
+
+impl UnixStream
+{
+    //--------------------------------------------------------------------------
+    /// Connects to the socket at the path. A local connection completes at
+    /// once, unless the backlog of the listener is full, in which case this
+    /// blocks until it has room.
+    //--------------------------------------------------------------------------
+    pub async fn connect( path: impl AsRef<Path> ) -> io::Result<Self>
+    {
+        Self::from_std(net::UnixStream::connect(path)?)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a pair of connected streams.
+    //--------------------------------------------------------------------------
+    pub fn pair() -> io::Result<(Self, Self)>
+    {
+        let (first, second) = net::UnixStream::pair()?;
+        Ok((Self::from_std(first)?, Self::from_std(second)?))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a UnixStream from a standard stream, which is switched to
+    /// non-blocking mode.
+    //--------------------------------------------------------------------------
+    pub fn from_std( stream: net::UnixStream ) -> io::Result<Self>
+    {
+        stream.set_nonblocking(true)?;
+        Ok(Self
+        {
+            registration: Registration::new(stream.as_raw_fd())?,
+            inner: stream,
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the local address of the connection.
+    //--------------------------------------------------------------------------
+    pub fn local_addr( &self ) -> io::Result<SocketAddr>
+    {
+        self.inner.local_addr()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the address of the peer.
+    //--------------------------------------------------------------------------
+    pub fn peer_addr( &self ) -> io::Result<SocketAddr>
+    {
+        self.inner.peer_addr()
+    }
+}
+
+impl AsyncRead for UnixStream
+{
+    fn poll_read
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        let this = self.get_mut();
+        this.registration.poll_read(cx, || (&this.inner).read(buf))
+    }
+}
+
+impl AsyncWrite for UnixStream
+{
+    fn poll_write
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        let this = self.get_mut();
+        this.registration.poll_write(cx, || (&this.inner).write(buf))
+    }
+
+    fn poll_flush( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Poll::Ready(self.inner.shutdown(Shutdown::Write))
+    }
+}
+
+impl AsRawFd for UnixStream
+{
+    fn as_raw_fd( &self ) -> RawFd
+    {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl fmt::Debug for UnixStream
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        self.inner.fmt(f)
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+    use crate::io::{ AsyncReadExt, AsyncWriteExt };
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn listener_accepts_a_connection()
+    {
+        let path = env::temp_dir().join(format!("eagle-unix-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+        assert_eq!(listener.local_addr().unwrap().as_pathname(), Some(path.as_path()));
+
+        let connect = path.clone();
+        let (request, response) = Executor::new(0).block_on(async move
+        {
+            let mut client = UnixStream::connect(&connect).await.unwrap();
+            let (mut server, _) = listener.accept().await.unwrap();
+            assert_eq!(client.peer_addr().unwrap().as_pathname(), Some(connect.as_path()));
+
+            client.write_all(b"ping").await.unwrap();
+            client.shutdown().await.unwrap();
+            let mut request = Vec::new();
+            server.read_to_end(&mut request).await.unwrap();
+            server.write_all(b"pong").await.unwrap();
+            drop(server);
+            let mut response = Vec::new();
+            client.read_to_end(&mut response).await.unwrap();
+            (request, response)
+        }).unwrap();
+        assert_eq!((request.as_slice(), response.as_slice()), (&b"ping"[..], &b"pong"[..]));
+
+        // The socket file is left behind, and a new bind fails until it is
+        // removed.
+        assert!(path.exists());
+        let error = UnixListener::bind(&path).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn pair_is_connected()
+    {
+        let (mut first, mut second) = UnixStream::pair().unwrap();
+        let received = Executor::new(0).block_on(async move
+        {
+            first.write_all(b"hello").await.unwrap();
+            let mut buf = [0; 5];
+            second.read_exact(&mut buf).await.unwrap();
+            buf
+        }).unwrap();
+        assert_eq!(&received, b"hello");
+    }
+
+    #[test]
+    fn connect_to_a_missing_path_fails()
+    {
+        let path = env::temp_dir().join(format!("eagle-unix-missing-{}.sock", process::id()));
+        let error = Executor::new(0).block_on(async move
+        {
+            UnixStream::connect(&path).await.err().unwrap()
+        }).unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
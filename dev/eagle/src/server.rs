@@ -2,10 +2,71 @@
 //! Server module
 //------------------------------------------------------------------------------
 
-use crate::executor::Executor;
+use crate::executor::{ spawn, task_dump, Executor, JoinSet };
+use crate::http::{ self, HttpOptions, Request, Response };
+use crate::listener::{ AcceptCounters, Handler, Listener, ListenerConfig, ListenerMetrics, UnixSocketOptions };
+use crate::signal::{ signal, SignalKind };
+use crate::stream::{ Stream, StreamExt };
+use crate::sync::watch;
+use crate::systemd;
+use crate::timer::Timer;
+use crate::upgrade::{ self, HandoffFd, Inherited, Upgrade };
 
-use std::io::{ self, Read, Write };
-use std::net::TcpListener;
+use std::env;
+use std::fmt::Write;
+use std::future::{ poll_fn, Future };
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::pin::{ pin, Pin };
+use std::sync::{ Arc, Mutex, PoisonError };
+use std::task::Poll;
+use std::time::Duration;
+
+/// Number of worker threads of the runtime.
+const WORKER_THREADS: usize = 10;
+
+/// Path of the task dump endpoint.
+const TASK_DUMP_PATH: &str = "/debug/tasks";
+
+/// Path of the listener metrics endpoint.
+const LISTENERS_PATH: &str = "/debug/listeners";
+
+
+//------------------------------------------------------------------------------
+/// # ServerConfig
+///
+/// The configuration of a server, filled by `EagleServerBuilder::build`.
+///
+/// - addresses: The addresses to listen on, with the configuration of their
+///   listeners. An address prefixed with `unix:` is the path of a Unix
+///   domain socket, created with the `unix_socket` options.
+/// - reuse_port: Whether each TCP address is bound by one `SO_REUSEPORT`
+///   listener per worker thread, each accepting in its own task.
+/// - socket_activation: Whether the server listens on the sockets passed by
+///   systemd instead of the addresses, if it has passed any.
+/// - systemd_notify: Whether the server notifies systemd when it is ready
+///   and when it stops, and pings its watchdog if enabled.
+/// - graceful_shutdown: Whether the server shuts down gracefully on SIGTERM.
+/// - debug_endpoint: Whether the debug endpoints are served.
+/// - task_dump_signal: The signal on which a task dump is printed.
+/// - upgrade_signal: The signal on which the server executes its binary
+///   again, passing it the listening sockets, and shuts down gracefully once
+///   the new process is ready.
+/// - http: The options of the HTTP connections.
+//------------------------------------------------------------------------------
+pub(crate) struct ServerConfig
+{
+    pub(crate) addresses: Vec<(String, ListenerConfig)>,
+    pub(crate) unix_socket: UnixSocketOptions,
+    pub(crate) reuse_port: bool,
+    pub(crate) socket_activation: bool,
+    pub(crate) systemd_notify: bool,
+    pub(crate) graceful_shutdown: bool,
+    pub(crate) debug_endpoint: bool,
+    pub(crate) task_dump_signal: Option<SignalKind>,
+    pub(crate) upgrade_signal: Option<SignalKind>,
+    pub(crate) http: HttpOptions,
+}
 
 
 //------------------------------------------------------------------------------
@@ -13,7 +74,8 @@ use std::net::TcpListener;
 //------------------------------------------------------------------------------
 pub struct EagleServer
 {
-    address: String,
+    config: ServerConfig,
+    accept_counters: Mutex<Vec<Arc<AcceptCounters>>>,
 }
 
 impl EagleServer
@@ -21,42 +83,504 @@ impl EagleServer
     //--------------------------------------------------------------------------
     /// Creates a new server.
     //--------------------------------------------------------------------------
-    pub fn new( address: String ) -> Self
+    pub(crate) fn new( config: ServerConfig ) -> Self
     {
         Self
         {
-            address,
+            config,
+            accept_counters: Mutex::new(Vec::new()),
         }
     }
 
+    //--------------------------------------------------------------------------
+    /// Returns the accept metrics of the listeners of the running server, in
+    /// the order they were bound. It is empty before the server is started.
+    //--------------------------------------------------------------------------
+    pub fn accept_metrics( &self ) -> Vec<ListenerMetrics>
+    {
+        self.accept_counters
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|counters| counters.snapshot())
+            .collect()
+    }
+
     //--------------------------------------------------------------------------
     /// Starts the server.
+    ///
+    /// When graceful shutdown is enabled, the server stops accepting new
+    /// connections on SIGTERM, closes the idle ones, and returns once the
+    /// requests in progress are answered.
+    ///
+    /// A dump of the live tasks is printed to the standard error on the task
+    /// dump signal. When the debug endpoint is enabled, the dump is served on
+    /// `GET /debug/tasks`, and the accept metrics on `GET /debug/listeners`.
+    ///
+    /// The socket files created for the Unix domain sockets are removed when
+    /// the server returns, unless they have been handed over to an upgraded
+    /// process.
     //--------------------------------------------------------------------------
     pub fn run( &self ) -> io::Result<()>
     {
-        let listener = TcpListener::bind(&self.address)?;
-        listener.set_nonblocking(true)?;
+        // A server started by an upgrade adopts the sockets of the previous
+        // process, whatever its configuration.
+        let inherited = Inherited::take()?;
+        let mut listeners = Vec::new();
+        for (fd, name, owns_file) in inherited.listeners
+        {
+            listeners.push(Listener::adopt(fd, Some(name), owns_file)?);
+        }
+        if listeners.is_empty() && self.config.socket_activation
+        {
+            for listen_fd in systemd::listen_fds()?
+            {
+                listeners.push(Listener::from_listen_fd(listen_fd)?);
+            }
+        }
+        if listeners.is_empty() && self.config.addresses.is_empty()
+        {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no address to listen on"));
+        }
+        let addresses = match listeners.is_empty()
+        {
+            true => self.config.addresses.as_slice(),
+            false => &[],
+        };
+        for (address, _) in addresses
+        {
+            match self.config.reuse_port
+            {
+                true =>
+                {
+                    let bound = Listener::bind_reuse_port(address, WORKER_THREADS, self.config.unix_socket)?;
+                    listeners.extend(bound);
+                },
+                false => listeners.push(Listener::bind(address, self.config.unix_socket)?),
+            }
+        }
+        let counters: Arc<[Arc<AcceptCounters>]> = listeners
+            .iter()
+            .map(|listener| listener.counters().clone())
+            .collect();
+        *self.accept_counters.lock().unwrap_or_else(PoisonError::into_inner) = counters.to_vec();
+
+        let handoff: Vec<_> = listeners
+            .iter()
+            .map(|listener| HandoffFd
+            {
+                fd: listener.as_raw_fd(),
+                name: listener.address().to_string(),
+                owns_file: listener.owns_file(),
+            })
+            .collect();
+        let mut names: Vec<_> = listeners.iter().map(Listener::address).collect();
+        names.dedup();
+        let names = names.join(", ");
 
-        println!("Server is running on {}", self.address);
+        // Each listener answers with the handler configured for the address
+        // it is named after.
+        let listeners: Vec<_> = listeners
+            .into_iter()
+            .map(|listener|
+            {
+                let handler = self.config.addresses
+                    .iter()
+                    .find(|(address, _)| address == listener.address())
+                    .and_then(|(_, config)| config.handler.clone());
+                let service = Arc::new(Service
+                {
+                    http: self.config.http,
+                    debug_endpoint: self.config.debug_endpoint,
+                    counters: counters.clone(),
+                    handler,
+                });
+                (listener, service)
+            })
+            .collect();
 
-        let mut executor = Executor::new(10);
+        // Each acceptor task accepts on its listeners and handles the
+        // connections one at a time.
+        let acceptors = match self.config.reuse_port
+        {
+            true => listeners.into_iter().map(|listener| vec![listener]).collect(),
+            false => vec![listeners],
+        };
+
+        // The binary is looked up now, as the path of the running executable
+        // no longer names it once it is replaced.
+        let program = match self.config.upgrade_signal
+        {
+            Some(_) => Some(env::current_exe()?),
+            None => None,
+        };
+        let mut upgrade_signal = match self.config.upgrade_signal
+        {
+            Some(kind) => Some(signal(kind)?),
+            None => None,
+        };
+        let mut shutdown = match self.config.graceful_shutdown
+        {
+            true => Some(signal(SignalKind::terminate())?),
+            false => None,
+        };
+        let dump_signal = match self.config.task_dump_signal
+        {
+            Some(kind) => Some(signal(kind)?),
+            None => None,
+        };
+        let systemd_notify = self.config.systemd_notify;
+
+        println!("Server is running on {}", names);
+
+        let mut executor = Executor::new(WORKER_THREADS);
         executor.start();
-        let _ = executor.block_on(async move
+        let result = executor.block_on(async move
         {
-            loop
+            let dumper = dump_signal.map(|mut dump_signal| spawn(async move
+            {
+                while dump_signal.next().await.is_some()
+                {
+                    eprint!("{}", task_dump());
+                }
+            }));
+
+            let (stop_tx, stop_rx) = watch::channel(false);
+            let acceptors: Vec<_> = acceptors
+                .into_iter()
+                .map(|listeners|
+                {
+                    spawn(accept_loop(listeners, stop_rx.clone()))
+                })
+                .collect();
+            drop(stop_rx);
+
+            let watchdog = match systemd_notify
+            {
+                true =>
+                {
+                    let _ = systemd::notify("READY=1");
+                    systemd::watchdog_interval().map(|interval| spawn(watchdog(interval)))
+                },
+                false => None,
+            };
+            if let Some(ready) = inherited.ready
+            {
+                if let Err(error) = upgrade::notify_ready(ready)
+                {
+                    eprintln!("Upgrade readiness report failed: {}", error);
+                }
+            }
+
+            let mut upgrade: Option<Upgrade> = None;
+            let handed_over = loop
             {
-                let (mut stream, _addr) = match listener.accept()
+                let event = poll_fn(|cx|
+                {
+                    if let Some(shutdown) = shutdown.as_mut()
+                    {
+                        if Pin::new(shutdown).poll_next(cx).is_ready()
+                        {
+                            return Poll::Ready(Event::Shutdown);
+                        }
+                    }
+                    if let Some(upgrade) = upgrade.as_mut()
+                    {
+                        if let Poll::Ready(result) = upgrade.poll_ready(cx)
+                        {
+                            return Poll::Ready(Event::UpgradeReady(result));
+                        }
+                    }
+                    if let Some(upgrade_signal) = upgrade_signal.as_mut()
+                    {
+                        if Pin::new(upgrade_signal).poll_next(cx).is_ready()
+                        {
+                            return Poll::Ready(Event::Upgrade);
+                        }
+                    }
+                    Poll::Pending
+                }).await;
+
+                match event
                 {
-                    Ok((stream, addr)) => (stream, addr),
-                    Err(_) => continue,
-                };
+                    Event::Shutdown => break false,
+                    Event::Upgrade if upgrade.is_none() =>
+                    {
+                        if let Some(program) = program.as_deref()
+                        {
+                            match Upgrade::spawn(program, &handoff)
+                            {
+                                Ok(started) => upgrade = Some(started),
+                                Err(error) => eprintln!("Upgrade failed: {}", error),
+                            }
+                        }
+                    },
+                    Event::Upgrade => {},
+                    Event::UpgradeReady(Ok(pid)) =>
+                    {
+                        println!("Server is handing over to process {}", pid);
+                        if systemd_notify
+                        {
+                            let _ = systemd::notify(&format!("MAINPID={}", pid));
+                        }
+                        break true;
+                    },
+                    Event::UpgradeReady(Err(error)) =>
+                    {
+                        eprintln!("Upgrade failed: {}", error);
+                        upgrade = None;
+                    },
+                }
+            };
 
-                let mut buffer = [0; 1024];
-                let _ = stream.read(&mut buffer);
-                let _ = stream.write(b"HTTP/1.1 200 OK\r\n\r\nHello, World\n");
-                let _ = stream.flush();
+            println!("Server is shutting down");
+            if systemd_notify && !handed_over
+            {
+                let _ = systemd::notify("STOPPING=1");
+            }
+            let _ = stop_tx.send(true);
+            for acceptor in acceptors
+            {
+                if let Ok(mut listeners) = acceptor.await
+                {
+                    for listener in listeners.iter_mut().filter(|_| handed_over)
+                    {
+                        listener.disown();
+                    }
+                }
             }
+            for task in [dumper, watchdog].into_iter().flatten()
+            {
+                task.abort();
+            }
+        });
+        result.map_err(|error| io::Error::other(error.to_string()))
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Event
+///
+/// Event awaited by the server while its acceptors run.
+//------------------------------------------------------------------------------
+enum Event
+{
+    Shutdown,
+    Upgrade,
+    UpgradeReady(io::Result<u32>),
+}
+
+
+//------------------------------------------------------------------------------
+/// # watchdog
+///
+/// Pings the systemd watchdog twice per interval, as long as the runtime
+/// polls the task.
+//------------------------------------------------------------------------------
+async fn watchdog( interval: Duration )
+{
+    let Ok(timer) = Timer::interval(interval / 2) else
+    {
+        return;
+    };
+    while timer.expired().await.is_ok()
+    {
+        let _ = systemd::notify("WATCHDOG=1");
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Service
+///
+/// What the connections of a listener share.
+//------------------------------------------------------------------------------
+struct Service
+{
+    http: HttpOptions,
+    debug_endpoint: bool,
+    counters: Arc<[Arc<AcceptCounters>]>,
+    handler: Option<Handler>,
+}
+
+impl Service
+{
+    //--------------------------------------------------------------------------
+    /// Answers the request.
+    //--------------------------------------------------------------------------
+    async fn respond( &self, request: Request ) -> Response
+    {
+        let debug = self.debug_endpoint && matches!(request.method.as_str(), "GET" | "HEAD");
+        let body = match request.target.as_str()
+        {
+            TASK_DUMP_PATH if debug => task_dump().to_string(),
+            LISTENERS_PATH if debug =>
+            {
+                let mut body = String::new();
+                for metrics in self.counters.iter().map(|counters| counters.snapshot())
+                {
+                    let _ = writeln!
+                    (
+                        body,
+                        "{} accepted={} failed={}",
+                        metrics.address,
+                        metrics.accepted,
+                        metrics.failed,
+                    );
+                }
+                body
+            },
+            _ => return match &self.handler
+            {
+                Some(handler) => handler(request).await,
+                None => Response::new(200, "Hello, World\n"),
+            },
+        };
+        Response::new(200, body).header("Content-Type", "text/plain")
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # accept_loop
+///
+/// Accepts the connections on the listeners and serves each one in its own
+/// task with the service of its listener, until the stop channel changes.
+/// Then waits for the connections to close, and returns the listeners. The
+/// listeners are polled starting after the one that last accepted, so that a
+/// busy listener does not starve the others.
+//------------------------------------------------------------------------------
+async fn accept_loop
+(
+    listeners: Vec<(Listener, Arc<Service>)>,
+    mut stop: watch::Receiver<bool>,
+) -> Vec<Listener>
+{
+    let mut connections = JoinSet::new();
+    let mut next = 0;
+    loop
+    {
+        let accepted =
+        {
+            let mut stopped = pin!(stop.changed());
+            poll_fn(|cx|
+            {
+                while let Poll::Ready(Some(_)) = connections.poll_join_next(cx) {}
+                if stopped.as_mut().poll(cx).is_ready()
+                {
+                    return Poll::Ready(None);
+                }
+                for offset in 0..listeners.len()
+                {
+                    let index = (next + offset) % listeners.len();
+                    if let Poll::Ready(accepted) = listeners[index].0.poll_accept(cx)
+                    {
+                        next = index + 1;
+                        return Poll::Ready(Some((accepted, index)));
+                    }
+                }
+                Poll::Pending
+            }).await
+        };
+
+        match accepted
+        {
+            Some((Ok(stream), index)) =>
+            {
+                let service = listeners[index].1.clone();
+                let stop = stop.clone();
+                connections.spawn(async move
+                {
+                    let http = service.http;
+                    http::serve(stream, http, stop, |request|
+                    {
+                        let service = service.clone();
+                        async move { service.respond(request).await }
+                    }).await;
+                });
+            },
+            Some((Err(_), _)) => continue,
+            None => break,
+        }
+    }
+
+    while connections.join_next().await.is_some() {}
+    listeners.into_iter().map(|(listener, _)| listener).collect()
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::http::Body;
+    use crate::EagleServerBuilder;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process;
+
+    fn respond( handler: Option<Handler>, target: &str ) -> (u16, Vec<u8>)
+    {
+        let service = Service
+        {
+            http: HttpOptions::default(),
+            debug_endpoint: true,
+            counters: Arc::new([]),
+            handler,
+        };
+        let request = Request
+        {
+            method: "GET".to_string(),
+            target: target.to_string(),
+            minor_version: 1,
+            headers: Vec::new(),
+            body: Body::empty(),
+        };
+        Executor::new(0).block_on(async move
+        {
+            let response = service.respond(request).await;
+            let (body, _) = response.body.collect(usize::MAX).await.unwrap();
+            (response.status, body)
+        }).unwrap()
+    }
+
+    #[test]
+    fn listener_handler_replaces_the_default_one()
+    {
+        let (status, body) = respond(None, "/");
+        assert_eq!((status, body.as_slice()), (200, &b"Hello, World\n"[..]));
+
+        let mut config = ListenerConfig::new();
+        config.handler(|request: Request| async move
+        {
+            Response::new(404, format!("no {}\n", request.target))
         });
-        Ok(())
+        let (status, body) = respond(config.handler.clone(), "/admin");
+        assert_eq!((status, body.as_slice()), (404, &b"no /admin\n"[..]));
+
+        // The debug endpoints are still served before the handler.
+        let (status, body) = respond(config.handler, LISTENERS_PATH);
+        assert_eq!((status, body.as_slice()), (200, &b""[..]));
+    }
+
+    #[test]
+    fn unix_address_is_bound_with_the_socket_mode()
+    {
+        let path = env::temp_dir().join(format!("eagle-server-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let address = format!("unix:{}", path.display());
+        let server = EagleServerBuilder::new()
+            .address(&address)
+            .unix_socket_mode(Some(0o600))
+            .build();
+
+        let (bound, _) = &server.config.addresses[0];
+        let listener = Listener::bind(bound, server.config.unix_socket).unwrap();
+        assert!(listener.owns_file());
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        drop(listener);
+        assert!(!path.exists());
     }
 }
@@ -0,0 +1,496 @@
+//------------------------------------------------------------------------------
+//! # Unix signals
+//!
+//! Signals are caught by a handler that writes the signal number into a
+//! self-pipe. The read end of the pipe is registered with the reactor, and the
+//! received signals are dispatched to every `Signal` listening on them.
+//!
+//! ```no_run
+//! use eagle::signal::{ signal, SignalKind };
+//! use eagle::stream::StreamExt;
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let mut hangup = signal(SignalKind::hangup())?;
+//! while hangup.next().await.is_some()
+//! {
+//!     println!("reloading");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//------------------------------------------------------------------------------
+
+use crate::executor::poll_proceed;
+use crate::executor::reactor::{ Reactor, Source };
+use crate::stream::Stream;
+
+use std::io;
+use std::os::unix::io::RawFd;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex, MutexGuard, OnceLock, Weak };
+use std::sync::atomic::{ AtomicI32, AtomicUsize, Ordering };
+use std::task::{ Context, Poll, Waker };
+
+static DRIVER: OnceLock<Driver> = OnceLock::new();
+
+/// Write end of the self-pipe, read by the signal handler.
+static PIPE_WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+
+//------------------------------------------------------------------------------
+/// # SignalKind
+///
+/// The kind of a Unix signal.
+//------------------------------------------------------------------------------
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SignalKind(libc::c_int);
+
+impl SignalKind
+{
+    //--------------------------------------------------------------------------
+    /// Creates a SignalKind from a raw signal number.
+    //--------------------------------------------------------------------------
+    pub const fn from_raw( signum: libc::c_int ) -> Self
+    {
+        Self(signum)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the raw signal number.
+    //--------------------------------------------------------------------------
+    pub const fn as_raw( &self ) -> libc::c_int
+    {
+        self.0
+    }
+
+    //--------------------------------------------------------------------------
+    /// SIGHUP
+    //--------------------------------------------------------------------------
+    pub const fn hangup() -> Self
+    {
+        Self(libc::SIGHUP)
+    }
+
+    //--------------------------------------------------------------------------
+    /// SIGINT
+    //--------------------------------------------------------------------------
+    pub const fn interrupt() -> Self
+    {
+        Self(libc::SIGINT)
+    }
+
+    //--------------------------------------------------------------------------
+    /// SIGQUIT
+    //--------------------------------------------------------------------------
+    pub const fn quit() -> Self
+    {
+        Self(libc::SIGQUIT)
+    }
+
+    //--------------------------------------------------------------------------
+    /// SIGTERM
+    //--------------------------------------------------------------------------
+    pub const fn terminate() -> Self
+    {
+        Self(libc::SIGTERM)
+    }
+
+    //--------------------------------------------------------------------------
+    /// SIGUSR1
+    //--------------------------------------------------------------------------
+    pub const fn user_defined1() -> Self
+    {
+        Self(libc::SIGUSR1)
+    }
+
+    //--------------------------------------------------------------------------
+    /// SIGUSR2
+    //--------------------------------------------------------------------------
+    pub const fn user_defined2() -> Self
+    {
+        Self(libc::SIGUSR2)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # signal
+///
+/// Creates a stream that yields every time the given signal is received.
+///
+/// The first call for a signal replaces its default disposition, so the
+/// process is no longer terminated by it.
+//------------------------------------------------------------------------------
+pub fn signal( kind: SignalKind ) -> io::Result<Signal>
+{
+    let driver = Driver::get()?;
+    driver.install(kind)?;
+
+    let slot = Arc::new(Slot
+    {
+        signum: kind.as_raw(),
+        pending: AtomicUsize::new(0),
+        waker: Mutex::new(None),
+    });
+    driver.lock_slots()?.push(Arc::downgrade(&slot));
+
+    Ok(Signal { driver, slot })
+}
+
+
+//------------------------------------------------------------------------------
+/// # Signal
+///
+/// Stream of received signals returned by `signal`.
+//------------------------------------------------------------------------------
+pub struct Signal
+{
+    driver: &'static Driver,
+    slot: Arc<Slot>,
+}
+
+impl Signal
+{
+    //--------------------------------------------------------------------------
+    /// Receives the next signal.
+    //--------------------------------------------------------------------------
+    pub async fn recv( &mut self ) -> Option<()>
+    {
+        std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for Signal
+{
+    type Item = ();
+
+    fn poll_next
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Self::Item>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        loop
+        {
+            if self.driver.dispatch().is_err()
+            {
+                return Poll::Ready(None);
+            }
+            if self.slot.take()
+            {
+                return Poll::Ready(Some(()));
+            }
+
+            match self.slot.waker.lock()
+            {
+                Ok(mut waker) => *waker = Some(cx.waker().clone()),
+                Err(_) => return Poll::Ready(None),
+            }
+            if self.slot.take()
+            {
+                return Poll::Ready(Some(()));
+            }
+
+            match self.driver.source.poll_readable(cx)
+            {
+                Poll::Ready(Ok(())) => continue,
+                Poll::Ready(Err(_)) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Slot
+///
+/// Number of signals received by a `Signal` and not consumed yet.
+//------------------------------------------------------------------------------
+struct Slot
+{
+    signum: libc::c_int,
+    pending: AtomicUsize,
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Slot
+{
+    //--------------------------------------------------------------------------
+    /// Consumes one pending signal.
+    //--------------------------------------------------------------------------
+    fn take( &self ) -> bool
+    {
+        self.pending
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Records a received signal and wakes the waiting task.
+    //--------------------------------------------------------------------------
+    fn notify( &self )
+    {
+        self.pending.fetch_add(1, Ordering::AcqRel);
+        if let Ok(mut waker) = self.waker.lock()
+        {
+            if let Some(waker) = waker.take()
+            {
+                waker.wake();
+            }
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Driver
+///
+/// Owns the self-pipe and dispatches the received signals.
+//------------------------------------------------------------------------------
+struct Driver
+{
+    read_fd: RawFd,
+    source: Arc<Source>,
+    slots: Mutex<Vec<Weak<Slot>>>,
+    installed: Mutex<Vec<libc::c_int>>,
+}
+
+impl Driver
+{
+    //--------------------------------------------------------------------------
+    /// Returns the global Driver, creating the self-pipe on the first call.
+    //--------------------------------------------------------------------------
+    fn get() -> io::Result<&'static Self>
+    {
+        if let Some(driver) = DRIVER.get()
+        {
+            return Ok(driver);
+        }
+
+        let mut fds = [0; 2];
+        let flags = libc::O_NONBLOCK | libc::O_CLOEXEC;
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), flags) } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let [read_fd, write_fd] = fds;
+
+        let source = match Reactor::get().and_then(|r| r.register(read_fd))
+        {
+            Ok(source) => source,
+            Err(error) =>
+            {
+                unsafe
+                {
+                    libc::close(read_fd);
+                    libc::close(write_fd);
+                }
+                return Err(error);
+            }
+        };
+
+        let driver = Self
+        {
+            read_fd,
+            source,
+            slots: Mutex::new(Vec::new()),
+            installed: Mutex::new(Vec::new()),
+        };
+        match DRIVER.set(driver)
+        {
+            Ok(()) => PIPE_WRITE_FD.store(write_fd, Ordering::SeqCst),
+            Err(driver) =>
+            {
+                if let Ok(reactor) = Reactor::get()
+                {
+                    let _ = reactor.deregister(&driver.source);
+                }
+                unsafe
+                {
+                    libc::close(read_fd);
+                    libc::close(write_fd);
+                }
+            },
+        }
+
+        DRIVER.get().ok_or_else(|| io::Error::other("signal driver is not running"))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Installs the signal handler for the signal.
+    //--------------------------------------------------------------------------
+    fn install( &self, kind: SignalKind ) -> io::Result<()>
+    {
+        let signum = kind.as_raw();
+        let forbidden = [libc::SIGKILL, libc::SIGSTOP, libc::SIGSEGV, libc::SIGILL];
+        if signum <= 0 || forbidden.contains(&signum)
+        {
+            return Err(io::Error::new
+            (
+                io::ErrorKind::InvalidInput,
+                format!("signal {} cannot be handled", signum),
+            ));
+        }
+
+        let mut installed = self.installed
+            .lock()
+            .map_err(|error| io::Error::other(error.to_string()))?;
+        if installed.contains(&signum)
+        {
+            return Ok(());
+        }
+
+        unsafe
+        {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signum, &action, std::ptr::null_mut()) < 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        installed.push(signum);
+        Ok(())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Reads the self-pipe and notifies the slots of the received signals.
+    //--------------------------------------------------------------------------
+    fn dispatch( &self ) -> io::Result<()>
+    {
+        let mut buffer = [0u8; 64];
+        loop
+        {
+            let count = unsafe
+            {
+                libc::read
+                (
+                    self.read_fd,
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                )
+            };
+            if count < 0
+            {
+                let error = io::Error::last_os_error();
+                return match error.kind()
+                {
+                    io::ErrorKind::WouldBlock => Ok(()),
+                    io::ErrorKind::Interrupted => continue,
+                    _ => Err(error),
+                };
+            }
+            if count == 0
+            {
+                return Ok(());
+            }
+
+            let mut slots = self.lock_slots()?;
+            slots.retain(|slot| slot.strong_count() > 0);
+            for signum in &buffer[..count as usize]
+            {
+                for slot in slots.iter().filter_map(Weak::upgrade)
+                {
+                    if slot.signum == *signum as libc::c_int
+                    {
+                        slot.notify();
+                    }
+                }
+            }
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Locks the slots.
+    //--------------------------------------------------------------------------
+    fn lock_slots( &self ) -> io::Result<MutexGuard<'_, Vec<Weak<Slot>>>>
+    {
+        self.slots
+            .lock()
+            .map_err(|error| io::Error::other(error.to_string()))
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # handler
+///
+/// Signal handler writing the signal number into the self-pipe. Only
+/// async-signal-safe functions may be called here.
+//------------------------------------------------------------------------------
+extern "C" fn handler( signum: libc::c_int )
+{
+    let fd = PIPE_WRITE_FD.load(Ordering::SeqCst);
+    if fd < 0
+    {
+        return;
+    }
+
+    unsafe
+    {
+        let errno = *libc::__errno_location();
+        let byte = signum as u8;
+        libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+        *libc::__errno_location() = errno;
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+    use crate::stream::StreamExt;
+    use crate::test_util::WakeCounter;
+
+    #[test]
+    fn every_stream_receives_the_signal()
+    {
+        let mut first = signal(SignalKind::user_defined1()).unwrap();
+        let mut second = signal(SignalKind::user_defined1()).unwrap();
+        assert_eq!(unsafe { libc::raise(libc::SIGUSR1) }, 0);
+
+        let (first, second) = Executor::new(0).block_on(async move
+        {
+            assert_eq!(first.next().await, Some(()));
+            assert_eq!(second.recv().await, Some(()));
+            (first, second)
+        }).unwrap();
+
+        // The signal was received once, so it is consumed.
+        let wakes = WakeCounter::new();
+        for mut signal in [first, second]
+        {
+            assert!(wakes.poll(Pin::new(&mut signal.next())).is_pending());
+        }
+    }
+
+    #[test]
+    fn forbidden_signals_are_rejected()
+    {
+        let driver = Driver::get().unwrap();
+        for signum in [libc::SIGKILL, libc::SIGSTOP, libc::SIGSEGV, libc::SIGILL, 0, -1]
+        {
+            let error = signal(SignalKind::from_raw(signum)).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput, "signal {}", signum);
+
+            let installed = driver.installed.lock().unwrap();
+            assert!(!installed.contains(&signum), "signal {}", signum);
+            let slots = driver.lock_slots().unwrap();
+            let registered = slots
+                .iter()
+                .filter_map(Weak::upgrade)
+                .any(|slot| slot.signum == signum);
+            assert!(!registered, "signal {}", signum);
+        }
+    }
+}
@@ -0,0 +1,460 @@
+//------------------------------------------------------------------------------
+//! # Deterministic simulation
+//!
+//! A `Simulation` runs all its tasks on the calling thread, and picks the
+//! next task to poll among the ready ones with a seeded random number
+//! generator. Time is virtual: `sleep` and `now` use a clock that only moves
+//! when every task is idle, and then jumps straight to the next timer. The
+//! sockets of `net` deliver their data with a random latency drawn from the
+//! same generator.
+//!
+//! The timers of the runtime, such as the keep-alive timeout of `http::serve`,
+//! also follow the virtual clock when created inside a simulation.
+//!
+//! Given the same seed, a simulation therefore replays the same interleaving,
+//! so a failing seed can be run again under a debugger. Real IO and threads
+//! are not controlled by the simulation and break the determinism.
+//!
+//! ```no_run
+//! use eagle::sim::{ self, Simulation };
+//! use std::time::Duration;
+//!
+//! for seed in 0..100
+//! {
+//!     let mut simulation = Simulation::new(seed);
+//!     let elapsed = simulation.block_on(async
+//!     {
+//!         let timer = eagle::spawn(sim::sleep(Duration::from_secs(60)));
+//!         let _ = timer.await;
+//!         sim::now()
+//!     });
+//!     assert_eq!(elapsed.unwrap(), Duration::from_secs(60), "seed {}", seed);
+//! }
+//! ```
+//------------------------------------------------------------------------------
+
+pub mod net;
+
+pub use crate::executor::{ SimError, Simulation };
+
+use net::Backlog;
+
+use std::cell::RefCell;
+use std::collections::{ BTreeMap, HashMap };
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex, PoisonError };
+use std::task::{ Context, Poll, Waker };
+use std::time::Duration;
+
+thread_local!
+{
+    static CURRENT: RefCell<Option<Arc<Mutex<World>>>> = const
+    {
+        RefCell::new(None)
+    };
+}
+
+
+//------------------------------------------------------------------------------
+/// # World
+///
+/// The state of a simulation shared by its tasks: the virtual clock, the
+/// timers, the random number generator and the network.
+//------------------------------------------------------------------------------
+pub(crate) struct World
+{
+    now: Duration,
+    rng: u64,
+    timers: BTreeMap<(Duration, u64), Waker>,
+    next_timer: u64,
+    latency: (Duration, Duration),
+    listeners: HashMap<String, Arc<Mutex<Backlog>>>,
+    next_port: u64,
+}
+
+impl World
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new World.
+    //--------------------------------------------------------------------------
+    pub(crate) fn new( seed: u64 ) -> Self
+    {
+        // splitmix64, so that close seeds do not give close sequences, and
+        // the xorshift state is never zero.
+        let mut rng = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        rng = (rng ^ (rng >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        rng = (rng ^ (rng >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        rng ^= rng >> 31;
+
+        Self
+        {
+            now: Duration::ZERO,
+            rng: rng | 1,
+            timers: BTreeMap::new(),
+            next_timer: 0,
+            latency: (Duration::ZERO, Duration::from_millis(1)),
+            listeners: HashMap::new(),
+            next_port: 1,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the virtual time elapsed since the start of the simulation.
+    //--------------------------------------------------------------------------
+    pub(crate) fn now( &self ) -> Duration
+    {
+        self.now
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a random number below the bound.
+    //--------------------------------------------------------------------------
+    pub(crate) fn random( &mut self, bound: usize ) -> usize
+    {
+        (self.next_random() % bound.max(1) as u64) as usize
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the next number of the generator.
+    //--------------------------------------------------------------------------
+    fn next_random( &mut self ) -> u64
+    {
+        // xorshift64
+        let mut x = self.rng;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.rng = x;
+        x
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the range of the network latency.
+    //--------------------------------------------------------------------------
+    pub(crate) fn set_latency( &mut self, min: Duration, max: Duration )
+    {
+        self.latency = (min, max.max(min));
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a random network latency.
+    //--------------------------------------------------------------------------
+    fn latency( &mut self ) -> Duration
+    {
+        let (min, max) = self.latency;
+        let range = u64::try_from((max - min).as_nanos()).unwrap_or(u64::MAX);
+        let nanos = match range
+        {
+            0 => 0,
+            range => self.next_random() % range,
+        };
+        min + Duration::from_nanos(nanos)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Wakes the waker when the clock reaches the given time. Returns the key
+    /// of the timer.
+    //--------------------------------------------------------------------------
+    fn add_timer( &mut self, at: Duration, waker: Waker ) -> (Duration, u64)
+    {
+        let key = (at, self.next_timer);
+        self.next_timer += 1;
+        self.timers.insert(key, waker);
+        key
+    }
+
+    //--------------------------------------------------------------------------
+    /// Advances the clock to the next timer, and returns the wakers of all the
+    /// timers due at that time. Returns nothing if there is no timer left.
+    //--------------------------------------------------------------------------
+    pub(crate) fn advance( &mut self ) -> Vec<Waker>
+    {
+        let at = match self.timers.keys().next()
+        {
+            Some((at, _)) => *at,
+            None => return Vec::new(),
+        };
+        self.now = self.now.max(at);
+
+        let mut wakers = Vec::new();
+        while let Some(entry) = self.timers.first_entry()
+        {
+            if entry.key().0 > self.now
+            {
+                break;
+            }
+            wakers.push(entry.remove());
+        }
+        wakers
+    }
+
+    //--------------------------------------------------------------------------
+    /// Removes all the timers and listeners. They are returned so that the
+    /// caller drops them with the world unlocked.
+    //--------------------------------------------------------------------------
+    pub(crate) fn clear( &mut self ) -> (Vec<Waker>, Vec<Arc<Mutex<Backlog>>>)
+    {
+        let timers = std::mem::take(&mut self.timers);
+        let listeners = std::mem::take(&mut self.listeners);
+        (timers.into_values().collect(), listeners.into_values().collect())
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # WorldGuard
+///
+/// Restores the previous world when dropped.
+//------------------------------------------------------------------------------
+pub(crate) struct WorldGuard
+{
+    previous: Option<Arc<Mutex<World>>>,
+}
+
+impl Drop for WorldGuard
+{
+    fn drop( &mut self )
+    {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # enter
+///
+/// Sets the world of the simulation running on the current thread.
+//------------------------------------------------------------------------------
+pub(crate) fn enter( world: Arc<Mutex<World>> ) -> WorldGuard
+{
+    let previous = CURRENT.with(|current| current.borrow_mut().replace(world));
+    WorldGuard { previous }
+}
+
+//------------------------------------------------------------------------------
+/// # is_entered
+///
+/// Returns whether a simulation is running on the current thread.
+//------------------------------------------------------------------------------
+pub(crate) fn is_entered() -> bool
+{
+    CURRENT.with(|current| current.borrow().is_some())
+}
+
+//------------------------------------------------------------------------------
+/// # with_world
+///
+/// Runs the closure on the world of the simulation running on the current
+/// thread, if any. Wakers must not be dropped or woken inside the closure,
+/// as that may drop a future accessing the world.
+//------------------------------------------------------------------------------
+fn with_world<R>( f: impl FnOnce(&mut World) -> R ) -> Option<R>
+{
+    let world = CURRENT.with(|current| current.borrow().clone())?;
+    let mut world = world.lock().unwrap_or_else(PoisonError::into_inner);
+    Some(f(&mut world))
+}
+
+//------------------------------------------------------------------------------
+/// # random
+///
+/// Returns a random number below the bound from the generator of the current
+/// simulation, or `None` outside of a simulation.
+//------------------------------------------------------------------------------
+pub(crate) fn random( bound: usize ) -> Option<usize>
+{
+    with_world(|world| world.random(bound))
+}
+
+
+//------------------------------------------------------------------------------
+/// # now
+///
+/// Returns the virtual time elapsed since the start of the current
+/// simulation.
+///
+/// # Panics
+///
+/// Panics if called outside of a simulation.
+//------------------------------------------------------------------------------
+pub fn now() -> Duration
+{
+    match with_world(|world| world.now())
+    {
+        Some(now) => now,
+        None => panic!("now must be called from the context of a simulation"),
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # sleep
+///
+/// Waits until the virtual clock has advanced by the given duration.
+///
+/// # Panics
+///
+/// The returned future panics if polled outside of a simulation.
+//------------------------------------------------------------------------------
+pub fn sleep( duration: Duration ) -> Sleep
+{
+    Sleep
+    {
+        duration,
+        deadline: None,
+        timer: None,
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Sleep
+///
+/// Future returned by `sleep`. The virtual clock starts counting on the first
+/// poll.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct Sleep
+{
+    duration: Duration,
+    deadline: Option<Duration>,
+    timer: Option<(Duration, u64)>,
+}
+
+impl Future for Sleep
+{
+    type Output = ();
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<()>
+    {
+        let this = &mut *self;
+        let polled = with_world(|world|
+        {
+            let deadline = *this.deadline.get_or_insert(world.now + this.duration);
+            let previous = this.timer.take().and_then(|key| world.timers.remove(&key));
+            if world.now < deadline
+            {
+                this.timer = Some(world.add_timer(deadline, cx.waker().clone()));
+            }
+            (world.now >= deadline, previous)
+        });
+
+        // The previous waker is dropped with the world unlocked.
+        match polled
+        {
+            Some((true, _previous)) => Poll::Ready(()),
+            Some((false, _previous)) => Poll::Pending,
+            None => panic!("sleep must be polled from the context of a simulation"),
+        }
+    }
+}
+
+impl Drop for Sleep
+{
+    fn drop( &mut self )
+    {
+        if let Some(key) = self.timer.take()
+        {
+            let waker = with_world(|world| world.timers.remove(&key));
+            drop(waker);
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Alarm
+///
+/// Timer on the virtual clock, backing `crate::timer::Timer` inside a
+/// simulation. Like the timer, it expires after a delay, then every period
+/// if any, and the expirations missed are coalesced into one.
+//------------------------------------------------------------------------------
+#[derive(Debug, Default)]
+pub(crate) struct Alarm
+{
+    deadline: Option<Duration>,
+    period: Option<Duration>,
+    timer: Option<(Duration, u64)>,
+}
+
+impl Alarm
+{
+    //--------------------------------------------------------------------------
+    /// Arms the alarm to expire after the delay, then every period if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a simulation.
+    //--------------------------------------------------------------------------
+    pub(crate) fn set( &mut self, delay: Duration, period: Option<Duration> )
+    {
+        // A zero period would expire forever without the clock moving.
+        self.period = period.map(|period| period.max(Duration::from_nanos(1)));
+        let previous = with_world(|world|
+        {
+            self.deadline = Some(world.now + delay);
+            self.timer.take().and_then(|key| world.timers.remove(&key))
+        });
+
+        // The previous waker is dropped with the world unlocked.
+        match previous
+        {
+            Some(_previous) => {},
+            None => panic!("timer must be set from the context of a simulation"),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for the next expiration. A disarmed alarm never expires.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a simulation.
+    //--------------------------------------------------------------------------
+    pub(crate) fn poll_expired( &mut self, cx: &mut Context ) -> Poll<()>
+    {
+        let polled = with_world(|world|
+        {
+            let previous = self.timer.take().and_then(|key| world.timers.remove(&key));
+            let Some(deadline) = self.deadline else
+            {
+                return (false, previous);
+            };
+            if world.now < deadline
+            {
+                self.timer = Some(world.add_timer(deadline, cx.waker().clone()));
+                return (false, previous);
+            }
+
+            self.deadline = self.period.map(|period|
+            {
+                let missed = (world.now - deadline).as_nanos() / period.as_nanos();
+                let next = (missed + 1) * period.as_nanos();
+                deadline + Duration::from_nanos(u64::try_from(next).unwrap_or(u64::MAX))
+            });
+            (true, previous)
+        });
+
+        // The previous waker is dropped with the world unlocked.
+        match polled
+        {
+            Some((true, _previous)) => Poll::Ready(()),
+            Some((false, _previous)) => Poll::Pending,
+            None => panic!("timer must be polled from the context of a simulation"),
+        }
+    }
+}
+
+impl Drop for Alarm
+{
+    fn drop( &mut self )
+    {
+        if let Some(key) = self.timer.take()
+        {
+            let waker = with_world(|world| world.timers.remove(&key));
+            drop(waker);
+        }
+    }
+}
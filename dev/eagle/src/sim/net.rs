@@ -0,0 +1,459 @@
+//------------------------------------------------------------------------------
+//! # Simulated network
+//!
+//! In-memory stream sockets connecting the tasks of a simulation. Addresses
+//! are plain strings, and every write is delivered as a whole after a random
+//! latency, in the order it was written. The buffers are unbounded, so
+//! writes never wait.
+//------------------------------------------------------------------------------
+
+use super::with_world;
+use crate::executor::poll_proceed;
+use crate::io::{ AsyncRead, AsyncWrite };
+
+use std::collections::VecDeque;
+use std::future::poll_fn;
+use std::io;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex, MutexGuard, PoisonError };
+use std::task::{ Context, Poll, Waker };
+use std::time::Duration;
+
+
+//------------------------------------------------------------------------------
+/// # Backlog
+///
+/// Connections waiting to be accepted by a listener.
+//------------------------------------------------------------------------------
+#[derive(Default)]
+pub(crate) struct Backlog
+{
+    streams: VecDeque<SimStream>,
+    waker: Option<Waker>,
+}
+
+
+//------------------------------------------------------------------------------
+/// # SimListener
+///
+/// Listener accepting the connections made to its address. Dropping it
+/// refuses the connections that were not accepted yet.
+//------------------------------------------------------------------------------
+pub struct SimListener
+{
+    address: String,
+    backlog: Arc<Mutex<Backlog>>,
+}
+
+impl SimListener
+{
+    //--------------------------------------------------------------------------
+    /// Binds a listener to the address.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a simulation.
+    //--------------------------------------------------------------------------
+    pub fn bind( address: &str ) -> io::Result<Self>
+    {
+        let backlog = Arc::new(Mutex::new(Backlog::default()));
+        let bound = with_world(|world|
+        {
+            if world.listeners.contains_key(address)
+            {
+                return false;
+            }
+            world.listeners.insert(address.to_string(), backlog.clone());
+            true
+        });
+
+        match bound
+        {
+            Some(true) => Ok(Self { address: address.to_string(), backlog }),
+            Some(false) => Err(io::Error::from(io::ErrorKind::AddrInUse)),
+            None => panic!("bind must be called from the context of a simulation"),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the address of the listener.
+    //--------------------------------------------------------------------------
+    pub fn local_addr( &self ) -> &str
+    {
+        &self.address
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits for a connection and returns it with the address of the peer.
+    //--------------------------------------------------------------------------
+    pub async fn accept( &self ) -> io::Result<(SimStream, String)>
+    {
+        poll_fn(|cx| self.poll_accept(cx)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for a connection. See `accept`.
+    //--------------------------------------------------------------------------
+    pub fn poll_accept( &self, cx: &mut Context ) -> Poll<io::Result<(SimStream, String)>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        let mut backlog = lock(&self.backlog);
+        match backlog.streams.pop_front()
+        {
+            Some(stream) =>
+            {
+                let peer = stream.peer.clone();
+                Poll::Ready(Ok((stream, peer)))
+            },
+            None =>
+            {
+                let previous = backlog.waker.replace(cx.waker().clone());
+                drop(backlog);
+                drop(previous);
+                Poll::Pending
+            },
+        }
+    }
+}
+
+impl Drop for SimListener
+{
+    fn drop( &mut self )
+    {
+        let listener = with_world(|world|
+        {
+            match world.listeners.get(&self.address)
+            {
+                Some(backlog) if Arc::ptr_eq(backlog, &self.backlog) =>
+                {
+                    world.listeners.remove(&self.address)
+                },
+                _ => None,
+            }
+        });
+        drop(listener);
+
+        let streams = std::mem::take(&mut lock(&self.backlog).streams);
+        drop(streams);
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Pipe
+///
+/// One direction of a connection.
+///
+/// - segments: The written data, with the time it is delivered at.
+/// - delivered: The delivery time of the last segment, which the next ones
+///   may not precede.
+/// - closed: The writer has shut down.
+/// - dropped: The reader has been dropped.
+//------------------------------------------------------------------------------
+#[derive(Default)]
+struct Pipe
+{
+    segments: VecDeque<(Duration, Vec<u8>)>,
+    delivered: Duration,
+    closed: bool,
+    dropped: bool,
+    reader: Option<Waker>,
+}
+
+
+//------------------------------------------------------------------------------
+/// # SimStream
+///
+/// A connection between two tasks of a simulation. Dropping it shuts down
+/// its write half.
+//------------------------------------------------------------------------------
+pub struct SimStream
+{
+    local: String,
+    peer: String,
+    read: Arc<Mutex<Pipe>>,
+    write: Arc<Mutex<Pipe>>,
+}
+
+impl SimStream
+{
+    //--------------------------------------------------------------------------
+    /// Connects to the listener bound to the address.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a simulation.
+    //--------------------------------------------------------------------------
+    pub async fn connect( address: &str ) -> io::Result<Self>
+    {
+        let found = with_world(|world|
+        {
+            let port = world.next_port;
+            world.next_port += 1;
+            (world.listeners.get(address).cloned(), port)
+        });
+        let (backlog, port) = match found
+        {
+            Some((Some(backlog), port)) => (backlog, port),
+            Some((None, _)) => return Err(io::Error::from(io::ErrorKind::ConnectionRefused)),
+            None => panic!("connect must be called from the context of a simulation"),
+        };
+
+        let outgoing = Arc::new(Mutex::new(Pipe::default()));
+        let incoming = Arc::new(Mutex::new(Pipe::default()));
+        let local = format!("client:{}", port);
+        let client = Self
+        {
+            local: local.clone(),
+            peer: address.to_string(),
+            read: incoming.clone(),
+            write: outgoing.clone(),
+        };
+        let server = Self
+        {
+            local: address.to_string(),
+            peer: local,
+            read: outgoing,
+            write: incoming,
+        };
+
+        let waker =
+        {
+            let mut backlog = lock(&backlog);
+            backlog.streams.push_back(server);
+            backlog.waker.take()
+        };
+        if let Some(waker) = waker
+        {
+            waker.wake();
+        }
+        Ok(client)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the local address of the connection.
+    //--------------------------------------------------------------------------
+    pub fn local_addr( &self ) -> &str
+    {
+        &self.local
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the address of the peer.
+    //--------------------------------------------------------------------------
+    pub fn peer_addr( &self ) -> &str
+    {
+        &self.peer
+    }
+
+    //--------------------------------------------------------------------------
+    /// Reads the delivered data into the buffer. Returns zero once the peer
+    /// has shut down and everything it wrote has been read.
+    //--------------------------------------------------------------------------
+    pub async fn read( &mut self, buf: &mut [u8] ) -> io::Result<usize>
+    {
+        poll_fn(|cx| self.poll_read(cx, buf)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for delivered data. See `read`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a simulation.
+    //--------------------------------------------------------------------------
+    pub fn poll_read
+    (
+        &mut self,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        if buf.is_empty()
+        {
+            return Poll::Ready(Ok(0));
+        }
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        let now = match with_world(|world| world.now())
+        {
+            Some(now) => now,
+            None => panic!("read must be called from the context of a simulation"),
+        };
+        let mut pipe = lock(&self.read);
+        let at = match pipe.segments.front().map(|(at, _)| *at)
+        {
+            Some(at) if at <= now =>
+            {
+                let data = &mut pipe.segments[0].1;
+                let len = data.len().min(buf.len());
+                buf[..len].copy_from_slice(&data[..len]);
+                data.drain(..len);
+                if data.is_empty()
+                {
+                    pipe.segments.pop_front();
+                }
+                return Poll::Ready(Ok(len));
+            },
+            Some(at) => at,
+            None if pipe.closed => return Poll::Ready(Ok(0)),
+            None =>
+            {
+                let previous = pipe.reader.replace(cx.waker().clone());
+                drop(pipe);
+                drop(previous);
+                return Poll::Pending;
+            },
+        };
+        drop(pipe);
+
+        // The next segment is still in flight.
+        with_world(|world| world.add_timer(at, cx.waker().clone()));
+        Poll::Pending
+    }
+
+    //--------------------------------------------------------------------------
+    /// Writes the buffer, which the peer receives after a random latency.
+    //--------------------------------------------------------------------------
+    pub async fn write( &mut self, buf: &[u8] ) -> io::Result<usize>
+    {
+        poll_fn(|cx| self.poll_write(cx, buf)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Writes the whole buffer.
+    //--------------------------------------------------------------------------
+    pub async fn write_all( &mut self, buf: &[u8] ) -> io::Result<()>
+    {
+        self.write(buf).await.map(|_| ())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for writing the buffer. See `write`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if called outside of a simulation.
+    //--------------------------------------------------------------------------
+    pub fn poll_write
+    (
+        &mut self,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        let mut pipe = lock(&self.write);
+        if pipe.closed || pipe.dropped
+        {
+            return Poll::Ready(Err(io::Error::from(io::ErrorKind::BrokenPipe)));
+        }
+        let at = match with_world(|world| world.now() + world.latency())
+        {
+            Some(at) => at.max(pipe.delivered),
+            None => panic!("write must be called from the context of a simulation"),
+        };
+        pipe.delivered = at;
+        pipe.segments.push_back((at, buf.to_vec()));
+        let reader = pipe.reader.take();
+        drop(pipe);
+
+        if let Some(reader) = reader
+        {
+            reader.wake();
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    //--------------------------------------------------------------------------
+    /// Shuts down the write half. The peer reads the data already written,
+    /// then reaches the end of the stream.
+    //--------------------------------------------------------------------------
+    pub fn shutdown( &self )
+    {
+        let reader =
+        {
+            let mut pipe = lock(&self.write);
+            pipe.closed = true;
+            pipe.reader.take()
+        };
+        if let Some(reader) = reader
+        {
+            reader.wake();
+        }
+    }
+}
+
+impl AsyncRead for SimStream
+{
+    fn poll_read
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        SimStream::poll_read(self.get_mut(), cx, buf)
+    }
+}
+
+impl AsyncWrite for SimStream
+{
+    fn poll_write
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        SimStream::poll_write(self.get_mut(), cx, buf)
+    }
+
+    fn poll_flush( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        self.shutdown();
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl Drop for SimStream
+{
+    fn drop( &mut self )
+    {
+        self.shutdown();
+        let previous =
+        {
+            let mut pipe = lock(&self.read);
+            pipe.dropped = true;
+            pipe.reader.take()
+        };
+        drop(previous);
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # lock
+///
+/// Locks the mutex, ignoring poisoning.
+//------------------------------------------------------------------------------
+fn lock<T>( mutex: &Mutex<T> ) -> MutexGuard<'_, T>
+{
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
@@ -0,0 +1,270 @@
+//------------------------------------------------------------------------------
+//! # Async sink
+//!
+//! A sink is the receiving counterpart of a stream: values are sent into it,
+//! and it may buffer them before writing them out.
+//------------------------------------------------------------------------------
+
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # Sink
+///
+/// A value is sent by waiting for `poll_ready`, then calling `start_send`.
+/// It may stay buffered until `poll_flush` completes.
+//------------------------------------------------------------------------------
+pub trait Sink<Item>
+{
+    type Error;
+
+    //--------------------------------------------------------------------------
+    /// Attempts to make room for a value.
+    //--------------------------------------------------------------------------
+    fn poll_ready
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Result<(), Self::Error>>;
+
+    //--------------------------------------------------------------------------
+    /// Starts sending a value. Must be preceded by a successful `poll_ready`.
+    //--------------------------------------------------------------------------
+    fn start_send( self: Pin<&mut Self>, item: Item ) -> Result<(), Self::Error>;
+
+    //--------------------------------------------------------------------------
+    /// Attempts to write out all the buffered values.
+    //--------------------------------------------------------------------------
+    fn poll_flush
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Result<(), Self::Error>>;
+
+    //--------------------------------------------------------------------------
+    /// Attempts to flush the sink and close it.
+    //--------------------------------------------------------------------------
+    fn poll_close
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Result<(), Self::Error>>;
+}
+
+impl<S: Sink<Item> + Unpin + ?Sized, Item> Sink<Item> for &mut S
+{
+    type Error = S::Error;
+
+    fn poll_ready
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Result<(), Self::Error>>
+    {
+        Pin::new(&mut **self).poll_ready(cx)
+    }
+
+    fn start_send( mut self: Pin<&mut Self>, item: Item ) -> Result<(), Self::Error>
+    {
+        Pin::new(&mut **self).start_send(item)
+    }
+
+    fn poll_flush
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Result<(), Self::Error>>
+    {
+        Pin::new(&mut **self).poll_flush(cx)
+    }
+
+    fn poll_close
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Result<(), Self::Error>>
+    {
+        Pin::new(&mut **self).poll_close(cx)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # SinkExt
+///
+/// Extension methods for sinks.
+//------------------------------------------------------------------------------
+pub trait SinkExt<Item>: Sink<Item>
+{
+    //--------------------------------------------------------------------------
+    /// Returns a future that sends the value and flushes the sink.
+    //--------------------------------------------------------------------------
+    fn send( &mut self, item: Item ) -> Send<'_, Self, Item>
+        where Self: Unpin
+    {
+        Send { sink: self, item: Some(item) }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a future that sends the value without flushing the sink, so
+    /// that several values can be written out together.
+    //--------------------------------------------------------------------------
+    fn feed( &mut self, item: Item ) -> Feed<'_, Self, Item>
+        where Self: Unpin
+    {
+        Feed { sink: self, item: Some(item) }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a future that flushes the sink.
+    //--------------------------------------------------------------------------
+    fn flush( &mut self ) -> Flush<'_, Self, Item>
+        where Self: Unpin
+    {
+        Flush { sink: self, item: PhantomData }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a future that closes the sink.
+    //--------------------------------------------------------------------------
+    fn close( &mut self ) -> Close<'_, Self, Item>
+        where Self: Unpin
+    {
+        Close { sink: self, item: PhantomData }
+    }
+}
+
+impl<S: Sink<Item> + ?Sized, Item> SinkExt<Item> for S {}
+
+
+//------------------------------------------------------------------------------
+/// # poll_feed
+///
+/// Waits for the sink to be ready, then starts sending the item if it has not
+/// been sent yet.
+//------------------------------------------------------------------------------
+fn poll_feed<S: Sink<Item> + Unpin + ?Sized, Item>
+(
+    sink: &mut S,
+    item: &mut Option<Item>,
+    cx: &mut Context,
+) -> Poll<Result<(), S::Error>>
+{
+    if item.is_some()
+    {
+        match Pin::new(&mut *sink).poll_ready(cx)
+        {
+            Poll::Ready(Ok(())) => {},
+            Poll::Ready(Err(error)) => return Poll::Ready(Err(error)),
+            Poll::Pending => return Poll::Pending,
+        }
+        if let Some(item) = item.take()
+        {
+            Pin::new(&mut *sink).start_send(item)?;
+        }
+    }
+    Poll::Ready(Ok(()))
+}
+
+
+//------------------------------------------------------------------------------
+/// # Send
+///
+/// Future returned by `SinkExt::send`.
+//------------------------------------------------------------------------------
+pub struct Send<'a, S: ?Sized, Item>
+{
+    sink: &'a mut S,
+    item: Option<Item>,
+}
+
+// The item is never pinned.
+impl<S: ?Sized, Item> Unpin for Send<'_, S, Item> {}
+
+impl<S: Sink<Item> + Unpin + ?Sized, Item> Future for Send<'_, S, Item>
+{
+    type Output = Result<(), S::Error>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = &mut *self;
+        match poll_feed(this.sink, &mut this.item, cx)
+        {
+            Poll::Ready(Ok(())) => Pin::new(&mut *this.sink).poll_flush(cx),
+            polled => polled,
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Feed
+///
+/// Future returned by `SinkExt::feed`.
+//------------------------------------------------------------------------------
+pub struct Feed<'a, S: ?Sized, Item>
+{
+    sink: &'a mut S,
+    item: Option<Item>,
+}
+
+// The item is never pinned.
+impl<S: ?Sized, Item> Unpin for Feed<'_, S, Item> {}
+
+impl<S: Sink<Item> + Unpin + ?Sized, Item> Future for Feed<'_, S, Item>
+{
+    type Output = Result<(), S::Error>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        let this = &mut *self;
+        poll_feed(this.sink, &mut this.item, cx)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Flush
+///
+/// Future returned by `SinkExt::flush`.
+//------------------------------------------------------------------------------
+pub struct Flush<'a, S: ?Sized, Item>
+{
+    sink: &'a mut S,
+    item: PhantomData<fn(Item)>,
+}
+
+impl<S: Sink<Item> + Unpin + ?Sized, Item> Future for Flush<'_, S, Item>
+{
+    type Output = Result<(), S::Error>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        Pin::new(&mut *self.sink).poll_flush(cx)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Close
+///
+/// Future returned by `SinkExt::close`.
+//------------------------------------------------------------------------------
+pub struct Close<'a, S: ?Sized, Item>
+{
+    sink: &'a mut S,
+    item: PhantomData<fn(Item)>,
+}
+
+impl<S: Sink<Item> + Unpin + ?Sized, Item> Future for Close<'_, S, Item>
+{
+    type Output = Result<(), S::Error>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        Pin::new(&mut *self.sink).poll_close(cx)
+    }
+}
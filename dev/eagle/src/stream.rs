@@ -0,0 +1,98 @@
+//------------------------------------------------------------------------------
+//! # Async stream
+//!
+//! A stream is the asynchronous version of an iterator.
+//------------------------------------------------------------------------------
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{ Context, Poll };
+
+
+//------------------------------------------------------------------------------
+/// # Stream
+//------------------------------------------------------------------------------
+pub trait Stream
+{
+    type Item;
+
+    //--------------------------------------------------------------------------
+    /// Attempts to pull out the next value of the stream.
+    ///
+    /// Returns `Poll::Ready(None)` when the stream is finished.
+    //--------------------------------------------------------------------------
+    fn poll_next
+    (
+        self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Self::Item>>;
+}
+
+impl<S: Stream + Unpin + ?Sized> Stream for &mut S
+{
+    type Item = S::Item;
+
+    fn poll_next
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Self::Item>>
+    {
+        Pin::new(&mut **self).poll_next(cx)
+    }
+}
+
+impl<S: Stream + Unpin + ?Sized> Stream for Box<S>
+{
+    type Item = S::Item;
+
+    fn poll_next
+    (
+        mut self: Pin<&mut Self>,
+        cx: &mut Context,
+    ) -> Poll<Option<Self::Item>>
+    {
+        Pin::new(&mut **self).poll_next(cx)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # StreamExt
+///
+/// Extension methods for streams.
+//------------------------------------------------------------------------------
+pub trait StreamExt: Stream
+{
+    //--------------------------------------------------------------------------
+    /// Returns a future that resolves to the next value of the stream.
+    //--------------------------------------------------------------------------
+    fn next( &mut self ) -> Next<'_, Self>
+        where Self: Unpin
+    {
+        Next { stream: self }
+    }
+}
+
+impl<S: Stream + ?Sized> StreamExt for S {}
+
+
+//------------------------------------------------------------------------------
+/// # Next
+///
+/// Future returned by `StreamExt::next`.
+//------------------------------------------------------------------------------
+pub struct Next<'a, S: ?Sized>
+{
+    stream: &'a mut S,
+}
+
+impl<S: Stream + Unpin + ?Sized> Future for Next<'_, S>
+{
+    type Output = Option<S::Item>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        Pin::new(&mut *self.stream).poll_next(cx)
+    }
+}
@@ -0,0 +1,169 @@
+//------------------------------------------------------------------------------
+//! # Async barrier
+//------------------------------------------------------------------------------
+
+use super::lock;
+
+use std::fmt;
+use std::future::poll_fn;
+use std::sync::Mutex;
+use std::task::{ Poll, Waker };
+
+
+//------------------------------------------------------------------------------
+/// # State
+//------------------------------------------------------------------------------
+struct State
+{
+    arrived: usize,
+    generation: u64,
+    wakers: Vec<Waker>,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Barrier
+///
+/// Lets a number of tasks wait until all of them have reached the barrier.
+/// The barrier can be reused once all the tasks have been released.
+//------------------------------------------------------------------------------
+pub struct Barrier
+{
+    parties: usize,
+    state: Mutex<State>,
+}
+
+impl Barrier
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new Barrier for the given number of tasks.
+    ///
+    /// A barrier for zero tasks behaves like a barrier for one task.
+    //--------------------------------------------------------------------------
+    pub fn new( parties: usize ) -> Self
+    {
+        Self
+        {
+            parties: parties.max(1),
+            state: Mutex::new(State
+            {
+                arrived: 0,
+                generation: 0,
+                wakers: Vec::new(),
+            }),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits until all the tasks have reached the barrier.
+    ///
+    /// The last task to arrive is the leader. Dropping the future before it
+    /// completes does not withdraw the task from the barrier.
+    //--------------------------------------------------------------------------
+    pub async fn wait( &self ) -> BarrierWaitResult
+    {
+        let generation =
+        {
+            let mut state = lock(&self.state);
+            state.arrived += 1;
+            if state.arrived == self.parties
+            {
+                state.arrived = 0;
+                state.generation = state.generation.wrapping_add(1);
+                let wakers = std::mem::take(&mut state.wakers);
+                drop(state);
+                for waker in wakers
+                {
+                    waker.wake();
+                }
+                return BarrierWaitResult(true);
+            }
+            state.generation
+        };
+
+        poll_fn(|cx|
+        {
+            let mut state = lock(&self.state);
+            if state.generation != generation
+            {
+                return Poll::Ready(BarrierWaitResult(false));
+            }
+            if state.wakers.iter().all(|w| !w.will_wake(cx.waker()))
+            {
+                state.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        }).await
+    }
+}
+
+impl fmt::Debug for Barrier
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        let state = lock(&self.state);
+        f.debug_struct("Barrier")
+            .field("parties", &self.parties)
+            .field("arrived", &state.arrived)
+            .finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # BarrierWaitResult
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BarrierWaitResult(bool);
+
+impl BarrierWaitResult
+{
+    //--------------------------------------------------------------------------
+    /// Returns whether this task was the last one to reach the barrier.
+    //--------------------------------------------------------------------------
+    pub fn is_leader( &self ) -> bool
+    {
+        self.0
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_util::WakeCounter;
+
+    use std::pin::pin;
+
+    #[test]
+    fn last_arrival_leads_and_releases_the_others()
+    {
+        let barrier = Barrier::new(3);
+        let counter = WakeCounter::new();
+        let mut first = pin!(barrier.wait());
+        let mut second = pin!(barrier.wait());
+        assert!(counter.poll(first.as_mut()).is_pending());
+        assert!(counter.poll(second.as_mut()).is_pending());
+
+        let leader = counter.poll(pin!(barrier.wait()));
+        assert_eq!(leader, Poll::Ready(BarrierWaitResult(true)));
+        assert!(counter.wakes() >= 1);
+        assert_eq!(counter.poll(first.as_mut()), Poll::Ready(BarrierWaitResult(false)));
+        assert_eq!(counter.poll(second.as_mut()), Poll::Ready(BarrierWaitResult(false)));
+    }
+
+    #[test]
+    fn barrier_is_reusable()
+    {
+        let barrier = Barrier::new(2);
+        let counter = WakeCounter::new();
+        for _ in 0..3
+        {
+            let mut waiting = pin!(barrier.wait());
+            assert!(counter.poll(waiting.as_mut()).is_pending());
+            assert!(counter.poll(pin!(barrier.wait())).is_ready());
+            assert!(counter.poll(waiting.as_mut()).is_ready());
+        }
+    }
+}
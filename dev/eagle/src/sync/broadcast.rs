@@ -0,0 +1,430 @@
+//------------------------------------------------------------------------------
+//! # Broadcast channel
+//!
+//! Every value sent is received by every receiver. The channel keeps the last
+//! `capacity` values; a receiver that falls further behind loses the oldest
+//! ones and is told how many it skipped with `RecvError::Lagged`.
+//------------------------------------------------------------------------------
+
+use super::lock;
+use crate::executor::poll_proceed;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::poll_fn;
+use std::sync::{ Arc, Mutex };
+use std::task::{ Context, Poll, Waker };
+
+
+//------------------------------------------------------------------------------
+/// # SendError
+///
+/// There is no receiver. The value is returned back.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "channel has no receiver")
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+
+//------------------------------------------------------------------------------
+/// # RecvError
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError
+{
+    Closed,
+    Lagged(u64),
+}
+
+impl fmt::Display for RecvError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Closed => write!(f, "channel closed"),
+            Self::Lagged(count) => write!(f, "receiver lagged by {}", count),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+
+//------------------------------------------------------------------------------
+/// # TryRecvError
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError
+{
+    Empty,
+    Closed,
+    Lagged(u64),
+}
+
+impl fmt::Display for TryRecvError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Empty => write!(f, "channel empty"),
+            Self::Closed => write!(f, "channel closed"),
+            Self::Lagged(count) => write!(f, "receiver lagged by {}", count),
+        }
+    }
+}
+
+impl std::error::Error for TryRecvError {}
+
+
+//------------------------------------------------------------------------------
+/// # channel
+///
+/// Creates a broadcast channel keeping the last `capacity` values.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+//------------------------------------------------------------------------------
+pub fn channel<T: Clone>( capacity: usize ) -> (Sender<T>, Receiver<T>)
+{
+    assert!(capacity > 0, "broadcast channel capacity must be greater than zero");
+    let shared = Arc::new(Mutex::new(State
+    {
+        buffer: VecDeque::with_capacity(capacity),
+        capacity,
+        tail: 0,
+        senders: 1,
+        receivers: 1,
+        wakers: Vec::new(),
+    }));
+    (Sender { shared: shared.clone() }, Receiver { shared, next: 0 })
+}
+
+
+//------------------------------------------------------------------------------
+/// # State
+//------------------------------------------------------------------------------
+struct State<T>
+{
+    buffer: VecDeque<T>,
+    capacity: usize,
+    tail: u64,
+    senders: usize,
+    receivers: usize,
+    wakers: Vec<Waker>,
+}
+
+impl<T> State<T>
+{
+    //--------------------------------------------------------------------------
+    /// Returns the position of the oldest value still in the buffer.
+    //--------------------------------------------------------------------------
+    fn head( &self ) -> u64
+    {
+        self.tail - self.buffer.len() as u64
+    }
+}
+
+impl<T: Clone> State<T>
+{
+    //--------------------------------------------------------------------------
+    /// Returns the value at the given position and advances it.
+    //--------------------------------------------------------------------------
+    fn recv( &self, next: &mut u64 ) -> Result<T, TryRecvError>
+    {
+        let head = self.head();
+        if *next < head
+        {
+            let lagged = head - *next;
+            *next = head;
+            return Err(TryRecvError::Lagged(lagged));
+        }
+        if *next < self.tail
+        {
+            let value = self.buffer[(*next - head) as usize].clone();
+            *next += 1;
+            return Ok(value);
+        }
+        if self.senders == 0
+        {
+            return Err(TryRecvError::Closed);
+        }
+        Err(TryRecvError::Empty)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Sender
+//------------------------------------------------------------------------------
+pub struct Sender<T>
+{
+    shared: Arc<Mutex<State<T>>>,
+}
+
+impl<T: Clone> Sender<T>
+{
+    //--------------------------------------------------------------------------
+    /// Sends a value to all the receivers and returns their number.
+    //--------------------------------------------------------------------------
+    pub fn send( &self, value: T ) -> Result<usize, SendError<T>>
+    {
+        let (receivers, wakers) =
+        {
+            let mut state = lock(&self.shared);
+            if state.receivers == 0
+            {
+                return Err(SendError(value));
+            }
+            if state.buffer.len() == state.capacity
+            {
+                state.buffer.pop_front();
+            }
+            state.buffer.push_back(value);
+            state.tail += 1;
+            (state.receivers, std::mem::take(&mut state.wakers))
+        };
+        for waker in wakers
+        {
+            waker.wake();
+        }
+        Ok(receivers)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new receiver receiving the values sent from now on.
+    //--------------------------------------------------------------------------
+    pub fn subscribe( &self ) -> Receiver<T>
+    {
+        let mut state = lock(&self.shared);
+        state.receivers += 1;
+        Receiver
+        {
+            shared: self.shared.clone(),
+            next: state.tail,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of receivers.
+    //--------------------------------------------------------------------------
+    pub fn receiver_count( &self ) -> usize
+    {
+        lock(&self.shared).receivers
+    }
+}
+
+impl<T> Clone for Sender<T>
+{
+    fn clone( &self ) -> Self
+    {
+        lock(&self.shared).senders += 1;
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T>
+{
+    fn drop( &mut self )
+    {
+        let wakers =
+        {
+            let mut state = lock(&self.shared);
+            state.senders -= 1;
+            match state.senders
+            {
+                0 => std::mem::take(&mut state.wakers),
+                _ => Vec::new(),
+            }
+        };
+        for waker in wakers
+        {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Receiver
+//------------------------------------------------------------------------------
+pub struct Receiver<T>
+{
+    shared: Arc<Mutex<State<T>>>,
+    next: u64,
+}
+
+impl<T: Clone> Receiver<T>
+{
+    //--------------------------------------------------------------------------
+    /// Receives the next value.
+    ///
+    /// If the receiver has fallen behind, `RecvError::Lagged` is returned once
+    /// with the number of skipped values, and the next call returns the
+    /// oldest value still kept.
+    //--------------------------------------------------------------------------
+    pub async fn recv( &mut self ) -> Result<T, RecvError>
+    {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for the next value.
+    //--------------------------------------------------------------------------
+    pub fn poll_recv( &mut self, cx: &mut Context ) -> Poll<Result<T, RecvError>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        let mut state = lock(&self.shared);
+        match state.recv(&mut self.next)
+        {
+            Ok(value) => Poll::Ready(Ok(value)),
+            Err(TryRecvError::Closed) => Poll::Ready(Err(RecvError::Closed)),
+            Err(TryRecvError::Lagged(n)) => Poll::Ready(Err(RecvError::Lagged(n))),
+            Err(TryRecvError::Empty) =>
+            {
+                if state.wakers.iter().all(|w| !w.will_wake(cx.waker()))
+                {
+                    state.wakers.push(cx.waker().clone());
+                }
+                Poll::Pending
+            },
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Tries to receive the next value without waiting.
+    //--------------------------------------------------------------------------
+    pub fn try_recv( &mut self ) -> Result<T, TryRecvError>
+    {
+        lock(&self.shared).recv(&mut self.next)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new receiver receiving the values sent from now on.
+    //--------------------------------------------------------------------------
+    pub fn resubscribe( &self ) -> Self
+    {
+        let mut state = lock(&self.shared);
+        state.receivers += 1;
+        Self
+        {
+            shared: self.shared.clone(),
+            next: state.tail,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T>
+{
+    fn drop( &mut self )
+    {
+        lock(&self.shared).receivers -= 1;
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Receiver")
+            .field("next", &self.next)
+            .finish_non_exhaustive()
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_util::WakeCounter;
+
+    use std::pin::pin;
+
+    #[test]
+    fn every_receiver_gets_every_value()
+    {
+        let (tx, mut first) = channel(4);
+        let mut second = tx.subscribe();
+        assert_eq!(tx.send(1), Ok(2));
+        assert_eq!(first.try_recv(), Ok(1));
+        assert_eq!(second.try_recv(), Ok(1));
+        assert_eq!(first.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn send_wakes_the_waiting_receivers()
+    {
+        let (tx, mut first) = channel(4);
+        let mut second = tx.subscribe();
+        let (a_counter, b_counter) = (WakeCounter::new(), WakeCounter::new());
+        let mut a = pin!(first.recv());
+        let mut b = pin!(second.recv());
+        assert!(a_counter.poll(a.as_mut()).is_pending());
+        assert!(b_counter.poll(b.as_mut()).is_pending());
+        tx.send(1).unwrap();
+        assert_eq!((a_counter.wakes(), b_counter.wakes()), (1, 1));
+        assert_eq!(a_counter.poll(a.as_mut()), Poll::Ready(Ok(1)));
+        assert_eq!(b_counter.poll(b.as_mut()), Poll::Ready(Ok(1)));
+    }
+
+    #[test]
+    fn slow_receiver_lags_then_resumes_at_the_oldest_value()
+    {
+        let (tx, mut rx) = channel(2);
+        for value in 0..5
+        {
+            tx.send(value).unwrap();
+        }
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Lagged(3)));
+        assert_eq!(rx.try_recv(), Ok(3));
+        assert_eq!(rx.try_recv(), Ok(4));
+    }
+
+    #[test]
+    fn dropping_the_senders_closes_after_the_kept_values()
+    {
+        let (tx, mut rx) = channel(2);
+        tx.send(1).unwrap();
+        let counter = WakeCounter::new();
+        assert_eq!(counter.poll(pin!(rx.recv())), Poll::Ready(Ok(1)));
+        let mut recv = pin!(rx.recv());
+        assert!(counter.poll(recv.as_mut()).is_pending());
+        drop(tx);
+        assert_eq!(counter.wakes(), 1);
+        assert_eq!(counter.poll(recv.as_mut()), Poll::Ready(Err(RecvError::Closed)));
+    }
+
+    #[test]
+    fn send_fails_without_receivers()
+    {
+        let (tx, rx) = channel(1);
+        drop(rx);
+        assert_eq!(tx.send(1), Err(SendError(1)));
+        let mut late = tx.subscribe();
+        tx.send(2).unwrap();
+        assert_eq!(late.try_recv(), Ok(2));
+    }
+}
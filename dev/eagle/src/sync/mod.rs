@@ -0,0 +1,40 @@
+//------------------------------------------------------------------------------
+//! # Async synchronization primitives
+//!
+//! These primitives suspend the waiting task and wake it through its waker
+//! instead of blocking the worker thread, so their guards can be held across
+//! `.await` points. Waiters are served in FIFO order by default.
+//------------------------------------------------------------------------------
+
+pub mod broadcast;
+pub mod mpsc;
+pub mod oneshot;
+pub mod watch;
+
+mod barrier;
+mod mutex;
+mod notify;
+mod once_cell;
+mod rwlock;
+mod semaphore;
+
+pub use barrier::{ Barrier, BarrierWaitResult };
+pub use mutex::{ Mutex, MutexGuard, TryLockError };
+pub use notify::{ Notified, Notify };
+pub use once_cell::{ OnceCell, SetError };
+pub use rwlock::{ RwLock, RwLockReadGuard, RwLockWriteGuard };
+pub use semaphore::{ AcquireError, Semaphore, SemaphorePermit, TryAcquireError };
+
+use std::sync::PoisonError;
+
+
+//------------------------------------------------------------------------------
+/// # lock
+///
+/// Locks the internal state of a primitive. The lock is never held while user
+/// code runs, so a poisoned lock still holds a consistent state.
+//------------------------------------------------------------------------------
+fn lock<T>( mutex: &std::sync::Mutex<T> ) -> std::sync::MutexGuard<'_, T>
+{
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
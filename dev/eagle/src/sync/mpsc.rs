@@ -0,0 +1,686 @@
+//------------------------------------------------------------------------------
+//! # Multi-producer, single-consumer channel
+//!
+//! The capacity of a bounded channel is a semaphore: a sender takes a permit
+//! before pushing a value and the receiver gives it back after popping it, so
+//! blocked senders are served in FIFO order.
+//------------------------------------------------------------------------------
+
+use super::lock;
+use super::semaphore::{ Semaphore, TryAcquireError };
+use crate::executor::poll_proceed;
+use crate::stream::Stream;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex };
+use std::task::{ Context, Poll, Waker };
+
+
+//------------------------------------------------------------------------------
+/// # SendError
+///
+/// The receiver has been closed. The value is returned back.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "channel closed")
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+
+//------------------------------------------------------------------------------
+/// # TrySendError
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrySendError<T>
+{
+    Full(T),
+    Closed(T),
+}
+
+impl<T> fmt::Display for TrySendError<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Full(_) => write!(f, "channel full"),
+            Self::Closed(_) => write!(f, "channel closed"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for TrySendError<T> {}
+
+
+//------------------------------------------------------------------------------
+/// # TryRecvError
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError
+{
+    Empty,
+    Disconnected,
+}
+
+impl fmt::Display for TryRecvError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Empty => write!(f, "channel empty"),
+            Self::Disconnected => write!(f, "channel disconnected"),
+        }
+    }
+}
+
+impl std::error::Error for TryRecvError {}
+
+
+//------------------------------------------------------------------------------
+/// # channel
+///
+/// Creates a bounded channel holding at most `capacity` values.
+///
+/// # Panics
+///
+/// Panics if `capacity` is zero.
+//------------------------------------------------------------------------------
+pub fn channel<T>( capacity: usize ) -> (Sender<T>, Receiver<T>)
+{
+    assert!(capacity > 0, "mpsc channel capacity must be greater than zero");
+    let chan = Arc::new(Chan::new(Some(Semaphore::new(capacity))));
+    (Sender { chan: chan.clone() }, Receiver { chan })
+}
+
+//------------------------------------------------------------------------------
+/// # unbounded_channel
+///
+/// Creates an unbounded channel.
+//------------------------------------------------------------------------------
+pub fn unbounded_channel<T>() -> (UnboundedSender<T>, UnboundedReceiver<T>)
+{
+    let chan = Arc::new(Chan::new(None));
+    (UnboundedSender { chan: chan.clone() }, UnboundedReceiver { chan })
+}
+
+
+//------------------------------------------------------------------------------
+/// # State
+//------------------------------------------------------------------------------
+struct State<T>
+{
+    queue: VecDeque<T>,
+    senders: usize,
+    closed: bool,
+    rx_waker: Option<Waker>,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Chan
+///
+/// The state shared by the senders and the receiver.
+//------------------------------------------------------------------------------
+struct Chan<T>
+{
+    state: Mutex<State<T>>,
+    semaphore: Option<Semaphore>,
+}
+
+impl<T> Chan<T>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new Chan.
+    //--------------------------------------------------------------------------
+    fn new( semaphore: Option<Semaphore> ) -> Self
+    {
+        Self
+        {
+            state: Mutex::new(State
+            {
+                queue: VecDeque::new(),
+                senders: 1,
+                closed: false,
+                rx_waker: None,
+            }),
+            semaphore,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pushes a value and wakes the receiver.
+    //--------------------------------------------------------------------------
+    fn push( &self, value: T ) -> Result<(), T>
+    {
+        let waker =
+        {
+            let mut state = lock(&self.state);
+            if state.closed
+            {
+                return Err(value);
+            }
+            state.queue.push_back(value);
+            state.rx_waker.take()
+        };
+        if let Some(waker) = waker
+        {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pops a value, registering the waker if the channel is empty.
+    //--------------------------------------------------------------------------
+    fn poll_recv( &self, cx: &mut Context ) -> Poll<Option<T>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        let mut state = lock(&self.state);
+        if let Some(value) = state.queue.pop_front()
+        {
+            drop(state);
+            self.release();
+            return Poll::Ready(Some(value));
+        }
+        if state.senders == 0 || state.closed
+        {
+            return Poll::Ready(None);
+        }
+        state.rx_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    //--------------------------------------------------------------------------
+    /// Pops a value without waiting.
+    //--------------------------------------------------------------------------
+    fn try_recv( &self ) -> Result<T, TryRecvError>
+    {
+        let mut state = lock(&self.state);
+        if let Some(value) = state.queue.pop_front()
+        {
+            drop(state);
+            self.release();
+            return Ok(value);
+        }
+        if state.senders == 0 || state.closed
+        {
+            return Err(TryRecvError::Disconnected);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Gives back the capacity of a popped value.
+    //--------------------------------------------------------------------------
+    fn release( &self )
+    {
+        if let Some(semaphore) = self.semaphore.as_ref()
+        {
+            semaphore.release(1);
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Closes the receiving half. The values already sent can still be
+    /// received.
+    //--------------------------------------------------------------------------
+    fn close( &self )
+    {
+        lock(&self.state).closed = true;
+        if let Some(semaphore) = self.semaphore.as_ref()
+        {
+            semaphore.close();
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the receiving half has been closed.
+    //--------------------------------------------------------------------------
+    fn is_closed( &self ) -> bool
+    {
+        lock(&self.state).closed
+    }
+
+    //--------------------------------------------------------------------------
+    /// Registers a new sender.
+    //--------------------------------------------------------------------------
+    fn add_sender( &self )
+    {
+        lock(&self.state).senders += 1;
+    }
+
+    //--------------------------------------------------------------------------
+    /// Unregisters a sender, waking the receiver if it was the last one.
+    //--------------------------------------------------------------------------
+    fn drop_sender( &self )
+    {
+        let waker =
+        {
+            let mut state = lock(&self.state);
+            state.senders -= 1;
+            match state.senders
+            {
+                0 => state.rx_waker.take(),
+                _ => None,
+            }
+        };
+        if let Some(waker) = waker
+        {
+            waker.wake();
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Closes the channel and drops the values still queued.
+    //--------------------------------------------------------------------------
+    fn drop_receiver( &self )
+    {
+        self.close();
+        let queue = std::mem::take(&mut lock(&self.state).queue);
+        drop(queue);
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Sender
+//------------------------------------------------------------------------------
+pub struct Sender<T>
+{
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Sender<T>
+{
+    //--------------------------------------------------------------------------
+    /// Sends a value, waiting until there is capacity.
+    //--------------------------------------------------------------------------
+    pub async fn send( &self, value: T ) -> Result<(), SendError<T>>
+    {
+        if let Some(semaphore) = self.chan.semaphore.as_ref()
+        {
+            if semaphore.acquire_permits(1).await.is_err()
+            {
+                return Err(SendError(value));
+            }
+        }
+        self.chan.push(value).map_err(SendError)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Tries to send a value without waiting.
+    //--------------------------------------------------------------------------
+    pub fn try_send( &self, value: T ) -> Result<(), TrySendError<T>>
+    {
+        if let Some(semaphore) = self.chan.semaphore.as_ref()
+        {
+            match semaphore.try_acquire_permits(1)
+            {
+                Ok(()) => {},
+                Err(TryAcquireError::Closed) =>
+                {
+                    return Err(TrySendError::Closed(value));
+                },
+                Err(TryAcquireError::NoPermits) =>
+                {
+                    return Err(TrySendError::Full(value));
+                },
+            }
+        }
+        self.chan.push(value).map_err(TrySendError::Closed)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the receiver has been closed.
+    //--------------------------------------------------------------------------
+    pub fn is_closed( &self ) -> bool
+    {
+        self.chan.is_closed()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the current capacity of the channel.
+    //--------------------------------------------------------------------------
+    pub fn capacity( &self ) -> usize
+    {
+        self.chan
+            .semaphore
+            .as_ref()
+            .map(Semaphore::available_permits)
+            .unwrap_or(0)
+    }
+}
+
+impl<T> Clone for Sender<T>
+{
+    fn clone( &self ) -> Self
+    {
+        self.chan.add_sender();
+        Self { chan: self.chan.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T>
+{
+    fn drop( &mut self )
+    {
+        self.chan.drop_sender();
+    }
+}
+
+impl<T> fmt::Debug for Sender<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Receiver
+//------------------------------------------------------------------------------
+pub struct Receiver<T>
+{
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> Receiver<T>
+{
+    //--------------------------------------------------------------------------
+    /// Receives the next value. Returns `None` once all the senders have been
+    /// dropped and the channel is empty.
+    //--------------------------------------------------------------------------
+    pub async fn recv( &mut self ) -> Option<T>
+    {
+        poll_fn(|cx| self.chan.poll_recv(cx)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for the next value.
+    //--------------------------------------------------------------------------
+    pub fn poll_recv( &mut self, cx: &mut Context ) -> Poll<Option<T>>
+    {
+        self.chan.poll_recv(cx)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Tries to receive the next value without waiting.
+    //--------------------------------------------------------------------------
+    pub fn try_recv( &mut self ) -> Result<T, TryRecvError>
+    {
+        self.chan.try_recv()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Closes the channel without dropping the receiver.
+    //--------------------------------------------------------------------------
+    pub fn close( &mut self )
+    {
+        self.chan.close();
+    }
+}
+
+impl<T> Stream for Receiver<T>
+{
+    type Item = T;
+
+    fn poll_next( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Option<T>>
+    {
+        self.chan.poll_recv(cx)
+    }
+}
+
+impl<T> Drop for Receiver<T>
+{
+    fn drop( &mut self )
+    {
+        self.chan.drop_receiver();
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # UnboundedSender
+//------------------------------------------------------------------------------
+pub struct UnboundedSender<T>
+{
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> UnboundedSender<T>
+{
+    //--------------------------------------------------------------------------
+    /// Sends a value without waiting.
+    //--------------------------------------------------------------------------
+    pub fn send( &self, value: T ) -> Result<(), SendError<T>>
+    {
+        self.chan.push(value).map_err(SendError)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the receiver has been closed.
+    //--------------------------------------------------------------------------
+    pub fn is_closed( &self ) -> bool
+    {
+        self.chan.is_closed()
+    }
+}
+
+impl<T> Clone for UnboundedSender<T>
+{
+    fn clone( &self ) -> Self
+    {
+        self.chan.add_sender();
+        Self { chan: self.chan.clone() }
+    }
+}
+
+impl<T> Drop for UnboundedSender<T>
+{
+    fn drop( &mut self )
+    {
+        self.chan.drop_sender();
+    }
+}
+
+impl<T> fmt::Debug for UnboundedSender<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("UnboundedSender").finish_non_exhaustive()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # UnboundedReceiver
+//------------------------------------------------------------------------------
+pub struct UnboundedReceiver<T>
+{
+    chan: Arc<Chan<T>>,
+}
+
+impl<T> UnboundedReceiver<T>
+{
+    //--------------------------------------------------------------------------
+    /// Receives the next value. Returns `None` once all the senders have been
+    /// dropped and the channel is empty.
+    //--------------------------------------------------------------------------
+    pub async fn recv( &mut self ) -> Option<T>
+    {
+        poll_fn(|cx| self.chan.poll_recv(cx)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for the next value.
+    //--------------------------------------------------------------------------
+    pub fn poll_recv( &mut self, cx: &mut Context ) -> Poll<Option<T>>
+    {
+        self.chan.poll_recv(cx)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Tries to receive the next value without waiting.
+    //--------------------------------------------------------------------------
+    pub fn try_recv( &mut self ) -> Result<T, TryRecvError>
+    {
+        self.chan.try_recv()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Closes the channel without dropping the receiver.
+    //--------------------------------------------------------------------------
+    pub fn close( &mut self )
+    {
+        self.chan.close();
+    }
+}
+
+impl<T> Stream for UnboundedReceiver<T>
+{
+    type Item = T;
+
+    fn poll_next( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Option<T>>
+    {
+        self.chan.poll_recv(cx)
+    }
+}
+
+impl<T> Drop for UnboundedReceiver<T>
+{
+    fn drop( &mut self )
+    {
+        self.chan.drop_receiver();
+    }
+}
+
+impl<T> fmt::Debug for UnboundedReceiver<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("UnboundedReceiver").finish_non_exhaustive()
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_util::WakeCounter;
+
+    use std::pin::pin;
+
+    #[test]
+    fn send_wakes_the_receiver()
+    {
+        let (tx, mut rx) = channel(4);
+        let counter = WakeCounter::new();
+        let mut recv = pin!(rx.recv());
+        assert!(counter.poll(recv.as_mut()).is_pending());
+        tx.try_send(1).unwrap();
+        assert_eq!(counter.wakes(), 1);
+        assert_eq!(counter.poll(recv.as_mut()), Poll::Ready(Some(1)));
+    }
+
+    #[test]
+    fn full_channel_blocks_until_a_value_is_received()
+    {
+        let (tx, mut rx) = channel(1);
+        tx.try_send(1).unwrap();
+        assert_eq!(tx.try_send(2), Err(TrySendError::Full(2)));
+
+        let counter = WakeCounter::new();
+        let mut send = pin!(tx.send(2));
+        assert!(counter.poll(send.as_mut()).is_pending());
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(counter.wakes(), 1);
+        assert_eq!(counter.poll(send.as_mut()), Poll::Ready(Ok(())));
+        assert_eq!(rx.try_recv(), Ok(2));
+    }
+
+    #[test]
+    fn cancelled_send_gives_the_capacity_back()
+    {
+        let (tx, mut rx) = channel(1);
+        tx.try_send(1).unwrap();
+        {
+            let counter = WakeCounter::new();
+            let mut send = pin!(tx.send(2));
+            assert!(counter.poll(send.as_mut()).is_pending());
+        }
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert!(tx.try_send(3).is_ok());
+    }
+
+    #[test]
+    fn dropping_the_senders_ends_the_stream_after_the_queued_values()
+    {
+        let (tx, mut rx) = channel(4);
+        let other = tx.clone();
+        tx.try_send(1).unwrap();
+        drop(tx);
+
+        let counter = WakeCounter::new();
+        assert_eq!(counter.poll(pin!(rx.recv())), Poll::Ready(Some(1)));
+        let mut recv = pin!(rx.recv());
+        assert!(counter.poll(recv.as_mut()).is_pending());
+        drop(other);
+        assert_eq!(counter.wakes(), 1);
+        assert_eq!(counter.poll(recv.as_mut()), Poll::Ready(None));
+    }
+
+    #[test]
+    fn close_fails_the_waiting_senders()
+    {
+        let (tx, mut rx) = channel(1);
+        tx.try_send(1).unwrap();
+        let counter = WakeCounter::new();
+        let mut send = pin!(tx.send(2));
+        assert!(counter.poll(send.as_mut()).is_pending());
+
+        rx.close();
+        assert_eq!(counter.wakes(), 1);
+        assert_eq!(counter.poll(send.as_mut()), Poll::Ready(Err(SendError(2))));
+        assert!(tx.is_closed());
+        assert_eq!(rx.try_recv(), Ok(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn unbounded_channel_never_blocks()
+    {
+        let (tx, mut rx) = unbounded_channel();
+        for value in 0..1000
+        {
+            tx.send(value).unwrap();
+        }
+        assert!((0..1000).all(|value| rx.try_recv() == Ok(value)));
+        drop(rx);
+        assert_eq!(tx.send(0), Err(SendError(0)));
+    }
+}
@@ -0,0 +1,262 @@
+//------------------------------------------------------------------------------
+//! # Async mutex
+//------------------------------------------------------------------------------
+
+use super::semaphore::Semaphore;
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::ops::{ Deref, DerefMut };
+
+
+//------------------------------------------------------------------------------
+/// # TryLockError
+///
+/// The lock is currently held.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TryLockError(pub(super) ());
+
+impl fmt::Display for TryLockError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "lock is already held")
+    }
+}
+
+impl std::error::Error for TryLockError {}
+
+
+//------------------------------------------------------------------------------
+/// # Mutex
+//------------------------------------------------------------------------------
+pub struct Mutex<T>
+{
+    semaphore: Semaphore,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new fair Mutex.
+    //--------------------------------------------------------------------------
+    pub fn new( value: T ) -> Self
+    {
+        Self
+        {
+            semaphore: Semaphore::new(1),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new Mutex that lets a new locker take the free lock ahead of
+    /// the queued waiters.
+    //--------------------------------------------------------------------------
+    pub fn unfair( value: T ) -> Self
+    {
+        Self
+        {
+            semaphore: Semaphore::unfair(1),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Locks the mutex, waiting until it is available.
+    //--------------------------------------------------------------------------
+    pub async fn lock( &self ) -> MutexGuard<'_, T>
+    {
+        if self.semaphore.acquire_permits(1).await.is_err()
+        {
+            unreachable!("the semaphore of a mutex is never closed");
+        }
+        MutexGuard { lock: self }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Tries to lock the mutex without waiting.
+    //--------------------------------------------------------------------------
+    pub fn try_lock( &self ) -> Result<MutexGuard<'_, T>, TryLockError>
+    {
+        match self.semaphore.try_acquire_permits(1)
+        {
+            Ok(()) => Ok(MutexGuard { lock: self }),
+            Err(_) => Err(TryLockError(())),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a mutable reference to the data without locking.
+    //--------------------------------------------------------------------------
+    pub fn get_mut( &mut self ) -> &mut T
+    {
+        self.data.get_mut()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Consumes the mutex and returns the data.
+    //--------------------------------------------------------------------------
+    pub fn into_inner( self ) -> T
+    {
+        self.data.into_inner()
+    }
+}
+
+impl<T: Default> Default for Mutex<T>
+{
+    fn default() -> Self
+    {
+        Self::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Mutex<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        let mut debug = f.debug_struct("Mutex");
+        match self.try_lock()
+        {
+            Ok(guard) => debug.field("data", &&*guard),
+            Err(_) => debug.field("data", &format_args!("<locked>")),
+        };
+        debug.finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # MutexGuard
+///
+/// The lock is released when this is dropped.
+//------------------------------------------------------------------------------
+pub struct MutexGuard<'a, T>
+{
+    lock: &'a Mutex<T>,
+}
+
+unsafe impl<T: Sync> Sync for MutexGuard<'_, T> {}
+
+impl<T> Deref for MutexGuard<'_, T>
+{
+    type Target = T;
+
+    fn deref( &self ) -> &T
+    {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T>
+{
+    fn deref_mut( &mut self ) -> &mut T
+    {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for MutexGuard<'_, T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T>
+{
+    fn drop( &mut self )
+    {
+        self.lock.semaphore.release(1);
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Simulation;
+    use crate::sim;
+    use crate::test_util::WakeCounter;
+
+    use std::pin::pin;
+    use std::sync::Arc;
+    use std::task::Poll;
+    use std::time::Duration;
+
+    #[test]
+    fn unlock_wakes_the_next_locker()
+    {
+        let mutex = Mutex::new(0);
+        let guard = mutex.try_lock().unwrap();
+        assert!(mutex.try_lock().is_err());
+
+        {
+            let counter = WakeCounter::new();
+            let mut waiting = pin!(mutex.lock());
+            assert!(counter.poll(waiting.as_mut()).is_pending());
+            drop(guard);
+            assert_eq!(counter.wakes(), 1);
+            match counter.poll(waiting.as_mut())
+            {
+                Poll::Ready(mut guard) => *guard += 1,
+                Poll::Pending => panic!("the lock was released"),
+            }
+        }
+        assert_eq!(mutex.into_inner(), 1);
+    }
+
+    #[test]
+    fn cancelled_locker_does_not_keep_the_lock()
+    {
+        let mutex = Mutex::new(());
+        let guard = mutex.try_lock().unwrap();
+        {
+            let counter = WakeCounter::new();
+            let mut waiting = pin!(mutex.lock());
+            assert!(counter.poll(waiting.as_mut()).is_pending());
+        }
+        drop(guard);
+        assert!(mutex.try_lock().is_ok());
+    }
+
+    #[test]
+    fn guard_is_exclusive_across_await_points()
+    {
+        for seed in 0..20
+        {
+            let mut simulation = Simulation::new(seed);
+            let total = simulation.block_on(async
+            {
+                let mutex = Arc::new(Mutex::new(0));
+                let tasks: Vec<_> = (0..8)
+                    .map(|_|
+                    {
+                        let mutex = mutex.clone();
+                        crate::spawn(async move
+                        {
+                            let mut guard = mutex.lock().await;
+                            let value = *guard;
+                            sim::sleep(Duration::from_millis(1)).await;
+                            *guard = value + 1;
+                        })
+                    })
+                    .collect();
+                for task in tasks
+                {
+                    task.await.unwrap();
+                }
+                let total = *mutex.lock().await;
+                total
+            });
+            assert_eq!(total.unwrap(), 8, "seed {}", seed);
+        }
+    }
+}
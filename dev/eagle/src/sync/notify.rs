@@ -0,0 +1,346 @@
+//------------------------------------------------------------------------------
+//! # Notify
+//!
+//! Notifies a single task or all the waiting tasks. A notification sent by
+//! `notify_one` while no task is waiting is stored as a permit and consumed
+//! by the next `notified().await`.
+//------------------------------------------------------------------------------
+
+use super::lock;
+use crate::executor::poll_proceed;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex };
+use std::task::{ Context, Poll, Waker };
+
+
+//------------------------------------------------------------------------------
+/// # Notification
+//------------------------------------------------------------------------------
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Notification
+{
+    None,
+    One,
+    All,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Waiter
+//------------------------------------------------------------------------------
+struct Waiter
+{
+    notification: Notification,
+    waker: Option<Waker>,
+}
+
+
+//------------------------------------------------------------------------------
+/// # State
+//------------------------------------------------------------------------------
+struct State
+{
+    permit: bool,
+    waiters: VecDeque<Arc<Mutex<Waiter>>>,
+    generation: u64,
+}
+
+impl State
+{
+    //--------------------------------------------------------------------------
+    /// Notifies the first waiter, or stores a permit if there is none.
+    //--------------------------------------------------------------------------
+    fn notify_one( &mut self ) -> Option<Waker>
+    {
+        match self.waiters.pop_front()
+        {
+            Some(waiter) =>
+            {
+                let mut waiter = lock(&waiter);
+                waiter.notification = Notification::One;
+                waiter.waker.take()
+            },
+            None =>
+            {
+                self.permit = true;
+                None
+            },
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Notify
+//------------------------------------------------------------------------------
+pub struct Notify
+{
+    state: Mutex<State>,
+}
+
+impl Notify
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new Notify.
+    //--------------------------------------------------------------------------
+    pub fn new() -> Self
+    {
+        Self
+        {
+            state: Mutex::new(State
+            {
+                permit: false,
+                waiters: VecDeque::new(),
+                generation: 0,
+            }),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a future that completes when the Notify is notified.
+    ///
+    /// The future receives the `notify_waiters` calls made after it is
+    /// created, even before it is polled for the first time.
+    //--------------------------------------------------------------------------
+    pub fn notified( &self ) -> Notified<'_>
+    {
+        Notified
+        {
+            notify: self,
+            generation: lock(&self.state).generation,
+            waiter: None,
+            done: false,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Notifies the task that has waited the longest. If no task is waiting,
+    /// the next call to `notified().await` completes immediately.
+    //--------------------------------------------------------------------------
+    pub fn notify_one( &self )
+    {
+        let waker = lock(&self.state).notify_one();
+        if let Some(waker) = waker
+        {
+            waker.wake();
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Notifies all the waiting tasks without storing a permit.
+    //--------------------------------------------------------------------------
+    pub fn notify_waiters( &self )
+    {
+        let waiters: Vec<_> =
+        {
+            let mut state = lock(&self.state);
+            state.generation = state.generation.wrapping_add(1);
+            state.waiters.drain(..).collect()
+        };
+        for waiter in waiters
+        {
+            let mut waiter = lock(&waiter);
+            waiter.notification = Notification::All;
+            if let Some(waker) = waiter.waker.take()
+            {
+                drop(waiter);
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl Default for Notify
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Notify
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        let state = lock(&self.state);
+        f.debug_struct("Notify")
+            .field("permit", &state.permit)
+            .field("waiters", &state.waiters.len())
+            .finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Notified
+///
+/// Future returned by `Notify::notified`. If it is dropped after receiving a
+/// `notify_one` notification, the notification is passed to the next waiter.
+//------------------------------------------------------------------------------
+pub struct Notified<'a>
+{
+    notify: &'a Notify,
+    generation: u64,
+    waiter: Option<Arc<Mutex<Waiter>>>,
+    done: bool,
+}
+
+impl Future for Notified<'_>
+{
+    type Output = ();
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<()>
+    {
+        if self.done
+        {
+            return Poll::Ready(());
+        }
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        let mut state = lock(&self.notify.state);
+        if let Some(waiter) = self.waiter.as_ref()
+        {
+            let mut waiter = lock(waiter);
+            if waiter.notification != Notification::None
+            {
+                drop(waiter);
+                self.done = true;
+                return Poll::Ready(());
+            }
+            waiter.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        if state.generation != self.generation || state.permit
+        {
+            if state.generation == self.generation
+            {
+                state.permit = false;
+            }
+            drop(state);
+            self.done = true;
+            return Poll::Ready(());
+        }
+
+        let waiter = Arc::new(Mutex::new(Waiter
+        {
+            notification: Notification::None,
+            waker: Some(cx.waker().clone()),
+        }));
+        state.waiters.push_back(waiter.clone());
+        drop(state);
+        self.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl Drop for Notified<'_>
+{
+    fn drop( &mut self )
+    {
+        let waiter = match self.waiter.take()
+        {
+            Some(waiter) => waiter,
+            None => return,
+        };
+
+        let waker =
+        {
+            let mut state = lock(&self.notify.state);
+            let notification = lock(&waiter).notification;
+            match notification
+            {
+                Notification::None =>
+                {
+                    state.waiters.retain(|w| !Arc::ptr_eq(w, &waiter));
+                    None
+                },
+                Notification::One if !self.done => state.notify_one(),
+                _ => None,
+            }
+        };
+        if let Some(waker) = waker
+        {
+            waker.wake();
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_util::WakeCounter;
+
+    use std::pin::pin;
+
+    #[test]
+    fn notify_one_stores_a_single_permit()
+    {
+        let notify = Notify::new();
+        notify.notify_one();
+        notify.notify_one();
+
+        let counter = WakeCounter::new();
+        assert!(counter.poll(pin!(notify.notified())).is_ready());
+        assert!(counter.poll(pin!(notify.notified())).is_pending());
+    }
+
+    #[test]
+    fn notify_one_wakes_the_oldest_waiter()
+    {
+        let notify = Notify::new();
+        let (first, second) = (WakeCounter::new(), WakeCounter::new());
+        let mut a = pin!(notify.notified());
+        let mut b = pin!(notify.notified());
+        assert!(first.poll(a.as_mut()).is_pending());
+        assert!(second.poll(b.as_mut()).is_pending());
+
+        notify.notify_one();
+        assert_eq!((first.wakes(), second.wakes()), (1, 0));
+        assert!(first.poll(a.as_mut()).is_ready());
+        assert!(second.poll(b.as_mut()).is_pending());
+    }
+
+    #[test]
+    fn notify_waiters_reaches_futures_not_polled_yet()
+    {
+        let notify = Notify::new();
+        let counter = WakeCounter::new();
+        let mut polled = pin!(notify.notified());
+        let mut created = pin!(notify.notified());
+        assert!(counter.poll(polled.as_mut()).is_pending());
+
+        notify.notify_waiters();
+        assert_eq!(counter.wakes(), 1);
+        assert!(counter.poll(polled.as_mut()).is_ready());
+        assert!(counter.poll(created.as_mut()).is_ready());
+
+        // No permit is stored for the later waiters.
+        assert!(counter.poll(pin!(notify.notified())).is_pending());
+    }
+
+    #[test]
+    fn cancelled_waiter_passes_the_notification_on()
+    {
+        let notify = Notify::new();
+        let (first, second) = (WakeCounter::new(), WakeCounter::new());
+        let mut b = pin!(notify.notified());
+        {
+            let mut a = pin!(notify.notified());
+            assert!(first.poll(a.as_mut()).is_pending());
+            assert!(second.poll(b.as_mut()).is_pending());
+            notify.notify_one();
+        }
+        assert_eq!(second.wakes(), 1);
+        assert!(second.poll(b.as_mut()).is_ready());
+    }
+}
@@ -0,0 +1,316 @@
+//------------------------------------------------------------------------------
+//! # Async once cell
+//!
+//! The initialization is serialized by a semaphore with a single permit. The
+//! semaphore is closed once the value is set, which releases every task
+//! waiting for the initialization.
+//------------------------------------------------------------------------------
+
+use super::semaphore::{ Semaphore, TryAcquireError };
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::future::Future;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{ AtomicBool, Ordering };
+
+
+//------------------------------------------------------------------------------
+/// # SetError
+///
+/// The value could not be set and is returned back.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SetError<T>
+{
+    AlreadyInitialized(T),
+    Initializing(T),
+}
+
+impl<T> fmt::Display for SetError<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::AlreadyInitialized(_) => write!(f, "cell is already initialized"),
+            Self::Initializing(_) => write!(f, "cell is being initialized"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SetError<T> {}
+
+
+//------------------------------------------------------------------------------
+/// # OnceCell
+//------------------------------------------------------------------------------
+pub struct OnceCell<T>
+{
+    value: UnsafeCell<MaybeUninit<T>>,
+    initialized: AtomicBool,
+    semaphore: Semaphore,
+}
+
+unsafe impl<T: Send> Send for OnceCell<T> {}
+unsafe impl<T: Send + Sync> Sync for OnceCell<T> {}
+
+impl<T> OnceCell<T>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new empty OnceCell.
+    //--------------------------------------------------------------------------
+    pub fn new() -> Self
+    {
+        Self
+        {
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            initialized: AtomicBool::new(false),
+            semaphore: Semaphore::new(1),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the value has been set.
+    //--------------------------------------------------------------------------
+    pub fn initialized( &self ) -> bool
+    {
+        self.initialized.load(Ordering::Acquire)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the value if it has been set.
+    //--------------------------------------------------------------------------
+    pub fn get( &self ) -> Option<&T>
+    {
+        match self.initialized()
+        {
+            true => Some(unsafe { self.get_unchecked() }),
+            false => None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a mutable reference to the value if it has been set.
+    //--------------------------------------------------------------------------
+    pub fn get_mut( &mut self ) -> Option<&mut T>
+    {
+        match *self.initialized.get_mut()
+        {
+            true => Some(unsafe { self.value.get_mut().assume_init_mut() }),
+            false => None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the value if the cell is empty and not being initialized.
+    //--------------------------------------------------------------------------
+    pub fn set( &self, value: T ) -> Result<(), SetError<T>>
+    {
+        match self.semaphore.try_acquire()
+        {
+            Ok(permit) =>
+            {
+                permit.forget();
+                unsafe { self.store(value) };
+                Ok(())
+            },
+            Err(TryAcquireError::Closed) => Err(SetError::AlreadyInitialized(value)),
+            Err(TryAcquireError::NoPermits) => Err(SetError::Initializing(value)),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the value, initializing it with the future returned by `init`
+    /// if the cell is empty.
+    ///
+    /// Only one task runs its initializer at a time. If it is cancelled, the
+    /// next waiting task runs its own.
+    //--------------------------------------------------------------------------
+    pub async fn get_or_init<F, Fut>( &self, init: F ) -> &T
+        where
+            F: FnOnce() -> Fut,
+            Fut: Future<Output = T>,
+    {
+        let result = self.get_or_try_init(|| async
+        {
+            Ok::<T, std::convert::Infallible>(init().await)
+        }).await;
+        match result
+        {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the value, initializing it with the future returned by `init`
+    /// if the cell is empty. If the initializer fails, the cell stays empty.
+    //--------------------------------------------------------------------------
+    pub async fn get_or_try_init<F, Fut, E>( &self, init: F ) -> Result<&T, E>
+        where
+            F: FnOnce() -> Fut,
+            Fut: Future<Output = Result<T, E>>,
+    {
+        if self.initialized()
+        {
+            return Ok(unsafe { self.get_unchecked() });
+        }
+
+        match self.semaphore.acquire().await
+        {
+            Ok(permit) =>
+            {
+                let value = init().await?;
+                permit.forget();
+                unsafe { self.store(value) };
+                Ok(unsafe { self.get_unchecked() })
+            },
+            Err(_) => Ok(unsafe { self.get_unchecked() }),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Takes the value out of the cell, leaving it empty.
+    //--------------------------------------------------------------------------
+    pub fn take( &mut self ) -> Option<T>
+    {
+        std::mem::take(self).into_inner()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Consumes the cell and returns the value if it has been set.
+    //--------------------------------------------------------------------------
+    pub fn into_inner( mut self ) -> Option<T>
+    {
+        match std::mem::replace(self.initialized.get_mut(), false)
+        {
+            true => Some(unsafe { self.value.get_mut().assume_init_read() }),
+            false => None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Stores the value and releases the waiting tasks.
+    ///
+    /// The caller must hold the initialization permit.
+    //--------------------------------------------------------------------------
+    unsafe fn store( &self, value: T )
+    {
+        (*self.value.get()).write(value);
+        self.initialized.store(true, Ordering::Release);
+        self.semaphore.close();
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the value without checking that it has been set.
+    //--------------------------------------------------------------------------
+    unsafe fn get_unchecked( &self ) -> &T
+    {
+        (*self.value.get()).assume_init_ref()
+    }
+}
+
+impl<T> Default for OnceCell<T>
+{
+    fn default() -> Self
+    {
+        Self::new()
+    }
+}
+
+impl<T> From<T> for OnceCell<T>
+{
+    fn from( value: T ) -> Self
+    {
+        let cell = Self::new();
+        let _ = cell.set(value);
+        cell
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for OnceCell<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("OnceCell")
+            .field("value", &self.get())
+            .finish()
+    }
+}
+
+impl<T> Drop for OnceCell<T>
+{
+    fn drop( &mut self )
+    {
+        if *self.initialized.get_mut()
+        {
+            unsafe { self.value.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_util::WakeCounter;
+
+    use std::future::pending;
+    use std::pin::pin;
+    use std::task::Poll;
+
+    #[test]
+    fn set_only_succeeds_once()
+    {
+        let cell = OnceCell::new();
+        assert!(cell.set(1).is_ok());
+        assert!(matches!(cell.set(2), Err(SetError::AlreadyInitialized(2))));
+        assert_eq!(cell.get(), Some(&1));
+    }
+
+    #[test]
+    fn waiters_get_the_value_of_the_initializer()
+    {
+        let cell = OnceCell::new();
+        let counter = WakeCounter::new();
+        let (tx, rx) = crate::sync::oneshot::channel();
+        let mut first = pin!(cell.get_or_init(|| async { rx.await.unwrap() }));
+        let mut second = pin!(cell.get_or_init(|| async { unreachable!() }));
+        assert!(counter.poll(first.as_mut()).is_pending());
+        assert!(counter.poll(second.as_mut()).is_pending());
+        assert!(matches!(cell.set(0), Err(SetError::Initializing(0))));
+
+        tx.send(7).unwrap();
+        assert_eq!(counter.poll(first.as_mut()), Poll::Ready(&7));
+        assert_eq!(counter.poll(second.as_mut()), Poll::Ready(&7));
+    }
+
+    #[test]
+    fn cancelled_initializer_lets_the_next_waiter_run()
+    {
+        let cell = OnceCell::new();
+        let counter = WakeCounter::new();
+        let mut second = pin!(cell.get_or_init(|| async { 2 }));
+        {
+            let mut first = pin!(cell.get_or_init(pending::<i32>));
+            assert!(counter.poll(first.as_mut()).is_pending());
+            assert!(counter.poll(second.as_mut()).is_pending());
+        }
+        assert!(counter.wakes() >= 1);
+        assert_eq!(counter.poll(second.as_mut()), Poll::Ready(&2));
+    }
+
+    #[test]
+    fn failed_initializer_leaves_the_cell_empty()
+    {
+        let cell = OnceCell::<i32>::new();
+        let counter = WakeCounter::new();
+        let failed = counter.poll(pin!(cell.get_or_try_init(|| async { Err("failed") })));
+        assert_eq!(failed, Poll::Ready(Err("failed")));
+        assert!(!cell.initialized());
+        let ok = counter.poll(pin!(cell.get_or_try_init(|| async { Ok::<_, ()>(3) })));
+        assert_eq!(ok, Poll::Ready(Ok(&3)));
+    }
+}
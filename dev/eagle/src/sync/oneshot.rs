@@ -0,0 +1,340 @@
+//------------------------------------------------------------------------------
+//! # One-shot channel
+//!
+//! Sends a single value from one task to another.
+//------------------------------------------------------------------------------
+
+use super::lock;
+use crate::executor::poll_proceed;
+
+use std::fmt;
+use std::future::{ poll_fn, Future };
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex };
+use std::task::{ Context, Poll, Waker };
+
+
+//------------------------------------------------------------------------------
+/// # RecvError
+///
+/// The sender has been dropped without sending a value.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError(());
+
+impl fmt::Display for RecvError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "channel closed")
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+
+//------------------------------------------------------------------------------
+/// # TryRecvError
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TryRecvError
+{
+    Empty,
+    Closed,
+}
+
+impl fmt::Display for TryRecvError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Empty => write!(f, "channel empty"),
+            Self::Closed => write!(f, "channel closed"),
+        }
+    }
+}
+
+impl std::error::Error for TryRecvError {}
+
+
+//------------------------------------------------------------------------------
+/// # channel
+///
+/// Creates a one-shot channel.
+//------------------------------------------------------------------------------
+pub fn channel<T>() -> (Sender<T>, Receiver<T>)
+{
+    let shared = Arc::new(Mutex::new(State
+    {
+        value: None,
+        tx_dropped: false,
+        rx_closed: false,
+        rx_waker: None,
+        tx_waker: None,
+    }));
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+
+//------------------------------------------------------------------------------
+/// # State
+//------------------------------------------------------------------------------
+struct State<T>
+{
+    value: Option<T>,
+    tx_dropped: bool,
+    rx_closed: bool,
+    rx_waker: Option<Waker>,
+    tx_waker: Option<Waker>,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Sender
+//------------------------------------------------------------------------------
+pub struct Sender<T>
+{
+    shared: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Sender<T>
+{
+    //--------------------------------------------------------------------------
+    /// Sends the value. The value is returned back if the receiver has been
+    /// closed.
+    //--------------------------------------------------------------------------
+    pub fn send( self, value: T ) -> Result<(), T>
+    {
+        let waker =
+        {
+            let mut state = lock(&self.shared);
+            if state.rx_closed
+            {
+                return Err(value);
+            }
+            state.value = Some(value);
+            state.rx_waker.take()
+        };
+        if let Some(waker) = waker
+        {
+            waker.wake();
+        }
+        Ok(())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the receiver has been closed.
+    //--------------------------------------------------------------------------
+    pub fn is_closed( &self ) -> bool
+    {
+        lock(&self.shared).rx_closed
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits until the receiver has been closed.
+    //--------------------------------------------------------------------------
+    pub async fn closed( &mut self )
+    {
+        poll_fn(|cx| self.poll_closed(cx)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls whether the receiver has been closed.
+    //--------------------------------------------------------------------------
+    pub fn poll_closed( &mut self, cx: &mut Context ) -> Poll<()>
+    {
+        let mut state = lock(&self.shared);
+        if state.rx_closed
+        {
+            return Poll::Ready(());
+        }
+        state.tx_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Sender<T>
+{
+    fn drop( &mut self )
+    {
+        let waker =
+        {
+            let mut state = lock(&self.shared);
+            state.tx_dropped = true;
+            state.rx_waker.take()
+        };
+        if let Some(waker) = waker
+        {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Sender<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Receiver
+///
+/// The receiver is a future resolving to the sent value.
+//------------------------------------------------------------------------------
+pub struct Receiver<T>
+{
+    shared: Arc<Mutex<State<T>>>,
+}
+
+impl<T> Receiver<T>
+{
+    //--------------------------------------------------------------------------
+    /// Tries to receive the value without waiting.
+    //--------------------------------------------------------------------------
+    pub fn try_recv( &mut self ) -> Result<T, TryRecvError>
+    {
+        let mut state = lock(&self.shared);
+        if let Some(value) = state.value.take()
+        {
+            return Ok(value);
+        }
+        if state.tx_dropped || state.rx_closed
+        {
+            return Err(TryRecvError::Closed);
+        }
+        Err(TryRecvError::Empty)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether a value has been sent or the sender has been dropped.
+    //--------------------------------------------------------------------------
+    pub(crate) fn is_complete( &self ) -> bool
+    {
+        let state = lock(&self.shared);
+        state.value.is_some() || state.tx_dropped
+    }
+
+    //--------------------------------------------------------------------------
+    /// Closes the channel so that the sender fails. A value sent before can
+    /// still be received.
+    //--------------------------------------------------------------------------
+    pub fn close( &mut self )
+    {
+        let waker =
+        {
+            let mut state = lock(&self.shared);
+            state.rx_closed = true;
+            state.tx_waker.take()
+        };
+        if let Some(waker) = waker
+        {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for Receiver<T>
+{
+    type Output = Result<T, RecvError>;
+
+    fn poll( self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        let mut state = lock(&self.shared);
+        if let Some(value) = state.value.take()
+        {
+            return Poll::Ready(Ok(value));
+        }
+        if state.tx_dropped || state.rx_closed
+        {
+            return Poll::Ready(Err(RecvError(())));
+        }
+        state.rx_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for Receiver<T>
+{
+    fn drop( &mut self )
+    {
+        self.close();
+    }
+}
+
+impl<T> fmt::Debug for Receiver<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_util::WakeCounter;
+
+    use std::pin::pin;
+
+    #[test]
+    fn send_wakes_the_receiver()
+    {
+        let (tx, rx) = channel();
+        let counter = WakeCounter::new();
+        let mut rx = pin!(rx);
+        assert!(counter.poll(rx.as_mut()).is_pending());
+        tx.send(1).unwrap();
+        assert_eq!(counter.wakes(), 1);
+        assert_eq!(counter.poll(rx.as_mut()), Poll::Ready(Ok(1)));
+    }
+
+    #[test]
+    fn dropped_sender_fails_the_receiver()
+    {
+        let (tx, rx) = channel::<i32>();
+        let counter = WakeCounter::new();
+        let mut rx = pin!(rx);
+        assert!(counter.poll(rx.as_mut()).is_pending());
+        drop(tx);
+        assert_eq!(counter.wakes(), 1);
+        assert_eq!(counter.poll(rx.as_mut()), Poll::Ready(Err(RecvError(()))));
+    }
+
+    #[test]
+    fn closed_receiver_wakes_and_fails_the_sender()
+    {
+        let (mut tx, mut rx) = channel();
+        let counter = WakeCounter::new();
+        {
+            let mut closed = pin!(tx.closed());
+            assert!(counter.poll(closed.as_mut()).is_pending());
+            rx.close();
+            assert_eq!(counter.wakes(), 1);
+            assert!(counter.poll(closed.as_mut()).is_ready());
+        }
+        assert!(tx.is_closed());
+        assert_eq!(tx.send(1), Err(1));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Closed));
+    }
+
+    #[test]
+    fn value_sent_before_close_is_still_received()
+    {
+        let (tx, mut rx) = channel();
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        tx.send(1).unwrap();
+        rx.close();
+        assert_eq!(rx.try_recv(), Ok(1));
+    }
+}
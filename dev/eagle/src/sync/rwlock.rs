@@ -0,0 +1,285 @@
+//------------------------------------------------------------------------------
+//! # Async reader-writer lock
+//!
+//! A reader takes one permit of the semaphore and a writer takes all of them.
+//! As the waiters are queued in FIFO order, a waiting writer is not starved by
+//! the readers arriving after it.
+//------------------------------------------------------------------------------
+
+use super::mutex::TryLockError;
+use super::semaphore::Semaphore;
+
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::ops::{ Deref, DerefMut };
+
+const MAX_READERS: usize = Semaphore::MAX_PERMITS;
+
+
+//------------------------------------------------------------------------------
+/// # RwLock
+//------------------------------------------------------------------------------
+pub struct RwLock<T>
+{
+    semaphore: Semaphore,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for RwLock<T> {}
+unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+impl<T> RwLock<T>
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new fair RwLock.
+    //--------------------------------------------------------------------------
+    pub fn new( value: T ) -> Self
+    {
+        Self
+        {
+            semaphore: Semaphore::new(MAX_READERS),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new RwLock that lets new readers and writers take the free
+    /// lock ahead of the queued waiters.
+    //--------------------------------------------------------------------------
+    pub fn unfair( value: T ) -> Self
+    {
+        Self
+        {
+            semaphore: Semaphore::unfair(MAX_READERS),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Locks with shared read access, waiting until it is available.
+    //--------------------------------------------------------------------------
+    pub async fn read( &self ) -> RwLockReadGuard<'_, T>
+    {
+        if self.semaphore.acquire_permits(1).await.is_err()
+        {
+            unreachable!("the semaphore of a rwlock is never closed");
+        }
+        RwLockReadGuard { lock: self }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Locks with exclusive write access, waiting until it is available.
+    //--------------------------------------------------------------------------
+    pub async fn write( &self ) -> RwLockWriteGuard<'_, T>
+    {
+        if self.semaphore.acquire_permits(MAX_READERS).await.is_err()
+        {
+            unreachable!("the semaphore of a rwlock is never closed");
+        }
+        RwLockWriteGuard { lock: self }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Tries to lock with shared read access without waiting.
+    //--------------------------------------------------------------------------
+    pub fn try_read( &self ) -> Result<RwLockReadGuard<'_, T>, TryLockError>
+    {
+        match self.semaphore.try_acquire_permits(1)
+        {
+            Ok(()) => Ok(RwLockReadGuard { lock: self }),
+            Err(_) => Err(TryLockError(())),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Tries to lock with exclusive write access without waiting.
+    //--------------------------------------------------------------------------
+    pub fn try_write( &self ) -> Result<RwLockWriteGuard<'_, T>, TryLockError>
+    {
+        match self.semaphore.try_acquire_permits(MAX_READERS)
+        {
+            Ok(()) => Ok(RwLockWriteGuard { lock: self }),
+            Err(_) => Err(TryLockError(())),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a mutable reference to the data without locking.
+    //--------------------------------------------------------------------------
+    pub fn get_mut( &mut self ) -> &mut T
+    {
+        self.data.get_mut()
+    }
+
+    //--------------------------------------------------------------------------
+    /// Consumes the lock and returns the data.
+    //--------------------------------------------------------------------------
+    pub fn into_inner( self ) -> T
+    {
+        self.data.into_inner()
+    }
+}
+
+impl<T: Default> Default for RwLock<T>
+{
+    fn default() -> Self
+    {
+        Self::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLock<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        let mut debug = f.debug_struct("RwLock");
+        match self.try_read()
+        {
+            Ok(guard) => debug.field("data", &&*guard),
+            Err(_) => debug.field("data", &format_args!("<locked>")),
+        };
+        debug.finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # RwLockReadGuard
+///
+/// The shared access is released when this is dropped.
+//------------------------------------------------------------------------------
+pub struct RwLockReadGuard<'a, T>
+{
+    lock: &'a RwLock<T>,
+}
+
+impl<T> Deref for RwLockReadGuard<'_, T>
+{
+    type Target = T;
+
+    fn deref( &self ) -> &T
+    {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLockReadGuard<'_, T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for RwLockReadGuard<'_, T>
+{
+    fn drop( &mut self )
+    {
+        self.lock.semaphore.release(1);
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # RwLockWriteGuard
+///
+/// The exclusive access is released when this is dropped.
+//------------------------------------------------------------------------------
+pub struct RwLockWriteGuard<'a, T>
+{
+    lock: &'a RwLock<T>,
+}
+
+unsafe impl<T: Sync> Sync for RwLockWriteGuard<'_, T> {}
+
+impl<T> Deref for RwLockWriteGuard<'_, T>
+{
+    type Target = T;
+
+    fn deref( &self ) -> &T
+    {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for RwLockWriteGuard<'_, T>
+{
+    fn deref_mut( &mut self ) -> &mut T
+    {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RwLockWriteGuard<'_, T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl<T> Drop for RwLockWriteGuard<'_, T>
+{
+    fn drop( &mut self )
+    {
+        self.lock.semaphore.release(MAX_READERS);
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_util::WakeCounter;
+
+    use std::pin::pin;
+
+    #[test]
+    fn readers_share_and_writer_excludes()
+    {
+        let lock = RwLock::new(1);
+        let first = lock.try_read().unwrap();
+        let second = lock.try_read().unwrap();
+        assert_eq!(*first + *second, 2);
+        assert!(lock.try_write().is_err());
+        drop((first, second));
+
+        let mut writer = lock.try_write().unwrap();
+        *writer = 2;
+        assert!(lock.try_read().is_err());
+        drop(writer);
+        assert_eq!(*lock.try_read().unwrap(), 2);
+    }
+
+    #[test]
+    fn queued_writer_blocks_new_readers_and_is_woken()
+    {
+        let lock = RwLock::new(());
+        let reader = lock.try_read().unwrap();
+        let counter = WakeCounter::new();
+        let mut writer = pin!(lock.write());
+        assert!(counter.poll(writer.as_mut()).is_pending());
+
+        // A fair lock does not starve the writer with new readers.
+        assert!(lock.try_read().is_err());
+        drop(reader);
+        assert_eq!(counter.wakes(), 1);
+        assert!(counter.poll(writer.as_mut()).is_ready());
+    }
+
+    #[test]
+    fn cancelled_writer_lets_readers_in()
+    {
+        let lock = RwLock::new(());
+        let reader = lock.try_read().unwrap();
+        {
+            let counter = WakeCounter::new();
+            let mut writer = pin!(lock.write());
+            assert!(counter.poll(writer.as_mut()).is_pending());
+        }
+        assert!(lock.try_read().is_ok());
+        drop(reader);
+        assert!(lock.try_write().is_ok());
+    }
+}
@@ -0,0 +1,559 @@
+//------------------------------------------------------------------------------
+//! # Semaphore
+//!
+//! Waiters are queued in FIFO order and permits are handed directly to the
+//! waiter at the head of the queue when they are released. A fair semaphore
+//! never lets a new acquirer take permits while others are queued, which is
+//! what makes `Mutex` and `RwLock` fair as well.
+//------------------------------------------------------------------------------
+
+use super::lock;
+use crate::executor::poll_proceed;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{ Arc, Mutex };
+use std::task::{ Context, Poll, Waker };
+
+
+//------------------------------------------------------------------------------
+/// # AcquireError
+///
+/// The semaphore has been closed.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcquireError(());
+
+impl fmt::Display for AcquireError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "semaphore closed")
+    }
+}
+
+impl std::error::Error for AcquireError {}
+
+
+//------------------------------------------------------------------------------
+/// # TryAcquireError
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TryAcquireError
+{
+    Closed,
+    NoPermits,
+}
+
+impl fmt::Display for TryAcquireError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        match self
+        {
+            Self::Closed => write!(f, "semaphore closed"),
+            Self::NoPermits => write!(f, "no permits available"),
+        }
+    }
+}
+
+impl std::error::Error for TryAcquireError {}
+
+
+//------------------------------------------------------------------------------
+/// # Waiter
+//------------------------------------------------------------------------------
+struct Waiter
+{
+    needed: usize,
+    granted: bool,
+    waker: Option<Waker>,
+}
+
+
+//------------------------------------------------------------------------------
+/// # State
+//------------------------------------------------------------------------------
+struct State
+{
+    permits: usize,
+    waiters: VecDeque<Arc<Mutex<Waiter>>>,
+    closed: bool,
+}
+
+impl State
+{
+    //--------------------------------------------------------------------------
+    /// Hands the available permits to the waiters at the head of the queue
+    /// and returns their wakers.
+    //--------------------------------------------------------------------------
+    fn assign( &mut self ) -> Vec<Waker>
+    {
+        let mut wakers = Vec::new();
+        while let Some(front) = self.waiters.front()
+        {
+            let mut waiter = lock(front);
+            if waiter.needed > self.permits
+            {
+                break;
+            }
+            self.permits -= waiter.needed;
+            waiter.granted = true;
+            if let Some(waker) = waiter.waker.take()
+            {
+                wakers.push(waker);
+            }
+            drop(waiter);
+            self.waiters.pop_front();
+        }
+        wakers
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Semaphore
+//------------------------------------------------------------------------------
+pub struct Semaphore
+{
+    state: Mutex<State>,
+    fair: bool,
+}
+
+impl Semaphore
+{
+    /// The maximum number of permits a semaphore can hold.
+    pub const MAX_PERMITS: usize = usize::MAX >> 3;
+
+    //--------------------------------------------------------------------------
+    /// Creates a new fair Semaphore.
+    //--------------------------------------------------------------------------
+    pub fn new( permits: usize ) -> Self
+    {
+        Self::with_fairness(permits, true)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new Semaphore that lets acquirers take free permits ahead of
+    /// the queued waiters.
+    //--------------------------------------------------------------------------
+    pub fn unfair( permits: usize ) -> Self
+    {
+        Self::with_fairness(permits, false)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new Semaphore with the given fairness.
+    //--------------------------------------------------------------------------
+    fn with_fairness( permits: usize, fair: bool ) -> Self
+    {
+        Self
+        {
+            state: Mutex::new(State
+            {
+                permits: permits.min(Self::MAX_PERMITS),
+                waiters: VecDeque::new(),
+                closed: false,
+            }),
+            fair,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of available permits.
+    //--------------------------------------------------------------------------
+    pub fn available_permits( &self ) -> usize
+    {
+        lock(&self.state).permits
+    }
+
+    //--------------------------------------------------------------------------
+    /// Adds permits to the semaphore.
+    //--------------------------------------------------------------------------
+    pub fn add_permits( &self, permits: usize )
+    {
+        self.release(permits);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Closes the semaphore. All the waiters and the later acquirers fail.
+    //--------------------------------------------------------------------------
+    pub fn close( &self )
+    {
+        let waiters: Vec<_> =
+        {
+            let mut state = lock(&self.state);
+            state.closed = true;
+            state.waiters.drain(..).collect()
+        };
+        for waiter in waiters
+        {
+            if let Some(waker) = lock(&waiter).waker.take()
+            {
+                waker.wake();
+            }
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the semaphore has been closed.
+    //--------------------------------------------------------------------------
+    pub fn is_closed( &self ) -> bool
+    {
+        lock(&self.state).closed
+    }
+
+    //--------------------------------------------------------------------------
+    /// Acquires a permit.
+    //--------------------------------------------------------------------------
+    pub async fn acquire( &self ) -> Result<SemaphorePermit<'_>, AcquireError>
+    {
+        self.acquire_many(1).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Acquires the given number of permits.
+    //--------------------------------------------------------------------------
+    pub async fn acquire_many
+    (
+        &self,
+        permits: usize,
+    ) -> Result<SemaphorePermit<'_>, AcquireError>
+    {
+        self.acquire_permits(permits).await?;
+        Ok(SemaphorePermit { semaphore: self, permits })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Tries to acquire a permit without waiting.
+    //--------------------------------------------------------------------------
+    pub fn try_acquire( &self ) -> Result<SemaphorePermit<'_>, TryAcquireError>
+    {
+        self.try_acquire_many(1)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Tries to acquire the given number of permits without waiting.
+    //--------------------------------------------------------------------------
+    pub fn try_acquire_many
+    (
+        &self,
+        permits: usize,
+    ) -> Result<SemaphorePermit<'_>, TryAcquireError>
+    {
+        self.try_acquire_permits(permits)?;
+        Ok(SemaphorePermit { semaphore: self, permits })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a future acquiring the permits without a guard.
+    //--------------------------------------------------------------------------
+    pub(crate) fn acquire_permits( &self, permits: usize ) -> Acquire<'_>
+    {
+        Acquire
+        {
+            semaphore: self,
+            permits,
+            waiter: None,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Tries to acquire the permits without a guard.
+    //--------------------------------------------------------------------------
+    pub(crate) fn try_acquire_permits
+    (
+        &self,
+        permits: usize,
+    ) -> Result<(), TryAcquireError>
+    {
+        let mut state = lock(&self.state);
+        if state.closed
+        {
+            return Err(TryAcquireError::Closed);
+        }
+        if (self.fair && !state.waiters.is_empty()) || state.permits < permits
+        {
+            return Err(TryAcquireError::NoPermits);
+        }
+        state.permits -= permits;
+        Ok(())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Releases the permits and wakes the waiters that can now proceed.
+    //--------------------------------------------------------------------------
+    pub(crate) fn release( &self, permits: usize )
+    {
+        let wakers =
+        {
+            let mut state = lock(&self.state);
+            state.permits = (state.permits + permits).min(Self::MAX_PERMITS);
+            state.assign()
+        };
+        for waker in wakers
+        {
+            waker.wake();
+        }
+    }
+}
+
+impl fmt::Debug for Semaphore
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        let state = lock(&self.state);
+        f.debug_struct("Semaphore")
+            .field("permits", &state.permits)
+            .field("waiters", &state.waiters.len())
+            .field("closed", &state.closed)
+            .finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # SemaphorePermit
+///
+/// The permits are released when this is dropped.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct SemaphorePermit<'a>
+{
+    semaphore: &'a Semaphore,
+    permits: usize,
+}
+
+impl SemaphorePermit<'_>
+{
+    //--------------------------------------------------------------------------
+    /// Returns the number of permits held.
+    //--------------------------------------------------------------------------
+    pub fn num_permits( &self ) -> usize
+    {
+        self.permits
+    }
+
+    //--------------------------------------------------------------------------
+    /// Forgets the permits without releasing them.
+    //--------------------------------------------------------------------------
+    pub fn forget( mut self )
+    {
+        self.permits = 0;
+    }
+}
+
+impl Drop for SemaphorePermit<'_>
+{
+    fn drop( &mut self )
+    {
+        if self.permits > 0
+        {
+            self.semaphore.release(self.permits);
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Acquire
+///
+/// Future acquiring permits. Dropping it removes the waiter from the queue, or
+/// gives back the permits if they were already granted.
+//------------------------------------------------------------------------------
+pub(crate) struct Acquire<'a>
+{
+    semaphore: &'a Semaphore,
+    permits: usize,
+    waiter: Option<Arc<Mutex<Waiter>>>,
+}
+
+impl Future for Acquire<'_>
+{
+    type Output = Result<(), AcquireError>;
+
+    fn poll( mut self: Pin<&mut Self>, cx: &mut Context ) -> Poll<Self::Output>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        let semaphore = self.semaphore;
+        let mut state = lock(&semaphore.state);
+
+        if let Some(waiter) = self.waiter.as_ref()
+        {
+            let mut waiter = lock(waiter);
+            if waiter.granted
+            {
+                drop(waiter);
+                self.waiter = None;
+                return Poll::Ready(Ok(()));
+            }
+            if state.closed
+            {
+                drop(waiter);
+                self.waiter = None;
+                return Poll::Ready(Err(AcquireError(())));
+            }
+            waiter.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+
+        if state.closed
+        {
+            return Poll::Ready(Err(AcquireError(())));
+        }
+        let can_barge = !semaphore.fair || state.waiters.is_empty();
+        if can_barge && state.permits >= self.permits
+        {
+            state.permits -= self.permits;
+            return Poll::Ready(Ok(()));
+        }
+
+        let waiter = Arc::new(Mutex::new(Waiter
+        {
+            needed: self.permits,
+            granted: false,
+            waker: Some(cx.waker().clone()),
+        }));
+        state.waiters.push_back(waiter.clone());
+        drop(state);
+        self.waiter = Some(waiter);
+        Poll::Pending
+    }
+}
+
+impl Drop for Acquire<'_>
+{
+    fn drop( &mut self )
+    {
+        let waiter = match self.waiter.take()
+        {
+            Some(waiter) => waiter,
+            None => return,
+        };
+
+        let wakers =
+        {
+            let mut state = lock(&self.semaphore.state);
+            if lock(&waiter).granted
+            {
+                state.permits += self.permits;
+            }
+            else
+            {
+                state.waiters.retain(|w| !Arc::ptr_eq(w, &waiter));
+            }
+            state.assign()
+        };
+        for waker in wakers
+        {
+            waker.wake();
+        }
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_util::WakeCounter;
+
+    use std::pin::pin;
+
+    #[test]
+    fn release_wakes_the_waiter_in_fifo_order()
+    {
+        let semaphore = Semaphore::new(1);
+        let held = semaphore.try_acquire().unwrap();
+        let (first, second) = (WakeCounter::new(), WakeCounter::new());
+        let mut a = pin!(semaphore.acquire());
+        let mut b = pin!(semaphore.acquire());
+        assert!(first.poll(a.as_mut()).is_pending());
+        assert!(second.poll(b.as_mut()).is_pending());
+
+        drop(held);
+        assert_eq!((first.wakes(), second.wakes()), (1, 0));
+        let permit = match first.poll(a.as_mut())
+        {
+            Poll::Ready(permit) => permit.unwrap(),
+            Poll::Pending => panic!("the permit was granted"),
+        };
+        assert!(second.poll(b.as_mut()).is_pending());
+        drop(permit);
+        assert_eq!(second.wakes(), 1);
+        assert!(second.poll(b.as_mut()).is_ready());
+    }
+
+    #[test]
+    fn fair_semaphore_does_not_let_acquirers_barge()
+    {
+        let semaphore = Semaphore::new(1);
+        let held = semaphore.try_acquire_many(1).unwrap();
+        let counter = WakeCounter::new();
+        let mut many = pin!(semaphore.acquire_many(2));
+        assert!(counter.poll(many.as_mut()).is_pending());
+
+        drop(held);
+        assert_eq!(semaphore.try_acquire().unwrap_err(), TryAcquireError::NoPermits);
+
+        let unfair = Semaphore::unfair(1);
+        let held = unfair.try_acquire().unwrap();
+        let mut many = pin!(unfair.acquire_many(2));
+        assert!(counter.poll(many.as_mut()).is_pending());
+        drop(held);
+        assert!(unfair.try_acquire().is_ok());
+    }
+
+    #[test]
+    fn cancelled_waiter_passes_the_permits_on()
+    {
+        let semaphore = Semaphore::new(0);
+        let (first, second) = (WakeCounter::new(), WakeCounter::new());
+        let mut b = pin!(semaphore.acquire());
+        {
+            let mut a = pin!(semaphore.acquire());
+            assert!(first.poll(a.as_mut()).is_pending());
+            assert!(second.poll(b.as_mut()).is_pending());
+
+            // The permit granted to the first waiter is given back when it
+            // is dropped before completing.
+            semaphore.add_permits(1);
+            assert_eq!(first.wakes(), 1);
+        }
+        assert_eq!(second.wakes(), 1);
+        let permit = second.poll(b.as_mut());
+        assert_eq!(semaphore.available_permits(), 0);
+        drop(permit);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+
+    #[test]
+    fn close_fails_waiters_and_acquirers()
+    {
+        let semaphore = Semaphore::new(0);
+        let counter = WakeCounter::new();
+        let mut waiting = pin!(semaphore.acquire());
+        assert!(counter.poll(waiting.as_mut()).is_pending());
+
+        semaphore.close();
+        assert_eq!(counter.wakes(), 1);
+        assert!(matches!(counter.poll(waiting.as_mut()), Poll::Ready(Err(AcquireError(())))));
+        assert_eq!(semaphore.try_acquire().unwrap_err(), TryAcquireError::Closed);
+        assert!(semaphore.is_closed());
+    }
+
+    #[test]
+    fn forgotten_permits_are_not_released()
+    {
+        let semaphore = Semaphore::new(3);
+        semaphore.try_acquire_many(2).unwrap().forget();
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+}
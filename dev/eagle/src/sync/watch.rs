@@ -0,0 +1,505 @@
+//------------------------------------------------------------------------------
+//! # Watch channel
+//!
+//! A single-producer, multi-consumer channel keeping only the latest value.
+//! Every value sent increments a version, and each receiver remembers the
+//! version it has seen last to know whether the value has changed.
+//------------------------------------------------------------------------------
+
+use super::lock;
+use crate::executor::poll_proceed;
+
+use std::fmt;
+use std::future::poll_fn;
+use std::ops::Deref;
+use std::sync::{ Arc, Mutex, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard };
+use std::sync::atomic::{ AtomicBool, AtomicU64, AtomicUsize, Ordering };
+use std::task::{ Poll, Waker };
+
+
+//------------------------------------------------------------------------------
+/// # SendError
+///
+/// There is no receiver. The value is returned back.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendError<T>(pub T);
+
+impl<T> fmt::Display for SendError<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "channel has no receiver")
+    }
+}
+
+impl<T: fmt::Debug> std::error::Error for SendError<T> {}
+
+
+//------------------------------------------------------------------------------
+/// # RecvError
+///
+/// The sender has been dropped.
+//------------------------------------------------------------------------------
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvError(());
+
+impl fmt::Display for RecvError
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        write!(f, "channel closed")
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+
+//------------------------------------------------------------------------------
+/// # channel
+///
+/// Creates a watch channel holding the initial value.
+//------------------------------------------------------------------------------
+pub fn channel<T>( init: T ) -> (Sender<T>, Receiver<T>)
+{
+    let shared = Arc::new(Shared
+    {
+        value: RwLock::new(init),
+        version: AtomicU64::new(0),
+        sender_alive: AtomicBool::new(true),
+        receivers: AtomicUsize::new(1),
+        rx_wakers: Mutex::new(Vec::new()),
+        tx_wakers: Mutex::new(Vec::new()),
+    });
+    (Sender { shared: shared.clone() }, Receiver { shared, seen: 0 })
+}
+
+
+//------------------------------------------------------------------------------
+/// # Shared
+//------------------------------------------------------------------------------
+struct Shared<T>
+{
+    value: RwLock<T>,
+    version: AtomicU64,
+    sender_alive: AtomicBool,
+    receivers: AtomicUsize,
+    rx_wakers: Mutex<Vec<Waker>>,
+    tx_wakers: Mutex<Vec<Waker>>,
+}
+
+impl<T> Shared<T>
+{
+    //--------------------------------------------------------------------------
+    /// Wakes the given wakers.
+    //--------------------------------------------------------------------------
+    fn wake_all( wakers: &Mutex<Vec<Waker>> )
+    {
+        let wakers = std::mem::take(&mut *lock(wakers));
+        for waker in wakers
+        {
+            waker.wake();
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Registers the waker unless it is already registered.
+    //--------------------------------------------------------------------------
+    fn register( wakers: &Mutex<Vec<Waker>>, waker: &Waker )
+    {
+        let mut wakers = lock(wakers);
+        if wakers.iter().all(|w| !w.will_wake(waker))
+        {
+            wakers.push(waker.clone());
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Locks the value for reading.
+    //--------------------------------------------------------------------------
+    fn read( &self ) -> RwLockReadGuard<'_, T>
+    {
+        self.value.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Locks the value for writing.
+    //--------------------------------------------------------------------------
+    fn write( &self ) -> RwLockWriteGuard<'_, T>
+    {
+        self.value.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Ref
+///
+/// A borrowed reference to the value. Holding it blocks the sender, so it
+/// should not be held across `.await` points.
+//------------------------------------------------------------------------------
+pub struct Ref<'a, T>
+{
+    guard: RwLockReadGuard<'a, T>,
+    has_changed: bool,
+}
+
+impl<T> Ref<'_, T>
+{
+    //--------------------------------------------------------------------------
+    /// Returns whether the value had changed since the receiver last saw it.
+    //--------------------------------------------------------------------------
+    pub fn has_changed( &self ) -> bool
+    {
+        self.has_changed
+    }
+}
+
+impl<T> Deref for Ref<'_, T>
+{
+    type Target = T;
+
+    fn deref( &self ) -> &T
+    {
+        &self.guard
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Ref<'_, T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Sender
+//------------------------------------------------------------------------------
+pub struct Sender<T>
+{
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Sender<T>
+{
+    //--------------------------------------------------------------------------
+    /// Sends a new value. Fails if there is no receiver.
+    //--------------------------------------------------------------------------
+    pub fn send( &self, value: T ) -> Result<(), SendError<T>>
+    {
+        if self.receiver_count() == 0
+        {
+            return Err(SendError(value));
+        }
+        self.send_replace(value);
+        Ok(())
+    }
+
+    //--------------------------------------------------------------------------
+    /// Replaces the value even if there is no receiver, and returns the old
+    /// one.
+    //--------------------------------------------------------------------------
+    pub fn send_replace( &self, value: T ) -> T
+    {
+        let old = std::mem::replace(&mut *self.shared.write(), value);
+        self.notify();
+        old
+    }
+
+    //--------------------------------------------------------------------------
+    /// Modifies the value in place and notifies the receivers.
+    //--------------------------------------------------------------------------
+    pub fn send_modify<F: FnOnce(&mut T)>( &self, modify: F )
+    {
+        modify(&mut self.shared.write());
+        self.notify();
+    }
+
+    //--------------------------------------------------------------------------
+    /// Bumps the version and wakes the receivers.
+    //--------------------------------------------------------------------------
+    fn notify( &self )
+    {
+        self.shared.version.fetch_add(1, Ordering::AcqRel);
+        Shared::<T>::wake_all(&self.shared.rx_wakers);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a reference to the current value.
+    //--------------------------------------------------------------------------
+    pub fn borrow( &self ) -> Ref<'_, T>
+    {
+        Ref
+        {
+            guard: self.shared.read(),
+            has_changed: false,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new receiver that has seen the current value.
+    //--------------------------------------------------------------------------
+    pub fn subscribe( &self ) -> Receiver<T>
+    {
+        self.shared.receivers.fetch_add(1, Ordering::AcqRel);
+        Receiver
+        {
+            shared: self.shared.clone(),
+            seen: self.shared.version.load(Ordering::Acquire),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the number of receivers.
+    //--------------------------------------------------------------------------
+    pub fn receiver_count( &self ) -> usize
+    {
+        self.shared.receivers.load(Ordering::Acquire)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether all the receivers have been dropped.
+    //--------------------------------------------------------------------------
+    pub fn is_closed( &self ) -> bool
+    {
+        self.receiver_count() == 0
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits until all the receivers have been dropped.
+    //--------------------------------------------------------------------------
+    pub async fn closed( &self )
+    {
+        poll_fn(|cx|
+        {
+            if self.is_closed()
+            {
+                return Poll::Ready(());
+            }
+            Shared::<T>::register(&self.shared.tx_wakers, cx.waker());
+            match self.is_closed()
+            {
+                true => Poll::Ready(()),
+                false => Poll::Pending,
+            }
+        }).await
+    }
+}
+
+impl<T> Drop for Sender<T>
+{
+    fn drop( &mut self )
+    {
+        self.shared.sender_alive.store(false, Ordering::Release);
+        Shared::<T>::wake_all(&self.shared.rx_wakers);
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Sender<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Sender")
+            .field("value", &*self.shared.read())
+            .finish()
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Receiver
+//------------------------------------------------------------------------------
+pub struct Receiver<T>
+{
+    shared: Arc<Shared<T>>,
+    seen: u64,
+}
+
+impl<T> Receiver<T>
+{
+    //--------------------------------------------------------------------------
+    /// Returns a reference to the current value without marking it as seen.
+    //--------------------------------------------------------------------------
+    pub fn borrow( &self ) -> Ref<'_, T>
+    {
+        let guard = self.shared.read();
+        let version = self.shared.version.load(Ordering::Acquire);
+        Ref
+        {
+            guard,
+            has_changed: version != self.seen,
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a reference to the current value and marks it as seen.
+    //--------------------------------------------------------------------------
+    pub fn borrow_and_update( &mut self ) -> Ref<'_, T>
+    {
+        let guard = self.shared.read();
+        let version = self.shared.version.load(Ordering::Acquire);
+        let has_changed = version != self.seen;
+        self.seen = version;
+        Ref { guard, has_changed }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the value has changed since it was last seen.
+    //--------------------------------------------------------------------------
+    pub fn has_changed( &self ) -> Result<bool, RecvError>
+    {
+        if !self.shared.sender_alive.load(Ordering::Acquire)
+        {
+            return Err(RecvError(()));
+        }
+        Ok(self.shared.version.load(Ordering::Acquire) != self.seen)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits until the value changes and marks it as seen.
+    ///
+    /// Fails once the sender has been dropped.
+    //--------------------------------------------------------------------------
+    pub async fn changed( &mut self ) -> Result<(), RecvError>
+    {
+        poll_fn(|cx|
+        {
+            if poll_proceed(cx).is_pending()
+            {
+                return Poll::Pending;
+            }
+
+            if let Some(result) = self.check_changed()
+            {
+                return Poll::Ready(result);
+            }
+            Shared::<T>::register(&self.shared.rx_wakers, cx.waker());
+            match self.check_changed()
+            {
+                Some(result) => Poll::Ready(result),
+                None => Poll::Pending,
+            }
+        }).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Marks the value as seen if it has changed, or fails if the sender has
+    /// been dropped. Returns `None` if there is nothing new.
+    //--------------------------------------------------------------------------
+    fn check_changed( &mut self ) -> Option<Result<(), RecvError>>
+    {
+        let version = self.shared.version.load(Ordering::Acquire);
+        if version != self.seen
+        {
+            self.seen = version;
+            return Some(Ok(()));
+        }
+        if !self.shared.sender_alive.load(Ordering::Acquire)
+        {
+            return Some(Err(RecvError(())));
+        }
+        None
+    }
+}
+
+impl<T> Clone for Receiver<T>
+{
+    fn clone( &self ) -> Self
+    {
+        self.shared.receivers.fetch_add(1, Ordering::AcqRel);
+        Self
+        {
+            shared: self.shared.clone(),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T>
+{
+    fn drop( &mut self )
+    {
+        if self.shared.receivers.fetch_sub(1, Ordering::AcqRel) == 1
+        {
+            Shared::<T>::wake_all(&self.shared.tx_wakers);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Receiver<T>
+{
+    fn fmt( &self, f: &mut fmt::Formatter ) -> fmt::Result
+    {
+        f.debug_struct("Receiver")
+            .field("value", &*self.shared.read())
+            .finish()
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::test_util::WakeCounter;
+
+    use std::pin::pin;
+
+    #[test]
+    fn changed_waits_for_a_new_value()
+    {
+        let (tx, mut rx) = channel(0);
+        let counter = WakeCounter::new();
+        {
+            let mut changed = pin!(rx.changed());
+            assert!(counter.poll(changed.as_mut()).is_pending());
+            tx.send(1).unwrap();
+            assert_eq!(counter.wakes(), 1);
+            assert_eq!(counter.poll(changed.as_mut()), Poll::Ready(Ok(())));
+        }
+        assert_eq!(*rx.borrow(), 1);
+        assert_eq!(rx.has_changed(), Ok(false));
+    }
+
+    #[test]
+    fn intermediate_values_are_coalesced()
+    {
+        let (tx, mut rx) = channel(0);
+        tx.send(1).unwrap();
+        tx.send_modify(|value| *value += 1);
+        assert!(rx.borrow().has_changed());
+        assert_eq!(*rx.borrow_and_update(), 2);
+        assert!(!rx.borrow().has_changed());
+    }
+
+    #[test]
+    fn dropped_sender_fails_changed()
+    {
+        let (tx, mut rx) = channel(0);
+        let counter = WakeCounter::new();
+        let mut changed = pin!(rx.changed());
+        assert!(counter.poll(changed.as_mut()).is_pending());
+        drop(tx);
+        assert_eq!(counter.wakes(), 1);
+        assert_eq!(counter.poll(changed.as_mut()), Poll::Ready(Err(RecvError(()))));
+    }
+
+    #[test]
+    fn closed_completes_when_the_last_receiver_is_dropped()
+    {
+        let (tx, rx) = channel(0);
+        let other = rx.clone();
+        let counter = WakeCounter::new();
+        let mut closed = pin!(tx.closed());
+        assert!(counter.poll(closed.as_mut()).is_pending());
+        drop(rx);
+        assert!(counter.poll(closed.as_mut()).is_pending());
+        drop(other);
+        assert!(counter.wakes() >= 1);
+        assert!(counter.poll(closed.as_mut()).is_ready());
+        assert_eq!(tx.send(1), Err(SendError(1)));
+        assert_eq!(tx.send_replace(2), 0);
+    }
+}
@@ -0,0 +1,299 @@
+//------------------------------------------------------------------------------
+//! # systemd integration
+//!
+//! Socket activation and service notifications, following the protocols of
+//! `sd_listen_fds(3)` and `sd_notify(3)`.
+//!
+//! ```no_run
+//! use eagle::systemd;
+//!
+//! # fn example() -> std::io::Result<()> {
+//! for listen_fd in systemd::listen_fds()?
+//! {
+//!     println!("inherited {:?} named {:?}", listen_fd.fd, listen_fd.name);
+//! }
+//! systemd::notify("READY=1")?;
+//! # Ok(())
+//! # }
+//! ```
+//------------------------------------------------------------------------------
+
+use std::env;
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{ FromRawFd, OwnedFd, RawFd };
+use std::os::unix::net::{ SocketAddr, UnixDatagram };
+use std::path::Path;
+use std::process;
+use std::time::Duration;
+
+/// First file descriptor passed by the service manager.
+const LISTEN_FDS_START: RawFd = 3;
+
+
+//------------------------------------------------------------------------------
+/// # ListenFd
+///
+/// A socket passed by the service manager, with the name given by
+/// `FileDescriptorName=` in the socket unit, if any.
+//------------------------------------------------------------------------------
+#[derive(Debug)]
+pub struct ListenFd
+{
+    pub fd: OwnedFd,
+    pub name: Option<String>,
+}
+
+
+//------------------------------------------------------------------------------
+/// # listen_fds
+///
+/// Takes the sockets passed by the service manager through `LISTEN_FDS` and
+/// `LISTEN_FDNAMES`. They are only taken if `LISTEN_PID` is the current
+/// process, and are marked close-on-exec. Fails with `InvalidData` if
+/// `LISTEN_FDS` is not a valid count.
+///
+/// The variables are removed from the environment, so that the sockets are
+/// taken once and not passed on to the child processes. As with
+/// `std::env::remove_var`, this should be called before other threads read
+/// the environment.
+//------------------------------------------------------------------------------
+pub fn listen_fds() -> io::Result<Vec<ListenFd>>
+{
+    take_listen_fds(LISTEN_FDS_START)
+}
+
+//------------------------------------------------------------------------------
+/// # take_listen_fds
+///
+/// Takes the sockets as `listen_fds` does, numbered from the given file
+/// descriptor.
+//------------------------------------------------------------------------------
+fn take_listen_fds( start: RawFd ) -> io::Result<Vec<ListenFd>>
+{
+    let pid = env::var("LISTEN_PID").ok();
+    let count = env::var("LISTEN_FDS").ok();
+    let names = env::var("LISTEN_FDNAMES").ok();
+    env::remove_var("LISTEN_PID");
+    env::remove_var("LISTEN_FDS");
+    env::remove_var("LISTEN_FDNAMES");
+
+    let (Some(pid), Some(count)) = (pid, count) else
+    {
+        return Ok(Vec::new());
+    };
+    if pid.parse::<u32>().ok() != Some(process::id())
+    {
+        return Ok(Vec::new());
+    }
+    let end = count.parse::<u32>()
+        .ok()
+        .and_then(|count| RawFd::try_from(count).ok())
+        .and_then(|count| start.checked_add(count))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid LISTEN_FDS"))?;
+
+    let mut names = names.as_deref().unwrap_or_default().split(':');
+    let mut listen_fds = Vec::new();
+    for fd in start..end
+    {
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        listen_fds.push(ListenFd
+        {
+            fd: unsafe { OwnedFd::from_raw_fd(fd) },
+            name: names.next().filter(|name| !name.is_empty()).map(str::to_string),
+        });
+    }
+    Ok(listen_fds)
+}
+
+
+//------------------------------------------------------------------------------
+/// # notify
+///
+/// Sends the state, such as `READY=1`, to the service manager through
+/// `NOTIFY_SOCKET`. Returns false without sending anything if the variable is
+/// not set.
+//------------------------------------------------------------------------------
+pub fn notify( state: &str ) -> io::Result<bool>
+{
+    let Some(path) = env::var_os("NOTIFY_SOCKET") else
+    {
+        return Ok(false);
+    };
+    let address = match path.as_bytes().strip_prefix(b"@")
+    {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(Path::new(&path))?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &address)?;
+    Ok(true)
+}
+
+
+//------------------------------------------------------------------------------
+/// # watchdog_interval
+///
+/// Returns the interval within which the service manager expects the
+/// `WATCHDOG=1` pings, from `WATCHDOG_USEC`, if the watchdog is enabled for
+/// the current process.
+//------------------------------------------------------------------------------
+pub fn watchdog_interval() -> Option<Duration>
+{
+    if let Ok(pid) = env::var("WATCHDOG_PID")
+    {
+        if pid.parse::<u32>().ok() != Some(process::id())
+        {
+            return None;
+        }
+    }
+    let usec = env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    match usec
+    {
+        0 => None,
+        usec => Some(Duration::from_micros(usec)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use std::os::unix::io::AsRawFd;
+    use std::sync::{ Mutex, PoisonError };
+
+    /// Serializes the tests reading and writing the environment.
+    static ENV: Mutex<()> = Mutex::new(());
+
+    //--------------------------------------------------------------------------
+    /// Sends the states with `NOTIFY_SOCKET` set to the address, and returns
+    /// what the socket received.
+    //--------------------------------------------------------------------------
+    fn notified( socket: &UnixDatagram, address: &str, states: &[&str] ) -> Vec<String>
+    {
+        let _env = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+        env::set_var("NOTIFY_SOCKET", address);
+        for state in states
+        {
+            assert!(notify(state).unwrap());
+        }
+        env::remove_var("NOTIFY_SOCKET");
+        assert!(!notify("READY=1").unwrap());
+
+        let mut buf = [0; 64];
+        states.iter().map(|_|
+        {
+            let len = socket.recv(&mut buf).unwrap();
+            String::from_utf8(buf[..len].to_vec()).unwrap()
+        }).collect()
+    }
+
+    #[test]
+    fn notify_sends_the_states_to_the_socket()
+    {
+        let states = ["READY=1", "WATCHDOG=1", "STOPPING=1"];
+        let path = env::temp_dir().join(format!("eagle-notify-{}.sock", process::id()));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+        let received = notified(&socket, path.to_str().unwrap(), &states);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(received, states);
+
+        let name = format!("eagle-notify-{}", process::id());
+        let address = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+        let socket = UnixDatagram::bind_addr(&address).unwrap();
+        let received = notified(&socket, &format!("@{}", name), &states);
+        assert_eq!(received, states);
+    }
+
+    //--------------------------------------------------------------------------
+    /// Sets the variables of socket activation, and takes the sockets as if
+    /// numbered from the given file descriptor.
+    //--------------------------------------------------------------------------
+    fn take( start: RawFd, pid: &str, count: &str, names: &str ) -> io::Result<Vec<ListenFd>>
+    {
+        let _env = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+        env::set_var("LISTEN_PID", pid);
+        env::set_var("LISTEN_FDS", count);
+        env::set_var("LISTEN_FDNAMES", names);
+        let taken = take_listen_fds(start);
+        for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"]
+        {
+            assert!(env::var_os(name).is_none(), "{} is still set", name);
+        }
+        taken
+    }
+
+    #[test]
+    fn listen_fds_takes_the_inherited_sockets()
+    {
+        // The sockets are moved to free numbers far above the ones the tests
+        // use, as the service manager would place them from 3.
+        let start = 700;
+        let (first, second) = UnixDatagram::pair().unwrap();
+        for (fd, socket) in [(start, &first), (start + 1, &second)]
+        {
+            assert_eq!(unsafe { libc::dup2(socket.as_raw_fd(), fd) }, fd);
+        }
+
+        let listen_fds = take(start, &process::id().to_string(), "2", "http:").unwrap();
+        let fds: Vec<_> = listen_fds.iter().map(|listen_fd| listen_fd.fd.as_raw_fd()).collect();
+        let names: Vec<_> = listen_fds.iter().map(|listen_fd| listen_fd.name.as_deref()).collect();
+        assert_eq!(fds, [start, start + 1]);
+        assert_eq!(names, [Some("http"), None]);
+        for listen_fd in &listen_fds
+        {
+            let flags = unsafe { libc::fcntl(listen_fd.fd.as_raw_fd(), libc::F_GETFD) };
+            assert_eq!(flags & libc::FD_CLOEXEC, libc::FD_CLOEXEC);
+        }
+
+        // The sockets are the same as the ones they were duplicated from.
+        let taken = UnixDatagram::from(listen_fds.into_iter().next().unwrap().fd);
+        taken.send(b"ping").unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(second.recv(&mut buf).unwrap(), 4);
+    }
+
+    #[test]
+    fn listen_fds_ignores_another_process()
+    {
+        let pid = (process::id() + 1).to_string();
+        assert!(take(700, &pid, "2", "").unwrap().is_empty());
+    }
+
+    #[test]
+    fn listen_fds_rejects_an_invalid_count()
+    {
+        let pid = process::id().to_string();
+        for count in ["-1", "", "two", "4294967296", "2147483647"]
+        {
+            let error = take(700, &pid, count, "").unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", count);
+        }
+        assert!(take(700, &pid, "0", "").unwrap().is_empty());
+    }
+
+    #[test]
+    fn watchdog_interval_is_for_the_current_process()
+    {
+        let _env = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+        env::set_var("WATCHDOG_USEC", "3000000");
+        env::set_var("WATCHDOG_PID", process::id().to_string());
+        assert_eq!(watchdog_interval(), Some(Duration::from_secs(3)));
+
+        env::set_var("WATCHDOG_PID", (process::id() + 1).to_string());
+        assert_eq!(watchdog_interval(), None);
+
+        env::remove_var("WATCHDOG_PID");
+        assert_eq!(watchdog_interval(), Some(Duration::from_secs(3)));
+
+        env::set_var("WATCHDOG_USEC", "0");
+        assert_eq!(watchdog_interval(), None);
+        env::remove_var("WATCHDOG_USEC");
+        assert_eq!(watchdog_interval(), None);
+    }
+}
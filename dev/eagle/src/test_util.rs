@@ -0,0 +1,175 @@
+//------------------------------------------------------------------------------
+//! # Test utilities
+//------------------------------------------------------------------------------
+
+use crate::io::{ AsyncRead, AsyncWrite };
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{ AtomicUsize, Ordering };
+use std::sync::{ Arc, Mutex, PoisonError };
+use std::task::{ Context, Poll, Wake, Waker };
+
+
+//------------------------------------------------------------------------------
+/// # WakeCounter
+///
+/// Polls futures by hand with a waker counting how many times it is woken.
+//------------------------------------------------------------------------------
+pub(crate) struct WakeCounter
+{
+    wakes: Arc<Counter>,
+    waker: Waker,
+}
+
+struct Counter(AtomicUsize);
+
+impl Wake for Counter
+{
+    fn wake( self: Arc<Self> )
+    {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+impl WakeCounter
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new WakeCounter.
+    //--------------------------------------------------------------------------
+    pub(crate) fn new() -> Self
+    {
+        let wakes = Arc::new(Counter(AtomicUsize::new(0)));
+        Self { waker: Waker::from(wakes.clone()), wakes }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns how many times the waker has been woken.
+    //--------------------------------------------------------------------------
+    pub(crate) fn wakes( &self ) -> usize
+    {
+        self.wakes.0.load(Ordering::SeqCst)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls the future once with the waker.
+    //--------------------------------------------------------------------------
+    pub(crate) fn poll<F: Future + ?Sized>( &self, future: Pin<&mut F> ) -> Poll<F::Output>
+    {
+        future.poll(&mut Context::from_waker(&self.waker))
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # MockStream
+///
+/// In-memory stream reading the given chunks one at a time, and keeping what
+/// is written to it. After the chunks, it reaches the end of the stream, or
+/// never becomes readable again if it stalls.
+//------------------------------------------------------------------------------
+pub(crate) struct MockStream
+{
+    reads: VecDeque<Vec<u8>>,
+    stall: bool,
+    max_write: usize,
+    written: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MockStream
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new MockStream reading the chunks.
+    //--------------------------------------------------------------------------
+    pub(crate) fn new<'a>( reads: impl IntoIterator<Item = &'a [u8]> ) -> Self
+    {
+        Self
+        {
+            reads: reads.into_iter().map(<[u8]>::to_vec).collect(),
+            stall: false,
+            max_write: usize::MAX,
+            written: Arc::default(),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Makes the stream wait forever after the chunks instead of ending.
+    //--------------------------------------------------------------------------
+    pub(crate) fn stall( mut self ) -> Self
+    {
+        self.stall = true;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Makes each write take at most the given number of bytes.
+    //--------------------------------------------------------------------------
+    pub(crate) fn max_write( mut self, max_write: usize ) -> Self
+    {
+        self.max_write = max_write;
+        self
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns the bytes written to the stream, which are still added to
+    /// once the stream is moved.
+    //--------------------------------------------------------------------------
+    pub(crate) fn written( &self ) -> Arc<Mutex<Vec<u8>>>
+    {
+        self.written.clone()
+    }
+}
+
+impl AsyncRead for MockStream
+{
+    fn poll_read
+    (
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        let Some(mut chunk) = self.reads.pop_front() else
+        {
+            return match self.stall
+            {
+                true => Poll::Pending,
+                false => Poll::Ready(Ok(0)),
+            };
+        };
+        let len = chunk.len().min(buf.len());
+        buf[..len].copy_from_slice(&chunk[..len]);
+        if len < chunk.len()
+        {
+            self.reads.push_front(chunk.split_off(len));
+        }
+        Poll::Ready(Ok(len))
+    }
+}
+
+impl AsyncWrite for MockStream
+{
+    fn poll_write
+    (
+        self: Pin<&mut Self>,
+        _cx: &mut Context,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>>
+    {
+        let len = buf.len().min(self.max_write);
+        self.written.lock().unwrap_or_else(PoisonError::into_inner).extend_from_slice(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+
+    fn poll_flush( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown( self: Pin<&mut Self>, _cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        Poll::Ready(Ok(()))
+    }
+}
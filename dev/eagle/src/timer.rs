@@ -0,0 +1,219 @@
+//------------------------------------------------------------------------------
+//! Timer module
+//------------------------------------------------------------------------------
+
+use crate::executor::poll_proceed;
+use crate::net::Registration;
+use crate::sim::{ self, Alarm };
+
+use std::future::poll_fn;
+use std::io;
+use std::mem;
+use std::os::unix::io::{ AsRawFd, FromRawFd, OwnedFd };
+use std::ptr;
+use std::sync::{ Mutex, PoisonError };
+use std::task::{ Context, Poll };
+use std::time::Duration;
+
+
+//------------------------------------------------------------------------------
+/// # Timer
+///
+/// Timer driven by the reactor, over a `timerfd`. An expiration only
+/// completes when a worker polls it, so it also shows that the runtime is
+/// responsive.
+///
+/// A timer created inside a simulation follows its virtual clock instead.
+//------------------------------------------------------------------------------
+pub(crate) enum Timer
+{
+    Os
+    {
+        registration: Registration,
+        fd: OwnedFd,
+    },
+    Sim(Mutex<Alarm>),
+}
+
+impl Timer
+{
+    //--------------------------------------------------------------------------
+    /// Creates a new Timer, disarmed.
+    //--------------------------------------------------------------------------
+    pub(crate) fn new() -> io::Result<Self>
+    {
+        if sim::is_entered()
+        {
+            return Ok(Self::Sim(Mutex::default()));
+        }
+
+        let fd = unsafe
+        {
+            libc::timerfd_create
+            (
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if fd < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        Ok(Self::Os
+        {
+            registration: Registration::new(fd.as_raw_fd())?,
+            fd,
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Creates a new Timer expiring every period, from one period from now.
+    //--------------------------------------------------------------------------
+    pub(crate) fn interval( period: Duration ) -> io::Result<Self>
+    {
+        let timer = Self::new()?;
+        timer.set(period, Some(period))?;
+        Ok(timer)
+    }
+
+    //--------------------------------------------------------------------------
+    /// Arms the timer to expire after the delay, then every period if any.
+    /// The expirations not waited for yet are discarded.
+    //--------------------------------------------------------------------------
+    pub(crate) fn set( &self, delay: Duration, period: Option<Duration> ) -> io::Result<()>
+    {
+        let fd = match self
+        {
+            Self::Os { fd, .. } => fd,
+            Self::Sim(alarm) =>
+            {
+                alarm.lock().unwrap_or_else(PoisonError::into_inner).set(delay, period);
+                return Ok(());
+            },
+        };
+
+        // A zero delay or period would disarm the timer.
+        let timespec = |duration: Duration|
+        {
+            let duration = duration.max(Duration::from_nanos(1));
+            libc::timespec
+            {
+                tv_sec: duration.as_secs() as libc::time_t,
+                tv_nsec: duration.subsec_nanos() as libc::c_long,
+            }
+        };
+        let spec = libc::itimerspec
+        {
+            it_interval: match period
+            {
+                Some(period) => timespec(period),
+                None => libc::timespec { tv_sec: 0, tv_nsec: 0 },
+            },
+            it_value: timespec(delay),
+        };
+        let result = unsafe
+        {
+            libc::timerfd_settime(fd.as_raw_fd(), 0, &spec, ptr::null_mut())
+        };
+        match result < 0
+        {
+            true => Err(io::Error::last_os_error()),
+            false => Ok(()),
+        }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Waits for the next expiration. The expirations missed since the last
+    /// call are coalesced into one.
+    //--------------------------------------------------------------------------
+    pub(crate) async fn expired( &self ) -> io::Result<()>
+    {
+        poll_fn(|cx| self.poll_expired(cx)).await
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for the next expiration. See `expired`.
+    //--------------------------------------------------------------------------
+    pub(crate) fn poll_expired( &self, cx: &mut Context ) -> Poll<io::Result<()>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+        let (registration, fd) = match self
+        {
+            Self::Os { registration, fd } => (registration, fd),
+            Self::Sim(alarm) =>
+            {
+                let mut alarm = alarm.lock().unwrap_or_else(PoisonError::into_inner);
+                return alarm.poll_expired(cx).map(Ok);
+            },
+        };
+        registration.poll_read(cx, ||
+        {
+            let mut expirations = 0u64;
+            let read = unsafe
+            {
+                libc::read
+                (
+                    fd.as_raw_fd(),
+                    &mut expirations as *mut u64 as *mut libc::c_void,
+                    mem::size_of::<u64>(),
+                )
+            };
+            match read < 0
+            {
+                true => Err(io::Error::last_os_error()),
+                false => Ok(()),
+            }
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::sim::{ self, Simulation };
+
+    #[test]
+    fn timer_follows_the_virtual_clock()
+    {
+        let elapsed = Simulation::new(0).block_on(async
+        {
+            let timer = Timer::new().unwrap();
+            timer.set(Duration::from_secs(30), None).unwrap();
+            timer.expired().await.unwrap();
+            let first = sim::now();
+
+            // Setting the timer again replaces the pending expiration.
+            timer.set(Duration::from_secs(60), None).unwrap();
+            timer.set(Duration::from_secs(10), None).unwrap();
+            timer.expired().await.unwrap();
+            (first, sim::now())
+        }).unwrap();
+        assert_eq!(elapsed, (Duration::from_secs(30), Duration::from_secs(40)));
+    }
+
+    #[test]
+    fn interval_coalesces_missed_expirations()
+    {
+        let elapsed = Simulation::new(0).block_on(async
+        {
+            let timer = Timer::interval(Duration::from_secs(10)).unwrap();
+            timer.expired().await.unwrap();
+            let first = sim::now();
+
+            sim::sleep(Duration::from_secs(25)).await;
+            timer.expired().await.unwrap();
+            let late = sim::now();
+            timer.expired().await.unwrap();
+            (first, late, sim::now())
+        }).unwrap();
+        let secs = Duration::from_secs;
+        assert_eq!(elapsed, (secs(10), secs(35), secs(40)));
+    }
+}
@@ -0,0 +1,408 @@
+//------------------------------------------------------------------------------
+//! Upgrade module
+//!
+//! Hot upgrade of a running server. The server executes the binary again with
+//! its listening sockets inherited, and the new process adopts them and
+//! reports that it is ready through a pipe. Both processes accept on the same
+//! sockets until the old one stops accepting and drains its connections, so
+//! no connection is refused during the upgrade.
+//------------------------------------------------------------------------------
+
+use crate::executor::poll_proceed;
+use crate::net::Registration;
+
+use std::env;
+use std::io;
+use std::os::unix::io::{ AsRawFd, FromRawFd, OwnedFd, RawFd };
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{ Child, Command };
+use std::task::{ Context, Poll };
+use std::thread;
+
+/// Variable listing the inherited sockets, one `<fd> <owns file> <name>` per
+/// line.
+const FDS_VAR: &str = "EAGLE_UPGRADE_FDS";
+
+/// Variable holding the write end of the readiness pipe.
+const READY_VAR: &str = "EAGLE_UPGRADE_READY";
+
+
+//------------------------------------------------------------------------------
+/// # HandoffFd
+///
+/// A listening socket handed over to the new process, with the name of its
+/// listener and whether the listener removes its socket file.
+//------------------------------------------------------------------------------
+pub(crate) struct HandoffFd
+{
+    pub(crate) fd: RawFd,
+    pub(crate) name: String,
+    pub(crate) owns_file: bool,
+}
+
+
+//------------------------------------------------------------------------------
+/// # Inherited
+///
+/// The sockets inherited from the process being upgraded, and the pipe to
+/// report readiness to it.
+//------------------------------------------------------------------------------
+pub(crate) struct Inherited
+{
+    pub(crate) listeners: Vec<(OwnedFd, String, bool)>,
+    pub(crate) ready: Option<OwnedFd>,
+}
+
+impl Inherited
+{
+    //--------------------------------------------------------------------------
+    /// Takes the sockets and the pipe inherited from the process being
+    /// upgraded, if any, and removes their variables from the environment.
+    /// They are marked close-on-exec, so that they are not passed on to the
+    /// child processes.
+    //--------------------------------------------------------------------------
+    pub(crate) fn take() -> io::Result<Self>
+    {
+        let fds = env::var(FDS_VAR).ok();
+        let ready = env::var(READY_VAR).ok();
+        env::remove_var(FDS_VAR);
+        env::remove_var(READY_VAR);
+
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid upgrade variables");
+        let mut listeners = Vec::new();
+        for line in fds.as_deref().unwrap_or_default().lines()
+        {
+            let mut fields = line.splitn(3, ' ');
+            let (Some(fd), Some(owns_file), Some(name)) =
+                (fields.next(), fields.next(), fields.next()) else
+            {
+                return Err(invalid());
+            };
+            let fd = adopt_fd(fd.parse().map_err(|_| invalid())?)?;
+            listeners.push((fd, name.to_string(), owns_file == "1"));
+        }
+        let ready = match ready
+        {
+            Some(fd) => Some(adopt_fd(fd.parse().map_err(|_| invalid())?)?),
+            None => None,
+        };
+        Ok(Self { listeners, ready })
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # notify_ready
+///
+/// Reports to the process being upgraded that the new one is ready. Fails if
+/// the report could not be written, in which case the pipe is closed and the
+/// process being upgraded keeps serving.
+//------------------------------------------------------------------------------
+pub(crate) fn notify_ready( ready: OwnedFd ) -> io::Result<()>
+{
+    let byte = 1u8;
+    loop
+    {
+        let written = unsafe
+        {
+            libc::write(ready.as_raw_fd(), &byte as *const u8 as *const libc::c_void, 1)
+        };
+        match written
+        {
+            1 => return Ok(()),
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            _ =>
+            {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted
+                {
+                    return Err(error);
+                }
+            },
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # Upgrade
+///
+/// A new process started with the listening sockets, until it reports that it
+/// is ready. It is killed if dropped before.
+//------------------------------------------------------------------------------
+pub(crate) struct Upgrade
+{
+    registration: Registration,
+    ready: OwnedFd,
+    child: Option<Child>,
+}
+
+impl Upgrade
+{
+    //--------------------------------------------------------------------------
+    /// Executes the program with the arguments of the current process,
+    /// passing it the listening sockets.
+    //--------------------------------------------------------------------------
+    pub(crate) fn spawn( program: &Path, handoff: &[HandoffFd] ) -> io::Result<Self>
+    {
+        let mut fds = [0; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC | libc::O_NONBLOCK) } < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let ready = unsafe { OwnedFd::from_raw_fd(fds[0]) };
+        let ready_tx = unsafe { OwnedFd::from_raw_fd(fds[1]) };
+
+        let listeners = handoff_var(handoff);
+        let inherited: Vec<RawFd> = handoff
+            .iter()
+            .map(|fd| fd.fd)
+            .chain([ready_tx.as_raw_fd()])
+            .collect();
+
+        let mut command = Command::new(program);
+        command
+            .args(env::args_os().skip(1))
+            .env(FDS_VAR, listeners)
+            .env(READY_VAR, ready_tx.as_raw_fd().to_string());
+
+        // Only the descriptor flags of the child are cleared, between the
+        // fork and the exec, where allocating is not allowed.
+        unsafe
+        {
+            command.pre_exec(move ||
+            {
+                for fd in &inherited
+                {
+                    if libc::fcntl(*fd, libc::F_SETFD, 0) < 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        drop(ready_tx);
+
+        Ok(Self
+        {
+            registration: Registration::new(ready.as_raw_fd())?,
+            ready,
+            child: Some(child),
+        })
+    }
+
+    //--------------------------------------------------------------------------
+    /// Polls for the new process to report that it is ready, and returns its
+    /// process id. Fails if it exits or closes the pipe before.
+    //--------------------------------------------------------------------------
+    pub(crate) fn poll_ready( &mut self, cx: &mut Context ) -> Poll<io::Result<u32>>
+    {
+        if poll_proceed(cx).is_pending()
+        {
+            return Poll::Pending;
+        }
+
+        let polled = self.registration.poll_read(cx, ||
+        {
+            let mut byte = 0u8;
+            let read = unsafe
+            {
+                libc::read(self.ready.as_raw_fd(), &mut byte as *mut u8 as *mut libc::c_void, 1)
+            };
+            match read < 0
+            {
+                true => Err(io::Error::last_os_error()),
+                false => Ok(read),
+            }
+        });
+        match polled
+        {
+            Poll::Ready(Ok(0)) =>
+            {
+                let error = "the new process exited before it was ready";
+                Poll::Ready(Err(io::Error::new(io::ErrorKind::UnexpectedEof, error)))
+            },
+            Poll::Ready(Ok(_)) => match self.child.take()
+            {
+                Some(child) => Poll::Ready(Ok(child.id())),
+                None => Poll::Ready(Err(io::ErrorKind::NotFound.into())),
+            },
+            Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for Upgrade
+{
+    fn drop( &mut self )
+    {
+        if let Some(mut child) = self.child.take()
+        {
+            let _ = child.kill();
+            thread::spawn(move || child.wait());
+        }
+    }
+}
+
+
+//------------------------------------------------------------------------------
+/// # handoff_var
+///
+/// Returns the value of the variable listing the sockets, as read back by
+/// `Inherited::take`.
+//------------------------------------------------------------------------------
+fn handoff_var( handoff: &[HandoffFd] ) -> String
+{
+    handoff
+        .iter()
+        .map(|fd| format!("{} {} {}\n", fd.fd, u8::from(fd.owns_file), fd.name))
+        .collect()
+}
+
+//------------------------------------------------------------------------------
+/// # adopt_fd
+///
+/// Takes ownership of an inherited file descriptor, and marks it
+/// close-on-exec.
+//------------------------------------------------------------------------------
+fn adopt_fd( fd: RawFd ) -> io::Result<OwnedFd>
+{
+    if fd < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0
+    {
+        return Err(io::Error::from_raw_os_error(libc::EBADF));
+    }
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+
+#[cfg(test)]
+mod tests
+{
+    use super::*;
+    use crate::executor::Executor;
+    use std::future::poll_fn;
+    use std::os::unix::io::IntoRawFd;
+    use std::os::unix::net::UnixDatagram;
+    use std::sync::{ Mutex, PoisonError };
+
+    /// Serializes the tests reading and writing the environment.
+    static ENV: Mutex<()> = Mutex::new(());
+
+    //--------------------------------------------------------------------------
+    /// Sets the variables, and takes what they pass.
+    //--------------------------------------------------------------------------
+    fn take( fds: &str, ready: Option<&str> ) -> io::Result<Inherited>
+    {
+        let _env = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+        env::set_var(FDS_VAR, fds);
+        match ready
+        {
+            Some(ready) => env::set_var(READY_VAR, ready),
+            None => env::remove_var(READY_VAR),
+        }
+        let inherited = Inherited::take();
+        assert!(env::var_os(FDS_VAR).is_none() && env::var_os(READY_VAR).is_none());
+        inherited
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns a pipe, read end first.
+    //--------------------------------------------------------------------------
+    fn pipe() -> (OwnedFd, OwnedFd)
+    {
+        let mut fds = [0; 2];
+        assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+        unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) }
+    }
+
+    //--------------------------------------------------------------------------
+    /// Returns whether the file descriptor is marked close-on-exec.
+    //--------------------------------------------------------------------------
+    fn cloexec( fd: &OwnedFd ) -> bool
+    {
+        let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFD) };
+        flags & libc::FD_CLOEXEC != 0
+    }
+
+    #[test]
+    fn take_reads_back_the_handed_over_sockets()
+    {
+        let (first, second) = UnixDatagram::pair().unwrap();
+        let (_ready_rx, ready_tx) = pipe();
+        let handoff =
+        [
+            HandoffFd { fd: first.into_raw_fd(), name: "tcp:[::]:80".to_string(), owns_file: false },
+            HandoffFd { fd: second.into_raw_fd(), name: "unix:/tmp/a b".to_string(), owns_file: true },
+        ];
+        let ready_fd = ready_tx.into_raw_fd();
+        for fd in handoff.iter().map(|fd| fd.fd).chain([ready_fd])
+        {
+            assert_eq!(unsafe { libc::fcntl(fd, libc::F_SETFD, 0) }, 0);
+        }
+
+        let inherited = take(&handoff_var(&handoff), Some(&ready_fd.to_string())).unwrap();
+        let listeners: Vec<_> = inherited.listeners
+            .iter()
+            .map(|(fd, name, owns_file)| (fd.as_raw_fd(), name.as_str(), *owns_file))
+            .collect();
+        assert_eq!(listeners,
+        [
+            (handoff[0].fd, "tcp:[::]:80", false),
+            (handoff[1].fd, "unix:/tmp/a b", true),
+        ]);
+        assert!(inherited.listeners.iter().all(|(fd, _, _)| cloexec(fd)));
+
+        let ready = inherited.ready.unwrap();
+        assert_eq!(ready.as_raw_fd(), ready_fd);
+        assert!(cloexec(&ready));
+
+        let inherited = take("", None).unwrap();
+        assert!(inherited.listeners.is_empty() && inherited.ready.is_none());
+    }
+
+    #[test]
+    fn take_rejects_malformed_variables()
+    {
+        for fds in ["3", "3 1", "x 1 name", "-\n", " 1 name"]
+        {
+            let error = take(fds, None).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", fds);
+        }
+        let error = take("", Some("ready")).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn ready_is_reported_through_the_pipe()
+    {
+        let (ready_rx, ready_tx) = pipe();
+        let child = Command::new("true").spawn().unwrap();
+        let pid = child.id();
+        let mut upgrade = Upgrade
+        {
+            registration: Registration::new(ready_rx.as_raw_fd()).unwrap(),
+            ready: ready_rx,
+            child: Some(child),
+        };
+        notify_ready(ready_tx).unwrap();
+        let ready = Executor::new(0).block_on(poll_fn(move |cx| upgrade.poll_ready(cx)));
+        assert_eq!(ready.unwrap().unwrap(), pid);
+
+        // A report to a process that is gone is not lost silently.
+        let (ready_rx, ready_tx) = pipe();
+        drop(ready_rx);
+        assert!(notify_ready(ready_tx).is_err());
+    }
+
+    #[test]
+    fn exit_before_ready_is_an_error()
+    {
+        let mut upgrade = Upgrade::spawn(Path::new("true"), &[]).unwrap();
+        let ready = Executor::new(0).block_on(poll_fn(move |cx| upgrade.poll_ready(cx)));
+        assert_eq!(ready.unwrap().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}